@@ -5,6 +5,10 @@ const API_URL = ''  // Empty for same-origin (uses Vite proxy)
 
 export function RoomPanel() {
   const currentRoom = useGameStore((s) => s.currentRoom)
+  const vitals = useGameStore((s) => s.vitals)
+  const occupants = useGameStore((s) => s.occupants)
+  const playerId = useGameStore((s) => s.playerId)
+  const others = occupants.filter((o) => o.id !== playerId)
   const { theme } = useTheme()
 
   // Construct image URL from hash
@@ -80,6 +84,24 @@ export function RoomPanel() {
             <span>{currentRoom.contents.join(', ')}</span>
           </div>
         )}
+
+        {/* Occupants (typed events only) */}
+        {others.length > 0 && (
+          <div style={{ marginTop: '0.75rem' }}>
+            <span className="text-sm text-muted">Here: </span>
+            <span>{others.map((o) => o.name).join(', ')}</span>
+          </div>
+        )}
+
+        {/* Vitals (typed events only) */}
+        {vitals && (
+          <div style={{ marginTop: '0.75rem' }}>
+            <span className="text-sm text-muted">HP: </span>
+            <span className={vitals.in_combat ? 'text-highlight' : 'text-primary'}>
+              {vitals.hp}/{vitals.max_hp}
+            </span>
+          </div>
+        )}
       </div>
     </div>
   )
@@ -1,6 +1,7 @@
 import { useEffect, useRef, useCallback } from 'react'
 import { useGameStore } from '../store/gameStore'
-import type { ServerMessage, ClientMessage } from '../types/messages'
+import { CLIENT_CAPABILITIES, PROTOCOL_VERSION } from '../types/messages'
+import type { SequencedMessage, ClientMessage } from '../types/messages'
 
 const WS_URL = `${window.location.protocol === 'https:' ? 'wss:' : 'ws:'}//${window.location.host}/ws`
 const PING_INTERVAL = 30000
@@ -12,6 +13,8 @@ export function useWebSocket() {
   const pingIntervalRef = useRef<number | null>(null)
   const reconnectTimeoutRef = useRef<number | null>(null)
   const reconnectDelayRef = useRef(RECONNECT_BASE_DELAY)
+  // Highest message sequence number seen, to resume from after a drop
+  const lastSeqRef = useRef(0)
 
   const {
     token,
@@ -20,8 +23,12 @@ export function useWebSocket() {
     setConnectionStatus,
     addMessage,
     setRoom,
+    setVitals,
+    setInventory,
+    setOccupants,
     setPlayerId,
     setThemeId,
+    logout,
   } = useGameStore()
 
   const cleanup = useCallback(() => {
@@ -41,13 +48,49 @@ export function useWebSocket() {
 
   const handleMessage = useCallback((event: MessageEvent) => {
     try {
-      const msg: ServerMessage = JSON.parse(event.data)
+      const msg: SequencedMessage = JSON.parse(event.data)
+      const previousSeq = lastSeqRef.current
+      if (msg.seq) {
+        lastSeqRef.current = Math.max(previousSeq, msg.seq)
+      }
 
       switch (msg.type) {
-        case 'welcome':
+        case 'welcome': {
+          // Same player and outbox as before the drop: pick up where we
+          // left off (a lower seq means the server started a new outbox)
+          const resuming =
+            previousSeq > 0 &&
+            (msg.seq ?? 0) > previousSeq &&
+            msg.player_id === useGameStore.getState().playerId
           setPlayerId(msg.player_id)
           setThemeId(msg.theme_id)
-          addMessage(`Welcome! Your player ID: ${msg.player_id}`, 'system')
+          if (!resuming) {
+            lastSeqRef.current = msg.seq ?? 0
+            addMessage(`Welcome! Your player ID: ${msg.player_id}`, 'system')
+          }
+          // Servers that offer typed events get a hello; older ones ignore it
+          if (msg.protocol && wsRef.current?.readyState === WebSocket.OPEN) {
+            const hello: ClientMessage = {
+              type: 'hello',
+              protocol: PROTOCOL_VERSION,
+              capabilities: CLIENT_CAPABILITIES,
+            }
+            wsRef.current.send(JSON.stringify(hello))
+          }
+          if (resuming && wsRef.current?.readyState === WebSocket.OPEN) {
+            const resume: ClientMessage = { type: 'resume', last_seq: previousSeq }
+            wsRef.current.send(JSON.stringify(resume))
+          }
+          break
+        }
+
+        case 'hello':
+          break
+
+        case 'resumed':
+          if (msg.missed > 0) {
+            addMessage(`${msg.missed} messages were lost while disconnected.`, 'system')
+          }
           break
 
         case 'output':
@@ -68,14 +111,82 @@ export function useWebSocket() {
           addMessage(msg.message, 'error')
           break
 
+        case 'rate_limited':
+          addMessage(msg.message, 'error')
+          break
+
         case 'echo':
           addMessage(`> ${msg.command}`, 'command')
           break
+
+        case 'vitals':
+          setVitals({ hp: msg.hp, max_hp: msg.max_hp, in_combat: msg.in_combat })
+          break
+
+        case 'inventory':
+          setInventory(msg.items)
+          break
+
+        case 'room_occupants_changed':
+          setOccupants(msg.occupants)
+          break
+
+        case 'combat_round': {
+          const { playerId } = useGameStore.getState()
+          // The attacker's own narration arrives as output
+          if (msg.attacker_id !== playerId) {
+            const outcome = msg.hit
+              ? `${msg.critical ? 'critically hits' : 'hits'} ${msg.target} for ${msg.damage} damage`
+              : `misses ${msg.target}`
+            addMessage(`${msg.attacker} ${outcome}.${msg.killed ? ` ${msg.target} is slain!` : ''}`, 'output')
+          }
+          break
+        }
+
+        case 'effect_applied':
+          if (msg.entity_id === useGameStore.getState().playerId) {
+            addMessage(`You are ${msg.effect}.`, 'system')
+          }
+          break
+
+        case 'effect_expired':
+          if (msg.entity_id === useGameStore.getState().playerId) {
+            addMessage(`You are no longer ${msg.effect}.`, 'system')
+          }
+          break
+
+        case 'channel_message':
+          addMessage(
+            msg.channel === 'say'
+              ? `${msg.sender} says, "${msg.text}"`
+              : msg.channel === 'tell'
+                ? `${msg.sender} tells you: ${msg.text}`
+                : `[${msg.channel}] ${msg.sender}: ${msg.text}`,
+            'output'
+          )
+          break
+
+        case 'prompt':
+          // The terminal shows its own input line; vitals drive the HP display
+          break
+
+        case 'presence_changed':
+          addMessage(
+            `Your friend ${msg.name} has logged ${msg.status === 'online' ? 'in' : 'out'}.`,
+            'system'
+          )
+          break
+
+        case 'disconnect':
+          // Session was ended server-side; reconnecting would be rejected
+          addMessage(msg.reason, 'system')
+          logout()
+          break
       }
     } catch (err) {
       console.error('Failed to parse WebSocket message:', err)
     }
-  }, [addMessage, setRoom, setPlayerId, setThemeId])
+  }, [addMessage, setRoom, setVitals, setInventory, setOccupants, setPlayerId, setThemeId, logout])
 
   const connect = useCallback(() => {
     if (!token || !universe || !isAuthenticated) return
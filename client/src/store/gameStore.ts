@@ -1,6 +1,6 @@
 import { create } from 'zustand'
 import { persist } from 'zustand/middleware'
-import type { RoomData, TerminalMessage } from '../types/messages'
+import type { ObjectRef, RoomData, TerminalMessage, Vitals } from '../types/messages'
 import { DEFAULT_THEME_ID } from '../theme/themeRegistry'
 
 export type ConnectionStatus = 'disconnected' | 'connecting' | 'connected' | 'error'
@@ -15,6 +15,9 @@ interface AuthState {
 interface GameState {
   messages: TerminalMessage[]
   currentRoom: RoomData | null
+  vitals: Vitals | null
+  inventory: ObjectRef[]
+  occupants: ObjectRef[]
   connectionStatus: ConnectionStatus
   playerId: string | null
   themeId: string
@@ -29,6 +32,9 @@ interface GameActions {
   // Game
   addMessage: (text: string, type: TerminalMessage['type']) => void
   setRoom: (room: RoomData) => void
+  setVitals: (vitals: Vitals) => void
+  setInventory: (items: ObjectRef[]) => void
+  setOccupants: (occupants: ObjectRef[]) => void
   setConnectionStatus: (status: ConnectionStatus) => void
   setPlayerId: (id: string) => void
   setThemeId: (id: string) => void
@@ -51,6 +57,9 @@ export const useGameStore = create<GameStore>()(
       // Game state
       messages: [],
       currentRoom: null,
+      vitals: null,
+      inventory: [],
+      occupants: [],
       connectionStatus: 'disconnected',
       playerId: null,
       themeId: DEFAULT_THEME_ID,
@@ -70,6 +79,9 @@ export const useGameStore = create<GameStore>()(
           isAuthenticated: false,
           messages: [],
           currentRoom: null,
+          vitals: null,
+          inventory: [],
+          occupants: [],
           connectionStatus: 'disconnected',
           playerId: null,
           themeId: DEFAULT_THEME_ID,
@@ -92,6 +104,15 @@ export const useGameStore = create<GameStore>()(
       setRoom: (room) =>
         set({ currentRoom: room }),
 
+      setVitals: (vitals) =>
+        set({ vitals }),
+
+      setInventory: (items) =>
+        set({ inventory: items }),
+
+      setOccupants: (occupants) =>
+        set({ occupants }),
+
       setConnectionStatus: (status) =>
         set({ connectionStatus: status }),
 
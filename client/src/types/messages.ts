@@ -1,15 +1,77 @@
-// Server → Client messages (match websocket.rs)
+// Server → Client messages (match websocket.rs). Most also carry a `seq`
+// for resuming after a reconnect; see SequencedMessage.
 export type ServerMessage =
-  | { type: 'welcome'; player_id: string; theme_id: string }
+  | { type: 'welcome'; player_id: string; theme_id: string; protocol?: number; capabilities?: string[] }
+  | { type: 'hello'; protocol: number; capabilities: string[] }
   | { type: 'output'; text: string }
   | { type: 'room'; name: string; description: string; exits: string[]; contents: string[]; image_hash?: string }
   | { type: 'error'; message: string }
   | { type: 'echo'; command: string }
+  | { type: 'rate_limited'; action: string; retry_after_secs: number; message: string }
+  | { type: 'disconnect'; reason: string }
+  | { type: 'resumed'; replayed: number; missed: number }
+  // Typed events (only sent after a hello that asked for them)
+  | { type: 'vitals'; hp: number; max_hp: number; in_combat: boolean }
+  | { type: 'inventory'; items: ObjectRef[] }
+  | { type: 'room_occupants_changed'; room_id: string; occupants: ObjectRef[] }
+  | {
+      type: 'combat_round'
+      attacker_id: string
+      attacker: string
+      target_id: string
+      target: string
+      hit: boolean
+      critical: boolean
+      fumble: boolean
+      damage: number
+      target_hp: number
+      target_max_hp: number
+      killed: boolean
+    }
+  | { type: 'effect_applied'; entity_id: string; effect: string; magnitude: number; remaining_ticks: number }
+  | { type: 'effect_expired'; entity_id: string; effect: string }
+  | { type: 'channel_message'; channel: string; sender_id: string; sender: string; text: string }
+  | { type: 'prompt'; text: string }
+  | { type: 'presence_changed'; player_id: string; name: string; status: 'online' | 'offline' }
+
+// A server message as received, with its outbox sequence number
+export type SequencedMessage = ServerMessage & { seq?: number }
+
+// Typed event protocol version this client speaks
+export const PROTOCOL_VERSION = 1
+
+// Typed events this client handles
+export const CLIENT_CAPABILITIES = [
+  'vitals',
+  'inventory',
+  'room_occupants_changed',
+  'combat_round',
+  'effect_applied',
+  'effect_expired',
+  'channel_message',
+  'prompt',
+  'presence_changed',
+]
 
 // Client → Server messages
 export type ClientMessage =
   | { type: 'command'; text: string }
   | { type: 'ping' }
+  | { type: 'hello'; protocol: number; capabilities: string[] }
+  | { type: 'resume'; last_seq: number }
+
+// An object named in an event
+export interface ObjectRef {
+  id: string
+  name: string
+}
+
+// Player health from vitals events
+export interface Vitals {
+  hp: number
+  max_hp: number
+  in_combat: boolean
+}
 
 // Room data structure for state
 export interface RoomData {
@@ -1,11 +1,24 @@
 -- Commands library for HemiMUD
 -- Provides high-level player commands built on game.* primitives
+--
+-- The server dispatches player input to Commands[verb](player_id, args),
+-- where args is the rest of the command line. A handler may return:
+--   false                  - decline; the server tries its built-in command
+--   nil or true            - handled, nothing more to show
+--   a string               - text shown to the player
+--   {message = ...}        - text shown to the player (or description)
+--   {room = room_id}       - show the full room view
+-- Define new functions on Commands to add verbs or override built-ins.
 
 Commands = {}
 
 -- Take an item from the current room into player inventory
 -- Returns {success: bool, message: string}
 function Commands.take(player_id, item_name)
+    if not item_name or item_name == "" then
+        return {success = false, message = "Take what?"}
+    end
+
     local player = game.get_object(player_id)
     if not player then
         return {success = false, message = "Player not found"}
@@ -31,11 +44,10 @@ function Commands.take(player_id, item_name)
     game.move_object(item.id, player_id)
 
     local name = item.name or item_name
-    game.send(player_id, string.format("You take %s.", name))
 
     -- Broadcast to room
     local player_name = player.name or "Someone"
-    game.broadcast(room_id, string.format("%s takes %s.", player_name, name))
+    game.broadcast(room_id, string.format("%s takes %s.", player_name, name), player_id)
 
     return {success = true, message = string.format("You take %s.", name)}
 end
@@ -43,6 +55,10 @@ end
 -- Drop an item from player inventory into the current room
 -- Returns {success: bool, message: string}
 function Commands.drop(player_id, item_name)
+    if not item_name or item_name == "" then
+        return {success = false, message = "Drop what?"}
+    end
+
     local player = game.get_object(player_id)
     if not player then
         return {success = false, message = "Player not found"}
@@ -63,18 +79,25 @@ function Commands.drop(player_id, item_name)
     game.move_object(item.id, room_id)
 
     local name = item.name or item_name
-    game.send(player_id, string.format("You drop %s.", name))
 
     -- Broadcast to room
     local player_name = player.name or "Someone"
-    game.broadcast(room_id, string.format("%s drops %s.", player_name, name))
+    game.broadcast(room_id, string.format("%s drops %s.", player_name, name), player_id)
 
     return {success = true, message = string.format("You drop %s.", name)}
 end
 
 -- Give an item to another player
+-- Accepts either (item, target) or a single "item to target" string
 -- Returns {success: bool, message: string}
 function Commands.give(player_id, item_name, target_name)
+    if item_name and not target_name then
+        item_name, target_name = item_name:match("^(.-)%s+to%s+(.+)$")
+    end
+    if not item_name or item_name == "" or not target_name then
+        return {success = false, message = "Give what to whom?"}
+    end
+
     local player = game.get_object(player_id)
     if not player then
         return {success = false, message = "Player not found"}
@@ -109,14 +132,13 @@ function Commands.give(player_id, item_name, target_name)
     local target_display = target.name or target_name
     local player_name = player.name or "Someone"
 
-    game.send(player_id, string.format("You give %s to %s.", item_display, target_display))
     game.send(target.id, string.format("%s gives you %s.", player_name, item_display))
 
     return {success = true, message = string.format("You give %s to %s.", item_display, target_display)}
 end
 
 -- Look at something in the room or inventory
--- Returns {success: bool, description: string}
+-- Returns {success: bool, description: string}, plus room when looking around
 function Commands.look(player_id, target_name)
     local player = game.get_object(player_id)
     if not player then
@@ -165,6 +187,7 @@ function Commands.look(player_id, target_name)
 
         return {
             success = true,
+            room = room_id,
             description = desc .. exit_str .. content_str
         }
     end
@@ -221,8 +244,7 @@ function Commands.flee(player_id)
     game.move_object(player_id, escape.dest)
 
     local player_name = player.name or "Someone"
-    game.broadcast(room_id, string.format("%s flees %s!", player_name, escape.dir))
-    game.send(player_id, string.format("You flee %s!", escape.dir))
+    game.broadcast(room_id, string.format("%s flees %s!", player_name, escape.dir), player_id)
 
     return {success = true, message = string.format("You flee %s!", escape.dir)}
 end
@@ -240,9 +262,8 @@ function Commands.say(player_id, message)
         return {success = false}
     end
 
-    local player_name = player.name or "Someone"
     game.send(player_id, string.format('You say, "%s"', message))
-    game.broadcast(room_id, string.format('%s says, "%s"', player_name, message))
+    game.say(room_id, player_id, message)
 
     return {success = true}
 end
@@ -278,9 +299,19 @@ function Commands.inventory(player_id)
     return {success = true, items = items, message = msg}
 end
 
--- Use an item
+-- Use an item, optionally on a target ("key on door")
 -- Returns {success: bool, message: string}
 function Commands.use(player_id, item_name, target_name)
+    if item_name and not target_name then
+        local item, target = item_name:match("^(.-)%s+on%s+(.+)$")
+        if item then
+            item_name, target_name = item, target
+        end
+    end
+    if not item_name or item_name == "" then
+        return {success = false, message = "Use what?"}
+    end
+
     local player = game.get_object(player_id)
     if not player then
         return {success = false, message = "Player not found"}
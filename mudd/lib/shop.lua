@@ -0,0 +1,81 @@
+-- Shop library for HemiMUD
+-- Lets NPC vendors sell the items they hold, built on game.sell
+--
+-- Items for sale live inside the vendor and carry a "price" property.
+-- The vendor and its stock must have the same owner, who is paid for
+-- each sale. A vendor's code points its handlers at the Shop actions:
+--
+--   return {
+--       on_list = Shop.list_action,
+--       on_buy = Shop.buy_action,
+--   }
+--
+-- and registers them with game.add_action("list", vendor_id, "on_list")
+-- and game.add_action("buy", vendor_id, "on_buy").
+
+Shop = {}
+
+-- Set the price of an item (0 or nil takes it off sale)
+function Shop.set_price(item_id, price)
+    return game.update_object(item_id, {price = price})
+end
+
+-- Items a vendor has for sale
+-- Returns an array of {id, name, price}
+function Shop.stock(vendor_id)
+    local stock = {}
+    for _, item in ipairs(game.get_children(vendor_id)) do
+        local price = item.metadata and item.metadata.price
+        if type(price) == "number" and price > 0 then
+            table.insert(stock, {id = item.id, name = item.name or item.id, price = price})
+        end
+    end
+    return stock
+end
+
+-- Buy an item from a vendor for the given player
+-- Returns {success: bool, message: string}
+function Shop.buy(vendor_id, player_id, item_name)
+    if not item_name or item_name == "" then
+        return {success = false, message = "Buy what?"}
+    end
+
+    local item = game.present(item_name, vendor_id)
+    local price = item and item.metadata and item.metadata.price
+    if not item or type(price) ~= "number" or price <= 0 then
+        return {success = false, message = "That isn't for sale here."}
+    end
+
+    local ok, err = game.sell(item.id, player_id)
+    if not ok then
+        if err and err:find("insufficient credits") then
+            return {success = false, message = "You can't afford that."}
+        end
+        return {success = false, message = "You can't buy that right now."}
+    end
+
+    local name = item.name or item_name
+    return {
+        success = true,
+        message = string.format("You buy %s for %s credits.", name, Utils.format_number(price))
+    }
+end
+
+-- Action handler for "list"
+function Shop.list_action(ctx)
+    local stock = Shop.stock(ctx.object_id)
+    if #stock == 0 then
+        return "There is nothing for sale."
+    end
+
+    local lines = {"For sale:"}
+    for _, entry in ipairs(stock) do
+        table.insert(lines, string.format("  %s - %s credits", entry.name, Utils.format_number(entry.price)))
+    end
+    return table.concat(lines, "\n")
+end
+
+-- Action handler for "buy <item>"
+function Shop.buy_action(ctx)
+    return Shop.buy(ctx.object_id, ctx.actor_id, ctx.args).message
+end
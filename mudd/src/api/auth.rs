@@ -1,16 +1,21 @@
 //! Authentication API endpoints
 
+use std::net::SocketAddr;
+
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header::USER_AGENT, HeaderMap, StatusCode},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 
+use super::limits::RateLimited;
+use super::universe::{authenticate_session, require_admin_request};
 use super::AppState;
-use crate::auth::accounts::{AccountService, AuthError};
+use crate::auth::accounts::{Account, AuthError, ClientInfo, Session};
+use crate::auth::wallet::WalletError;
 
 /// Build auth router
 pub fn router() -> Router<AppState> {
@@ -19,6 +24,30 @@ pub fn router() -> Router<AppState> {
         .route("/auth/login", post(login))
         .route("/auth/logout", post(logout))
         .route("/auth/validate", get(validate))
+        .route("/auth/wallet/nonce", post(wallet_nonce))
+        .route("/auth/wallet/login", post(wallet_login))
+        .route("/auth/wallet/link", post(link_wallet))
+        .route(
+            "/auth/sessions",
+            get(list_sessions).delete(revoke_all_sessions),
+        )
+        .route("/auth/sessions/{session_id}", delete(revoke_session))
+        .route(
+            "/auth/accounts/{account_id}/sessions",
+            get(list_account_sessions),
+        )
+        .route("/auth/accounts/{account_id}/logout", post(force_logout))
+}
+
+/// Describe the client making a request, for its session record
+fn client_info(headers: &HeaderMap, addr: SocketAddr) -> ClientInfo {
+    ClientInfo {
+        user_agent: headers
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string),
+        ip_address: Some(addr.ip().to_string()),
+    }
 }
 
 /// Registration request
@@ -46,11 +75,13 @@ pub struct ErrorResponse {
 /// Register a new account
 async fn register(
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(req): Json<RegisterRequest>,
 ) -> impl IntoResponse {
-    let service = AccountService::new(state.db.pool().clone());
+    let client = client_info(&headers, addr);
 
-    match service.create_account(&req.username, &req.password).await {
+    match password_register(&state, &req.username, &req.password, &client).await {
         Ok((account, token)) => (
             StatusCode::CREATED,
             Json(AuthResponse {
@@ -61,14 +92,22 @@ async fn register(
             }),
         )
             .into_response(),
-        Err(AuthError::UsernameExists) => (
+        Err(LoginRefused::Limited(limited)) => limited.into_response(),
+        Err(LoginRefused::Failed(AuthError::UsernameExists)) => (
             StatusCode::CONFLICT,
             Json(ErrorResponse {
                 error: "username already exists".to_string(),
             }),
         )
             .into_response(),
-        Err(e) => (
+        Err(LoginRefused::Failed(AuthError::ReservedUsername)) => (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: AuthError::ReservedUsername.to_string(),
+            }),
+        )
+            .into_response(),
+        Err(LoginRefused::Failed(e)) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
                 error: e.to_string(),
@@ -86,10 +125,15 @@ pub struct LoginRequest {
 }
 
 /// Login with username and password
-async fn login(State(state): State<AppState>, Json(req): Json<LoginRequest>) -> impl IntoResponse {
-    let service = AccountService::new(state.db.pool().clone());
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<LoginRequest>,
+) -> impl IntoResponse {
+    let client = client_info(&headers, addr);
 
-    match service.login(&req.username, &req.password).await {
+    match password_login(&state, &req.username, &req.password, &client).await {
         Ok((account, token)) => (
             StatusCode::OK,
             Json(AuthResponse {
@@ -100,14 +144,15 @@ async fn login(State(state): State<AppState>, Json(req): Json<LoginRequest>) ->
             }),
         )
             .into_response(),
-        Err(AuthError::InvalidCredentials) => (
+        Err(LoginRefused::Limited(limited)) => limited.into_response(),
+        Err(LoginRefused::Failed(AuthError::InvalidCredentials)) => (
             StatusCode::UNAUTHORIZED,
             Json(ErrorResponse {
                 error: "invalid credentials".to_string(),
             }),
         )
             .into_response(),
-        Err(e) => (
+        Err(LoginRefused::Failed(e)) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
                 error: e.to_string(),
@@ -117,6 +162,64 @@ async fn login(State(state): State<AppState>, Json(req): Json<LoginRequest>) ->
     }
 }
 
+/// Why a password login or registration was refused
+pub(super) enum LoginRefused {
+    /// Too many attempts, or locked out after failures
+    Limited(RateLimited),
+    Failed(AuthError),
+}
+
+/// Log in with a username and password, subject to the login limits
+///
+/// Wrong passwords count towards the lockout; a success clears the count.
+pub(super) async fn password_login(
+    state: &AppState,
+    username: &str,
+    password: &str,
+    client: &ClientInfo,
+) -> Result<(Account, String), LoginRefused> {
+    let ip = client.ip_address.as_deref();
+    state
+        .limits
+        .check_login(username, ip)
+        .map_err(LoginRefused::Limited)?;
+
+    match state.accounts().login(username, password, client).await {
+        Ok(login) => {
+            state.limits.login_succeeded(username);
+            Ok(login)
+        }
+        Err(e) => {
+            if matches!(e, AuthError::InvalidCredentials) {
+                state.limits.login_failed(username, ip);
+            }
+            Err(LoginRefused::Failed(e))
+        }
+    }
+}
+
+/// Register an account with a password, subject to the per-IP login limit
+///
+/// Each registration hashes a password, so it spends from the same bucket
+/// as a login from that address.
+pub(super) async fn password_register(
+    state: &AppState,
+    username: &str,
+    password: &str,
+    client: &ClientInfo,
+) -> Result<(Account, String), LoginRefused> {
+    state
+        .limits
+        .check_register(client.ip_address.as_deref())
+        .map_err(LoginRefused::Limited)?;
+
+    state
+        .accounts()
+        .create_account(username, password, client)
+        .await
+        .map_err(LoginRefused::Failed)
+}
+
 /// Logout request
 #[derive(Debug, Deserialize)]
 pub struct LogoutRequest {
@@ -129,15 +232,24 @@ pub struct LogoutResponse {
     pub success: bool,
 }
 
-/// Logout by invalidating token
+/// Logout by ending the token's session, closing its connections
 async fn logout(
     State(state): State<AppState>,
     Json(req): Json<LogoutRequest>,
 ) -> impl IntoResponse {
-    let service = AccountService::new(state.db.pool().clone());
+    let service = state.accounts();
+    let session = service.validate_session(&req.token).await.ok().flatten();
 
     match service.logout(&req.token).await {
-        Ok(success) => (StatusCode::OK, Json(LogoutResponse { success })).into_response(),
+        Ok(success) => {
+            if let Some((_, session)) = session {
+                state
+                    .connections
+                    .disconnect_session(&session.id, "Logged out")
+                    .await;
+            }
+            (StatusCode::OK, Json(LogoutResponse { success })).into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
@@ -171,7 +283,7 @@ async fn validate(
     State(state): State<AppState>,
     Query(params): Query<ValidateQuery>,
 ) -> impl IntoResponse {
-    let service = AccountService::new(state.db.pool().clone());
+    let service = state.accounts();
 
     match service.validate_token(&params.token).await {
         Ok(Some(account)) => Json(ValidateResponse {
@@ -194,3 +306,273 @@ async fn validate(
         }),
     }
 }
+
+/// Wallet nonce request
+#[derive(Debug, Deserialize)]
+pub struct WalletNonceRequest {
+    pub address: String,
+}
+
+/// Wallet nonce response
+#[derive(Debug, Serialize)]
+pub struct WalletNonceResponse {
+    pub nonce: String,
+    pub expires_at: String,
+    /// Domain the sign-in message must name
+    pub domain: String,
+}
+
+/// Signed Sign-In with Ethereum message
+#[derive(Debug, Deserialize)]
+pub struct WalletSignatureRequest {
+    pub message: String,
+    pub signature: String,
+}
+
+/// Wallet link response
+#[derive(Debug, Serialize)]
+pub struct WalletLinkResponse {
+    pub wallet_address: String,
+}
+
+/// Map a wallet sign-in failure to a response
+fn wallet_error(e: AuthError) -> axum::response::Response {
+    let status = match e {
+        AuthError::Wallet(WalletError::Malformed(_))
+        | AuthError::Wallet(WalletError::InvalidAddress)
+        | AuthError::Wallet(WalletError::MalformedSignature) => StatusCode::BAD_REQUEST,
+        AuthError::Wallet(_) | AuthError::InvalidNonce => StatusCode::UNAUTHORIZED,
+        AuthError::WalletInUse | AuthError::WalletAlreadyLinked | AuthError::UsernameExists => {
+            StatusCode::CONFLICT
+        }
+        _ => return internal_error(e),
+    };
+    (
+        status,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// POST /auth/wallet/nonce - Issue a nonce for a wallet sign-in message
+async fn wallet_nonce(
+    State(state): State<AppState>,
+    Json(req): Json<WalletNonceRequest>,
+) -> impl IntoResponse {
+    let service = state.accounts();
+
+    match service.wallet_nonce(&req.address).await {
+        Ok((nonce, expires_at)) => Json(WalletNonceResponse {
+            nonce,
+            expires_at,
+            domain: state.public_domain.clone(),
+        })
+        .into_response(),
+        Err(e) => wallet_error(e),
+    }
+}
+
+/// POST /auth/wallet/login - Sign in with a signed SIWE message
+async fn wallet_login(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<WalletSignatureRequest>,
+) -> impl IntoResponse {
+    let service = state.accounts();
+    let client = client_info(&headers, addr);
+
+    match service
+        .login_with_wallet(&req.message, &req.signature, &state.public_domain, &client)
+        .await
+    {
+        Ok((account, token)) => Json(AuthResponse {
+            token,
+            account_id: account.id,
+            username: account.username,
+            access_level: account.access_level,
+        })
+        .into_response(),
+        Err(e) => wallet_error(e),
+    }
+}
+
+/// POST /auth/wallet/link - Bind a wallet to the caller's account
+async fn link_wallet(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<WalletSignatureRequest>,
+) -> impl IntoResponse {
+    let (account, _) = match authenticate_session(&headers, &state).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let service = state.accounts();
+    match service
+        .link_wallet(
+            &account.id,
+            &req.message,
+            &req.signature,
+            &state.public_domain,
+        )
+        .await
+    {
+        Ok(wallet_address) => Json(WalletLinkResponse { wallet_address }).into_response(),
+        Err(e) => wallet_error(e),
+    }
+}
+
+/// A session in a session list
+#[derive(Debug, Serialize)]
+pub struct SessionItem {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+/// Result of revoking sessions
+#[derive(Debug, Serialize)]
+pub struct RevokeResponse {
+    /// Sessions ended
+    pub revoked: u64,
+    /// WebSocket connections closed
+    pub disconnected: usize,
+}
+
+/// GET /auth/sessions - List the caller's sessions
+async fn list_sessions(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let (account, current) = match authenticate_session(&headers, &state).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let service = state.accounts();
+    match service.list_sessions(&account.id).await {
+        Ok(sessions) => Json(
+            sessions
+                .into_iter()
+                .map(|session| SessionItem {
+                    current: session.id == current.id,
+                    session,
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// DELETE /auth/sessions - End all of the caller's sessions
+async fn revoke_all_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (account, _) = match authenticate_session(&headers, &state).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    logout_account(&state, &account.id, "Logged out everywhere").await
+}
+
+/// DELETE /auth/sessions/{session_id} - End one of the caller's sessions
+async fn revoke_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let (account, _) = match authenticate_session(&headers, &state).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+
+    let service = state.accounts();
+    match service.revoke_session(&account.id, &session_id).await {
+        Ok(true) => {
+            let disconnected = state
+                .connections
+                .disconnect_session(&session_id, "Session revoked")
+                .await;
+            Json(RevokeResponse {
+                revoked: 1,
+                disconnected,
+            })
+            .into_response()
+        }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "session not found".to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// GET /auth/accounts/{account_id}/sessions - List an account's sessions (admin only)
+async fn list_account_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    let service = state.accounts();
+    match service.list_sessions(&account_id).await {
+        Ok(sessions) => Json(sessions).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// POST /auth/accounts/{account_id}/logout - Force-logout an account (admin only)
+async fn force_logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(account_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    logout_account(&state, &account_id, "Logged out by an administrator").await
+}
+
+/// End all of an account's sessions and close its connections
+async fn logout_account(
+    state: &AppState,
+    account_id: &str,
+    reason: &str,
+) -> axum::response::Response {
+    let service = state.accounts();
+    match service.revoke_all_sessions(account_id).await {
+        Ok(revoked) => {
+            let disconnected = state
+                .connections
+                .disconnect_account(account_id, reason)
+                .await;
+            Json(RevokeResponse {
+                revoked,
+                disconnected,
+            })
+            .into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+fn internal_error(e: AuthError) -> axum::response::Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+        .into_response()
+}
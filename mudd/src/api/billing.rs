@@ -0,0 +1,98 @@
+//! Billing - charge Lua executions against player credits
+//!
+//! Universes opt in by storing a cost table under the `costs` universe
+//! setting. A billed execution is refused up front if its worst-case
+//! compute cost exceeds the player's balance, Venice calls are refused
+//! once they would overdraw it, and the metered total is charged when
+//! the execution finishes: as much of it as the balance still covers,
+//! with fractions of a credit carried to the player's next execution.
+
+use tracing::warn;
+
+use super::AppState;
+use crate::lua::{to_credits, Billing, CostTable, GameApi, SandboxConfig};
+
+/// Load a universe's cost table, or None if the universe isn't billed
+async fn cost_table(state: &AppState, universe_id: &str) -> Option<CostTable> {
+    let raw = match state
+        .object_store
+        .get_universe_setting(universe_id, CostTable::SETTING_KEY)
+        .await
+    {
+        Ok(raw) => raw?,
+        Err(e) => {
+            warn!("Failed to load cost table for {}: {}", universe_id, e);
+            return None;
+        }
+    };
+
+    match serde_json::from_str(&raw) {
+        Ok(costs) => Some(costs),
+        Err(e) => {
+            warn!(
+                "Invalid cost table for {}, using defaults: {}",
+                universe_id, e
+            );
+            Some(CostTable::default())
+        }
+    }
+}
+
+/// Attach billing to an execution about to run as `account_id`
+///
+/// Fails if the execution's worst-case cost exceeds the balance.
+pub(super) async fn open(
+    state: &AppState,
+    game_api: &mut GameApi,
+    universe_id: &str,
+    account_id: &str,
+    config: &SandboxConfig,
+) -> Result<(), String> {
+    let Some(costs) = cost_table(state, universe_id).await else {
+        return Ok(());
+    };
+
+    let balance = state.credits.get_balance(universe_id, account_id).await;
+    let estimate = to_credits(costs.execution_estimate(config));
+    if estimate > balance {
+        return Err(format!(
+            "Insufficient credits: this may cost {} credits and you have {}",
+            estimate, balance
+        ));
+    }
+
+    game_api.set_billing(Some(Billing { costs, balance }));
+    Ok(())
+}
+
+/// Charge a finished execution's metered cost to `account_id`
+pub(super) async fn settle(
+    state: &AppState,
+    game_api: &GameApi,
+    universe_id: &str,
+    account_id: &str,
+    label: &str,
+) {
+    let Some(billing) = game_api.billing() else {
+        return;
+    };
+
+    let metering = game_api.metering();
+    let millicredits = metering.cost_millicredits(&billing.costs);
+    if millicredits == 0 {
+        return;
+    }
+
+    let reason = format!(
+        "{}: {} instructions, {} db reads, {} db writes, {} venice calls",
+        label,
+        metering.instructions(),
+        metering.db_reads(),
+        metering.db_writes(),
+        metering.venice_calls()
+    );
+    state
+        .credits
+        .charge_usage(universe_id, account_id, millicredits, &reason)
+        .await;
+}
@@ -0,0 +1,593 @@
+//! Chat - channels, tells and ignore lists
+//!
+//! Players talk on a channel they've joined by using its name as a verb
+//! (`gossip hello`). `channel join|leave|history|mute|unmute` manage
+//! them. Joined channels and ignored players are kept on the player object
+//! as `channels` and `ignored` lists; a player who never changed their
+//! channels is on the auto-join ones they may use.
+//!
+//! Channel messages and tells reach clients as `channel_message` events.
+//! Ignored players' channel messages and tells are never delivered.
+
+use std::collections::BTreeSet;
+
+use tracing::warn;
+
+use super::presence::resolve_player;
+use super::{AppState, ServerMessage};
+use crate::channels::{channel_defs, ChannelDef, ChannelPost, ChannelSetting, Scope};
+use crate::permissions::AccessLevel;
+
+/// Player object property holding the joined channels
+pub const CHANNELS_PROPERTY: &str = "channels";
+
+/// Player object property holding the ignored player ids
+pub const IGNORED_PROPERTY: &str = "ignored";
+
+/// Messages `channel history` shows unless asked for more
+const DEFAULT_HISTORY: i64 = 20;
+
+/// Channels open to a universe
+pub(super) async fn universe_channels(state: &AppState, universe_id: &str) -> Vec<ChannelDef> {
+    let raw = match state
+        .object_store
+        .get_universe_setting(universe_id, ChannelSetting::SETTING_KEY)
+        .await
+    {
+        Ok(Some(raw)) => raw,
+        Ok(None) => return channel_defs(&[]),
+        Err(e) => {
+            warn!("Failed to load channels for {}: {}", universe_id, e);
+            return channel_defs(&[]);
+        }
+    };
+
+    let settings: Vec<ChannelSetting> = serde_json::from_str(&raw).unwrap_or_else(|e| {
+        warn!(
+            "Invalid channels for {}, using built-in channels only: {}",
+            universe_id, e
+        );
+        Vec::new()
+    });
+    channel_defs(&settings)
+}
+
+/// Channels a player joins on entering the game when they've never
+/// changed theirs
+pub(super) async fn default_channels(
+    state: &AppState,
+    universe_id: &str,
+    access_level: AccessLevel,
+    guild: Option<&str>,
+) -> BTreeSet<String> {
+    universe_channels(state, universe_id)
+        .await
+        .into_iter()
+        .filter(|def| def.auto_join && may_use(def, access_level, guild))
+        .map(|def| def.name)
+        .collect()
+}
+
+/// Whether a player may join and talk on a channel
+fn may_use(def: &ChannelDef, access_level: AccessLevel, guild: Option<&str>) -> bool {
+    access_level >= def.min_level
+        && def
+            .guild
+            .as_deref()
+            .is_none_or(|required| guild.is_some_and(|g| g.eq_ignore_ascii_case(required)))
+}
+
+/// The player's `guild` property
+async fn player_guild(state: &AppState, player_id: &str) -> Option<String> {
+    state
+        .object_store
+        .get(player_id)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|p| p.get_string("guild").map(str::to_string))
+}
+
+/// A channel the player may use, by name
+async fn find_channel(
+    state: &AppState,
+    player_id: &str,
+    access_level: AccessLevel,
+    name: &str,
+) -> Result<(ChannelDef, String), ServerMessage> {
+    let Some(universe_id) = state.connections.get_universe_id(player_id).await else {
+        return Err(ServerMessage::Error {
+            message: "Session error: no universe".to_string(),
+        });
+    };
+    let name = name.to_lowercase();
+    let def = universe_channels(state, &universe_id)
+        .await
+        .into_iter()
+        .find(|def| def.name == name);
+    let guild = player_guild(state, player_id).await;
+    match def {
+        Some(def) if may_use(&def, access_level, guild.as_deref()) => Ok((def, universe_id)),
+        _ => Err(ServerMessage::Output {
+            text: format!("There is no channel called {}.", name),
+        }),
+    }
+}
+
+/// `channels`: the channels the player may use
+pub(super) async fn list_channels(
+    state: &AppState,
+    player_id: &str,
+    access_level: AccessLevel,
+) -> ServerMessage {
+    let Some(universe_id) = state.connections.get_universe_id(player_id).await else {
+        return ServerMessage::Error {
+            message: "Session error: no universe".to_string(),
+        };
+    };
+    let guild = player_guild(state, player_id).await;
+    let joined = state.connections.channels(player_id).await;
+
+    let mut text = String::from("Channels:");
+    for def in universe_channels(state, &universe_id).await {
+        if !may_use(&def, access_level, guild.as_deref()) {
+            continue;
+        }
+        let scope = match def.scope {
+            Scope::Global => "all universes",
+            Scope::Universe => "this universe",
+        };
+        let status = if joined.contains(&def.name) {
+            "joined"
+        } else {
+            ""
+        };
+        let line = format!("\n  {:<12} {:<14} {}", def.name, scope, status);
+        text.push_str(line.trim_end());
+    }
+    ServerMessage::Output { text }
+}
+
+/// `channel join|leave|history|mute|unmute <channel> ...`
+pub(super) async fn channel(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    access_level: AccessLevel,
+    args: &[&str],
+) -> ServerMessage {
+    let (action, name, rest) = match args {
+        [action, name, rest @ ..] => (action.to_lowercase(), *name, rest),
+        _ => {
+            return ServerMessage::Error {
+                message: "Usage: channel join|leave|history|mute|unmute <channel> [player]"
+                    .to_string(),
+            };
+        }
+    };
+    let (def, universe_id) = match find_channel(state, player_id, access_level, name).await {
+        Ok(found) => found,
+        Err(msg) => return msg,
+    };
+
+    match (action.as_str(), rest) {
+        ("join", []) => set_joined(state, player_id, account_id, &def, true).await,
+        ("leave", []) => set_joined(state, player_id, account_id, &def, false).await,
+        ("history", []) => history(state, &def, &universe_id, DEFAULT_HISTORY).await,
+        ("history", [count]) => match count.parse::<i64>() {
+            Ok(count) if count > 0 => history(state, &def, &universe_id, count).await,
+            _ => ServerMessage::Error {
+                message: "Usage: channel history <channel> [count]".to_string(),
+            },
+        },
+        ("mute" | "unmute", [target]) => {
+            if access_level < def.moderator_level() {
+                return ServerMessage::Error {
+                    message: format!(
+                        "Permission denied: {}+ required to moderate {}",
+                        def.moderator_level().as_str(),
+                        def.name
+                    ),
+                };
+            }
+            let Some(target_id) = resolve_player(state, player_id, target).await else {
+                return ServerMessage::Output {
+                    text: format!("There is no player called {}.", target),
+                };
+            };
+            moderate(
+                state,
+                player_id,
+                &def,
+                &universe_id,
+                &target_id,
+                target,
+                action == "mute",
+            )
+            .await
+        }
+        _ => ServerMessage::Error {
+            message: "Usage: channel join|leave|history|mute|unmute <channel> [player]".to_string(),
+        },
+    }
+}
+
+async fn set_joined(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    def: &ChannelDef,
+    joining: bool,
+) -> ServerMessage {
+    let mut channels = state.connections.channels(player_id).await;
+    let changed = if joining {
+        channels.insert(def.name.clone())
+    } else {
+        channels.remove(&def.name)
+    };
+    if !changed {
+        return ServerMessage::Output {
+            text: if joining {
+                format!("You are already on {}.", def.name)
+            } else {
+                format!("You aren't on {}.", def.name)
+            },
+        };
+    }
+
+    // Guests' channels last as long as their connection
+    if !account_id.is_empty() {
+        let list = serde_json::json!(channels);
+        if let Err(e) = state
+            .player_manager
+            .set_property(player_id, CHANNELS_PROPERTY, list)
+            .await
+        {
+            return ServerMessage::Error {
+                message: format!("Failed to save channels: {}", e),
+            };
+        }
+    }
+    state.connections.set_channels(player_id, channels).await;
+
+    ServerMessage::Output {
+        text: if joining {
+            format!("You join {}.", def.name)
+        } else {
+            format!("You leave {}.", def.name)
+        },
+    }
+}
+
+async fn history(
+    state: &AppState,
+    def: &ChannelDef,
+    universe_id: &str,
+    count: i64,
+) -> ServerMessage {
+    let posts = match state
+        .channels
+        .history(&def.name, def.scope_key(universe_id), count)
+        .await
+    {
+        Ok(posts) => posts,
+        Err(e) => {
+            return ServerMessage::Error {
+                message: format!("Failed to load history: {}", e),
+            };
+        }
+    };
+    if posts.is_empty() {
+        return ServerMessage::Output {
+            text: format!("Nothing has been said on {} yet.", def.name),
+        };
+    }
+
+    let mut text = format!("Recent messages on {}:", def.name);
+    for post in posts {
+        let time = chrono::DateTime::parse_from_rfc3339(&post.created_at)
+            .map(|t| t.with_timezone(&chrono::Utc).format("%H:%M").to_string())
+            .unwrap_or_default();
+        text.push_str(&format!("\n  [{}] {}: {}", time, post.sender, post.text));
+    }
+    ServerMessage::Output { text }
+}
+
+async fn moderate(
+    state: &AppState,
+    player_id: &str,
+    def: &ChannelDef,
+    universe_id: &str,
+    target_id: &str,
+    target: &str,
+    muting: bool,
+) -> ServerMessage {
+    let scope_key = def.scope_key(universe_id);
+    let result = if muting {
+        state
+            .channels
+            .mute(&def.name, scope_key, target_id, player_id)
+            .await
+            .map(|_| true)
+    } else {
+        state.channels.unmute(&def.name, scope_key, target_id).await
+    };
+    match result {
+        Ok(true) if muting => ServerMessage::Output {
+            text: format!("{} is muted on {}.", target, def.name),
+        },
+        Ok(true) => ServerMessage::Output {
+            text: format!("{} may talk on {} again.", target, def.name),
+        },
+        Ok(false) => ServerMessage::Output {
+            text: format!("{} isn't muted on {}.", target, def.name),
+        },
+        Err(e) => ServerMessage::Error {
+            message: format!("Failed to update mutes: {}", e),
+        },
+    }
+}
+
+/// `<channel> <message>`: talk on a joined channel
+///
+/// Returns `None` when the verb isn't a channel the player may use.
+pub(super) async fn speak(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    access_level: AccessLevel,
+    channel: &str,
+    message: &str,
+) -> Option<ServerMessage> {
+    let (def, universe_id) = find_channel(state, player_id, access_level, channel)
+        .await
+        .ok()?;
+
+    if !state
+        .connections
+        .channels(player_id)
+        .await
+        .contains(&def.name)
+    {
+        return Some(ServerMessage::Output {
+            text: format!(
+                "You aren't on {}. Join it with: channel join {}",
+                def.name, def.name
+            ),
+        });
+    }
+    if account_id.is_empty() {
+        return Some(ServerMessage::Error {
+            message: "Guests can only listen on channels.".to_string(),
+        });
+    }
+    if message.is_empty() {
+        return Some(ServerMessage::Error {
+            message: format!("Usage: {} <message>", def.name),
+        });
+    }
+    match state
+        .channels
+        .is_muted(&def.name, def.scope_key(&universe_id), player_id)
+        .await
+    {
+        Ok(false) => {}
+        Ok(true) => {
+            return Some(ServerMessage::Output {
+                text: format!("You have been muted on {}.", def.name),
+            });
+        }
+        Err(e) => {
+            return Some(ServerMessage::Error {
+                message: format!("Failed to check mutes: {}", e),
+            });
+        }
+    }
+
+    let sender = state
+        .connections
+        .get_name(player_id)
+        .await
+        .unwrap_or_default();
+    let post = ChannelPost::new(&def, &universe_id, player_id, &sender, message);
+    if let Err(e) = state.channels.record(&post).await {
+        warn!("Failed to record message on {}: {}", def.name, e);
+    }
+
+    let said = ServerMessage::ChannelMessage {
+        channel: def.name.clone(),
+        sender_id: player_id.to_string(),
+        sender,
+        text: message.to_string(),
+    };
+    state
+        .connections
+        .broadcast_channel(&def, &universe_id, player_id, said.clone())
+        .await;
+    Some(said)
+}
+
+/// `tell <player> <message>`: a private message to a player in any
+/// universe
+pub(super) async fn tell(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    args: &[&str],
+) -> ServerMessage {
+    let (name, message) = match args {
+        [name, words @ ..] if !words.is_empty() => (*name, words.join(" ")),
+        _ => {
+            return ServerMessage::Error {
+                message: "Usage: tell <player> <message>".to_string(),
+            };
+        }
+    };
+    if account_id.is_empty() {
+        return ServerMessage::Error {
+            message: "Guests can't send tells.".to_string(),
+        };
+    }
+    let Some(target_id) = state.connections.find_online(name).await else {
+        return ServerMessage::Output {
+            text: format!("{} isn't online.", name),
+        };
+    };
+    if target_id == player_id {
+        return ServerMessage::Output {
+            text: "You mutter to yourself.".to_string(),
+        };
+    }
+    if state
+        .connections
+        .ignored(&target_id)
+        .await
+        .contains(player_id)
+    {
+        return ServerMessage::Output {
+            text: format!("{} isn't listening to you.", name),
+        };
+    }
+
+    let sender = state
+        .connections
+        .get_name(player_id)
+        .await
+        .unwrap_or_default();
+    let told = ServerMessage::ChannelMessage {
+        channel: "tell".to_string(),
+        sender_id: player_id.to_string(),
+        sender,
+        text: message.clone(),
+    };
+    state.connections.send_to_player(&target_id, told).await;
+
+    let target = state.connections.online_player(&target_id).await;
+    let target_name = target
+        .as_ref()
+        .map_or_else(|| name.to_string(), |t| t.name.clone());
+    let mut text = format!("You tell {}: {}", target_name, message);
+    match target.and_then(|t| t.afk) {
+        Some(afk) if afk.is_empty() => text.push_str(&format!("\n{} is AFK.", target_name)),
+        Some(afk) => text.push_str(&format!("\n{} is AFK: {}", target_name, afk)),
+        None => {}
+    }
+    ServerMessage::Output { text }
+}
+
+/// `ignore [player]` and `unignore <player>`: list or change the players
+/// whose channel messages and tells the player doesn't see
+pub(super) async fn ignore(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    name: &str,
+    ignoring: bool,
+) -> ServerMessage {
+    if account_id.is_empty() {
+        return ServerMessage::Error {
+            message: "Guests can't keep an ignore list.".to_string(),
+        };
+    }
+    let mut ignored = state.connections.ignored(player_id).await;
+
+    if name.is_empty() {
+        if !ignoring {
+            return ServerMessage::Error {
+                message: "Usage: unignore <player>".to_string(),
+            };
+        }
+        return list_ignored(state, &ignored).await;
+    }
+
+    let Some(target_id) = resolve_player(state, player_id, name).await else {
+        return ServerMessage::Output {
+            text: format!("There is no player called {}.", name),
+        };
+    };
+    if target_id == player_id {
+        return ServerMessage::Output {
+            text: "You can't ignore yourself.".to_string(),
+        };
+    }
+
+    let changed = if ignoring {
+        ignored.insert(target_id)
+    } else {
+        ignored.remove(&target_id)
+    };
+    if !changed {
+        return ServerMessage::Output {
+            text: if ignoring {
+                format!("You are already ignoring {}.", name)
+            } else {
+                format!("You aren't ignoring {}.", name)
+            },
+        };
+    }
+
+    let list = serde_json::json!(ignored);
+    if let Err(e) = state
+        .player_manager
+        .set_property(player_id, IGNORED_PROPERTY, list)
+        .await
+    {
+        return ServerMessage::Error {
+            message: format!("Failed to save ignore list: {}", e),
+        };
+    }
+    state.connections.set_ignored(player_id, ignored).await;
+
+    ServerMessage::Output {
+        text: if ignoring {
+            format!("You are now ignoring {}.", name)
+        } else {
+            format!("You are no longer ignoring {}.", name)
+        },
+    }
+}
+
+async fn list_ignored(state: &AppState, ignored: &BTreeSet<String>) -> ServerMessage {
+    if ignored.is_empty() {
+        return ServerMessage::Output {
+            text: "You aren't ignoring anyone.".to_string(),
+        };
+    }
+
+    let mut text = String::from("Ignoring:");
+    for ignored_id in ignored {
+        let name = match state.connections.get_name(ignored_id).await {
+            Some(name) => name,
+            None => state
+                .object_store
+                .get(ignored_id)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|p| p.get_string("name").map(str::to_string))
+                .unwrap_or_else(|| ignored_id.clone()),
+        };
+        text.push_str(&format!("\n  {}", name));
+    }
+    ServerMessage::Output { text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_use() {
+        let settings: Vec<ChannelSetting> =
+            serde_json::from_str(r#"[{"name": "thieves", "guild": "Thieves"}]"#).unwrap();
+        let defs = channel_defs(&settings);
+        let (gossip, wiz, thieves) = (&defs[0], &defs[2], &defs[3]);
+
+        assert!(may_use(gossip, AccessLevel::Player, None));
+        assert!(!may_use(wiz, AccessLevel::Builder, None));
+        assert!(may_use(wiz, AccessLevel::Wizard, None));
+        assert!(!may_use(thieves, AccessLevel::Owner, None));
+        assert!(!may_use(thieves, AccessLevel::Player, Some("mages")));
+        assert!(may_use(thieves, AccessLevel::Player, Some("thieves")));
+    }
+}
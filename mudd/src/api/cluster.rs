@@ -0,0 +1,130 @@
+//! Cluster API - Inspect and change Raft cluster membership (admin only)
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use super::universe::{require_admin_request, ErrorResponse};
+use super::AppState;
+use crate::raft::NodeId;
+
+/// Request to add a learner node
+#[derive(Debug, Deserialize)]
+struct AddLearnerRequest {
+    node_id: NodeId,
+    /// Raft RPC address of the new node ("host:port")
+    addr: String,
+}
+
+/// Request naming an existing node
+#[derive(Debug, Deserialize)]
+struct NodeRequest {
+    node_id: NodeId,
+}
+
+/// Build the cluster router
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/cluster/members", get(members))
+        .route("/cluster/learner", post(add_learner))
+        .route("/cluster/voter", post(promote_voter))
+        .route("/cluster/remove", post(remove_node))
+}
+
+/// Reject membership changes on followers, pointing at the leader instead
+fn require_leader(state: &AppState) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if state.raft_writer.is_leader() {
+        return Ok(());
+    }
+
+    let error = match state.raft_writer.current_leader() {
+        Some(leader) => format!("Not the leader; send membership changes to node {}", leader),
+        None => "No leader elected".to_string(),
+    };
+    Err((StatusCode::CONFLICT, Json(ErrorResponse { error })))
+}
+
+/// GET /cluster/members
+async fn members(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    Json(state.raft_writer.membership()).into_response()
+}
+
+/// POST /cluster/learner
+async fn add_learner(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<AddLearnerRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+    if let Err(e) = require_leader(&state) {
+        return e.into_response();
+    }
+
+    match state
+        .raft_writer
+        .add_learner(request.node_id, &request.addr)
+        .await
+    {
+        Ok(()) => Json(state.raft_writer.membership()).into_response(),
+        Err(e) => membership_error(e),
+    }
+}
+
+/// POST /cluster/voter
+async fn promote_voter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<NodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+    if let Err(e) = require_leader(&state) {
+        return e.into_response();
+    }
+
+    match state.raft_writer.promote_voter(request.node_id).await {
+        Ok(()) => Json(state.raft_writer.membership()).into_response(),
+        Err(e) => membership_error(e),
+    }
+}
+
+/// POST /cluster/remove
+async fn remove_node(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<NodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+    if let Err(e) = require_leader(&state) {
+        return e.into_response();
+    }
+
+    match state.raft_writer.remove_node(request.node_id).await {
+        Ok(()) => Json(state.raft_writer.membership()).into_response(),
+        Err(e) => membership_error(e),
+    }
+}
+
+fn membership_error(e: anyhow::Error) -> axum::response::Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+        .into_response()
+}
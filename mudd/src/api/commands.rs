@@ -0,0 +1,440 @@
+//! Command dispatch - routes player input to actions, mudlib commands and built-ins
+//!
+//! A verb is resolved in this order:
+//! 1. Privileged built-ins (eval, goto, setportal, create), which universe
+//!    code can't shadow
+//! 2. Actions registered with `game.add_action`: those objects gave the
+//!    player from `on_init`, then those on objects the player carries, then
+//!    on objects in the room, then on the room itself
+//! 3. The universe's Lua `Commands[verb]` handler
+//! 4. The remaining built-ins (look, movement, attack, ...)
+//!
+//! Action and command handlers may return `false` to decline, in which case
+//! dispatch moves on to the next step.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use mlua::{Function, Table, Value};
+use tracing::warn;
+
+use super::websocket::{build_room_message, execute_command, lua_value_to_string};
+use super::{billing, events, sandboxes, AppState, ServerMessage};
+use crate::lua::handlers::{self, Frame, HandlerSource};
+use crate::lua::pool::{LibHash, PooledSandbox};
+use crate::lua::{Action, GameApi, MessageQueue, Sandbox, SandboxConfig};
+use crate::permissions::AccessLevel;
+
+/// Built-ins that are resolved before any universe code
+const PRIVILEGED_VERBS: &[&str] = &["eval", "goto", "setportal", "create"];
+
+/// Verb abbreviations understood by mudlib command handlers
+const COMMAND_ALIASES: &[(&str, &str)] = &[
+    ("l", "look"),
+    ("get", "take"),
+    ("inv", "inventory"),
+    ("i", "inventory"),
+];
+
+/// What a Lua handler asked the server to do
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// Handler declined; keep dispatching
+    Declined,
+    /// Handled, nothing to show
+    Silent,
+    /// Show text to the player
+    Text(String),
+    /// Show the full view of a room
+    Room(String),
+    /// Handler failed
+    Failed(String),
+}
+
+/// Per-command context shared by the dispatch steps
+struct Dispatch<'a> {
+    state: &'a AppState,
+    player_id: &'a str,
+    account_id: &'a str,
+    universe_id: String,
+    room_id: Option<String>,
+    libs: Vec<LibHash>,
+}
+
+/// Execute a player command
+///
+/// Returns `None` when a handler dealt with the command without producing
+/// a response (it may still have sent messages).
+pub(super) async fn dispatch(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    access_level: AccessLevel,
+    command: &str,
+) -> Option<ServerMessage> {
+    let command = command.trim();
+    let (verb, args) = match command.split_once(char::is_whitespace) {
+        Some((verb, args)) => (verb.to_lowercase(), args.trim()),
+        None => (command.to_lowercase(), ""),
+    };
+
+    if verb.is_empty() || PRIVILEGED_VERBS.contains(&verb.as_str()) {
+        return Some(execute_command(state, player_id, account_id, access_level, command).await);
+    }
+
+    let Some(universe_id) = state.connections.get_universe_id(player_id).await else {
+        return Some(ServerMessage::Error {
+            message: "Session error: no universe".to_string(),
+        });
+    };
+    let libs = match sandboxes::lib_hashes(state, &universe_id).await {
+        Ok(libs) => libs,
+        Err(e) => {
+            return Some(ServerMessage::Error {
+                message: format!("Failed to load universe libs: {}", e),
+            });
+        }
+    };
+    let ctx = Dispatch {
+        state,
+        player_id,
+        account_id,
+        universe_id,
+        room_id: state.connections.get_room_id(player_id).await,
+        libs,
+    };
+
+    for action in ctx.find_actions(&verb).await {
+        let outcome = ctx.run_action(&action, args).await;
+        if outcome != Outcome::Declined {
+            return ctx.respond(outcome).await;
+        }
+    }
+
+    // Guests have no player object for mudlib commands to act on
+    if !account_id.is_empty() {
+        let verb = COMMAND_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == verb)
+            .map_or(verb.as_str(), |(_, canonical)| canonical);
+        let outcome = ctx.run_command(verb, args).await;
+        if outcome != Outcome::Declined {
+            return ctx.respond(outcome).await;
+        }
+    }
+
+    Some(execute_command(state, player_id, account_id, access_level, command).await)
+}
+
+impl Dispatch<'_> {
+    /// Actions for a verb, nearest first
+    async fn find_actions(&self, verb: &str) -> Vec<Action> {
+        let store = &self.state.object_store;
+        let mut holders = vec![self.player_id.to_string()];
+        if let Some(ref room_id) = self.room_id {
+            holders.push(room_id.clone());
+        }
+
+        let mut found = self
+            .state
+            .actions
+            .get_living_actions(self.player_id, verb)
+            .await;
+        for holder in &holders {
+            let contents = match store.get_contents(holder).await {
+                Ok(contents) => contents,
+                Err(e) => {
+                    warn!("Failed to list contents of {}: {}", holder, e);
+                    continue;
+                }
+            };
+            for obj in contents {
+                if let Some(action) = self.state.actions.get_object_action(&obj.id, verb).await {
+                    found.push(action);
+                }
+            }
+        }
+        if let Some(ref room_id) = self.room_id {
+            found.extend(self.state.actions.get_room_actions(room_id, verb).await);
+        }
+        found
+    }
+
+    /// Call an action's method on the object that registered it
+    ///
+    /// The method may come from the object's code or its classes'.
+    async fn run_action(&self, action: &Action, args: &str) -> Outcome {
+        let store = &self.state.object_store;
+        let sources = match store.get(&action.object_id).await {
+            Ok(Some(obj)) => handlers::object_sources(store, &self.state.classes, &obj)
+                .await
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        if sources.is_empty() {
+            warn!(
+                "Action {} on {} has no code to run",
+                action.verb, action.object_id
+            );
+            return Outcome::Declined;
+        }
+
+        let messages = MessageQueue::shared();
+        let mut game_api = match self.game_api(messages.clone()).await {
+            Ok(game_api) => game_api,
+            Err(e) => return Outcome::Failed(e),
+        };
+        game_api.set_object_context(Some(action.object_id.clone()));
+        let outcome = match self.sandbox(&game_api).await {
+            Ok(mut sandbox) => {
+                run_object_handler(&mut sandbox, sources, action, self.player_id, args).await
+            }
+            Err(e) => Outcome::Failed(e),
+        };
+        self.settle(&game_api, &action.verb).await;
+        self.deliver(&messages).await;
+        outcome
+    }
+
+    /// Call the universe's `Commands[verb]` handler, if it has one
+    async fn run_command(&self, verb: &str, args: &str) -> Outcome {
+        let messages = MessageQueue::shared();
+        let game_api = match self.game_api(messages.clone()).await {
+            Ok(game_api) => game_api,
+            Err(e) => return Outcome::Failed(e),
+        };
+        let outcome = match self.sandbox(&game_api).await {
+            Ok(mut sandbox) => run_command_handler(&mut sandbox, verb, self.player_id, args).await,
+            Err(e) => Outcome::Failed(e),
+        };
+        self.settle(&game_api, verb).await;
+        self.deliver(&messages).await;
+        outcome
+    }
+
+    /// Game API for a handler run by this player, billed if the universe is
+    async fn game_api(&self, messages: Arc<MessageQueue>) -> Result<GameApi, String> {
+        let mut game_api = self.state.game_api(&self.universe_id, messages);
+        game_api.set_room_context(self.room_id.clone());
+        if !self.account_id.is_empty() {
+            game_api.set_user_context(Some(self.account_id.to_string()));
+            game_api.set_player_input(true);
+            billing::open(
+                self.state,
+                &mut game_api,
+                &self.universe_id,
+                self.account_id,
+                &sandbox_config(),
+            )
+            .await?;
+        }
+        Ok(game_api)
+    }
+
+    /// A sandbox with the game API and universe libraries loaded
+    async fn sandbox(&self, game_api: &GameApi) -> Result<PooledSandbox, String> {
+        sandboxes::checkout(
+            self.state,
+            &self.universe_id,
+            &self.libs,
+            game_api,
+            sandbox_config(),
+        )
+        .await
+    }
+
+    /// Charge the player for a finished handler
+    async fn settle(&self, game_api: &GameApi, verb: &str) {
+        if !self.account_id.is_empty() {
+            let label = format!("command {}", verb);
+            billing::settle(
+                self.state,
+                game_api,
+                &self.universe_id,
+                self.account_id,
+                &label,
+            )
+            .await;
+        }
+    }
+
+    async fn deliver(&self, messages: &MessageQueue) {
+        self.state
+            .connections
+            .deliver(messages.drain().await, &self.state.object_store)
+            .await;
+    }
+
+    /// Turn a handled outcome into the player's response
+    async fn respond(&self, outcome: Outcome) -> Option<ServerMessage> {
+        self.sync_room().await;
+
+        match outcome {
+            Outcome::Declined | Outcome::Silent => None,
+            Outcome::Text(text) => Some(ServerMessage::Output { text }),
+            Outcome::Room(room_id) => {
+                let account = (!self.account_id.is_empty()).then_some(self.account_id);
+                Some(
+                    build_room_message(self.state, &room_id, account)
+                        .await
+                        .unwrap_or(ServerMessage::Output {
+                            text: "You are nowhere.".to_string(),
+                        }),
+                )
+            }
+            Outcome::Failed(message) => Some(ServerMessage::Error { message }),
+        }
+    }
+
+    /// Follow the player if a handler moved them to another room
+    async fn sync_room(&self) {
+        if self.account_id.is_empty() {
+            return;
+        }
+        if let Ok(Some(player)) = self.state.object_store.get(self.player_id).await {
+            if player.parent_id.is_some() && player.parent_id != self.room_id {
+                events::change_room(self.state, self.player_id, player.parent_id).await;
+            }
+        }
+    }
+}
+
+/// Sandbox limits for command and action handlers
+fn sandbox_config() -> SandboxConfig {
+    SandboxConfig {
+        max_instructions: 10_000_000,
+        timeout: Duration::from_secs(5),
+        ..Default::default()
+    }
+}
+
+/// Run an action method from the object's handlers
+///
+/// The method receives a table with object_id, actor_id, verb and args.
+async fn run_object_handler(
+    sandbox: &mut Sandbox,
+    sources: Vec<HandlerSource>,
+    action: &Action,
+    actor_id: &str,
+    args: &str,
+) -> Outcome {
+    // Resolve the method inside the sandbox so its limits apply
+    let method = action.method.clone();
+    let object_id = action.object_id.clone();
+    let sources = Arc::new(sources);
+    let handler = sandbox.lua().create_async_function(move |lua, ctx: Table| {
+        let sources = sources.clone();
+        let method = method.clone();
+        let frame = Frame::new(object_id.as_str());
+        async move {
+            match handlers::call(&lua, Some(frame), &sources, &method, ctx).await? {
+                Some(value) => Ok((true, value)),
+                None => Ok((false, Value::Nil)),
+            }
+        }
+    });
+    let handler = match handler {
+        Ok(handler) => handler,
+        Err(e) => return Outcome::Failed(format!("Lua error: {}", e)),
+    };
+
+    let call_args = sandbox.lua().create_table().and_then(|t| {
+        t.set("object_id", action.object_id.as_str())?;
+        t.set("actor_id", actor_id)?;
+        t.set("verb", action.verb.as_str())?;
+        t.set("args", args)?;
+        Ok(t)
+    });
+    let call_args = match call_args {
+        Ok(t) => t,
+        Err(e) => return Outcome::Failed(format!("Lua error: {}", e)),
+    };
+
+    match sandbox
+        .call_async::<_, (bool, Value)>(handler, call_args)
+        .await
+    {
+        Ok((true, value)) => outcome_from_lua(value),
+        Ok((false, _)) => {
+            warn!(
+                "Object {} has no {} method for action {}",
+                action.object_id, action.method, action.verb
+            );
+            Outcome::Declined
+        }
+        Err(e) => Outcome::Failed(format!("Lua error: {}", e)),
+    }
+}
+
+/// Run `Commands[verb](player_id, args)` from the universe libraries
+async fn run_command_handler(
+    sandbox: &mut Sandbox,
+    verb: &str,
+    player_id: &str,
+    args: &str,
+) -> Outcome {
+    let handler = sandbox
+        .lua()
+        .globals()
+        .get::<Table>("Commands")
+        .and_then(|commands| commands.get::<Function>(verb));
+    let Ok(handler) = handler else {
+        return Outcome::Declined;
+    };
+
+    match sandbox
+        .call_async::<_, Value>(handler, (player_id, args))
+        .await
+    {
+        Ok(value) => outcome_from_lua(value),
+        Err(e) => Outcome::Failed(format!("Lua error: {}", e)),
+    }
+}
+
+/// Interpret a handler's return value
+fn outcome_from_lua(value: Value) -> Outcome {
+    match value {
+        Value::Boolean(false) => Outcome::Declined,
+        Value::Nil | Value::Boolean(true) => Outcome::Silent,
+        Value::String(s) => Outcome::Text(s.to_string_lossy()),
+        Value::Table(t) => {
+            if let Ok(Some(room_id)) = t.get::<Option<String>>("room") {
+                return Outcome::Room(room_id);
+            }
+            for key in ["message", "description"] {
+                if let Ok(Some(text)) = t.get::<Option<String>>(key) {
+                    return Outcome::Text(text);
+                }
+            }
+            Outcome::Silent
+        }
+        other => Outcome::Text(lua_value_to_string(&other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(code: &str) -> Outcome {
+        let lua = mlua::Lua::new();
+        outcome_from_lua(lua.load(code).eval().unwrap())
+    }
+
+    #[test]
+    fn test_outcome_from_lua() {
+        assert_eq!(eval("return false"), Outcome::Declined);
+        assert_eq!(eval("return nil"), Outcome::Silent);
+        assert_eq!(eval("return true"), Outcome::Silent);
+        assert_eq!(eval("return 'hi'"), Outcome::Text("hi".to_string()));
+        assert_eq!(
+            eval("return {success = false, message = 'Take what?'}"),
+            Outcome::Text("Take what?".to_string())
+        );
+        assert_eq!(
+            eval("return {room = '/rooms/hall', description = 'A hall'}"),
+            Outcome::Room("/rooms/hall".to_string())
+        );
+        assert_eq!(eval("return {success = true}"), Outcome::Silent);
+    }
+}
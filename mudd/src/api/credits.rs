@@ -0,0 +1,122 @@
+//! Credits API - Audit and adjust player credit balances (admin only)
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use super::universe::{require_admin_request, ErrorResponse};
+use super::AppState;
+
+/// Default number of transactions returned
+const DEFAULT_LIMIT: u32 = 50;
+
+/// Maximum number of transactions returned
+const MAX_LIMIT: u32 = 500;
+
+/// Query parameters for listing transactions
+#[derive(Debug, Deserialize)]
+struct TransactionsQuery {
+    limit: Option<u32>,
+}
+
+/// Request to grant credits
+#[derive(Debug, Deserialize)]
+struct GrantRequest {
+    amount: i64,
+    reason: String,
+}
+
+/// Build the credits router
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/credits/{universe_id}/{account_id}/transactions",
+            get(transactions),
+        )
+        .route("/credits/{universe_id}/{account_id}/audit", get(audit))
+        .route("/credits/{universe_id}/{account_id}/grant", post(grant))
+}
+
+/// GET /credits/{universe_id}/{account_id}/transactions
+async fn transactions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((universe_id, account_id)): Path<(String, String)>,
+    Query(query): Query<TransactionsQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    match state
+        .credits
+        .get_transactions(&universe_id, &account_id, limit)
+        .await
+    {
+        Ok(transactions) => Json(transactions).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// GET /credits/{universe_id}/{account_id}/audit
+async fn audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((universe_id, account_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    match state.credits.audit_balance(&universe_id, &account_id).await {
+        Ok(audit) => Json(audit).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// POST /credits/{universe_id}/{account_id}/grant
+async fn grant(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((universe_id, account_id)): Path<(String, String)>,
+    Json(request): Json<GrantRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin_request(&headers, &state).await {
+        return e.into_response();
+    }
+
+    if !state
+        .credits
+        .grant(&universe_id, &account_id, request.amount, &request.reason)
+        .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Failed to grant credits".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    match state.credits.audit_balance(&universe_id, &account_id).await {
+        Ok(audit) => Json(audit).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn internal_error(e: anyhow::Error) -> axum::response::Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+        .into_response()
+}
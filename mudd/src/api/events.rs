@@ -0,0 +1,431 @@
+//! Typed game events for rich clients
+//!
+//! Besides text, the server can describe game state with typed events
+//! (vitals, inventory, who is in the room, combat rounds, ...). A client
+//! opts in by sending `hello` with the protocol version it speaks and the
+//! events it understands; the server answers with what it will send.
+//!
+//! Sessions that never say hello keep the original protocol: events that
+//! have a text form reach them as plain `output`, the rest are dropped.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use super::{AppState, ServerMessage};
+use crate::combat::{EffectType, StatusEffect};
+
+/// Current version of the typed event protocol
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Events a client can ask for in its hello
+pub const EVENT_KINDS: &[&str] = &[
+    "vitals",
+    "inventory",
+    "room_occupants_changed",
+    "combat_round",
+    "effect_applied",
+    "effect_expired",
+    "channel_message",
+    "prompt",
+    "presence_changed",
+];
+
+/// Hit points for players without combat state
+pub(super) const DEFAULT_PLAYER_HP: i32 = 100;
+
+/// An object named in an event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectRef {
+    pub id: String,
+    pub name: String,
+}
+
+/// What a connection negotiated with its hello
+///
+/// The default is a client that never said hello.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    protocol: u32,
+    events: BTreeSet<&'static str>,
+}
+
+impl Capabilities {
+    /// Agree on a protocol version and the requested events this server
+    /// knows. Unknown events are ignored; protocol 0 means no events.
+    pub fn negotiate(protocol: u32, requested: &[String]) -> Self {
+        let protocol = protocol.min(PROTOCOL_VERSION);
+        if protocol == 0 {
+            return Self::default();
+        }
+        let events = EVENT_KINDS
+            .iter()
+            .copied()
+            .filter(|kind| requested.iter().any(|r| r == kind))
+            .collect();
+        Self { protocol, events }
+    }
+
+    /// Negotiated protocol version (0 for clients that never said hello)
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// Negotiated events
+    pub fn events(&self) -> Vec<String> {
+        self.events.iter().map(|kind| kind.to_string()).collect()
+    }
+
+    /// Whether this client asked for an event
+    pub fn wants(&self, kind: &str) -> bool {
+        self.events.contains(kind)
+    }
+
+    /// Fit a message to this client
+    ///
+    /// Events the client didn't ask for become `output` when they have a
+    /// text form for `player_id`, and are dropped otherwise.
+    pub fn adapt(&self, msg: ServerMessage, player_id: &str) -> Option<ServerMessage> {
+        match msg.event_kind() {
+            Some(kind) if self.wants(kind) => Some(msg),
+            Some(_) => msg
+                .plain_text(player_id)
+                .map(|text| ServerMessage::Output { text }),
+            None => Some(msg),
+        }
+    }
+}
+
+impl ServerMessage {
+    /// Event name for typed events, None for the base protocol
+    pub fn event_kind(&self) -> Option<&'static str> {
+        match self {
+            ServerMessage::Vitals { .. } => Some("vitals"),
+            ServerMessage::Inventory { .. } => Some("inventory"),
+            ServerMessage::RoomOccupantsChanged { .. } => Some("room_occupants_changed"),
+            ServerMessage::CombatRound { .. } => Some("combat_round"),
+            ServerMessage::EffectApplied { .. } => Some("effect_applied"),
+            ServerMessage::EffectExpired { .. } => Some("effect_expired"),
+            ServerMessage::ChannelMessage { .. } => Some("channel_message"),
+            ServerMessage::Prompt { .. } => Some("prompt"),
+            ServerMessage::PresenceChanged { .. } => Some("presence_changed"),
+            _ => None,
+        }
+    }
+
+    /// Text shown to `player_id` in place of an event they didn't ask for
+    pub(super) fn plain_text(&self, player_id: &str) -> Option<String> {
+        match self {
+            ServerMessage::CombatRound {
+                attacker_id,
+                attacker,
+                target,
+                hit,
+                critical,
+                fumble,
+                damage,
+                killed,
+                ..
+            } => {
+                // The attacker already got their own account of the round
+                if attacker_id == player_id {
+                    return None;
+                }
+                let mut text = if *critical {
+                    format!(
+                        "CRITICAL HIT! {} strikes {} for {} damage!",
+                        attacker, target, damage
+                    )
+                } else if *hit {
+                    format!("{} hits {} for {} damage.", attacker, target, damage)
+                } else if *fumble {
+                    format!("{} fumbles an attack against {}!", attacker, target)
+                } else {
+                    format!("{} misses {}.", attacker, target)
+                };
+                if *killed {
+                    text.push_str(&format!("\n{} is slain!", target));
+                }
+                Some(text)
+            }
+            ServerMessage::EffectApplied {
+                entity_id, effect, ..
+            } if entity_id == player_id => Some(format!("You are {}.", effect)),
+            ServerMessage::EffectExpired { entity_id, effect } if entity_id == player_id => {
+                Some(format!("You are no longer {}.", effect))
+            }
+            ServerMessage::ChannelMessage {
+                channel,
+                sender,
+                text,
+                ..
+            } => Some(if channel == "say" {
+                format!("{} says, \"{}\"", sender, text)
+            } else if channel == "tell" {
+                format!("{} tells you: {}", sender, text)
+            } else {
+                format!("[{}] {}: {}", channel, sender, text)
+            }),
+            ServerMessage::PresenceChanged { name, status, .. } => Some(if status == "online" {
+                format!("Your friend {} has logged in.", name)
+            } else {
+                format!("Your friend {} has logged out.", name)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Event for a status effect that took hold of an entity
+    pub fn effect_applied(entity_id: &str, effect: &StatusEffect) -> Self {
+        ServerMessage::EffectApplied {
+            entity_id: entity_id.to_string(),
+            effect: effect.effect_type.to_string(),
+            magnitude: effect.magnitude,
+            remaining_ticks: effect.remaining_ticks,
+        }
+    }
+
+    /// Event for a status effect that wore off an entity
+    pub fn effect_expired(entity_id: &str, effect: EffectType) -> Self {
+        ServerMessage::EffectExpired {
+            entity_id: entity_id.to_string(),
+            effect: effect.to_string(),
+        }
+    }
+}
+
+/// Build the vitals event for a player
+async fn vitals(state: &AppState, player_id: &str) -> ServerMessage {
+    if let Some(combat) = state.combat.get_state(player_id).await {
+        return ServerMessage::Vitals {
+            hp: combat.hp,
+            max_hp: combat.max_hp,
+            in_combat: combat.in_combat,
+        };
+    }
+
+    let player = state.object_store.get(player_id).await.ok().flatten();
+    let prop = |key: &str| {
+        player
+            .as_ref()
+            .and_then(|p| p.properties.get(key))
+            .and_then(|v| v.as_i64())
+            .map(|v| v as i32)
+    };
+    let max_hp = prop("max_hp").unwrap_or(DEFAULT_PLAYER_HP);
+    ServerMessage::Vitals {
+        hp: prop("hp").unwrap_or(max_hp),
+        max_hp,
+        in_combat: false,
+    }
+}
+
+/// Name of an object, for event payloads
+fn object_name(obj: &crate::objects::Object) -> String {
+    obj.get_string("name").unwrap_or(&obj.id).to_string()
+}
+
+/// Send a player the state events they asked for
+///
+/// Called after the hello and after every command; events identical to
+/// the last ones sent are skipped.
+pub(super) async fn refresh(state: &AppState, player_id: &str) {
+    let caps = state.connections.capabilities(player_id).await;
+    let vitals = if caps.wants("vitals") || caps.wants("prompt") {
+        Some(vitals(state, player_id).await)
+    } else {
+        None
+    };
+
+    if let Some(ServerMessage::Vitals { hp, max_hp, .. }) = vitals {
+        let prompt = ServerMessage::Prompt {
+            text: format!("{}/{} HP > ", hp, max_hp),
+        };
+        state.connections.send_state(player_id, prompt).await;
+    }
+    if let Some(vitals) = vitals {
+        state.connections.send_state(player_id, vitals).await;
+    }
+
+    if caps.wants("inventory") {
+        let items = state
+            .object_store
+            .get_contents(player_id)
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(|obj| ObjectRef {
+                id: obj.id.clone(),
+                name: object_name(obj),
+            })
+            .collect();
+        state
+            .connections
+            .send_state(player_id, ServerMessage::Inventory { items })
+            .await;
+    }
+}
+
+/// Tell everyone in a room who is there now
+///
+/// Occupants are the connected players in the room plus its NPCs.
+pub(super) async fn occupants_changed(state: &AppState, room_id: &str) {
+    let mut occupants = state.connections.players_in_room(room_id).await;
+    let npcs = state
+        .object_store
+        .get_contents(room_id)
+        .await
+        .unwrap_or_default();
+    occupants.extend(
+        npcs.iter()
+            .filter(|obj| obj.class == "npc" || obj.class == "monster")
+            .map(|obj| ObjectRef {
+                id: obj.id.clone(),
+                name: object_name(obj),
+            }),
+    );
+
+    let msg = ServerMessage::RoomOccupantsChanged {
+        room_id: room_id.to_string(),
+        occupants,
+    };
+    state.connections.broadcast_room(room_id, msg).await;
+}
+
+/// Move a player's session to another room and update both rooms' occupants
+pub(super) async fn change_room(state: &AppState, player_id: &str, room_id: Option<String>) {
+    let previous = state.connections.get_room_id(player_id).await;
+    if previous == room_id {
+        return;
+    }
+    state
+        .connections
+        .update_room(player_id, room_id.clone())
+        .await;
+
+    for room in [previous, room_id].into_iter().flatten() {
+        occupants_changed(state, &room).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combat_round() -> ServerMessage {
+        ServerMessage::CombatRound {
+            attacker_id: "/players/alice".to_string(),
+            attacker: "Alice".to_string(),
+            target_id: "/npcs/bat".to_string(),
+            target: "Giant Bat".to_string(),
+            hit: true,
+            critical: false,
+            fumble: false,
+            damage: 4,
+            target_hp: 0,
+            target_max_hp: 4,
+            killed: true,
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let requested = vec![
+            "vitals".to_string(),
+            "prompt".to_string(),
+            "telepathy".to_string(),
+        ];
+        let caps = Capabilities::negotiate(7, &requested);
+        assert_eq!(caps.protocol(), PROTOCOL_VERSION);
+        assert_eq!(caps.events(), vec!["prompt", "vitals"]);
+        assert!(caps.wants("vitals"));
+        assert!(!caps.wants("telepathy"));
+        assert!(!caps.wants("inventory"));
+
+        let legacy = Capabilities::negotiate(0, &requested);
+        assert_eq!(legacy.protocol(), 0);
+        assert!(legacy.events().is_empty());
+    }
+
+    #[test]
+    fn test_adapt_for_legacy_clients() {
+        let legacy = Capabilities::default();
+
+        // Pure state events are dropped
+        let vitals = ServerMessage::Vitals {
+            hp: 5,
+            max_hp: 10,
+            in_combat: false,
+        };
+        assert!(legacy.adapt(vitals, "/players/alice").is_none());
+
+        // Onlookers read the round as text; the attacker has their own
+        match legacy.adapt(combat_round(), "/players/bob") {
+            Some(ServerMessage::Output { text }) => {
+                assert_eq!(
+                    text,
+                    "Alice hits Giant Bat for 4 damage.\nGiant Bat is slain!"
+                );
+            }
+            other => panic!("expected output, got {:?}", other),
+        }
+        assert!(legacy.adapt(combat_round(), "/players/alice").is_none());
+
+        let said = ServerMessage::ChannelMessage {
+            channel: "say".to_string(),
+            sender_id: "/players/alice".to_string(),
+            sender: "Alice".to_string(),
+            text: "hi".to_string(),
+        };
+        assert!(matches!(
+            legacy.adapt(said, "/players/bob"),
+            Some(ServerMessage::Output { text }) if text == "Alice says, \"hi\""
+        ));
+
+        // Base protocol messages pass through
+        let output = ServerMessage::Output {
+            text: "hi".to_string(),
+        };
+        assert!(matches!(
+            legacy.adapt(output, "/players/alice"),
+            Some(ServerMessage::Output { .. })
+        ));
+    }
+
+    #[test]
+    fn test_adapt_for_negotiated_clients() {
+        let caps = Capabilities::negotiate(1, &["combat_round".to_string()]);
+        assert!(matches!(
+            caps.adapt(combat_round(), "/players/alice"),
+            Some(ServerMessage::CombatRound { .. })
+        ));
+
+        let json = serde_json::to_value(combat_round()).unwrap();
+        assert_eq!(json["type"], "combat_round");
+        assert_eq!(json["target"], "Giant Bat");
+        assert_eq!(json["killed"], true);
+    }
+
+    #[test]
+    fn test_effect_events() {
+        let effect = StatusEffect::new(EffectType::Poisoned, 3, 2);
+        let applied = ServerMessage::effect_applied("/players/alice", &effect);
+        let json = serde_json::to_value(&applied).unwrap();
+        assert_eq!(json["type"], "effect_applied");
+        assert_eq!(json["effect"], "poisoned");
+        assert_eq!(json["remaining_ticks"], 3);
+
+        let legacy = Capabilities::default();
+        assert!(matches!(
+            legacy.adapt(applied.clone(), "/players/alice"),
+            Some(ServerMessage::Output { text }) if text == "You are poisoned."
+        ));
+        assert!(legacy.adapt(applied, "/players/bob").is_none());
+
+        let expired = ServerMessage::effect_expired("/players/alice", EffectType::Poisoned);
+        assert!(matches!(
+            legacy.adapt(expired, "/players/alice"),
+            Some(ServerMessage::Output { text }) if text == "You are no longer poisoned."
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! Lifecycle events - run object and class handlers for server actions
+//!
+//! When the server itself creates, moves, damages or kills an object, the
+//! matching handler runs in a pooled sandbox with the object as context, the
+//! same way a timer callback does. The handler may come from the object's
+//! own code or any class in its inheritance chain. The object's owner is
+//! billed for it, and any messages it queues are delivered afterwards.
+//!
+//! Handlers fired from inside a running script (`game.move_object` and
+//! friends) run in that script's sandbox instead; see `lua::handlers`.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use mlua::{LuaSerdeExt, Value};
+use serde_json::json;
+use tracing::warn;
+
+use super::{billing, sandboxes, AppState};
+use crate::lua::handlers::{self, Frame, HandlerSource};
+use crate::lua::init::{self, INIT_HANDLER};
+use crate::lua::{MessageQueue, Sandbox, SandboxConfig};
+use crate::objects::Object;
+
+/// Run one handler on an object and deliver its messages
+///
+/// The handler's context gets `object_id`, `universe_id` and each of
+/// `fields`. `label` describes the execution on the owner's bill. Returns
+/// false when neither the object nor its classes define the handler.
+pub(super) async fn run(
+    state: &AppState,
+    object: &Object,
+    handler: &str,
+    fields: serde_json::Value,
+    label: &str,
+) -> Result<bool> {
+    let sources = handlers::object_sources(&state.object_store, &state.classes, object).await?;
+    if sources.is_empty() {
+        return Ok(false);
+    }
+    let libs = sandboxes::lib_hashes(state, &object.universe_id)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+    // Collect this handler's messages separately from other executions
+    let messages = MessageQueue::shared();
+    let mut game_api = state.game_api(&object.universe_id, messages.clone());
+    // Handlers act with the authority of the object's owner, who pays for them
+    game_api.set_user_context(object.owner_id.clone());
+    game_api.set_room_context(object.parent_id.clone());
+    game_api.set_object_context(Some(object.id.clone()));
+
+    let config = SandboxConfig::default();
+    if let Some(ref owner_id) = object.owner_id {
+        billing::open(state, &mut game_api, &object.universe_id, owner_id, &config)
+            .await
+            .map_err(|e| anyhow!(e))?;
+    }
+
+    let result =
+        match sandboxes::checkout(state, &object.universe_id, &libs, &game_api, config).await {
+            Ok(mut sandbox) => call_handler(&mut sandbox, sources, object, handler, fields).await,
+            Err(e) => Err(e),
+        };
+    if let Some(ref owner_id) = object.owner_id {
+        billing::settle(state, &game_api, &object.universe_id, owner_id, label).await;
+    }
+    state
+        .connections
+        .deliver(messages.drain().await, &state.object_store)
+        .await;
+    result.map_err(|e| anyhow!(e))
+}
+
+/// Run a handler on an object by id, logging rather than returning failures
+///
+/// Lifecycle handlers never stop the action that fired them.
+pub(super) async fn fire(
+    state: &AppState,
+    object_id: &str,
+    handler: &str,
+    fields: serde_json::Value,
+) {
+    let object = match state.object_store.get(object_id).await {
+        Ok(Some(object)) => object,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Failed to load {} for {}: {}", object_id, handler, e);
+            return;
+        }
+    };
+    if let Err(e) = run(state, &object, handler, fields, handler).await {
+        warn!("Handler {} on {} failed: {:#}", handler, object_id, e);
+    }
+}
+
+/// Fire the handlers for an object the server moved between containers
+///
+/// Mirrors `lua::handlers::moved`: on_leave on the old container, on_move
+/// on the object, on_enter on the new container, then on_init for each
+/// pair that now shares an environment.
+pub(super) async fn moved(state: &AppState, object_id: &str, from: Option<&str>, to: Option<&str>) {
+    if let Err(e) = init::forget(&state.object_store, &state.actions, object_id).await {
+        warn!("Failed to clear actions for {}: {}", object_id, e);
+    }
+    if let Some(from) = from {
+        fire(
+            state,
+            from,
+            "on_leave",
+            json!({"actor_id": object_id, "to_id": to}),
+        )
+        .await;
+    }
+    fire(
+        state,
+        object_id,
+        "on_move",
+        json!({"from_id": from, "to_id": to}),
+    )
+    .await;
+    if let Some(to) = to {
+        fire(
+            state,
+            to,
+            "on_enter",
+            json!({"actor_id": object_id, "from_id": from}),
+        )
+        .await;
+
+        match init::calls(&state.object_store, &state.classes, object_id, to).await {
+            Ok(calls) => {
+                for call in calls {
+                    let fields = json!({"actor_id": call.actor_id});
+                    fire(state, &call.object_id, INIT_HANDLER, fields).await;
+                }
+            }
+            Err(e) => warn!("Failed to find init calls for {}: {}", object_id, e),
+        }
+    }
+}
+
+/// Fire the handlers for damage the combat manager has dealt
+///
+/// Each hit runs `on_damage` on its target, then `on_death` if it killed
+/// the target.
+pub(super) async fn damage_dealt(state: &AppState) {
+    for event in state.combat.take_damage_events().await {
+        let damaged = json!({
+            "actor_id": event.actor_id,
+            "amount": event.damage.final_damage,
+            "damage_type": event.damage.damage_type.to_string(),
+        });
+        fire(state, &event.target_id, "on_damage", damaged).await;
+        if event.killed {
+            let died = json!({"actor_id": event.actor_id});
+            fire(state, &event.target_id, "on_death", died).await;
+        }
+    }
+}
+
+/// Run the handler inside a sandbox with the universe libraries loaded
+async fn call_handler(
+    sandbox: &mut Sandbox,
+    sources: Vec<HandlerSource>,
+    object: &Object,
+    handler: &str,
+    fields: serde_json::Value,
+) -> Result<bool, String> {
+    // Only the server's own on_init calls name the living to give verbs to
+    let frame = match fields.get("actor_id").and_then(|a| a.as_str()) {
+        Some(actor_id) if handler == INIT_HANDLER => Frame::init(object.id.as_str(), actor_id),
+        _ => Frame::new(object.id.as_str()),
+    };
+
+    let lua_error = |e: mlua::Error| format!("Lua error: {}", e);
+    let lua = sandbox.lua();
+    let ctx = lua.create_table().map_err(lua_error)?;
+    ctx.set("object_id", object.id.as_str())
+        .map_err(lua_error)?;
+    ctx.set("universe_id", object.universe_id.as_str())
+        .map_err(lua_error)?;
+    if let serde_json::Value::Object(fields) = fields {
+        for (key, value) in fields {
+            // Leave absent fields nil rather than mlua's null sentinel
+            if !value.is_null() {
+                ctx.set(key, lua.to_value(&value).map_err(lua_error)?)
+                    .map_err(lua_error)?;
+            }
+        }
+    }
+
+    // Resolve and call the handler inside the sandbox's limits
+    let handler = handler.to_string();
+    let sources = Arc::new(sources);
+    let dispatch = lua
+        .create_async_function(move |lua, ctx: mlua::Table| {
+            let sources = sources.clone();
+            let handler = handler.clone();
+            let frame = frame.clone();
+            async move {
+                Ok(handlers::call(&lua, Some(frame), &sources, &handler, ctx)
+                    .await?
+                    .is_some())
+            }
+        })
+        .map_err(lua_error)?;
+    sandbox
+        .call_async::<_, bool>(dispatch, Value::Table(ctx))
+        .await
+        .map_err(|e| format!("Lua error: {}", e))
+}
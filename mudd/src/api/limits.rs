@@ -0,0 +1,562 @@
+//! Rate limits for commands, connections and logins
+//!
+//! Token buckets per account and per IP address. Command and connection
+//! limits are set per universe with the `rate_limits` universe setting.
+//! Logins happen before a universe is chosen, so their limits and the
+//! lockout after repeated failures are server-wide, set in the server's
+//! `Config`. Registrations share the per-IP login bucket.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use axum::http::{header::RETRY_AFTER, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::websocket::ServerMessage;
+use super::AppState;
+
+/// Buckets kept before full ones are forgotten
+const MAX_BUCKETS: usize = 10_000;
+
+/// Per-universe limits, in requests per minute (0 = unlimited)
+///
+/// Stored as JSON in `universe_settings` under `rate_limits`; missing
+/// fields take the defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    /// Commands from each player (design.md §11)
+    pub commands_per_minute: u32,
+    /// Commands from each IP address
+    pub ip_commands_per_minute: u32,
+    /// Connections for each account
+    pub connects_per_minute: u32,
+    /// Connections from each IP address
+    pub ip_connects_per_minute: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            commands_per_minute: 60,
+            ip_commands_per_minute: 300,
+            connects_per_minute: 10,
+            ip_connects_per_minute: 30,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Key in universe_settings
+    pub const SETTING_KEY: &'static str = "rate_limits";
+}
+
+/// Server-wide login limits (0 = unlimited)
+///
+/// Registrations spend from the same per-IP bucket as logins, since both
+/// hash a password.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginLimits {
+    /// Login attempts per minute for each username
+    pub logins_per_minute: u32,
+    /// Login and registration attempts per minute from each IP address
+    pub ip_logins_per_minute: u32,
+    /// Consecutive failed logins before a username is locked out
+    pub max_failed_logins: u32,
+    /// Consecutive failed logins before an IP address is locked out
+    pub max_ip_failed_logins: u32,
+    /// How long a lockout lasts, in seconds
+    pub lockout_secs: u64,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        Self {
+            logins_per_minute: 10,
+            ip_logins_per_minute: 30,
+            max_failed_logins: 5,
+            max_ip_failed_logins: 20,
+            lockout_secs: 300,
+        }
+    }
+}
+
+impl LoginLimits {
+    fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_secs)
+    }
+}
+
+/// A request refused for coming too fast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    /// What was limited: "command", "connect", "login" or "register"
+    pub action: &'static str,
+    /// How long until a retry can succeed
+    pub retry_after: Duration,
+    /// Refused because of repeated failed logins
+    pub locked_out: bool,
+}
+
+impl RateLimited {
+    /// Whole seconds to wait, at least 1
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    pub fn message(&self) -> String {
+        if self.locked_out {
+            return format!(
+                "Too many failed logins. Try again in {} seconds.",
+                self.retry_after_secs()
+            );
+        }
+        let what = match self.action {
+            "command" => "commands",
+            "connect" => "connection attempts",
+            "register" => "registrations",
+            _ => "login attempts",
+        };
+        format!(
+            "Too many {}. Try again in {} seconds.",
+            what,
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl From<RateLimited> for ServerMessage {
+    fn from(limited: RateLimited) -> Self {
+        ServerMessage::RateLimited {
+            action: limited.action.to_string(),
+            retry_after_secs: limited.retry_after_secs(),
+            message: limited.message(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RateLimitedResponse {
+    error: String,
+    action: &'static str,
+    retry_after_secs: u64,
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after_secs();
+        let body = RateLimitedResponse {
+            error: self.message(),
+            action: self.action,
+            retry_after_secs: retry_after,
+        };
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+/// Token buckets and failed-login counts for the whole server
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    login: LoginLimits,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl RateLimiter {
+    pub fn new(login: LoginLimits) -> Self {
+        Self {
+            login,
+            ..Self::default()
+        }
+    }
+
+    /// Count a command from `player_id` in a universe
+    pub fn check_command(
+        &self,
+        limits: &RateLimits,
+        universe_id: &str,
+        player_id: &str,
+        ip: Option<&str>,
+    ) -> Result<(), RateLimited> {
+        let mut keys = vec![(
+            format!("command:{}:player:{}", universe_id, player_id),
+            limits.commands_per_minute,
+        )];
+        if let Some(ip) = ip {
+            keys.push((
+                format!("command:{}:ip:{}", universe_id, ip),
+                limits.ip_commands_per_minute,
+            ));
+        }
+        self.acquire("command", &keys, Instant::now())
+    }
+
+    /// Count a connection to a universe; guests have no account
+    pub fn check_connect(
+        &self,
+        limits: &RateLimits,
+        universe_id: &str,
+        account_id: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<(), RateLimited> {
+        let mut keys = Vec::new();
+        if let Some(account_id) = account_id {
+            keys.push((
+                format!("connect:{}:account:{}", universe_id, account_id),
+                limits.connects_per_minute,
+            ));
+        }
+        if let Some(ip) = ip {
+            keys.push((
+                format!("connect:{}:ip:{}", universe_id, ip),
+                limits.ip_connects_per_minute,
+            ));
+        }
+        self.acquire("connect", &keys, Instant::now())
+    }
+
+    /// Count a login attempt, refusing it during a lockout
+    pub fn check_login(&self, username: &str, ip: Option<&str>) -> Result<(), RateLimited> {
+        self.check_login_at(username, ip, Instant::now())
+    }
+
+    fn check_login_at(
+        &self,
+        username: &str,
+        ip: Option<&str>,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let failure_keys = self.login_failure_keys(username, ip);
+        {
+            let failures = self.failures.lock();
+            let locked_until = failure_keys
+                .iter()
+                .filter_map(|(key, _)| failures.get(key)?.locked_until)
+                .filter(|until| *until > now)
+                .max();
+            if let Some(until) = locked_until {
+                return Err(RateLimited {
+                    action: "login",
+                    retry_after: until - now,
+                    locked_out: true,
+                });
+            }
+        }
+
+        let mut keys = vec![(
+            format!("login:user:{}", username),
+            self.login.logins_per_minute,
+        )];
+        if let Some(ip) = ip {
+            keys.push((format!("login:ip:{}", ip), self.login.ip_logins_per_minute));
+        }
+        self.acquire("login", &keys, now)
+    }
+
+    /// Count a registration against the address's login bucket
+    pub fn check_register(&self, ip: Option<&str>) -> Result<(), RateLimited> {
+        self.check_register_at(ip, Instant::now())
+    }
+
+    fn check_register_at(&self, ip: Option<&str>, now: Instant) -> Result<(), RateLimited> {
+        let keys: Vec<_> = ip
+            .map(|ip| (format!("login:ip:{}", ip), self.login.ip_logins_per_minute))
+            .into_iter()
+            .collect();
+        self.acquire("register", &keys, now)
+    }
+
+    /// Record a failed login; enough in a row lock the username or address
+    pub fn login_failed(&self, username: &str, ip: Option<&str>) {
+        self.login_failed_at(username, ip, Instant::now());
+    }
+
+    fn login_failed_at(&self, username: &str, ip: Option<&str>, now: Instant) {
+        let mut failures = self.failures.lock();
+        if failures.len() > MAX_BUCKETS {
+            // Only lockouts still running need remembering
+            failures.retain(|_, f| f.locked_until.is_some_and(|until| until > now));
+        }
+        for (key, max) in self.login_failure_keys(username, ip) {
+            if max == 0 {
+                continue;
+            }
+            let entry = failures.entry(key).or_default();
+            if entry.locked_until.is_some_and(|until| until <= now) {
+                *entry = Failures::default();
+            }
+            entry.count += 1;
+            if entry.count >= max && entry.locked_until.is_none() {
+                warn!(
+                    "Locking out {} after {} failed logins",
+                    username, entry.count
+                );
+                entry.locked_until = Some(now + self.login.lockout());
+            }
+        }
+    }
+
+    /// Clear the username's failure count after a successful login
+    ///
+    /// The address keeps its count, so logging in to one account doesn't
+    /// reset guesses at others from the same address.
+    pub fn login_succeeded(&self, username: &str) {
+        let (key, _) = self.login_failure_keys(username, None).remove(0);
+        self.failures.lock().remove(&key);
+    }
+
+    /// Take a token from every bucket, or from none if any is empty
+    fn acquire(
+        &self,
+        action: &'static str,
+        keys: &[(String, u32)],
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let mut buckets = self.buckets.lock();
+        if buckets.len() > MAX_BUCKETS {
+            // Full buckets are the same as missing ones
+            buckets.retain(|_, bucket| bucket.tokens < 1.0);
+        }
+
+        let mut retry_after = Duration::ZERO;
+        let mut refilled = Vec::with_capacity(keys.len());
+        for (key, per_minute) in keys.iter().filter(|(_, n)| *n > 0) {
+            let capacity = f64::from(*per_minute);
+            let per_second = capacity / 60.0;
+            let tokens = match buckets.get(key) {
+                Some(bucket) => {
+                    let elapsed = now.saturating_duration_since(bucket.updated);
+                    (bucket.tokens + elapsed.as_secs_f64() * per_second).min(capacity)
+                }
+                None => capacity,
+            };
+            if tokens < 1.0 {
+                let wait = Duration::from_secs_f64((1.0 - tokens) / per_second);
+                retry_after = retry_after.max(wait);
+            }
+            refilled.push((key, tokens));
+        }
+
+        if !retry_after.is_zero() {
+            return Err(RateLimited {
+                action,
+                retry_after,
+                locked_out: false,
+            });
+        }
+        for (key, tokens) in refilled {
+            buckets.insert(
+                key.clone(),
+                Bucket {
+                    tokens: tokens - 1.0,
+                    updated: now,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Failure counters a login touches, with the count that locks each
+    fn login_failure_keys(&self, username: &str, ip: Option<&str>) -> Vec<(String, u32)> {
+        let mut keys = vec![(format!("user:{}", username), self.login.max_failed_logins)];
+        if let Some(ip) = ip {
+            keys.push((format!("ip:{}", ip), self.login.max_ip_failed_logins));
+        }
+        keys
+    }
+}
+
+/// Load a universe's rate limits, or the defaults
+pub(super) async fn universe_limits(state: &AppState, universe_id: &str) -> RateLimits {
+    let raw = match state
+        .object_store
+        .get_universe_setting(universe_id, RateLimits::SETTING_KEY)
+        .await
+    {
+        Ok(Some(raw)) => raw,
+        Ok(None) => return RateLimits::default(),
+        Err(e) => {
+            warn!("Failed to load rate limits for {}: {}", universe_id, e);
+            return RateLimits::default();
+        }
+    };
+
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        warn!(
+            "Invalid rate limits for {}, using defaults: {}",
+            universe_id, e
+        );
+        RateLimits::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limiter = RateLimiter::default();
+        let keys = vec![("k".to_string(), 60)];
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert!(limiter.acquire("command", &keys, start).is_ok());
+        }
+        let limited = limiter.acquire("command", &keys, start).unwrap_err();
+        assert_eq!(limited.retry_after_secs(), 1);
+
+        // One token a second at 60 a minute
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.acquire("command", &keys, later).is_ok());
+        assert!(limiter.acquire("command", &keys, later).is_err());
+    }
+
+    #[test]
+    fn test_all_buckets_or_none() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        let tight = vec![("player".to_string(), 1), ("ip".to_string(), 100)];
+        assert!(limiter.acquire("command", &tight, now).is_ok());
+        assert!(limiter.acquire("command", &tight, now).is_err());
+
+        // The refused request didn't spend from the IP bucket
+        let ip_bucket = limiter.buckets.lock()["ip"].tokens;
+        assert_eq!(ip_bucket, 99.0);
+
+        // Zero means unlimited
+        let unlimited = vec![("free".to_string(), 0)];
+        for _ in 0..1000 {
+            assert!(limiter.acquire("command", &unlimited, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_failed_logins_lock_out() {
+        let limiter = RateLimiter::default();
+        let max_failed = limiter.login.max_failed_logins;
+        let lockout = limiter.login.lockout();
+        let now = Instant::now();
+
+        for _ in 0..max_failed {
+            assert!(limiter
+                .check_login_at("alice", Some("10.0.0.1"), now)
+                .is_ok());
+            limiter.login_failed_at("alice", Some("10.0.0.1"), now);
+        }
+        let refused = limiter
+            .check_login_at("alice", Some("10.0.0.2"), now)
+            .unwrap_err();
+        assert!(refused.locked_out);
+        assert_eq!(refused.retry_after, lockout);
+
+        // Other accounts from the same address can still log in
+        assert!(limiter.check_login_at("bob", Some("10.0.0.1"), now).is_ok());
+
+        // The lockout expires, and a success clears the count
+        let later = now + lockout;
+        assert!(limiter.check_login_at("alice", None, later).is_ok());
+        limiter.login_failed_at("alice", Some("10.0.0.1"), later);
+        limiter.login_succeeded("alice");
+        assert!(limiter.failures.lock().get("user:alice").is_none());
+
+        // The address's count survives the success
+        assert_eq!(limiter.failures.lock()["ip:10.0.0.1"].count, max_failed + 1);
+    }
+
+    #[test]
+    fn test_failures_forgotten_except_lockouts() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..limiter.login.max_failed_logins {
+            limiter.login_failed_at("alice", None, now);
+        }
+        for i in 0..MAX_BUCKETS {
+            limiter.login_failed_at(&format!("guess{}", i), None, now);
+        }
+
+        limiter.login_failed_at("bob", None, now);
+        let failures = limiter.failures.lock();
+        assert_eq!(failures.len(), 2);
+        assert!(failures["user:alice"].locked_until.is_some());
+        assert_eq!(failures["user:bob"].count, 1);
+    }
+
+    #[test]
+    fn test_login_limits_configurable() {
+        let limiter = RateLimiter::new(LoginLimits {
+            max_failed_logins: 2,
+            lockout_secs: 60,
+            ..LoginLimits::default()
+        });
+        let now = Instant::now();
+        limiter.login_failed_at("alice", None, now);
+        assert!(limiter.check_login_at("alice", None, now).is_ok());
+        limiter.login_failed_at("alice", None, now);
+
+        let refused = limiter.check_login_at("alice", None, now).unwrap_err();
+        assert!(refused.locked_out);
+        assert_eq!(refused.retry_after, Duration::from_secs(60));
+
+        // Zero turns the lockout off
+        let limiter = RateLimiter::new(LoginLimits {
+            max_failed_logins: 0,
+            ..LoginLimits::default()
+        });
+        for _ in 0..10 {
+            limiter.login_failed_at("bob", None, now);
+        }
+        assert!(limiter.check_login_at("bob", None, now).is_ok());
+    }
+
+    #[test]
+    fn test_registrations_share_ip_login_bucket() {
+        let limiter = RateLimiter::new(LoginLimits {
+            ip_logins_per_minute: 3,
+            ..LoginLimits::default()
+        });
+        let now = Instant::now();
+        let ip = Some("10.0.0.1");
+
+        for _ in 0..2 {
+            assert!(limiter.check_register_at(ip, now).is_ok());
+        }
+        assert!(limiter.check_login_at("alice", ip, now).is_ok());
+
+        let refused = limiter.check_register_at(ip, now).unwrap_err();
+        assert_eq!(refused.action, "register");
+        assert!(limiter.check_login_at("bob", ip, now).is_err());
+
+        // Other addresses are unaffected
+        assert!(limiter.check_register_at(Some("10.0.0.2"), now).is_ok());
+    }
+
+    #[test]
+    fn test_limits_setting_defaults() {
+        let limits: RateLimits = serde_json::from_str(r#"{"commands_per_minute": 5}"#).unwrap();
+        assert_eq!(limits.commands_per_minute, 5);
+        assert_eq!(limits.connects_per_minute, 10);
+    }
+}
@@ -1,7 +1,20 @@
 //! HTTP API module - REST endpoints and WebSocket
 
 mod auth;
+mod billing;
+mod chat;
+mod cluster;
+mod commands;
+mod credits;
+mod events;
 mod images;
+mod lifecycle;
+mod limits;
+mod outbox;
+mod presence;
+mod sandboxes;
+mod scheduler;
+mod telnet;
 mod universe;
 mod websocket;
 
@@ -9,13 +22,17 @@ use std::sync::Arc;
 
 use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 use serde::Serialize;
-use tokio::sync::RwLock;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, RwLock};
 
+use crate::auth::accounts::AccountService;
+use crate::channels::ChannelStore;
 use crate::combat::CombatManager;
 use crate::credits::CreditManager;
 use crate::db::Database;
 use crate::images::ImageStore;
-use crate::lua::{ActionRegistry, MessageQueue};
+use crate::lua::pool::SandboxPool;
+use crate::lua::{ActionRegistry, GameApi, MessageQueue};
 use crate::objects::{ClassRegistry, ObjectStore};
 use crate::permissions::PermissionManager;
 use crate::player::PlayerManager;
@@ -23,6 +40,10 @@ use crate::raft::RaftWriter;
 use crate::theme::ThemeRegistry;
 use crate::timers::TimerManager;
 use crate::venice::VeniceClient;
+pub use events::{Capabilities, ObjectRef, PROTOCOL_VERSION};
+pub use limits::{LoginLimits, RateLimited, RateLimiter, RateLimits};
+pub use outbox::{Outbox, Outgoing};
+pub use presence::OnlinePlayer;
 pub use websocket::{ConnectionManager, PlayerSession, ServerMessage};
 
 /// Shared application state
@@ -34,7 +55,6 @@ pub struct AppState {
     pub object_store: Arc<ObjectStore>,
     pub classes: Arc<RwLock<ClassRegistry>>,
     pub actions: Arc<ActionRegistry>,
-    pub messages: Arc<MessageQueue>,
     pub permissions: Arc<PermissionManager>,
     pub player_manager: Arc<PlayerManager>,
     pub timers: Arc<TimerManager>,
@@ -43,69 +63,136 @@ pub struct AppState {
     pub image_store: Arc<ImageStore>,
     pub themes: Arc<ThemeRegistry>,
     pub combat: Arc<CombatManager>,
+    pub limits: Arc<RateLimiter>,
+    pub channels: Arc<ChannelStore>,
+    /// Warm sandboxes with each universe's libraries loaded
+    pub sandboxes: Arc<SandboxPool>,
+    /// Domain wallet sign-in messages must name
+    pub public_domain: String,
 }
 
-/// Build the API router
-pub async fn router(db: Arc<Database>, raft_writer: Arc<RaftWriter>) -> Router {
-    let connections = Arc::new(ConnectionManager::new());
-    let object_store = Arc::new(ObjectStore::new(
-        db.pool().clone(),
-        Some(raft_writer.clone()),
-    ));
-    let mut class_registry = ClassRegistry::with_db(db.pool().clone(), raft_writer.clone());
-    let actions = Arc::new(ActionRegistry::new());
-    let messages = Arc::new(MessageQueue::new());
-    let permissions = Arc::new(PermissionManager::with_db(
-        db.pool().clone(),
-        Some(raft_writer.clone()),
-    ));
-    let player_manager = Arc::new(PlayerManager::new(object_store.clone()));
-    let timers = Arc::new(TimerManager::new(
-        Some(db.pool().clone()),
-        Some(raft_writer.clone()),
-    ));
-    let credits = Arc::new(CreditManager::new(
-        Some(db.pool().clone()),
-        Some(raft_writer.clone()),
-    ));
-    let venice = Arc::new(VeniceClient::new());
-    let image_store = Arc::new(ImageStore::new(db.pool().clone(), raft_writer.clone()));
-    let themes = Arc::new(ThemeRegistry::new());
-    let combat = Arc::new(CombatManager::with_db(db.pool().clone()));
-
-    // Load persisted data on startup
-    if let Err(e) = timers.load_from_db().await {
-        tracing::warn!("Failed to load timers from database: {}", e);
-    }
-    if let Err(e) = permissions.load_path_grants().await {
-        tracing::warn!("Failed to load path grants from database: {}", e);
+impl AppState {
+    /// Create all managers and load persisted data
+    pub async fn new(
+        db: Arc<Database>,
+        raft_writer: Arc<RaftWriter>,
+        public_domain: impl Into<String>,
+        login_limits: LoginLimits,
+    ) -> Self {
+        let connections = Arc::new(ConnectionManager::new());
+        let object_store = Arc::new(ObjectStore::new(
+            db.pool().clone(),
+            Some(raft_writer.clone()),
+        ));
+        let mut class_registry = ClassRegistry::with_db(db.pool().clone(), raft_writer.clone());
+        let actions = Arc::new(ActionRegistry::new());
+        let permissions = Arc::new(PermissionManager::with_db(
+            db.pool().clone(),
+            Some(raft_writer.clone()),
+        ));
+        let player_manager = Arc::new(PlayerManager::new(object_store.clone()));
+        let timers = Arc::new(TimerManager::new(
+            Some(db.pool().clone()),
+            Some(raft_writer.clone()),
+        ));
+        let credits = Arc::new(CreditManager::new(
+            Some(db.pool().clone()),
+            Some(raft_writer.clone()),
+        ));
+        let venice = Arc::new(VeniceClient::new());
+        let image_store = Arc::new(ImageStore::new(db.pool().clone(), raft_writer.clone()));
+        let themes = Arc::new(ThemeRegistry::new());
+        let combat = Arc::new(CombatManager::with_db(
+            db.pool().clone(),
+            Some(raft_writer.clone()),
+        ));
+        let channels = Arc::new(ChannelStore::new(
+            db.pool().clone(),
+            Some(raft_writer.clone()),
+        ));
+
+        // Load persisted data on startup
+        if let Err(e) = timers.load_from_db().await {
+            tracing::warn!("Failed to load timers from database: {}", e);
+        }
+        if let Err(e) = permissions.load_path_grants().await {
+            tracing::warn!("Failed to load path grants from database: {}", e);
+        }
+        if let Err(e) = class_registry.load_from_db().await {
+            tracing::warn!("Failed to load classes from database: {}", e);
+        }
+        if let Err(e) = combat.load_from_db().await {
+            tracing::warn!("Failed to load combat states from database: {}", e);
+        }
+
+        let classes = Arc::new(RwLock::new(class_registry));
+
+        Self {
+            db,
+            raft_writer,
+            connections,
+            object_store,
+            classes,
+            actions,
+            permissions,
+            player_manager,
+            timers,
+            credits,
+            venice,
+            image_store,
+            themes,
+            combat,
+            limits: Arc::new(RateLimiter::new(login_limits)),
+            channels,
+            sandboxes: Arc::new(SandboxPool::new()),
+            public_domain: public_domain.into(),
+        }
     }
-    if let Err(e) = class_registry.load_from_db().await {
-        tracing::warn!("Failed to load classes from database: {}", e);
+
+    /// Game API for one sandbox execution in a universe
+    ///
+    /// Messages the code sends collect in `messages`; deliver them with
+    /// `ConnectionManager::deliver` once execution finishes.
+    pub fn game_api(&self, universe_id: &str, messages: Arc<MessageQueue>) -> GameApi {
+        let mut api = GameApi::new(
+            self.object_store.clone(),
+            self.classes.clone(),
+            self.actions.clone(),
+            messages,
+            self.permissions.clone(),
+            self.timers.clone(),
+            self.credits.clone(),
+            self.venice.clone(),
+            self.image_store.clone(),
+            universe_id,
+        );
+        api.set_connections(self.connections.clone());
+        api
     }
-    if let Err(e) = combat.load_from_db().await {
-        tracing::warn!("Failed to load combat states from database: {}", e);
+
+    /// Account service that writes through this node's Raft writer
+    pub fn accounts(&self) -> AccountService {
+        AccountService::new(self.db.pool().clone(), Some(self.raft_writer.clone()))
     }
+}
 
-    let classes = Arc::new(RwLock::new(class_registry));
-
-    let state = AppState {
-        db,
-        raft_writer,
-        connections,
-        object_store,
-        classes,
-        actions,
-        messages,
-        permissions,
-        player_manager,
-        timers,
-        credits,
-        venice,
-        image_store,
-        themes,
-        combat,
-    };
+/// Build the API router
+///
+/// Also starts the timer scheduler and, given a `telnet` listener, the
+/// telnet gateway; both stop when `shutdown_rx` fires.
+pub async fn router(
+    db: Arc<Database>,
+    raft_writer: Arc<RaftWriter>,
+    shutdown_rx: watch::Receiver<bool>,
+    telnet: Option<TcpListener>,
+    public_domain: String,
+    login_limits: LoginLimits,
+) -> Router {
+    let state = AppState::new(db, raft_writer, public_domain, login_limits).await;
+    if let Some(listener) = telnet {
+        tokio::spawn(telnet::serve(listener, state.clone(), shutdown_rx.clone()));
+    }
+    tokio::spawn(scheduler::run(state.clone(), shutdown_rx));
 
     Router::new()
         .route("/health", get(health_check))
@@ -113,6 +200,8 @@ pub async fn router(db: Arc<Database>, raft_writer: Arc<RaftWriter>) -> Router {
         .route("/ws", get(websocket::ws_handler))
         .nest("/images", images::router())
         .merge(auth::router())
+        .merge(cluster::router())
+        .merge(credits::router())
         .merge(universe::router())
         .with_state(state)
 }
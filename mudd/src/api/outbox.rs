@@ -0,0 +1,143 @@
+//! Per-player outbox for resuming after a reconnect
+//!
+//! Every message queued for a player is numbered and kept here, whether or
+//! not their connection is up. A client that reconnects sends the last
+//! sequence number it saw and gets what it missed.
+
+use std::collections::VecDeque;
+
+use super::websocket::ServerMessage;
+
+/// Messages kept for each player
+pub const OUTBOX_CAPACITY: usize = 256;
+
+/// A message on its way to a client
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    /// Position in the player's outbox; 0 for messages that aren't kept
+    pub seq: u64,
+    pub msg: ServerMessage,
+}
+
+impl Outgoing {
+    /// A message that isn't kept for replay
+    pub fn unsequenced(msg: ServerMessage) -> Self {
+        Self { seq: 0, msg }
+    }
+
+    /// Wire form: the message with its `seq` alongside `type`
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(&self.msg)?;
+        if let (Some(fields), true) = (value.as_object_mut(), self.seq > 0) {
+            fields.insert("seq".to_string(), self.seq.into());
+        }
+        serde_json::to_string(&value)
+    }
+}
+
+/// Recent messages for one player, oldest first
+#[derive(Debug)]
+pub struct Outbox {
+    next_seq: u64,
+    messages: VecDeque<Outgoing>,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            next_seq: 1,
+            messages: VecDeque::new(),
+        }
+    }
+}
+
+impl Outbox {
+    /// Number and keep a message, dropping the oldest when full
+    pub fn push(&mut self, msg: ServerMessage) -> Outgoing {
+        let out = Outgoing {
+            seq: self.next_seq,
+            msg,
+        };
+        self.next_seq += 1;
+        if self.messages.len() == OUTBOX_CAPACITY {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(out.clone());
+        out
+    }
+
+    /// Sequence number the next message will get
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Messages after `last_seq` and before `until`, and how many of those
+    /// are no longer kept
+    pub fn replay(&self, last_seq: u64, until: u64) -> (Vec<Outgoing>, u64) {
+        let kept: Vec<Outgoing> = self
+            .messages
+            .iter()
+            .filter(|out| out.seq > last_seq && out.seq < until)
+            .cloned()
+            .collect();
+        let wanted = until.saturating_sub(last_seq.saturating_add(1));
+        let missed = wanted.saturating_sub(kept.len() as u64);
+        (kept, missed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(text: &str) -> ServerMessage {
+        ServerMessage::Output {
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_replay_between_sequence_numbers() {
+        let mut outbox = Outbox::default();
+        for text in ["one", "two", "three", "four"] {
+            outbox.push(output(text));
+        }
+        assert_eq!(outbox.next_seq(), 5);
+
+        let (replayed, missed) = outbox.replay(1, 4);
+        let seqs: Vec<u64> = replayed.iter().map(|out| out.seq).collect();
+        assert_eq!(seqs, vec![2, 3]);
+        assert_eq!(missed, 0);
+
+        // Client is ahead of this outbox (e.g. the server restarted)
+        assert_eq!(outbox.replay(10, 5).0.len(), 0);
+        assert_eq!(outbox.replay(10, 5).1, 0);
+    }
+
+    #[test]
+    fn test_overflow_counts_missed_messages() {
+        let mut outbox = Outbox::default();
+        for i in 0..OUTBOX_CAPACITY + 10 {
+            outbox.push(output(&i.to_string()));
+        }
+        let (replayed, missed) = outbox.replay(0, outbox.next_seq());
+        assert_eq!(replayed.len(), OUTBOX_CAPACITY);
+        assert_eq!(replayed[0].seq, 11);
+        assert_eq!(missed, 10);
+    }
+
+    #[test]
+    fn test_wire_form_carries_seq() {
+        let mut outbox = Outbox::default();
+        let out = outbox.push(output("hi"));
+        let json: serde_json::Value = serde_json::from_str(&out.to_json().unwrap()).unwrap();
+        assert_eq!(json["type"], "output");
+        assert_eq!(json["seq"], 1);
+
+        let control = Outgoing::unsequenced(ServerMessage::Disconnect {
+            reason: "bye".to_string(),
+        });
+        let json: serde_json::Value = serde_json::from_str(&control.to_json().unwrap()).unwrap();
+        assert!(json.get("seq").is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! Presence - who is online, idle and AFK status, and friends
+//!
+//! The `who`, `finger`, `afk` and `friend` built-ins read the sessions in
+//! the `ConnectionManager`. Friends are kept on the player object as a
+//! `friends` list of player ids; online players are told when a friend
+//! logs in or out. Last login and logout times are kept on the player
+//! object too, so `finger` works for players who are offline.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use tracing::warn;
+
+use super::{AppState, ServerMessage};
+
+/// Player object property holding the friends list
+pub const FRIENDS_PROPERTY: &str = "friends";
+
+/// A connected player, as listed by `who`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OnlinePlayer {
+    pub player_id: String,
+    pub name: String,
+    pub access_level: String,
+    /// Seconds since the player's last command
+    pub idle_secs: u64,
+    /// Seconds since the player connected
+    pub online_secs: u64,
+    /// AFK message, if the player is away ("" when they gave none)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub afk: Option<String>,
+}
+
+/// Note a command from a player, ending any AFK unless it's `afk` again
+pub(super) async fn activity(state: &AppState, player_id: &str, command: &str) {
+    let was_afk = state.connections.touch(player_id).await;
+    let verb = command.split_whitespace().next().unwrap_or_default();
+    if was_afk.is_some() && !verb.eq_ignore_ascii_case("afk") {
+        state
+            .connections
+            .send_to_player(
+                player_id,
+                ServerMessage::Output {
+                    text: "You are no longer AFK.".to_string(),
+                },
+            )
+            .await;
+    }
+}
+
+/// A player entered the game: record the time and tell their friends
+pub(super) async fn logged_in(state: &AppState, player_id: &str) {
+    record_time(state, player_id, "last_login").await;
+    notify_friends(state, player_id, "online").await;
+}
+
+/// A player left the game for good (not just a dropped connection)
+pub(super) async fn logged_out(state: &AppState, player_id: &str) {
+    record_time(state, player_id, "last_logout").await;
+    notify_friends(state, player_id, "offline").await;
+}
+
+async fn record_time(state: &AppState, player_id: &str, key: &str) {
+    let now = serde_json::json!(chrono::Utc::now().to_rfc3339());
+    if let Err(e) = state.player_manager.set_property(player_id, key, now).await {
+        warn!("Failed to record {} for {}: {}", key, player_id, e);
+    }
+}
+
+async fn notify_friends(state: &AppState, player_id: &str, status: &str) {
+    let Some(name) = state.connections.get_name(player_id).await else {
+        return;
+    };
+    let msg = ServerMessage::PresenceChanged {
+        player_id: player_id.to_string(),
+        name,
+        status: status.to_string(),
+    };
+    state.connections.notify_friends(player_id, msg).await;
+}
+
+/// `who`: everyone connected to the player's universe
+pub(super) async fn who(state: &AppState, player_id: &str) -> ServerMessage {
+    let Some(universe_id) = state.connections.get_universe_id(player_id).await else {
+        return ServerMessage::Error {
+            message: "Session error: no universe".to_string(),
+        };
+    };
+    let players = state.connections.online_players(&universe_id).await;
+
+    let mut text = format!(
+        "{} {} online:",
+        players.len(),
+        if players.len() == 1 {
+            "player"
+        } else {
+            "players"
+        }
+    );
+    for player in &players {
+        let mut line = format!("\n  {:<16} {:<8}", player.name, player.access_level);
+        if player.idle_secs >= 60 {
+            line.push_str(&format!(" idle {}", format_duration(player.idle_secs)));
+        }
+        match player.afk.as_deref() {
+            Some("") => line.push_str(" [AFK]"),
+            Some(message) => line.push_str(&format!(" [AFK: {}]", message)),
+            None => {}
+        }
+        text.push_str(line.trim_end());
+    }
+    ServerMessage::Output { text }
+}
+
+/// `finger <player>`: access level, login times and idle time
+pub(super) async fn finger(state: &AppState, player_id: &str, name: &str) -> ServerMessage {
+    if name.is_empty() {
+        return ServerMessage::Error {
+            message: "Usage: finger <player>".to_string(),
+        };
+    }
+    let Some(target_id) = resolve_player(state, player_id, name).await else {
+        return ServerMessage::Output {
+            text: format!("There is no player called {}.", name),
+        };
+    };
+
+    let player = state.object_store.get(&target_id).await.ok().flatten();
+    let property = |key: &str| {
+        player
+            .as_ref()
+            .and_then(|p| p.get_string(key))
+            .map(format_timestamp)
+    };
+
+    let online = state.connections.online_player(&target_id).await;
+    let (display_name, access_level) = match &online {
+        Some(online) => (online.name.clone(), online.access_level.clone()),
+        None => match state.accounts().get_by_username(name).await {
+            Ok(Some(account)) => (account.username, account.access_level),
+            _ => (name.to_string(), "player".to_string()),
+        },
+    };
+
+    let mut text = format!("{} [{}]", display_name, access_level);
+    match &online {
+        Some(online) => {
+            text.push_str(&format!(
+                "\nOnline for {}, idle {}",
+                format_duration(online.online_secs),
+                format_duration(online.idle_secs)
+            ));
+            match online.afk.as_deref() {
+                Some("") => text.push_str("\nAFK"),
+                Some(message) => text.push_str(&format!("\nAFK: {}", message)),
+                None => {}
+            }
+        }
+        None => match property("last_logout") {
+            Some(when) => text.push_str(&format!("\nOffline, last seen {}", when)),
+            None => text.push_str("\nOffline"),
+        },
+    }
+    if let Some(when) = property("last_login") {
+        text.push_str(&format!("\nLast login: {}", when));
+    }
+    ServerMessage::Output { text }
+}
+
+/// `afk [message]`: mark the player away until their next command
+pub(super) async fn afk(state: &AppState, player_id: &str, message: &str) -> ServerMessage {
+    state
+        .connections
+        .set_afk(player_id, Some(message.to_string()))
+        .await;
+    ServerMessage::Output {
+        text: if message.is_empty() {
+            "You are now AFK.".to_string()
+        } else {
+            format!("You are now AFK: {}", message)
+        },
+    }
+}
+
+/// `friend [add|remove <player>]`: list or change the friends list
+pub(super) async fn friend(
+    state: &AppState,
+    player_id: &str,
+    account_id: &str,
+    args: &[&str],
+) -> ServerMessage {
+    if account_id.is_empty() {
+        return ServerMessage::Error {
+            message: "Guests can't keep a friends list.".to_string(),
+        };
+    }
+    let mut friends = state.connections.friends(player_id).await;
+
+    let (adding, name) = match args {
+        [] => return list_friends(state, &friends).await,
+        ["add", name] => (true, *name),
+        ["remove", name] => (false, *name),
+        _ => {
+            return ServerMessage::Error {
+                message: "Usage: friend [add|remove <player>]".to_string(),
+            };
+        }
+    };
+
+    let Some(friend_id) = resolve_player(state, player_id, name).await else {
+        return ServerMessage::Output {
+            text: format!("There is no player called {}.", name),
+        };
+    };
+    if friend_id == player_id {
+        return ServerMessage::Output {
+            text: "You can't be your own friend.".to_string(),
+        };
+    }
+
+    let changed = if adding {
+        friends.insert(friend_id)
+    } else {
+        friends.remove(&friend_id)
+    };
+    if !changed {
+        return ServerMessage::Output {
+            text: if adding {
+                format!("{} is already your friend.", name)
+            } else {
+                format!("{} isn't your friend.", name)
+            },
+        };
+    }
+
+    let list = serde_json::json!(friends);
+    if let Err(e) = state
+        .player_manager
+        .set_property(player_id, FRIENDS_PROPERTY, list)
+        .await
+    {
+        return ServerMessage::Error {
+            message: format!("Failed to save friends: {}", e),
+        };
+    }
+    state.connections.set_friends(player_id, friends).await;
+
+    ServerMessage::Output {
+        text: if adding {
+            format!("{} is now your friend.", name)
+        } else {
+            format!("{} is no longer your friend.", name)
+        },
+    }
+}
+
+async fn list_friends(state: &AppState, friends: &BTreeSet<String>) -> ServerMessage {
+    if friends.is_empty() {
+        return ServerMessage::Output {
+            text: "You have no friends yet. Add one with: friend add <player>".to_string(),
+        };
+    }
+
+    let mut text = String::from("Friends:");
+    for friend_id in friends {
+        let online = state.connections.online_player(friend_id).await;
+        let name = match &online {
+            Some(online) => online.name.clone(),
+            None => state
+                .object_store
+                .get(friend_id)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|p| p.get_string("name").map(str::to_string))
+                .unwrap_or_else(|| friend_id.clone()),
+        };
+        let status = if online.is_some() {
+            "online"
+        } else {
+            "offline"
+        };
+        text.push_str(&format!("\n  {} ({})", name, status));
+    }
+    ServerMessage::Output { text }
+}
+
+/// Find a player by name: someone online in the same universe, then any
+/// account with that username
+pub(super) async fn resolve_player(
+    state: &AppState,
+    player_id: &str,
+    name: &str,
+) -> Option<String> {
+    if let Some(universe_id) = state.connections.get_universe_id(player_id).await {
+        if let Some(found) = state.connections.find_by_name(&universe_id, name).await {
+            return Some(found);
+        }
+    }
+    match state.accounts().get_by_username(name).await {
+        Ok(Some(account)) => Some(crate::player::PlayerManager::player_path(&account.id)),
+        _ => None,
+    }
+}
+
+/// "1h 5m", "3m", "42s"
+fn format_duration(secs: u64) -> String {
+    let (hours, minutes) = (secs / 3600, secs / 60 % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", secs),
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+/// An RFC 3339 time as "2026-01-31 14:05 UTC"
+fn format_timestamp(rfc3339: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(rfc3339)
+        .map(|t| {
+            t.with_timezone(&chrono::Utc)
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        })
+        .unwrap_or_else(|_| rfc3339.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(180), "3m");
+        assert_eq!(format_duration(7200), "2h");
+        assert_eq!(format_duration(3900), "1h 5m");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(
+            format_timestamp("2026-01-31T14:05:09+00:00"),
+            "2026-01-31 14:05 UTC"
+        );
+        assert_eq!(format_timestamp("yesterday"), "yesterday");
+    }
+}
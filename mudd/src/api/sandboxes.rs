@@ -0,0 +1,166 @@
+//! Sandboxes for universe code - warm states from the pool
+//!
+//! Commands, eval, handlers and scripts all run in a sandbox checked out of
+//! `AppState::sandboxes` with the universe's libraries already run (see
+//! `lua::pool`). A universe's libraries are the code hashes in its config's
+//! `lib_hashes`, run in name order.
+
+use super::AppState;
+use crate::lua::pool::{LibHash, PooledSandbox};
+use crate::lua::{GameApi, SandboxConfig};
+
+/// A universe's libraries, sorted by name for determinism
+pub(super) async fn lib_hashes(
+    state: &AppState,
+    universe_id: &str,
+) -> Result<Vec<LibHash>, String> {
+    let universe = match state.object_store.get_universe(universe_id).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return Ok(Vec::new()), // No universe = no libs to load
+        Err(e) => return Err(format!("Failed to get universe: {}", e)),
+    };
+
+    let Some(hashes) = universe
+        .config
+        .get("lib_hashes")
+        .and_then(|hashes| hashes.as_object())
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut libs: Vec<LibHash> = hashes
+        .iter()
+        .filter_map(|(name, hash)| Some((name.clone(), hash.as_str()?.to_string())))
+        .collect();
+    libs.sort();
+    Ok(libs)
+}
+
+/// A sandbox with `libs` loaded, working for `game_api`'s execution
+///
+/// Reuses an idle sandbox when the pool has one, switching its game API to
+/// this execution; otherwise creates one and runs the libraries in it, with
+/// `game_api` already registered so they can call it.
+pub(super) async fn checkout(
+    state: &AppState,
+    universe_id: &str,
+    libs: &[LibHash],
+    game_api: &GameApi,
+    config: SandboxConfig,
+) -> Result<PooledSandbox, String> {
+    if let Some(mut sandbox) = state.sandboxes.take(universe_id, libs) {
+        sandbox
+            .set_config(config)
+            .map_err(|e| format!("Failed to configure sandbox: {}", e))?;
+        sandbox.set_metering(game_api.metering().clone());
+        game_api
+            .enter(sandbox.lua())
+            .map_err(|e| format!("Failed to enter game API: {}", e))?;
+        return Ok(sandbox);
+    }
+
+    let mut sources = Vec::new();
+    for (name, hash) in libs {
+        match state.object_store.get_code(hash).await {
+            Ok(Some(code)) => sources.push((name, hash, code)),
+            Ok(None) => {
+                tracing::warn!(
+                    "Library {} with hash {} not found in code_store",
+                    name,
+                    hash
+                );
+            }
+            Err(e) => tracing::warn!("Failed to load library {}: {}", name, e),
+        }
+    }
+
+    let mut sandbox = state
+        .sandboxes
+        .create(universe_id, libs, config)
+        .map_err(|e| format!("Failed to create sandbox: {}", e))?;
+    sandbox.set_metering(game_api.metering().clone());
+    game_api
+        .register(sandbox.lua())
+        .map_err(|e| format!("Failed to register game API: {}", e))?;
+    for (name, hash, code) in sources {
+        sandbox
+            .run_library(name, hash, &code)
+            .await
+            .map_err(|e| format!("Failed to execute library {}: {}", name, e))?;
+    }
+    sandbox
+        .ready()
+        .map_err(|e| format!("Failed to save sandbox state: {}", e))?;
+    Ok(sandbox)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::LoginLimits;
+    use crate::db::Database;
+    use crate::lua::MessageQueue;
+    use crate::raft::RaftWriter;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_pooled_library_sees_each_execution() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+        let db = Database::new(Some(db_path)).await.unwrap();
+        let raft_writer = RaftWriter::single_node(db.pool().clone(), 1, 19111, db_path)
+            .await
+            .unwrap();
+        raft_writer
+            .wait_for_leader(Duration::from_secs(5))
+            .await
+            .unwrap();
+        let state = AppState::new(
+            Arc::new(db),
+            Arc::new(raft_writer),
+            "localhost",
+            LoginLimits::default(),
+        )
+        .await;
+        for name in ["alice", "bob"] {
+            sqlx::query(
+                "INSERT INTO accounts (id, username, password_hash, salt) VALUES (?, ?, '', '')",
+            )
+            .bind(name)
+            .bind(name)
+            .execute(state.db.pool())
+            .await
+            .unwrap();
+        }
+        state
+            .object_store
+            .create_universe("u1", "Test", "alice", serde_json::json!({}))
+            .await
+            .unwrap();
+        state.credits.grant("u1", "alice", 5, "test").await;
+        state.credits.grant("u1", "bob", 7, "test").await;
+
+        // The library keeps its own references to the game API
+        let code = "local g, get_credits = game, game.get_credits
+            function Balance() g.get_object('/nowhere'); return get_credits() end";
+        let hash = state.object_store.store_code(code).await.unwrap();
+        let libs = vec![("bank".to_string(), hash)];
+
+        let mut balances = Vec::new();
+        for user in ["alice", "bob"] {
+            let mut game_api = state.game_api("u1", Arc::new(MessageQueue::new()));
+            game_api.set_user_context(Some(user.to_string()));
+            let mut sandbox = checkout(&state, "u1", &libs, &game_api, SandboxConfig::default())
+                .await
+                .unwrap();
+            let balance: i64 = sandbox.eval_async("return Balance()").await.unwrap();
+            balances.push(balance);
+            assert_eq!(game_api.metering().db_reads(), 1);
+        }
+        assert_eq!(balances, vec![5, 7]);
+        assert_eq!(state.sandboxes.idle_count("u1"), 1);
+    }
+}
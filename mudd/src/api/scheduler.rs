@@ -0,0 +1,268 @@
+//! Timer scheduler - fires call_outs and heartbeats
+//!
+//! Only the Raft leader runs timer callbacks, so each timer fires once per
+//! cluster. Each callback runs the named method as a lifecycle handler: in a
+//! fresh sandbox with the object as context, from the object's code or its
+//! classes', delivering any messages it queued. The object's owner is billed
+//! for it.
+//!
+//! Every node also fires `on_damage` and `on_death` for damage its combat
+//! manager dealt outside a player's attack.
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde_json::json;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+use super::{lifecycle, AppState};
+use crate::timers::TimerFired;
+
+/// How often due timers are checked
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Run the scheduler until shutdown is signalled
+pub async fn run(state: AppState, mut shutdown_rx: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(TICK_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut was_leader = false;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.changed() => break,
+        }
+
+        let is_leader = state.raft_writer.is_leader();
+        if is_leader && !was_leader {
+            // Timers may have been added or fired under a previous leader
+            if let Err(e) = state.timers.load_from_db().await {
+                warn!("Failed to reload timers on becoming leader: {}", e);
+            }
+        }
+        was_leader = is_leader;
+
+        if is_leader {
+            tick(&state).await;
+        }
+        // Combat runs on every node, so each fires its own damage handlers
+        lifecycle::damage_dealt(&state).await;
+    }
+}
+
+/// Fire all due timers and heartbeats once
+pub(super) async fn tick(state: &AppState) {
+    for fired in state.timers.tick().await {
+        if let Err(e) = fire(state, &fired).await {
+            warn!(
+                "Timer {} on {} failed: {:#}",
+                fired.method, fired.object_id, e
+            );
+        }
+    }
+}
+
+/// Run one timer callback and deliver its messages
+///
+/// The handler receives a table with object_id, universe_id, method and,
+/// for call_outs, args. It may come from the object's code or its classes.
+async fn fire(state: &AppState, fired: &TimerFired) -> Result<()> {
+    let Some(object) = state.object_store.get(&fired.object_id).await? else {
+        // Object is gone; stop its heartbeat so it doesn't fire forever
+        state.timers.remove_heartbeat(&fired.object_id).await;
+        return Ok(());
+    };
+
+    let fields = json!({"method": fired.method, "args": fired.args});
+    let label = format!("timer {}", fired.method);
+    if !lifecycle::run(state, &object, &fired.method, fields, &label).await? {
+        bail!("Object has no {} handler", fired.method);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{LoginLimits, PlayerSession, ServerMessage};
+    use crate::combat::DamageType;
+    use crate::db::Database;
+    use crate::objects::Object;
+    use crate::permissions::AccessLevel;
+    use crate::raft::RaftWriter;
+    use crate::timers::{HeartBeat, Timer};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    async fn test_state(dir: &TempDir, raft_port: u16) -> AppState {
+        let db_path = dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+        let db = Database::new(Some(db_path)).await.unwrap();
+        let raft_writer = RaftWriter::single_node(db.pool().clone(), 1, raft_port, db_path)
+            .await
+            .unwrap();
+        raft_writer
+            .wait_for_leader(Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        let state = AppState::new(
+            Arc::new(db),
+            Arc::new(raft_writer),
+            "localhost",
+            LoginLimits::default(),
+        )
+        .await;
+        sqlx::query("INSERT INTO accounts (id, username, password_hash, salt) VALUES ('owner', 'owner', '', '')")
+            .execute(state.db.pool())
+            .await
+            .unwrap();
+        state
+            .object_store
+            .create_universe("u1", "Test", "owner", serde_json::json!({}))
+            .await
+            .unwrap();
+        state
+    }
+
+    #[tokio::test]
+    async fn test_call_out_runs_handler_and_delivers() {
+        let dir = TempDir::new().unwrap();
+        let state = test_state(&dir, 19101).await;
+
+        let code = r#"
+            return {
+                on_alarm = function(ctx)
+                    game.send("/players/bob", ctx.object_id .. " rings: " .. ctx.args)
+                end
+            }
+        "#;
+        let hash = state.object_store.store_code(code).await.unwrap();
+        let mut clock = Object::new("/items/clock", "u1", "item").unwrap();
+        clock.code_hash = Some(hash);
+        state.object_store.create(&clock).await.unwrap();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        state
+            .connections
+            .register(PlayerSession {
+                player_id: "/players/bob".to_string(),
+                account_id: "owner".to_string(),
+                name: "Bob".to_string(),
+                session_id: None,
+                ip_address: None,
+                universe_id: "u1".to_string(),
+                room_id: None,
+                access_level: AccessLevel::Player,
+                sender,
+                capabilities: Default::default(),
+                last_state: Default::default(),
+                outbox: Default::default(),
+                resume_from: 0,
+                connected_at: std::time::Instant::now(),
+                last_active: std::time::Instant::now(),
+                afk: None,
+                friends: Default::default(),
+                channels: Default::default(),
+                ignored: Default::default(),
+            })
+            .await;
+
+        let mut timer = Timer::new("u1", "/items/clock", "on_alarm", 0, Some("wake".into()));
+        timer.fire_at -= 1;
+        state.timers.add_timer(timer).await;
+
+        tick(&state).await;
+
+        match receiver.try_recv().unwrap().msg {
+            ServerMessage::Output { text } => assert_eq!(text, "/items/clock rings: wake"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert_eq!(state.timers.timer_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_for_missing_object_is_removed() {
+        let dir = TempDir::new().unwrap();
+        let state = test_state(&dir, 19102).await;
+
+        state
+            .timers
+            .set_heartbeat(HeartBeat::new("u1", "/npcs/ghost", 0))
+            .await;
+
+        tick(&state).await;
+        assert_eq!(state.timers.heartbeat_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_direct_damage_fires_damage_and_death() {
+        let dir = TempDir::new().unwrap();
+        let state = test_state(&dir, 19103).await;
+
+        let code = r#"
+            return {
+                on_damage = function(ctx)
+                    game.update_object(ctx.object_id, {
+                        hurt = ctx.amount,
+                        hurt_by = tostring(ctx.actor_id),
+                        hurt_with = ctx.damage_type,
+                    })
+                end,
+                on_death = function(ctx)
+                    game.update_object(ctx.object_id, {slain = true})
+                end
+            }
+        "#;
+        let hash = state.object_store.store_code(code).await.unwrap();
+        let mut troll = Object::new("/npcs/troll", "u1", "npc").unwrap();
+        troll.code_hash = Some(hash);
+        troll.owner_id = Some("owner".to_string());
+        state.object_store.create(&troll).await.unwrap();
+        state.combat.init_entity("/npcs/troll", 20).await;
+
+        state
+            .combat
+            .deal_damage("/npcs/troll", 5, DamageType::Fire, false)
+            .await
+            .unwrap();
+        lifecycle::damage_dealt(&state).await;
+        let troll = state
+            .object_store
+            .get("/npcs/troll")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(troll.get_i64("hurt"), Some(5));
+        assert_eq!(troll.properties["hurt_by"], "nil");
+        assert_eq!(troll.properties["hurt_with"], "fire");
+        assert!(!troll.properties.contains_key("slain"));
+
+        state
+            .combat
+            .deal_damage("/npcs/troll", 15, DamageType::Fire, false)
+            .await
+            .unwrap();
+        lifecycle::damage_dealt(&state).await;
+        let troll = state
+            .object_store
+            .get("/npcs/troll")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(troll.properties["slain"], true);
+
+        // Only the blow that kills counts as a death
+        state
+            .combat
+            .deal_damage("/npcs/troll", 15, DamageType::Fire, false)
+            .await
+            .unwrap();
+        let events = state.combat.take_damage_events().await;
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].killed);
+    }
+}
@@ -0,0 +1,414 @@
+//! Telnet byte stream handling
+//!
+//! Splits incoming bytes into data and option negotiation (RFC 854/855),
+//! assembles input lines, and frames output: IAC escaping, subnegotiation
+//! and MCCP2 compression.
+
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const GA: u8 = 249;
+pub const SE: u8 = 240;
+
+pub const OPT_ECHO: u8 = 1;
+pub const OPT_TTYPE: u8 = 24;
+pub const OPT_NAWS: u8 = 31;
+pub const OPT_MCCP2: u8 = 86;
+pub const OPT_GMCP: u8 = 201;
+
+/// TTYPE subnegotiation commands (RFC 1091)
+pub const TTYPE_IS: u8 = 0;
+pub const TTYPE_SEND: u8 = 1;
+
+/// Longest subnegotiation payload accepted before it is dropped
+const MAX_SUBNEGOTIATION: usize = 8192;
+
+/// Longest input line kept; the rest of the line is discarded
+const MAX_LINE: usize = 4096;
+
+/// Something the client sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Ordinary input bytes
+    Data(Vec<u8>),
+    /// DO/DONT/WILL/WONT for an option
+    Negotiate { command: u8, option: u8 },
+    /// IAC SB option ... IAC SE
+    Subnegotiation { option: u8, payload: Vec<u8> },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    Negotiate(u8),
+    SbOption,
+    Sb,
+    SbIac,
+}
+
+/// Incremental telnet parser; commands may be split across reads
+#[derive(Debug, Default)]
+pub struct Parser {
+    state: State,
+    sb_option: u8,
+    sb_payload: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the next chunk of input
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut data = Vec::new();
+
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, _) => {
+                    data.push(byte);
+                    State::Data
+                }
+                (State::Iac, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Iac, DO | DONT | WILL | WONT) => State::Negotiate(byte),
+                (State::Iac, SB) => State::SbOption,
+                // NOP, GA, AYT and friends carry nothing we act on
+                (State::Iac, _) => State::Data,
+                (State::Negotiate(command), option) => {
+                    flush_data(&mut data, &mut events);
+                    events.push(Event::Negotiate { command, option });
+                    State::Data
+                }
+                (State::SbOption, option) => {
+                    self.sb_option = option;
+                    self.sb_payload.clear();
+                    State::Sb
+                }
+                (State::Sb, IAC) => State::SbIac,
+                (State::Sb, _) => {
+                    if self.sb_payload.len() < MAX_SUBNEGOTIATION {
+                        self.sb_payload.push(byte);
+                    }
+                    State::Sb
+                }
+                (State::SbIac, IAC) => {
+                    if self.sb_payload.len() < MAX_SUBNEGOTIATION {
+                        self.sb_payload.push(IAC);
+                    }
+                    State::Sb
+                }
+                (State::SbIac, SE) => {
+                    flush_data(&mut data, &mut events);
+                    events.push(Event::Subnegotiation {
+                        option: self.sb_option,
+                        payload: std::mem::take(&mut self.sb_payload),
+                    });
+                    State::Data
+                }
+                // Malformed subnegotiation; drop it
+                (State::SbIac, _) => {
+                    self.sb_payload.clear();
+                    State::Data
+                }
+            };
+        }
+
+        flush_data(&mut data, &mut events);
+        events
+    }
+}
+
+fn flush_data(data: &mut Vec<u8>, events: &mut Vec<Event>) {
+    if !data.is_empty() {
+        events.push(Event::Data(std::mem::take(data)));
+    }
+}
+
+/// Collects input bytes into lines
+///
+/// Lines end at LF; CR, NUL and control characters are dropped and
+/// backspace/DEL erase the previous character.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    current: Vec<u8>,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add input and return any lines it completed
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for &byte in bytes {
+            match byte {
+                b'\n' => {
+                    let line = String::from_utf8_lossy(&self.current).trim().to_string();
+                    lines.push(line);
+                    self.current.clear();
+                }
+                0x08 | 0x7f => {
+                    self.current.pop();
+                }
+                b'\t' => self.push_byte(b' '),
+                _ if byte < 0x20 => {}
+                _ => self.push_byte(byte),
+            }
+        }
+        lines
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if self.current.len() < MAX_LINE {
+            self.current.push(byte);
+        }
+    }
+}
+
+/// Encode text for the wire: escape IAC and use CRLF line endings
+pub fn encode_text(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + 8);
+    for &byte in text.as_bytes() {
+        match byte {
+            b'\n' => out.extend_from_slice(b"\r\n"),
+            b'\r' => {}
+            IAC => out.extend_from_slice(&[IAC, IAC]),
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+/// IAC <command> <option>
+pub fn negotiate(command: u8, option: u8) -> [u8; 3] {
+    [IAC, command, option]
+}
+
+/// IAC SB <option> <payload> IAC SE, escaping IAC in the payload
+pub fn subnegotiation(option: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![IAC, SB, option];
+    for &byte in payload {
+        if byte == IAC {
+            out.push(IAC);
+        }
+        out.push(byte);
+    }
+    out.extend_from_slice(&[IAC, SE]);
+    out
+}
+
+/// A GMCP message: "Package.Name <json>"
+pub fn gmcp(package: &str, data: &serde_json::Value) -> Vec<u8> {
+    let payload = format!("{} {}", package, data);
+    subnegotiation(OPT_GMCP, payload.as_bytes())
+}
+
+/// Window size from a NAWS subnegotiation payload
+pub fn parse_naws(payload: &[u8]) -> Option<(u16, u16)> {
+    match payload {
+        [w1, w2, h1, h2] => Some((
+            u16::from_be_bytes([*w1, *w2]),
+            u16::from_be_bytes([*h1, *h2]),
+        )),
+        _ => None,
+    }
+}
+
+/// Terminal name from a TTYPE IS subnegotiation payload
+pub fn parse_ttype(payload: &[u8]) -> Option<String> {
+    match payload.split_first() {
+        Some((&TTYPE_IS, name)) if !name.is_empty() => {
+            Some(String::from_utf8_lossy(name).trim().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Split a GMCP payload into package name and JSON data
+pub fn parse_gmcp(payload: &[u8]) -> Option<(String, serde_json::Value)> {
+    let text = String::from_utf8_lossy(payload);
+    let (package, data) = match text.split_once(' ') {
+        Some((package, data)) => (package, serde_json::from_str(data.trim()).ok()?),
+        None => (text.as_ref(), serde_json::Value::Null),
+    };
+    Some((package.to_string(), data))
+}
+
+/// Output framing, compressed once MCCP2 starts
+#[derive(Default)]
+pub struct Output {
+    compressor: Option<ZlibEncoder<Vec<u8>>>,
+}
+
+impl Output {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes that switch the client to compressed input, after which
+    /// everything sent is compressed; empty if already compressing
+    pub fn start_compression(&mut self) -> Vec<u8> {
+        if self.compressor.is_some() {
+            return Vec::new();
+        }
+        self.compressor = Some(ZlibEncoder::new(Vec::new(), Compression::default()));
+        subnegotiation(OPT_MCCP2, &[])
+    }
+
+    /// Frame bytes for the wire
+    pub fn encode(&mut self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self.compressor.as_mut() {
+            Some(encoder) => {
+                encoder.write_all(bytes)?;
+                // Sync flush so the client can decode everything sent so far
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            None => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+/// Word-wrap text to a terminal width
+///
+/// Existing line breaks are kept; words longer than the width are left
+/// whole.
+pub fn wrap(text: &str, width: usize) -> String {
+    if width < 20 {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut column = 0;
+        for word in line.split(' ') {
+            let len = word.chars().count();
+            if column > 0 && column + 1 + len > width {
+                out.push('\n');
+                column = 0;
+            } else if column > 0 {
+                out.push(' ');
+                column += 1;
+            }
+            out.push_str(word);
+            column += len;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_parser_splits_data_and_commands() {
+        let mut parser = Parser::new();
+        let events = parser.feed(&[
+            b'h', b'i', IAC, WILL, OPT_NAWS, IAC, SB, OPT_NAWS, 0, 80, 0, 24, IAC, SE, b'!',
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                Event::Data(b"hi".to_vec()),
+                Event::Negotiate {
+                    command: WILL,
+                    option: OPT_NAWS
+                },
+                Event::Subnegotiation {
+                    option: OPT_NAWS,
+                    payload: vec![0, 80, 0, 24]
+                },
+                Event::Data(b"!".to_vec()),
+            ]
+        );
+        assert_eq!(parse_naws(&[0, 80, 0, 24]), Some((80, 24)));
+    }
+
+    #[test]
+    fn test_parser_handles_split_reads_and_escapes() {
+        let mut parser = Parser::new();
+        assert!(parser.feed(&[IAC]).is_empty());
+        assert!(parser.feed(&[SB, OPT_TTYPE, TTYPE_IS]).is_empty());
+        assert!(parser.feed(b"Mudlet").is_empty());
+        assert_eq!(
+            parser.feed(&[IAC, SE, IAC, IAC]),
+            vec![
+                Event::Subnegotiation {
+                    option: OPT_TTYPE,
+                    payload: b"\0Mudlet".to_vec()
+                },
+                Event::Data(vec![IAC]),
+            ]
+        );
+        assert_eq!(parse_ttype(b"\0Mudlet"), Some("Mudlet".to_string()));
+    }
+
+    #[test]
+    fn test_line_buffer() {
+        let mut lines = LineBuffer::new();
+        assert!(lines.push(b"loo").is_empty());
+        assert_eq!(lines.push(b"k\r\nsay hx\x08i\r\n"), vec!["look", "say hi"]);
+    }
+
+    #[test]
+    fn test_output_framing() {
+        assert_eq!(encode_text("a\nb"), b"a\r\nb".to_vec());
+        assert_eq!(
+            subnegotiation(OPT_GMCP, &[1, IAC]),
+            vec![IAC, SB, OPT_GMCP, 1, IAC, IAC, IAC, SE]
+        );
+        let msg = gmcp("Char.Vitals", &serde_json::json!({"hp": 5}));
+        assert_eq!(
+            parse_gmcp(&msg[3..msg.len() - 2]),
+            Some(("Char.Vitals".to_string(), serde_json::json!({"hp": 5})))
+        );
+    }
+
+    #[test]
+    fn test_mccp2_stream_decompresses() {
+        let mut output = Output::new();
+        assert_eq!(output.encode(b"plain").unwrap(), b"plain".to_vec());
+        assert_eq!(
+            output.start_compression(),
+            vec![IAC, SB, OPT_MCCP2, IAC, SE]
+        );
+
+        let mut wire = output.encode(b"Hello, ").unwrap();
+        wire.extend(output.encode(b"adventurer.").unwrap());
+        let mut decoded = Vec::new();
+        // A live stream never ends, so read what has been flushed so far
+        let _ = ZlibDecoder::new(&wire[..]).read_to_end(&mut decoded);
+        assert_eq!(decoded, b"Hello, adventurer.".to_vec());
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("the quick brown fox jumps over the lazy dog", 20),
+            "the quick brown fox\njumps over the lazy\ndog"
+        );
+        assert_eq!(wrap("short\nlines", 80), "short\nlines");
+    }
+}
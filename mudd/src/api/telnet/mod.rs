@@ -0,0 +1,544 @@
+//! Telnet gateway for classic MUD clients
+//!
+//! Players log in with their account, then play the same game as the
+//! browser client: commands go through `handle_client_message` and output
+//! arrives through the `ConnectionManager`, so telnet and WebSocket
+//! players share rooms. Typed events are rendered as text, and as GMCP
+//! for clients that enable it.
+
+mod codec;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info, warn};
+
+use self::codec::{Event, LineBuffer, Output, Parser};
+use super::auth::{password_login, password_register, LoginRefused};
+use super::events::{EVENT_KINDS, PROTOCOL_VERSION};
+use super::limits;
+use super::outbox::Outgoing;
+use super::websocket::{self, ClientMessage, Entered, ServerMessage};
+use super::AppState;
+use crate::auth::accounts::{Account, AuthError, ClientInfo, Session};
+
+/// Failed logins allowed before the connection is closed
+const MAX_LOGIN_ATTEMPTS: usize = 3;
+
+/// Accept telnet connections until `shutdown_rx` fires
+pub(super) async fn serve(
+    listener: TcpListener,
+    state: AppState,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, addr)) => {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let mut conn = Connection::new(stream);
+                        if let Err(e) = conn.run(&state, addr).await {
+                            debug!("Telnet connection from {} ended: {}", addr, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Telnet accept failed: {}", e),
+            },
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+}
+
+/// One telnet client
+struct Connection {
+    stream: TcpStream,
+    parser: Parser,
+    lines: LineBuffer,
+    pending: VecDeque<String>,
+    output: Output,
+    /// Client agreed to receive GMCP
+    gmcp: bool,
+    /// Terminal width from NAWS
+    width: Option<u16>,
+    /// Client name from TTYPE
+    terminal: Option<String>,
+    /// Prompt shown after each batch of output
+    prompt: String,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            parser: Parser::new(),
+            lines: LineBuffer::new(),
+            pending: VecDeque::new(),
+            output: Output::new(),
+            gmcp: false,
+            width: None,
+            terminal: None,
+            prompt: "> ".to_string(),
+        }
+    }
+
+    async fn run(&mut self, state: &AppState, addr: SocketAddr) -> std::io::Result<()> {
+        for (command, option) in [
+            (codec::WILL, codec::OPT_GMCP),
+            (codec::WILL, codec::OPT_MCCP2),
+            (codec::DO, codec::OPT_NAWS),
+            (codec::DO, codec::OPT_TTYPE),
+        ] {
+            self.stream
+                .write_all(&codec::negotiate(command, option))
+                .await?;
+        }
+
+        self.send_text("Welcome to HemiMUD.\n").await?;
+        let Some((login, token)) = self.login(state, addr).await? else {
+            return Ok(());
+        };
+
+        // The session only lasts as long as the connection
+        let result = self.play(state, addr, login).await;
+        if let Err(e) = state.accounts().logout(&token).await {
+            warn!("Failed to end telnet session: {}", e);
+        }
+        result
+    }
+
+    /// Enter a universe and relay commands and output until the player leaves
+    async fn play(
+        &mut self,
+        state: &AppState,
+        addr: SocketAddr,
+        login: (Account, Session),
+    ) -> std::io::Result<()> {
+        let Some(universe_id) = self.choose_universe(state).await? else {
+            return Ok(());
+        };
+
+        let ip_address = addr.ip().to_string();
+        let rate_limits = limits::universe_limits(state, &universe_id).await;
+        if let Err(limited) = state.limits.check_connect(
+            &rate_limits,
+            &universe_id,
+            Some(&login.0.id),
+            Some(&ip_address),
+        ) {
+            self.send_text(&format!("{}\n", limited.message())).await?;
+            return Ok(());
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Outgoing>();
+        let Entered {
+            player_id,
+            account_id,
+            access_level,
+        } = websocket::enter_game(state, Some(login), universe_id, Some(ip_address), tx).await;
+
+        // Ask for every typed event; render() decides how each is shown
+        let hello = ClientMessage::Hello {
+            protocol: PROTOCOL_VERSION,
+            capabilities: EVENT_KINDS.iter().map(|kind| kind.to_string()).collect(),
+        };
+        websocket::handle_client_message(state, &player_id, &account_id, access_level, hello).await;
+
+        let result: std::io::Result<()> = async {
+            let mut buf = [0u8; 1024];
+            loop {
+                while let Some(text) = self.pending.pop_front() {
+                    if text.eq_ignore_ascii_case("quit") {
+                        self.send_text("Goodbye.\n").await?;
+                        return Ok(());
+                    }
+                    if text.is_empty() {
+                        self.send_prompt().await?;
+                        continue;
+                    }
+                    let msg = ClientMessage::Command { text };
+                    websocket::handle_client_message(
+                        state,
+                        &player_id,
+                        &account_id,
+                        access_level,
+                        msg,
+                    )
+                    .await;
+                }
+
+                tokio::select! {
+                    Some(out) = rx.recv() => {
+                        // Render everything queued, then prompt once
+                        let mut next = Some(out.msg);
+                        let mut shown = false;
+                        while let Some(msg) = next {
+                            if matches!(msg, ServerMessage::Disconnect { .. }) {
+                                self.render(&msg, &player_id).await?;
+                                return Ok(());
+                            }
+                            shown |= self.render(&msg, &player_id).await?;
+                            next = rx.try_recv().ok().map(|out| out.msg);
+                        }
+                        if shown {
+                            self.send_prompt().await?;
+                        }
+                    }
+                    n = self.stream.read(&mut buf) => {
+                        let n = n?;
+                        if n == 0 {
+                            return Ok(());
+                        }
+                        self.receive(&buf[..n]).await?;
+                    }
+                }
+            }
+        }
+        .await;
+
+        // Close the channel so the session reads as disconnected
+        drop(rx);
+        websocket::leave_game(state, &player_id, &account_id).await;
+        result
+    }
+
+    /// Ask for credentials until they check out
+    ///
+    /// "new" as the username registers an account instead. Returns the
+    /// account, its new session and the session's token.
+    async fn login(
+        &mut self,
+        state: &AppState,
+        addr: SocketAddr,
+    ) -> std::io::Result<Option<((Account, Session), String)>> {
+        let service = state.accounts();
+
+        for _ in 0..MAX_LOGIN_ATTEMPTS {
+            let Some(mut username) = self.ask("Username (or 'new'): ").await? else {
+                return Ok(None);
+            };
+            let registering = username.eq_ignore_ascii_case("new");
+            if registering {
+                username = match self.ask("Choose a username: ").await? {
+                    Some(name) => name,
+                    None => return Ok(None),
+                };
+            }
+            if username.is_empty() {
+                continue;
+            }
+            let Some(password) = self.ask_secret("Password: ").await? else {
+                return Ok(None);
+            };
+
+            let client = ClientInfo {
+                user_agent: self.terminal.clone(),
+                ip_address: Some(addr.ip().to_string()),
+            };
+            let result = if registering {
+                password_register(state, &username, &password, &client).await
+            } else {
+                password_login(state, &username, &password, &client).await
+            };
+
+            match result {
+                Ok((_, token)) => {
+                    if let Ok(Some(login)) = service.validate_session(&token).await {
+                        info!("Telnet login: {} from {}", username, addr);
+                        return Ok(Some((login, token)));
+                    }
+                    let _ = service.logout(&token).await;
+                    self.send_text("Login failed.\n").await?;
+                }
+                Err(LoginRefused::Limited(limited)) => {
+                    self.send_text(&format!("{}\n", limited.message())).await?;
+                    return Ok(None);
+                }
+                Err(LoginRefused::Failed(AuthError::InvalidCredentials)) => {
+                    self.send_text("Invalid username or password.\n").await?;
+                }
+                Err(LoginRefused::Failed(AuthError::UsernameExists)) => {
+                    self.send_text("That name is taken.\n").await?;
+                }
+                Err(LoginRefused::Failed(AuthError::ReservedUsername)) => {
+                    self.send_text("That name is reserved for wallet accounts.\n")
+                        .await?;
+                }
+                Err(LoginRefused::Failed(e)) => {
+                    warn!("Telnet login error: {}", e);
+                    self.send_text("Login failed.\n").await?;
+                }
+            }
+        }
+
+        self.send_text("Too many attempts.\n").await?;
+        Ok(None)
+    }
+
+    /// Pick the universe to play in, asking if there is more than one
+    async fn choose_universe(&mut self, state: &AppState) -> std::io::Result<Option<String>> {
+        let universes = match state.object_store.list_universes().await {
+            Ok(universes) => universes,
+            Err(e) => {
+                warn!("Failed to list universes: {}", e);
+                Vec::new()
+            }
+        };
+
+        match universes.as_slice() {
+            [] => {
+                self.send_text("No universes are available.\n").await?;
+                Ok(None)
+            }
+            [(id, _)] => Ok(Some(id.clone())),
+            _ => {
+                let mut menu = String::from("Universes:\n");
+                for (i, (_, name)) in universes.iter().enumerate() {
+                    menu.push_str(&format!("  {}. {}\n", i + 1, name));
+                }
+                self.send_text(&menu).await?;
+                loop {
+                    let Some(choice) = self.ask("Choose a universe: ").await? else {
+                        return Ok(None);
+                    };
+                    let picked = choice
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|n| universes.get(n.wrapping_sub(1)))
+                        .or_else(|| universes.iter().find(|(id, _)| *id == choice));
+                    if let Some((id, _)) = picked {
+                        return Ok(Some(id.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Show a prompt and wait for the reply
+    async fn ask(&mut self, prompt: &str) -> std::io::Result<Option<String>> {
+        self.send_text(prompt).await?;
+        self.send_raw(&[codec::IAC, codec::GA]).await?;
+        self.read_line().await
+    }
+
+    /// Like `ask`, with the client's local echo turned off
+    async fn ask_secret(&mut self, prompt: &str) -> std::io::Result<Option<String>> {
+        self.send_raw(&codec::negotiate(codec::WILL, codec::OPT_ECHO))
+            .await?;
+        let reply = self.ask(prompt).await;
+        self.send_raw(&codec::negotiate(codec::WONT, codec::OPT_ECHO))
+            .await?;
+        // The client didn't echo the newline either
+        self.send_text("\n").await?;
+        reply
+    }
+
+    /// Next input line, None once the client hangs up
+    async fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut buf = [0u8; 1024];
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return Ok(Some(line));
+            }
+            let n = self.stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.receive(&buf[..n]).await?;
+        }
+    }
+
+    /// Process bytes from the client, queueing completed lines
+    async fn receive(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        for event in self.parser.feed(bytes) {
+            match event {
+                Event::Data(data) => {
+                    let lines = self.lines.push(&data);
+                    self.pending.extend(lines);
+                }
+                Event::Negotiate { command, option } => self.negotiate(command, option).await?,
+                Event::Subnegotiation { option, payload } => match option {
+                    codec::OPT_NAWS => {
+                        if let Some((width, _)) = codec::parse_naws(&payload) {
+                            self.width = Some(width);
+                        }
+                    }
+                    codec::OPT_TTYPE => {
+                        if let Some(terminal) = codec::parse_ttype(&payload) {
+                            debug!("Telnet client terminal: {}", terminal);
+                            self.terminal = Some(terminal);
+                        }
+                    }
+                    codec::OPT_GMCP => {
+                        // Core.Hello, Core.Supports.* and the like; nothing
+                        // the server acts on yet
+                        if let Some((package, _)) = codec::parse_gmcp(&payload) {
+                            debug!("Ignoring GMCP {} from client", package);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer the client's side of option negotiation
+    async fn negotiate(&mut self, command: u8, option: u8) -> std::io::Result<()> {
+        match (command, option) {
+            (codec::DO, codec::OPT_GMCP) => self.gmcp = true,
+            (codec::DONT, codec::OPT_GMCP) => self.gmcp = false,
+            (codec::DO, codec::OPT_MCCP2) => {
+                // The start marker itself goes out uncompressed; it is
+                // empty if compression already started
+                let start = self.output.start_compression();
+                self.stream.write_all(&start).await?;
+            }
+            (codec::WILL, codec::OPT_TTYPE) => {
+                let send = codec::subnegotiation(codec::OPT_TTYPE, &[codec::TTYPE_SEND]);
+                self.send_raw(&send).await?;
+            }
+            // Replies to options we offered or asked for
+            (codec::DO | codec::DONT, codec::OPT_ECHO | codec::OPT_MCCP2)
+            | (codec::WILL | codec::WONT, codec::OPT_NAWS | codec::OPT_TTYPE) => {}
+            (codec::DO, _) => {
+                self.send_raw(&codec::negotiate(codec::WONT, option))
+                    .await?
+            }
+            (codec::WILL, _) => {
+                self.send_raw(&codec::negotiate(codec::DONT, option))
+                    .await?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Show a server message; true if it printed any text
+    async fn render(&mut self, msg: &ServerMessage, player_id: &str) -> std::io::Result<bool> {
+        if self.gmcp {
+            if let Some(package) = gmcp_package(msg) {
+                let mut data = serde_json::to_value(msg).unwrap_or_default();
+                if let Some(fields) = data.as_object_mut() {
+                    fields.remove("type");
+                }
+                self.send_raw(&codec::gmcp(package, &data)).await?;
+            }
+        }
+
+        let text = match msg {
+            ServerMessage::Output { text } => Some(text.clone()),
+            ServerMessage::Error { message } => Some(message.clone()),
+            ServerMessage::RateLimited { message, .. } => Some(message.clone()),
+            ServerMessage::Disconnect { reason } => Some(reason.clone()),
+            ServerMessage::Room {
+                name,
+                description,
+                exits,
+                contents,
+                ..
+            } => Some(room_text(name, description, exits, contents)),
+            ServerMessage::Prompt { text } => {
+                self.prompt = text.clone();
+                None
+            }
+            // Telnet clients echo input themselves
+            ServerMessage::Welcome { .. } | ServerMessage::Hello { .. } => None,
+            ServerMessage::Echo { .. } => None,
+            _ => msg.plain_text(player_id),
+        };
+
+        match text {
+            Some(text) => {
+                self.send_text(&format!("{}\n", text)).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn send_prompt(&mut self) -> std::io::Result<()> {
+        let prompt = self.prompt.clone();
+        self.send_text(&prompt).await?;
+        self.send_raw(&[codec::IAC, codec::GA]).await
+    }
+
+    /// Send text, wrapped to the client's width
+    async fn send_text(&mut self, text: &str) -> std::io::Result<()> {
+        let text = match self.width {
+            Some(width) => codec::wrap(text, usize::from(width)),
+            None => text.to_string(),
+        };
+        self.send_raw(&codec::encode_text(&text)).await
+    }
+
+    async fn send_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let framed = self.output.encode(bytes)?;
+        self.stream.write_all(&framed).await
+    }
+}
+
+/// GMCP package a message is sent as
+fn gmcp_package(msg: &ServerMessage) -> Option<&'static str> {
+    match msg {
+        ServerMessage::Room { .. } => Some("Room.Info"),
+        ServerMessage::Vitals { .. } => Some("Char.Vitals"),
+        ServerMessage::Inventory { .. } => Some("Char.Items.List"),
+        ServerMessage::RoomOccupantsChanged { .. } => Some("Room.Occupants"),
+        ServerMessage::CombatRound { .. } => Some("Combat.Round"),
+        ServerMessage::EffectApplied { .. } => Some("Char.Effects.Add"),
+        ServerMessage::EffectExpired { .. } => Some("Char.Effects.Remove"),
+        ServerMessage::ChannelMessage { .. } => Some("Comm.Channel.Text"),
+        _ => None,
+    }
+}
+
+/// Text form of a room description
+fn room_text(name: &str, description: &str, exits: &[String], contents: &[String]) -> String {
+    let mut text = format!("{}\n{}\n", name, description);
+    if exits.is_empty() {
+        text.push_str("There are no obvious exits.");
+    } else {
+        text.push_str(&format!("Exits: {}", exits.join(", ")));
+    }
+    if !contents.is_empty() {
+        text.push_str(&format!("\nYou see: {}", contents.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_text() {
+        let exits = vec!["north".to_string(), "east".to_string()];
+        let contents = vec!["a rusty sword".to_string()];
+        assert_eq!(
+            room_text("Hall", "A long hall.", &exits, &contents),
+            "Hall\nA long hall.\nExits: north, east\nYou see: a rusty sword"
+        );
+        assert_eq!(
+            room_text("Cell", "Bare stone.", &[], &[]),
+            "Cell\nBare stone.\nThere are no obvious exits."
+        );
+    }
+
+    #[test]
+    fn test_gmcp_packages() {
+        let vitals = ServerMessage::Vitals {
+            hp: 10,
+            max_hp: 20,
+            in_combat: false,
+        };
+        assert_eq!(gmcp_package(&vitals), Some("Char.Vitals"));
+        let prompt = ServerMessage::Prompt {
+            text: "> ".to_string(),
+        };
+        assert_eq!(gmcp_package(&prompt), None);
+    }
+}
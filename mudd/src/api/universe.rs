@@ -14,17 +14,15 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use zip::ZipArchive;
 
-use super::AppState;
-use crate::auth::accounts::{Account, AccountService};
-use crate::lua::{GameApi, Sandbox, SandboxConfig};
+use super::{sandboxes, AppState};
+use crate::auth::accounts::{Account, Session};
+use crate::lua::{MessageQueue, SandboxConfig};
+use crate::objects::ObjectStore;
 use crate::permissions::AccessLevel;
 use crate::universe::validate_universe_id;
 
-/// Extract and validate bearer token from Authorization header
-async fn authenticate(
-    headers: &HeaderMap,
-    state: &AppState,
-) -> Result<Account, (StatusCode, Json<ErrorResponse>)> {
+/// Extract the bearer token from the Authorization header
+pub(super) fn bearer_token(headers: &HeaderMap) -> Result<&str, (StatusCode, Json<ErrorResponse>)> {
     let auth_header = headers
         .get(AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
@@ -37,7 +35,7 @@ async fn authenticate(
             )
         })?;
 
-    let token = auth_header.strip_prefix("Bearer ").ok_or_else(|| {
+    auth_header.strip_prefix("Bearer ").ok_or_else(|| {
         (
             StatusCode::UNAUTHORIZED,
             Json(ErrorResponse {
@@ -45,11 +43,29 @@ async fn authenticate(
                     .to_string(),
             }),
         )
-    })?;
+    })
+}
+
+/// Extract and validate bearer token from Authorization header
+pub(super) async fn authenticate(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<Account, (StatusCode, Json<ErrorResponse>)> {
+    authenticate_session(headers, state)
+        .await
+        .map(|(account, _)| account)
+}
+
+/// Validate the bearer token, returning its account and session
+pub(super) async fn authenticate_session(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<(Account, Session), (StatusCode, Json<ErrorResponse>)> {
+    let token = bearer_token(headers)?;
 
-    let service = AccountService::new(state.db.pool().clone());
+    let service = state.accounts();
     service
-        .validate_token(token)
+        .validate_session(token)
         .await
         .ok()
         .flatten()
@@ -64,7 +80,7 @@ async fn authenticate(
 }
 
 /// Check if account has admin access
-fn require_admin(account: &Account) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+pub(super) fn require_admin(account: &Account) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
     let level: AccessLevel = account.access_level.parse().unwrap_or_default();
     if !level.can_admin() {
         return Err((
@@ -77,6 +93,15 @@ fn require_admin(account: &Account) -> Result<(), (StatusCode, Json<ErrorRespons
     Ok(())
 }
 
+/// Authenticate the request and require admin access
+pub(super) async fn require_admin_request(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let account = authenticate(headers, state).await?;
+    require_admin(&account)
+}
+
 /// Universe creation request - JSON with libs as code strings
 #[derive(Debug, Deserialize)]
 struct UniverseCreateRequest {
@@ -111,8 +136,8 @@ struct UniverseListItem {
 
 /// Error response
 #[derive(Debug, Serialize)]
-struct ErrorResponse {
-    error: String,
+pub(super) struct ErrorResponse {
+    pub(super) error: String,
 }
 
 /// Request to run a script file
@@ -137,6 +162,7 @@ pub fn router() -> Router<AppState> {
         .route("/universe/create", post(create_universe))
         .route("/universe/upload", post(upload_universe))
         .route("/universe/{id}/run_script", post(run_script))
+        .route("/universe/{id}/online", get(list_online))
 }
 
 /// GET /universe/list
@@ -165,6 +191,36 @@ async fn list_universes(State(state): State<AppState>, headers: HeaderMap) -> im
     }
 }
 
+/// GET /universe/{id}/online
+/// Returns the players in a universe (requires authentication)
+async fn list_online(
+    State(state): State<AppState>,
+    Path(universe_id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(e) = authenticate(&headers, &state).await {
+        return e.into_response();
+    }
+
+    match state.object_store.universe_exists(&universe_id).await {
+        Ok(true) => Json(state.connections.online_players(&universe_id).await).into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("Universe not found: {}", universe_id),
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!("Failed to check universe: {}", e),
+            }),
+        )
+            .into_response(),
+    }
+}
+
 /// POST /universe/create
 /// Accepts JSON with universe config and optional Lua libraries (requires admin)
 async fn create_universe(
@@ -206,13 +262,11 @@ async fn process_universe_request(
     let mut libs_loaded: Vec<String> = lib_hashes.keys().cloned().collect();
     libs_loaded.sort(); // Deterministic ordering
 
-    // Store request libs and merge (request libs override core libs)
+    // Merge request libs (request libs override core libs); code is stored
+    // together with the universe below
+    let mut sources = Vec::new();
     for (name, source) in &request.libs {
-        let hash = state
-            .object_store
-            .store_code(source)
-            .await
-            .map_err(|e| format!("Failed to store lib {}: {}", name, e))?;
+        let hash = ObjectStore::hash_code(source);
 
         // If this is a new lib (not overriding core), add to libs_loaded
         if !lib_hashes.contains_key(name) {
@@ -220,6 +274,7 @@ async fn process_universe_request(
         }
 
         lib_hashes.insert(name.clone(), hash);
+        sources.push(source.clone());
     }
 
     // Build final config with lib hashes
@@ -232,10 +287,16 @@ async fn process_universe_request(
         });
     }
 
-    // Create universe in database
+    // Create universe and store its code in one atomic write
     state
         .object_store
-        .create_universe(&universe_id, &request.name, &request.owner_id, final_config)
+        .create_universe_with_code(
+            &universe_id,
+            &request.name,
+            &request.owner_id,
+            final_config,
+            &sources,
+        )
         .await
         .map_err(|e| format!("Failed to create universe: {}", e))?;
 
@@ -350,13 +411,11 @@ async fn create_universe_from_zip(
     let mut libs_loaded: Vec<String> = lib_hashes.keys().cloned().collect();
     libs_loaded.sort(); // Deterministic ordering
 
-    // Store ZIP Lua files and merge (ZIP libs override core libs)
+    // Merge ZIP Lua files (ZIP libs override core libs); code is stored
+    // together with the universe below
+    let mut sources = Vec::new();
     for (name, source) in lua_files {
-        let hash = state
-            .object_store
-            .store_code(&source)
-            .await
-            .map_err(|e| format!("Failed to store {}: {}", name, e))?;
+        let hash = ObjectStore::hash_code(&source);
 
         // If this is a new lib (not overriding core), add to libs_loaded
         if !lib_hashes.contains_key(&name) {
@@ -364,6 +423,7 @@ async fn create_universe_from_zip(
         }
 
         lib_hashes.insert(name, hash);
+        sources.push(source);
     }
 
     // Build final config with lib hashes
@@ -376,14 +436,15 @@ async fn create_universe_from_zip(
         });
     }
 
-    // Create universe in database
+    // Create universe and store its code in one atomic write
     state
         .object_store
-        .create_universe(
+        .create_universe_with_code(
             &universe_id,
             &universe_config.name,
             &universe_config.owner_id,
             final_config,
+            &sources,
         )
         .await
         .map_err(|e| format!("Failed to create universe: {}", e))?;
@@ -473,51 +534,52 @@ async fn run_script(
         }
     };
 
-    // Create sandbox and game API
-    let mut sandbox = match Sandbox::new(SandboxConfig::default()) {
-        Ok(s) => s,
+    // Collect the script's messages and deliver them when it finishes
+    let messages = MessageQueue::shared();
+
+    let mut game_api = state.game_api(&universe_id, messages.clone());
+
+    // Set user context if provided
+    if let Some(account_id) = request.account_id {
+        game_api.set_user_context(Some(account_id));
+    }
+
+    let libs = match sandboxes::lib_hashes(&state, &universe_id).await {
+        Ok(libs) => libs,
         Err(e) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to create sandbox: {}", e),
-                }),
+                Json(ErrorResponse { error: e }),
             )
                 .into_response();
         }
     };
-
-    let mut game_api = GameApi::new(
-        state.object_store.clone(),
-        state.classes.clone(),
-        state.actions.clone(),
-        state.messages.clone(),
-        state.permissions.clone(),
-        state.timers.clone(),
-        state.credits.clone(),
-        state.venice.clone(),
-        state.image_store.clone(),
+    let result: Result<String, String> = match sandboxes::checkout(
+        &state,
         &universe_id,
-    );
-
-    // Set user context if provided
-    if let Some(account_id) = request.account_id {
-        game_api.set_user_context(Some(account_id));
-    }
-
-    // Register game API
-    if let Err(e) = game_api.register(sandbox.lua()) {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("Failed to register game API: {}", e),
-            }),
-        )
-            .into_response();
-    }
+        &libs,
+        &game_api,
+        SandboxConfig::default(),
+    )
+    .await
+    {
+        Ok(mut sandbox) => sandbox
+            .eval_async(&script_content)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error: e }),
+            )
+                .into_response();
+        }
+    };
 
-    // Execute script
-    let result: Result<String, _> = sandbox.execute(&script_content);
+    state
+        .connections
+        .deliver(messages.drain().await, &state.object_store)
+        .await;
 
     match result {
         Ok(output) => (StatusCode::OK, Json(RunScriptResponse { result: output })).into_response(),
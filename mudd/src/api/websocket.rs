@@ -1,24 +1,33 @@
 //! WebSocket handler for real-time player connections
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::time::Instant;
 
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ConnectInfo, Query, State,
     },
     response::IntoResponse,
 };
 use mlua::Value;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, RwLock};
 use tracing::{info, warn};
 
-use super::AppState;
-use crate::auth::accounts::{Account, AccountService};
+use super::chat::{self, CHANNELS_PROPERTY, IGNORED_PROPERTY};
+use super::events::{self, Capabilities, ObjectRef, DEFAULT_PLAYER_HP, EVENT_KINDS};
+use super::outbox::{Outbox, Outgoing};
+use super::presence::{self, OnlinePlayer, FRIENDS_PROPERTY};
+use super::{billing, commands, lifecycle, limits, sandboxes, AppState};
+use crate::auth::accounts::{Account, Session};
+use crate::channels::{ChannelDef, Scope};
 use crate::combat::DamageType;
 use crate::images::generate_room_image;
-use crate::lua::{GameApi, Sandbox, SandboxConfig};
+use crate::lua::{GameMessage, MessageQueue, Sandbox, SandboxConfig};
+use crate::objects::ObjectStore;
 use crate::permissions::AccessLevel;
 use crate::theme::DEFAULT_THEME_ID;
 use crate::universe::validate_universe_id;
@@ -28,15 +37,72 @@ use crate::universe::validate_universe_id;
 pub struct PlayerSession {
     pub player_id: String,
     pub account_id: String,
+    /// Display name shown to other players
+    pub name: String,
+    /// Login session the connection was opened with (None for guests)
+    pub session_id: Option<String>,
+    /// Address the client connected from
+    pub ip_address: Option<String>,
     pub universe_id: String,
     pub room_id: Option<String>,
     pub access_level: AccessLevel,
-    pub sender: mpsc::Sender<ServerMessage>,
+    pub sender: mpsc::UnboundedSender<Outgoing>,
+    /// Typed events negotiated with `hello`
+    pub capabilities: Capabilities,
+    /// Last state event of each kind sent, to skip repeats
+    pub last_state: BTreeMap<&'static str, String>,
+    /// Messages kept for `resume`, carried over when the player reconnects
+    pub outbox: Mutex<Outbox>,
+    /// First sequence number sent on this connection (set by `register`)
+    pub resume_from: u64,
+    /// When the player entered the game (kept across reconnects)
+    pub connected_at: Instant,
+    /// When the player last sent a command
+    pub last_active: Instant,
+    /// AFK message while the player is away
+    pub afk: Option<String>,
+    /// Player ids on the player's friends list
+    pub friends: BTreeSet<String>,
+    /// Chat channels the player has joined
+    pub channels: BTreeSet<String>,
+    /// Player ids whose channel messages and tells the player doesn't see
+    pub ignored: BTreeSet<String>,
+}
+
+impl PlayerSession {
+    /// Queue a message for the client and keep it for replay
+    ///
+    /// While the client is away the send fails and the message just waits
+    /// in the outbox.
+    fn send(&self, msg: ServerMessage) {
+        let out = self.outbox.lock().push(msg);
+        let _ = self.sender.send(out);
+    }
+
+    /// Whether the client's connection is still open
+    pub fn is_connected(&self) -> bool {
+        !self.sender.is_closed()
+    }
+
+    /// How this session shows in `who`
+    pub fn online_player(&self) -> OnlinePlayer {
+        OnlinePlayer {
+            player_id: self.player_id.clone(),
+            name: self.name.clone(),
+            access_level: self.access_level.as_str().to_string(),
+            idle_secs: self.last_active.elapsed().as_secs(),
+            online_secs: self.connected_at.elapsed().as_secs(),
+            afk: self.afk.clone(),
+        }
+    }
 }
 
 /// Grace period for reconnection (prevents inventory drop on brief disconnects)
 const RECONNECT_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(5);
 
+/// Longest grace period a universe can configure
+const MAX_RECONNECT_GRACE_SECS: u64 = 3600;
+
 /// Connection manager for all active WebSocket connections
 pub struct ConnectionManager {
     sessions: RwLock<BTreeMap<String, PlayerSession>>,
@@ -89,9 +155,20 @@ impl ConnectionManager {
     }
 
     /// Register a new player session
-    pub async fn register(&self, session: PlayerSession) {
-        let player_id = session.player_id.clone();
-        self.sessions.write().await.insert(player_id, session);
+    ///
+    /// A session replacing one for the same player takes over its outbox.
+    /// Returns whether there was one to replace.
+    pub async fn register(&self, mut session: PlayerSession) -> bool {
+        let mut sessions = self.sessions.write().await;
+        let previous = sessions.remove(&session.player_id);
+        let replaced = previous.is_some();
+        if let Some(previous) = previous {
+            session.outbox = previous.outbox;
+            session.connected_at = previous.connected_at;
+        }
+        session.resume_from = session.outbox.get_mut().next_seq();
+        sessions.insert(session.player_id.clone(), session);
+        replaced
     }
 
     /// Remove a player session
@@ -99,8 +176,45 @@ impl ConnectionManager {
         self.sessions.write().await.remove(player_id);
     }
 
+    /// Close connections opened with a login session
+    ///
+    /// Returns the number of connections told to close.
+    pub async fn disconnect_session(&self, session_id: &str, reason: &str) -> usize {
+        self.disconnect_where(|s| s.session_id.as_deref() == Some(session_id), reason)
+            .await
+    }
+
+    /// Close all of an account's connections
+    pub async fn disconnect_account(&self, account_id: &str, reason: &str) -> usize {
+        self.disconnect_where(
+            |s| !s.account_id.is_empty() && s.account_id == account_id,
+            reason,
+        )
+        .await
+    }
+
+    /// Tell matching connections to close; each socket loop sends the
+    /// reason to its client and then hangs up
+    async fn disconnect_where(
+        &self,
+        matches: impl Fn(&PlayerSession) -> bool,
+        reason: &str,
+    ) -> usize {
+        let sessions = self.sessions.read().await;
+        let mut count = 0;
+        for session in sessions.values().filter(|s| matches(s)) {
+            let msg = ServerMessage::Disconnect {
+                reason: reason.to_string(),
+            };
+            if session.sender.send(Outgoing::unsequenced(msg)).is_ok() {
+                count += 1;
+            }
+        }
+        count
+    }
+
     /// Get a player's sender channel
-    pub async fn get_sender(&self, player_id: &str) -> Option<mpsc::Sender<ServerMessage>> {
+    pub async fn get_sender(&self, player_id: &str) -> Option<mpsc::UnboundedSender<Outgoing>> {
         self.sessions
             .read()
             .await
@@ -108,23 +222,193 @@ impl ConnectionManager {
             .map(|s| s.sender.clone())
     }
 
+    /// Record the typed events a player's client negotiated
+    pub async fn set_capabilities(&self, player_id: &str, capabilities: Capabilities) {
+        if let Some(session) = self.sessions.write().await.get_mut(player_id) {
+            session.capabilities = capabilities;
+            session.last_state.clear();
+        }
+    }
+
+    /// Typed events a player's client negotiated
+    pub async fn capabilities(&self, player_id: &str) -> Capabilities {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(|s| s.capabilities.clone())
+            .unwrap_or_default()
+    }
+
     /// Send a message to a specific player
+    ///
+    /// Typed events are adapted to what the player's client negotiated.
     pub async fn send_to_player(&self, player_id: &str, msg: ServerMessage) {
-        if let Some(sender) = self.get_sender(player_id).await {
-            if sender.send(msg).await.is_err() {
-                warn!("Failed to send message to player {}", player_id);
+        let sessions = self.sessions.read().await;
+        if let Some(session) = sessions.get(player_id) {
+            if let Some(msg) = session.capabilities.adapt(msg, player_id) {
+                session.send(msg);
+            }
+        }
+    }
+
+    /// Replay what a reconnecting client missed
+    ///
+    /// Sends the kept messages numbered after `last_seq` from before this
+    /// connection opened, then a `resumed` summary.
+    pub async fn resume(&self, player_id: &str, last_seq: u64) {
+        let sessions = self.sessions.read().await;
+        let Some(session) = sessions.get(player_id) else {
+            return;
+        };
+        let (messages, missed) = session.outbox.lock().replay(last_seq, session.resume_from);
+        let replayed = messages.len() as u64;
+        for out in messages {
+            let _ = session.sender.send(out);
+        }
+        session.send(ServerMessage::Resumed { replayed, missed });
+    }
+
+    /// Whether a player has a session with an open connection
+    pub async fn is_connected(&self, player_id: &str) -> bool {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .is_some_and(|s| s.is_connected())
+    }
+
+    /// Send a state event (vitals, inventory, ...) if the player asked for
+    /// it and it differs from the last one of its kind
+    pub async fn send_state(&self, player_id: &str, msg: ServerMessage) {
+        let Some(kind) = msg.event_kind() else {
+            return self.send_to_player(player_id, msg).await;
+        };
+        let Ok(json) = serde_json::to_string(&msg) else {
+            return;
+        };
+        {
+            let mut sessions = self.sessions.write().await;
+            let Some(session) = sessions.get_mut(player_id) else {
+                return;
+            };
+            if !session.capabilities.wants(kind) || session.last_state.get(kind) == Some(&json) {
+                return;
             }
+            session.last_state.insert(kind, json);
         }
+        self.send_to_player(player_id, msg).await;
     }
 
     /// Broadcast a message to all players in a room
     pub async fn broadcast_room(&self, room_id: &str, msg: ServerMessage) {
+        self.broadcast_room_except(room_id, None, msg).await;
+    }
+
+    /// Broadcast a message to all players in a room except one
+    pub async fn broadcast_room_except(
+        &self,
+        room_id: &str,
+        exclude_id: Option<&str>,
+        msg: ServerMessage,
+    ) {
         let sessions = self.sessions.read().await;
         for session in sessions.values() {
-            if session.room_id.as_deref() == Some(room_id)
-                && session.sender.send(msg.clone()).await.is_err()
+            if session.room_id.as_deref() != Some(room_id)
+                || Some(session.player_id.as_str()) == exclude_id
             {
-                warn!("Failed to broadcast to player {}", session.player_id);
+                continue;
+            }
+            if let Some(msg) = session.capabilities.adapt(msg.clone(), &session.player_id) {
+                session.send(msg);
+            }
+        }
+    }
+
+    /// Deliver messages queued by Lua code to connected players
+    ///
+    /// Region broadcasts reach every player whose room has a matching
+    /// `region_id` property.
+    pub async fn deliver(&self, messages: Vec<GameMessage>, objects: &ObjectStore) {
+        let mut room_regions: BTreeMap<String, Option<String>> = BTreeMap::new();
+
+        for message in messages {
+            match message {
+                GameMessage::Send { target_id, message } => {
+                    self.send_to_player(&target_id, ServerMessage::Output { text: message })
+                        .await;
+                }
+                GameMessage::Broadcast {
+                    room_id,
+                    message,
+                    exclude_id,
+                } => {
+                    self.broadcast_room_except(
+                        &room_id,
+                        exclude_id.as_deref(),
+                        ServerMessage::Output { text: message },
+                    )
+                    .await;
+                }
+                GameMessage::BroadcastRegion {
+                    region_id,
+                    message,
+                    exclude_id,
+                } => {
+                    // Snapshot sessions so room lookups don't hold the lock
+                    let players: Vec<(String, String)> = self
+                        .sessions
+                        .read()
+                        .await
+                        .values()
+                        .filter(|s| Some(s.player_id.as_str()) != exclude_id.as_deref())
+                        .filter_map(|s| Some((s.player_id.clone(), s.room_id.clone()?)))
+                        .collect();
+
+                    for (player_id, room_id) in players {
+                        if !room_regions.contains_key(&room_id) {
+                            let region = match objects.get(&room_id).await {
+                                Ok(Some(room)) => room.get_string("region_id").map(str::to_string),
+                                _ => None,
+                            };
+                            room_regions.insert(room_id.clone(), region);
+                        }
+                        if room_regions[&room_id].as_deref() == Some(region_id.as_str()) {
+                            self.send_to_player(
+                                &player_id,
+                                ServerMessage::Output {
+                                    text: message.clone(),
+                                },
+                            )
+                            .await;
+                        }
+                    }
+                }
+                GameMessage::Say {
+                    room_id,
+                    speaker_id,
+                    message,
+                } => {
+                    let speaker = match objects.get(&speaker_id).await {
+                        Ok(Some(obj)) => obj.get_string("name").map(str::to_string),
+                        _ => None,
+                    };
+                    let speaker = match speaker {
+                        Some(name) => name,
+                        None => self
+                            .get_name(&speaker_id)
+                            .await
+                            .unwrap_or_else(|| "Someone".to_string()),
+                    };
+                    let said = ServerMessage::ChannelMessage {
+                        channel: "say".to_string(),
+                        sender_id: speaker_id.clone(),
+                        sender: speaker,
+                        text: message,
+                    };
+                    self.broadcast_room_except(&room_id, Some(&speaker_id), said)
+                        .await;
+                }
             }
         }
     }
@@ -145,6 +429,29 @@ impl ConnectionManager {
             .and_then(|s| s.room_id.clone())
     }
 
+    /// Get a player's display name
+    pub async fn get_name(&self, player_id: &str) -> Option<String> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(|s| s.name.clone())
+    }
+
+    /// Connected players in a room
+    pub async fn players_in_room(&self, room_id: &str) -> Vec<ObjectRef> {
+        self.sessions
+            .read()
+            .await
+            .values()
+            .filter(|s| s.room_id.as_deref() == Some(room_id))
+            .map(|s| ObjectRef {
+                id: s.player_id.clone(),
+                name: s.name.clone(),
+            })
+            .collect()
+    }
+
     /// Get player's universe ID
     pub async fn get_universe_id(&self, player_id: &str) -> Option<String> {
         self.sessions
@@ -153,15 +460,190 @@ impl ConnectionManager {
             .get(player_id)
             .map(|s| s.universe_id.clone())
     }
+
+    /// Note a command from a player, clearing their AFK status
+    ///
+    /// Returns the AFK message they had, if they were away.
+    pub async fn touch(&self, player_id: &str) -> Option<String> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(player_id)?;
+        session.last_active = Instant::now();
+        session.afk.take()
+    }
+
+    /// Mark a player away, or back with None
+    pub async fn set_afk(&self, player_id: &str, message: Option<String>) {
+        if let Some(session) = self.sessions.write().await.get_mut(player_id) {
+            session.afk = message;
+        }
+    }
+
+    /// Player ids on a player's friends list
+    pub async fn friends(&self, player_id: &str) -> BTreeSet<String> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(|s| s.friends.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace a player's friends list
+    pub async fn set_friends(&self, player_id: &str, friends: BTreeSet<String>) {
+        if let Some(session) = self.sessions.write().await.get_mut(player_id) {
+            session.friends = friends;
+        }
+    }
+
+    /// Chat channels a player has joined
+    pub async fn channels(&self, player_id: &str) -> BTreeSet<String> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(|s| s.channels.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace a player's joined channels
+    pub async fn set_channels(&self, player_id: &str, channels: BTreeSet<String>) {
+        if let Some(session) = self.sessions.write().await.get_mut(player_id) {
+            session.channels = channels;
+        }
+    }
+
+    /// Player ids a player is ignoring
+    pub async fn ignored(&self, player_id: &str) -> BTreeSet<String> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(|s| s.ignored.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace a player's ignore list
+    pub async fn set_ignored(&self, player_id: &str, ignored: BTreeSet<String>) {
+        if let Some(session) = self.sessions.write().await.get_mut(player_id) {
+            session.ignored = ignored;
+        }
+    }
+
+    /// Players in a universe, by name
+    pub async fn online_players(&self, universe_id: &str) -> Vec<OnlinePlayer> {
+        let mut players: Vec<OnlinePlayer> = self
+            .sessions
+            .read()
+            .await
+            .values()
+            .filter(|s| s.universe_id == universe_id)
+            .map(PlayerSession::online_player)
+            .collect();
+        players.sort_by_key(|p| p.name.to_lowercase());
+        players
+    }
+
+    /// A player's `who` entry, if they're in the game
+    pub async fn online_player(&self, player_id: &str) -> Option<OnlinePlayer> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .map(PlayerSession::online_player)
+    }
+
+    /// Find a player in a universe by name, ignoring case
+    pub async fn find_by_name(&self, universe_id: &str, name: &str) -> Option<String> {
+        self.sessions
+            .read()
+            .await
+            .values()
+            .find(|s| s.universe_id == universe_id && s.name.eq_ignore_ascii_case(name))
+            .map(|s| s.player_id.clone())
+    }
+
+    /// Find a player in any universe by name, ignoring case
+    pub async fn find_online(&self, name: &str) -> Option<String> {
+        self.sessions
+            .read()
+            .await
+            .values()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .map(|s| s.player_id.clone())
+    }
+
+    /// Send a channel message to everyone else on the channel who may use
+    /// it and isn't ignoring the sender
+    pub async fn broadcast_channel(
+        &self,
+        def: &ChannelDef,
+        universe_id: &str,
+        sender_id: &str,
+        msg: ServerMessage,
+    ) {
+        let sessions = self.sessions.read().await;
+        for session in sessions.values() {
+            let in_scope = def.scope == Scope::Global || session.universe_id == universe_id;
+            if !in_scope
+                || session.player_id == sender_id
+                || session.access_level < def.min_level
+                || !session.channels.contains(&def.name)
+                || session.ignored.contains(sender_id)
+            {
+                continue;
+            }
+            if let Some(msg) = session.capabilities.adapt(msg.clone(), &session.player_id) {
+                session.send(msg);
+            }
+        }
+    }
+
+    /// Send a message to the players in the same universe who have
+    /// `player_id` as a friend
+    pub async fn notify_friends(&self, player_id: &str, msg: ServerMessage) {
+        let sessions = self.sessions.read().await;
+        let Some(universe_id) = sessions.get(player_id).map(|s| s.universe_id.clone()) else {
+            return;
+        };
+        for session in sessions.values() {
+            if session.universe_id != universe_id || !session.friends.contains(player_id) {
+                continue;
+            }
+            if let Some(msg) = session.capabilities.adapt(msg.clone(), &session.player_id) {
+                session.send(msg);
+            }
+        }
+    }
+
+    /// Get the address a player connected from
+    pub async fn get_ip_address(&self, player_id: &str) -> Option<String> {
+        self.sessions
+            .read()
+            .await
+            .get(player_id)
+            .and_then(|s| s.ip_address.clone())
+    }
 }
 
 /// Messages sent from server to client
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
-    /// Welcome message on connect
+    /// Welcome message on connect, offering the typed events this server
+    /// can send
     #[serde(rename = "welcome")]
-    Welcome { player_id: String, theme_id: String },
+    Welcome {
+        player_id: String,
+        theme_id: String,
+        protocol: u32,
+        capabilities: Vec<String>,
+    },
+    /// Reply to the client's hello: the protocol version and events agreed
+    #[serde(rename = "hello")]
+    Hello {
+        protocol: u32,
+        capabilities: Vec<String>,
+    },
     /// Text output to display
     #[serde(rename = "output")]
     Output { text: String },
@@ -181,6 +663,83 @@ pub enum ServerMessage {
     /// Command echo (for confirmation)
     #[serde(rename = "echo")]
     Echo { command: String },
+    /// A request refused by the rate limits ("command", "connect" or
+    /// "login"); retrying after `retry_after_secs` will succeed
+    #[serde(rename = "rate_limited")]
+    RateLimited {
+        action: String,
+        retry_after_secs: u64,
+        message: String,
+    },
+    /// Reply to `resume`: how many missed messages were replayed, and how
+    /// many had already been dropped from the outbox
+    #[serde(rename = "resumed")]
+    Resumed { replayed: u64, missed: u64 },
+    /// The server is closing this connection (e.g. the session was revoked)
+    #[serde(rename = "disconnect")]
+    Disconnect { reason: String },
+
+    // Typed events, sent only to clients that asked for them (see events.rs)
+    /// Player health
+    #[serde(rename = "vitals")]
+    Vitals {
+        hp: i32,
+        max_hp: i32,
+        in_combat: bool,
+    },
+    /// Everything the player is carrying
+    #[serde(rename = "inventory")]
+    Inventory { items: Vec<ObjectRef> },
+    /// Players and NPCs now in the player's room
+    #[serde(rename = "room_occupants_changed")]
+    RoomOccupantsChanged {
+        room_id: String,
+        occupants: Vec<ObjectRef>,
+    },
+    /// One attack in a fight
+    #[serde(rename = "combat_round")]
+    CombatRound {
+        attacker_id: String,
+        attacker: String,
+        target_id: String,
+        target: String,
+        hit: bool,
+        critical: bool,
+        fumble: bool,
+        damage: i32,
+        target_hp: i32,
+        target_max_hp: i32,
+        killed: bool,
+    },
+    /// A status effect took hold of an entity
+    #[serde(rename = "effect_applied")]
+    EffectApplied {
+        entity_id: String,
+        effect: String,
+        magnitude: i32,
+        remaining_ticks: u32,
+    },
+    /// A status effect wore off an entity
+    #[serde(rename = "effect_expired")]
+    EffectExpired { entity_id: String, effect: String },
+    /// Something said on a channel ("say" is the current room)
+    #[serde(rename = "channel_message")]
+    ChannelMessage {
+        channel: String,
+        sender_id: String,
+        sender: String,
+        text: String,
+    },
+    /// Prompt to show before the player's next command
+    #[serde(rename = "prompt")]
+    Prompt { text: String },
+    /// A friend logged in ("online") or out ("offline")
+    #[serde(rename = "presence_changed")]
+    PresenceChanged {
+        player_id: String,
+        name: String,
+        status: String,
+    },
 }
 
 /// Messages sent from client to server
@@ -193,6 +752,16 @@ pub enum ClientMessage {
     /// Ping to keep connection alive
     #[serde(rename = "ping")]
     Ping,
+    /// Opt in to typed events
+    #[serde(rename = "hello")]
+    Hello {
+        protocol: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// After reconnecting, ask for messages sent after `last_seq`
+    #[serde(rename = "resume")]
+    Resume { last_seq: u64 },
 }
 
 /// WebSocket query parameters
@@ -207,6 +776,7 @@ pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Query(params): Query<WsParams>,
     State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
     // Validate universe ID (required)
     let universe_id = match &params.universe {
@@ -249,33 +819,117 @@ pub async fn ws_handler(
         }
     }
 
-    // Validate token if provided
-    let account = if let Some(token) = params.token {
-        let service = AccountService::new(state.db.pool().clone());
-        service.validate_token(&token).await.ok().flatten()
-    } else {
-        None
+    // Validate token if provided; a bad token is refused rather than
+    // silently connecting as a guest
+    let login = match params.token {
+        Some(token) => {
+            let service = state.accounts();
+            match service.validate_session(&token).await.ok().flatten() {
+                Some(login) => Some(login),
+                None => {
+                    return (
+                        axum::http::StatusCode::UNAUTHORIZED,
+                        "Invalid or expired token",
+                    )
+                        .into_response();
+                }
+            }
+        }
+        None => None,
     };
 
-    ws.on_upgrade(move |socket| handle_socket(socket, state, account, universe_id))
+    let ip_address = addr.ip().to_string();
+    let account_id = login.as_ref().map(|(account, _)| account.id.as_str());
+    let rate_limits = limits::universe_limits(&state, &universe_id).await;
+    if let Err(limited) =
+        state
+            .limits
+            .check_connect(&rate_limits, &universe_id, account_id, Some(&ip_address))
+    {
+        return limited.into_response();
+    }
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, login, universe_id, ip_address))
 }
 
 /// Handle an individual WebSocket connection
 async fn handle_socket(
     mut socket: WebSocket,
     state: AppState,
-    account: Option<Account>,
+    login: Option<(Account, Session)>,
     universe_id: String,
+    ip_address: String,
 ) {
     // Create message channel for this connection
-    let (tx, mut rx) = mpsc::channel::<ServerMessage>(32);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Outgoing>();
+
+    let Entered {
+        player_id,
+        account_id,
+        access_level,
+    } = enter_game(&state, login, universe_id, Some(ip_address), tx).await;
+
+    // Main loop: handle incoming messages and outgoing messages
+    loop {
+        tokio::select! {
+            // Handle outgoing messages from our channel
+            Some(out) = rx.recv() => {
+                let closing = matches!(out.msg, ServerMessage::Disconnect { .. });
+                if let Ok(json) = out.to_json() {
+                    if socket.send(Message::Text(json.into())).await.is_err() {
+                        break;
+                    }
+                }
+                if closing {
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+            }
+            // Handle incoming messages from WebSocket
+            result = socket.recv() => {
+                match result {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
+                            handle_client_message(&state, &player_id, &account_id, access_level, client_msg).await;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Close the channel so the session reads as disconnected
+    drop(rx);
+    leave_game(&state, &player_id, &account_id).await;
+}
 
-    let (account_id, username, access_level) = match &account {
-        Some(acc) => {
+/// A player who has entered a universe
+pub(super) struct Entered {
+    pub player_id: String,
+    pub account_id: String,
+    pub access_level: AccessLevel,
+}
+
+/// Put a newly connected player into the game
+///
+/// Registers the connection with `sender` and queues the welcome and the
+/// spawn room on it. Every transport (WebSocket, telnet) goes through here,
+/// so their players share the same rooms.
+pub(super) async fn enter_game(
+    state: &AppState,
+    login: Option<(Account, Session)>,
+    universe_id: String,
+    ip_address: Option<String>,
+    sender: mpsc::UnboundedSender<Outgoing>,
+) -> Entered {
+    let (account_id, username, access_level, session_id) = match login {
+        Some((acc, session)) => {
             let access = acc.access_level.parse().unwrap_or(AccessLevel::Player);
-            (acc.id.clone(), Some(acc.username.clone()), access)
+            (acc.id, Some(acc.username), access, Some(session.id))
         }
-        None => (String::new(), None, AccessLevel::Player),
+        None => (String::new(), None, AccessLevel::Player, None),
     };
 
     // Generate player ID:
@@ -286,7 +940,6 @@ async fn handle_socket(
     } else {
         uuid::Uuid::new_v4().to_string()
     };
-    let player_id_clone = player_id.clone();
 
     // Cancel any pending disconnect for this player (reconnection within grace period)
     if !account_id.is_empty() {
@@ -300,6 +953,10 @@ async fn handle_socket(
     }
 
     // For authenticated users, ensure persistent player object exists
+    let mut friends = BTreeSet::new();
+    let mut ignored = BTreeSet::new();
+    let mut channels: Option<BTreeSet<String>> = None;
+    let mut guild = None;
     let spawn_room_id = if !account_id.is_empty() {
         let name = username.as_deref().unwrap_or("Unknown");
         match state
@@ -309,9 +966,19 @@ async fn handle_socket(
         {
             Ok(player) => {
                 info!(
-                    "WebSocket connected: {} ({}) universe={} access={:?}",
+                    "Player connected: {} ({}) universe={} access={:?}",
                     player_id, name, universe_id, access_level
                 );
+                if let Some(list) = player.properties.get(FRIENDS_PROPERTY) {
+                    friends = serde_json::from_value(list.clone()).unwrap_or_default();
+                }
+                if let Some(list) = player.properties.get(IGNORED_PROPERTY) {
+                    ignored = serde_json::from_value(list.clone()).unwrap_or_default();
+                }
+                if let Some(list) = player.properties.get(CHANNELS_PROPERTY) {
+                    channels = serde_json::from_value(list.clone()).ok();
+                }
+                guild = player.get_string("guild").map(str::to_string);
                 // Get spawn location from player manager
                 state
                     .player_manager
@@ -327,7 +994,7 @@ async fn handle_socket(
         }
     } else {
         info!(
-            "WebSocket connected: {} (guest) universe={}",
+            "Player connected: {} (guest) universe={}",
             player_id, universe_id
         );
         // Guests spawn at portal
@@ -339,17 +1006,35 @@ async fn handle_socket(
             .flatten()
     };
 
+    let channels = match channels {
+        Some(channels) => channels,
+        None => chat::default_channels(state, &universe_id, access_level, guild.as_deref()).await,
+    };
+
     // Create session
     let session = PlayerSession {
         player_id: player_id.clone(),
         account_id: account_id.clone(),
+        name: username.unwrap_or_else(|| "A guest".to_string()),
+        session_id,
+        ip_address,
         universe_id: universe_id.clone(),
         room_id: spawn_room_id.clone(),
         access_level,
-        sender: tx,
+        sender,
+        capabilities: Capabilities::default(),
+        last_state: BTreeMap::new(),
+        outbox: Mutex::default(),
+        resume_from: 0,
+        connected_at: Instant::now(),
+        last_active: Instant::now(),
+        afk: None,
+        friends,
+        channels,
+        ignored,
     };
 
-    state.connections.register(session).await;
+    let returning = state.connections.register(session).await;
 
     // Send welcome message with theme
     // TODO: Get theme_id from universe config
@@ -357,10 +1042,10 @@ async fn handle_socket(
     let welcome = ServerMessage::Welcome {
         player_id: player_id.clone(),
         theme_id,
+        protocol: events::PROTOCOL_VERSION,
+        capabilities: EVENT_KINDS.iter().map(|kind| kind.to_string()).collect(),
     };
-    if let Ok(json) = serde_json::to_string(&welcome) {
-        let _ = socket.send(Message::Text(json.into())).await;
-    }
+    state.connections.send_to_player(&player_id, welcome).await;
 
     // Move player to spawn room and send room description
     if let Some(room_id) = spawn_room_id {
@@ -377,85 +1062,132 @@ async fn handle_socket(
         } else {
             Some(account_id.as_str())
         };
-        if let Some(room_msg) = build_room_message(&state, &room_id, acct_ref).await {
-            if let Ok(json) = serde_json::to_string(&room_msg) {
-                let _ = socket.send(Message::Text(json.into())).await;
-            }
+        if let Some(room_msg) = build_room_message(state, &room_id, acct_ref).await {
+            state.connections.send_to_player(&player_id, room_msg).await;
+        }
+        events::occupants_changed(state, &room_id).await;
+
+        // A reconnecting player never left, so the room needn't greet them
+        if !returning {
+            lifecycle::moved(state, &player_id, None, Some(&room_id)).await;
         }
     } else {
         // No spawn location - player stays nowhere
         let msg = ServerMessage::Output {
             text: "Universe not initialized. Wizards: use 'setportal' command.".to_string(),
         };
-        if let Ok(json) = serde_json::to_string(&msg) {
-            let _ = socket.send(Message::Text(json.into())).await;
-        }
+        state.connections.send_to_player(&player_id, msg).await;
     }
 
-    // Main loop: handle incoming messages and outgoing messages
-    loop {
-        tokio::select! {
-            // Handle outgoing messages from our channel
-            Some(msg) = rx.recv() => {
-                if let Ok(json) = serde_json::to_string(&msg) {
-                    if socket.send(Message::Text(json.into())).await.is_err() {
-                        break;
-                    }
-                }
-            }
-            // Handle incoming messages from WebSocket
-            result = socket.recv() => {
-                match result {
-                    Some(Ok(Message::Text(text))) => {
-                        if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
-                            handle_client_message(&state, &player_id, &account_id, access_level, client_msg).await;
-                        }
-                    }
-                    Some(Ok(Message::Close(_))) | None => break,
-                    _ => {}
-                }
-            }
-        }
+    // Reconnecting players never left as far as their friends know
+    if !account_id.is_empty() && !returning {
+        presence::logged_in(state, &player_id).await;
     }
 
-    // Unregister session immediately (stops message delivery)
-    state.connections.unregister(&player_id_clone).await;
-    info!("WebSocket disconnected: {}", player_id_clone);
+    Entered {
+        player_id,
+        account_id,
+        access_level,
+    }
+}
 
-    // For authenticated users, use grace period before handling disconnect
-    // This allows reconnection without dropping inventory
-    if !account_id.is_empty() {
-        let cancel_rx = state
-            .connections
-            .schedule_disconnect(player_id_clone.clone())
-            .await;
+/// Take a player out of the game once their connection has closed
+///
+/// Authenticated players keep their session, still collecting messages in
+/// its outbox, for their universe's reconnect grace period. Reconnecting
+/// within it picks up where they left off.
+pub(super) async fn leave_game(state: &AppState, player_id: &str, account_id: &str) {
+    // A newer connection has already taken this player over
+    if state.connections.is_connected(player_id).await {
+        info!("Player {} connection replaced", player_id);
+        return;
+    }
+    info!("Player disconnected: {}", player_id);
 
-        // Clone what we need for the spawned task
-        let state_clone = state.clone();
-        let player_id_for_task = player_id_clone.clone();
-
-        tokio::spawn(async move {
-            // Wait for grace period or cancellation
-            tokio::select! {
-                _ = tokio::time::sleep(RECONNECT_GRACE_PERIOD) => {
-                    // Grace period expired - execute disconnect handling
-                    state_clone.connections.clear_pending_disconnect(&player_id_for_task).await;
-                    if let Err(e) = state_clone.player_manager.handle_disconnect(&player_id_for_task).await {
-                        warn!("Error handling player disconnect: {}", e);
-                    }
-                    info!("Disconnect handling completed for {} (grace period expired)", player_id_for_task);
+    // Guests can't reconnect, so they leave straight away
+    if account_id.is_empty() {
+        remove_player(state, player_id).await;
+        return;
+    }
+
+    let grace_period = match state.connections.get_universe_id(player_id).await {
+        Some(universe_id) => reconnect_grace_period(state, &universe_id).await,
+        None => RECONNECT_GRACE_PERIOD,
+    };
+    let cancel_rx = state
+        .connections
+        .schedule_disconnect(player_id.to_string())
+        .await;
+
+    // Clone what we need for the spawned task
+    let state_clone = state.clone();
+    let player_id_for_task = player_id.to_string();
+
+    tokio::spawn(async move {
+        // Wait for grace period or cancellation
+        tokio::select! {
+            _ = tokio::time::sleep(grace_period) => {
+                // Grace period expired - execute disconnect handling
+                state_clone.connections.clear_pending_disconnect(&player_id_for_task).await;
+                if state_clone.connections.is_connected(&player_id_for_task).await {
+                    return;
                 }
-                _ = cancel_rx => {
-                    // Reconnection cancelled the disconnect - do nothing
-                    info!("Disconnect handling cancelled for {} (reconnected)", player_id_for_task);
+                presence::logged_out(&state_clone, &player_id_for_task).await;
+                remove_player(&state_clone, &player_id_for_task).await;
+                if let Err(e) = state_clone.player_manager.handle_disconnect(&player_id_for_task).await {
+                    warn!("Error handling player disconnect: {}", e);
                 }
+                info!("Disconnect handling completed for {} (grace period expired)", player_id_for_task);
+            }
+            _ = cancel_rx => {
+                // Reconnection cancelled the disconnect - do nothing
+                info!("Disconnect handling cancelled for {} (reconnected)", player_id_for_task);
             }
-        });
+        }
+    });
+}
+
+/// Drop a player's session and tell their room they've gone
+async fn remove_player(state: &AppState, player_id: &str) {
+    let last_room_id = state.connections.get_room_id(player_id).await;
+    state.connections.unregister(player_id).await;
+    state.actions.clear_by_object(player_id).await;
+    if let Some(room_id) = last_room_id {
+        events::occupants_changed(state, &room_id).await;
+    }
+}
+
+/// How long a universe holds a disconnected player's session
+///
+/// Set with `reconnect_grace_secs` in the universe config.
+async fn reconnect_grace_period(state: &AppState, universe_id: &str) -> std::time::Duration {
+    let configured = state
+        .object_store
+        .get_universe(universe_id)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|universe| universe.config.get("reconnect_grace_secs")?.as_u64());
+    match configured {
+        Some(secs) => std::time::Duration::from_secs(secs.min(MAX_RECONNECT_GRACE_SECS)),
+        None => RECONNECT_GRACE_PERIOD,
     }
 }
 
+/// Count a command against the player's universe limits
+async fn check_command_limit(state: &AppState, player_id: &str) -> Result<(), limits::RateLimited> {
+    let Some(universe_id) = state.connections.get_universe_id(player_id).await else {
+        return Ok(());
+    };
+    let ip_address = state.connections.get_ip_address(player_id).await;
+    let rate_limits = limits::universe_limits(state, &universe_id).await;
+    state
+        .limits
+        .check_command(&rate_limits, &universe_id, player_id, ip_address.as_deref())
+}
+
 /// Handle a message from the client
-async fn handle_client_message(
+pub(super) async fn handle_client_message(
     state: &AppState,
     player_id: &str,
     account_id: &str,
@@ -464,7 +1196,15 @@ async fn handle_client_message(
 ) {
     match msg {
         ClientMessage::Command { text } => {
+            if let Err(limited) = check_command_limit(state, player_id).await {
+                state
+                    .connections
+                    .send_to_player(player_id, limited.into())
+                    .await;
+                return;
+            }
             info!("Player {} command: {}", player_id, text);
+            presence::activity(state, player_id, &text).await;
 
             // Echo the command back
             state
@@ -477,19 +1217,44 @@ async fn handle_client_message(
                 )
                 .await;
 
-            // Parse and execute the command
-            let response = execute_command(state, player_id, account_id, access_level, &text).await;
-            state.connections.send_to_player(player_id, response).await;
+            // Dispatch to actions, mudlib commands or built-ins
+            if let Some(response) =
+                commands::dispatch(state, player_id, account_id, access_level, &text).await
+            {
+                state.connections.send_to_player(player_id, response).await;
+            }
+            events::refresh(state, player_id).await;
         }
         ClientMessage::Ping => {
             // Just keep the connection alive, no response needed
         }
+        ClientMessage::Hello {
+            protocol,
+            capabilities,
+        } => {
+            let caps = Capabilities::negotiate(protocol, &capabilities);
+            let reply = ServerMessage::Hello {
+                protocol: caps.protocol(),
+                capabilities: caps.events(),
+            };
+            state.connections.set_capabilities(player_id, caps).await;
+            state.connections.send_to_player(player_id, reply).await;
+
+            // Start the client off with the current state
+            events::refresh(state, player_id).await;
+            if let Some(room_id) = state.connections.get_room_id(player_id).await {
+                events::occupants_changed(state, &room_id).await;
+            }
+        }
+        ClientMessage::Resume { last_seq } => {
+            state.connections.resume(player_id, last_seq).await;
+        }
     }
 }
 
 /// Build a Room message from a room object
 /// Also triggers background image generation if room has no image and Venice is configured
-async fn build_room_message(
+pub(super) async fn build_room_message(
     state: &AppState,
     room_id: &str,
     account_id: Option<&str>,
@@ -595,7 +1360,7 @@ async fn build_room_message(
 }
 
 /// Parse and execute a player command
-async fn execute_command(
+pub(super) async fn execute_command(
     state: &AppState,
     player_id: &str,
     account_id: &str,
@@ -666,10 +1431,7 @@ async fn execute_command(
             };
 
             // Update player's room in memory
-            state
-                .connections
-                .update_room(player_id, Some(dest_room_id.clone()))
-                .await;
+            events::change_room(state, player_id, Some(dest_room_id.clone())).await;
 
             // Sync to database for authenticated users
             if !account_id.is_empty() {
@@ -677,6 +1439,7 @@ async fn execute_command(
                     warn!("Failed to sync player location to DB: {}", e);
                 }
             }
+            lifecycle::moved(state, player_id, Some(&current_room_id), Some(&dest_room_id)).await;
 
             // Return new room description
             let acct_ref = if account_id.is_empty() {
@@ -694,57 +1457,48 @@ async fn execute_command(
         }
         "say" => {
             let message = parts[1..].join(" ");
+            if let Some(room_id) = state.connections.get_room_id(player_id).await {
+                let sender = state
+                    .connections
+                    .get_name(player_id)
+                    .await
+                    .unwrap_or_default();
+                let said = ServerMessage::ChannelMessage {
+                    channel: "say".to_string(),
+                    sender_id: player_id.to_string(),
+                    sender,
+                    text: message.clone(),
+                };
+                state
+                    .connections
+                    .broadcast_room_except(&room_id, Some(player_id), said)
+                    .await;
+            }
             ServerMessage::Output {
                 text: format!("You say: {}", message),
             }
         }
         "help" => ServerMessage::Output {
-            text: "Commands: look, north/south/east/west, say <message>, get/take <item>, drop <item>, inventory/i, attack <target>, eval <lua>, goto <room_id>, setportal [room_id], help"
+            text: "Commands: look, north/south/east/west, say <message>, get/take <item>, drop <item>, inventory/i, attack <target>, who, finger <player>, afk [message], friend [add|remove <player>], channels, channel join|leave|history <channel>, <channel> <message>, tell <player> <message>, ignore [player], unignore <player>, eval <lua>, goto <room_id>, setportal [room_id], help"
                 .to_string(),
         },
-        "get" | "take" => {
-            // Get item name from args
-            let item_name = if parts.len() > 1 {
-                parts[1..].join(" ")
-            } else {
-                return ServerMessage::Error {
-                    message: "Take what?".to_string(),
-                };
-            };
-
-            // Execute Commands.take() via Lua
-            let code = format!(
-                r#"local r = Commands.take("{}", "{}"); return r.message"#,
-                player_id.replace('"', r#"\""#),
-                item_name.replace('"', r#"\""#)
-            );
-            execute_lua(state, player_id, account_id, &code).await
-        }
-        "drop" => {
-            // Get item name from args
-            let item_name = if parts.len() > 1 {
-                parts[1..].join(" ")
-            } else {
-                return ServerMessage::Error {
-                    message: "Drop what?".to_string(),
-                };
-            };
-
-            // Execute Commands.drop() via Lua
-            let code = format!(
-                r#"local r = Commands.drop("{}", "{}"); return r.message"#,
-                player_id.replace('"', r#"\""#),
-                item_name.replace('"', r#"\""#)
-            );
-            execute_lua(state, player_id, account_id, &code).await
-        }
-        "inventory" | "inv" | "i" => {
-            // Execute Commands.inventory() via Lua
-            let code = format!(
-                r#"local r = Commands.inventory("{}"); return r.message"#,
-                player_id.replace('"', r#"\""#)
-            );
-            execute_lua(state, player_id, account_id, &code).await
+        // Players get these from the mudlib; guests have nothing to carry
+        "get" | "take" | "drop" if account_id.is_empty() => ServerMessage::Output {
+            text: "Guests can't carry things. Log in to pick them up.".to_string(),
+        },
+        "inventory" | "inv" | "i" if account_id.is_empty() => ServerMessage::Output {
+            text: "You are carrying nothing.".to_string(),
+        },
+        "who" => presence::who(state, player_id).await,
+        "finger" => presence::finger(state, player_id, &parts[1..].join(" ")).await,
+        "afk" => presence::afk(state, player_id, &parts[1..].join(" ")).await,
+        "friend" | "friends" => presence::friend(state, player_id, account_id, &parts[1..]).await,
+        "channels" => chat::list_channels(state, player_id, access_level).await,
+        "channel" => chat::channel(state, player_id, account_id, access_level, &parts[1..]).await,
+        "tell" => chat::tell(state, player_id, account_id, &parts[1..]).await,
+        "ignore" => chat::ignore(state, player_id, account_id, &parts[1..].join(" "), true).await,
+        "unignore" => {
+            chat::ignore(state, player_id, account_id, &parts[1..].join(" "), false).await
         }
         "goto" => {
             // Wizard+ only
@@ -767,9 +1521,9 @@ async fn execute_command(
             match state.object_store.get(&room_id).await {
                 Ok(Some(room)) if room.class == "room" => {
                     // Update player's room
-                    state
-                        .connections
-                        .update_room(player_id, Some(room_id.clone()))
+                    let from_room_id = state.connections.get_room_id(player_id).await;
+                    events::change_room(state, player_id, Some(room_id.clone())).await;
+                    lifecycle::moved(state, player_id, from_room_id.as_deref(), Some(&room_id))
                         .await;
 
                     // Return room description
@@ -933,7 +1687,11 @@ async fn execute_command(
                 .to_string();
 
             // Check if target is attackable (NPC or PvP-flagged player)
-            let is_npc = target.class == "npc" || target.class == "monster";
+            let is_npc = {
+                let classes = state.classes.read().await;
+                classes.is_a(&target.universe_id, &target.class, "npc")
+                    || target.class == "monster"
+            };
             if !is_npc {
                 return ServerMessage::Error {
                     message: format!("You can't attack {}.", target_display_name),
@@ -974,7 +1732,7 @@ async fn execute_command(
                     .init_entity_with_universe(
                         player_id,
                         state.connections.get_universe_id(player_id).await.as_deref(),
-                        100, // Default player HP
+                        DEFAULT_PLAYER_HP,
                     )
                     .await;
             }
@@ -1002,9 +1760,14 @@ async fn execute_command(
 
             // Build result message
             let mut messages = Vec::new();
+            let damage = attack_result
+                .damage
+                .as_ref()
+                .map(|d| d.final_damage)
+                .unwrap_or(0);
+            let mut killed = false;
 
             if attack_result.hit {
-                let damage = attack_result.damage.as_ref().map(|d| d.final_damage).unwrap_or(0);
                 if attack_result.critical {
                     messages.push(format!(
                         "CRITICAL HIT! You strike {} for {} damage!",
@@ -1017,9 +1780,12 @@ async fn execute_command(
                     ));
                 }
 
+                lifecycle::damage_dealt(state).await;
+
                 // Check if target is dead
                 if state.combat.is_dead(&target_id).await {
                     messages.push(format!("{} is slain!", target_display_name));
+                    killed = true;
 
                     // End combat and remove target from room
                     state.combat.end_combat(player_id).await;
@@ -1028,7 +1794,9 @@ async fn execute_command(
                     // Remove NPC from room (set parent_id to None)
                     if let Ok(Some(mut dead_target)) = state.object_store.get(&target_id).await {
                         dead_target.parent_id = None;
-                        let _ = state.object_store.update(&dead_target).await;
+                        if state.object_store.update(&dead_target).await.is_ok() {
+                            lifecycle::moved(state, &target_id, Some(&room_id), None).await;
+                        }
                     }
                 } else {
                     // Show remaining HP
@@ -1051,16 +1819,41 @@ async fn execute_command(
                 ));
             }
 
-            // Broadcast combat message to room
-            let combat_msg = ServerMessage::Output {
-                text: messages.join("\n"),
+            // Tell the room; onlookers without typed events get it as text
+            let target_hp = if killed {
+                0
+            } else {
+                state
+                    .combat
+                    .get_state(&target_id)
+                    .await
+                    .map_or(target_hp, |s| s.hp)
             };
-            state
-                .connections
-                .broadcast_room(&room_id, combat_msg.clone())
-                .await;
+            let round = ServerMessage::CombatRound {
+                attacker_id: player_id.to_string(),
+                attacker: state
+                    .connections
+                    .get_name(player_id)
+                    .await
+                    .unwrap_or_default(),
+                target_id: target_id.clone(),
+                target: target_display_name,
+                hit: attack_result.hit,
+                critical: attack_result.critical,
+                fumble: attack_result.fumble,
+                damage: if attack_result.hit { damage } else { 0 },
+                target_hp,
+                target_max_hp,
+                killed,
+            };
+            state.connections.broadcast_room(&room_id, round).await;
+            if killed {
+                events::occupants_changed(state, &room_id).await;
+            }
 
-            combat_msg
+            ServerMessage::Output {
+                text: messages.join("\n"),
+            }
         }
         "create" => {
             // Builder+ only
@@ -1082,9 +1875,15 @@ async fn execute_command(
             // Execute create command
             execute_create_command(state, player_id, account_id, access_level, args).await
         }
-        _ => ServerMessage::Output {
-            text: format!("Unknown command: {}", verb),
-        },
+        _ => {
+            let message = parts[1..].join(" ");
+            match chat::speak(state, player_id, account_id, access_level, &verb, &message).await {
+                Some(response) => response,
+                None => ServerMessage::Output {
+                    text: format!("Unknown command: {}", verb),
+                },
+            }
+        }
     }
 }
 
@@ -1105,9 +1904,8 @@ async fn execute_lua(
         }
     };
 
-    // Pre-load universe libraries (before creating sandbox)
-    let lib_codes = match load_universe_lib_codes(state, &universe_id).await {
-        Ok(codes) => codes,
+    let libs = match sandboxes::lib_hashes(state, &universe_id).await {
+        Ok(libs) => libs,
         Err(e) => {
             return ServerMessage::Error {
                 message: format!("Failed to load universe libs: {}", e),
@@ -1115,56 +1913,46 @@ async fn execute_lua(
         }
     };
 
+    // Collect this execution's messages and deliver them when it finishes
+    let messages = MessageQueue::shared();
+
     // Create game API with all managers
-    let mut game_api = GameApi::new(
-        state.object_store.clone(),
-        state.classes.clone(),
-        state.actions.clone(),
-        state.messages.clone(),
-        state.permissions.clone(),
-        state.timers.clone(),
-        state.credits.clone(),
-        state.venice.clone(),
-        state.image_store.clone(),
-        &universe_id,
-    );
+    let mut game_api = state.game_api(&universe_id, messages.clone());
     game_api.set_user_context(Some(account_id.to_string()));
+    game_api.set_player_input(true);
+    game_api.set_room_context(state.connections.get_room_id(player_id).await);
+
+    let config = eval_sandbox_config();
+    if let Err(message) =
+        billing::open(state, &mut game_api, &universe_id, account_id, &config).await
+    {
+        return ServerMessage::Error { message };
+    }
+
+    let response = match sandboxes::checkout(state, &universe_id, &libs, &game_api, config).await {
+        Ok(mut sandbox) => run_sandbox(&mut sandbox, code).await,
+        Err(message) => ServerMessage::Error { message },
+    };
+    billing::settle(state, &game_api, &universe_id, account_id, "eval").await;
+    state
+        .connections
+        .deliver(messages.drain().await, &state.object_store)
+        .await;
+    response
+}
 
-    // Create sandbox with generous limits for wizards
-    let config = SandboxConfig {
+/// Sandbox limits for eval, generous since only wizards can use it
+fn eval_sandbox_config() -> SandboxConfig {
+    SandboxConfig {
         max_instructions: 10_000_000, // 10M instructions
         timeout: std::time::Duration::from_secs(5),
         ..Default::default()
-    };
-
-    let mut sandbox = match Sandbox::new(config) {
-        Ok(s) => s,
-        Err(e) => {
-            return ServerMessage::Error {
-                message: format!("Failed to create sandbox: {}", e),
-            };
-        }
-    };
-
-    // Register game API
-    if let Err(e) = game_api.register(sandbox.lua()) {
-        return ServerMessage::Error {
-            message: format!("Failed to register game API: {}", e),
-        };
-    }
-
-    // Execute pre-loaded universe libraries
-    for (lib_name, lib_code) in &lib_codes {
-        let result: Result<(), _> = sandbox.execute(lib_code);
-        if let Err(e) = result {
-            return ServerMessage::Error {
-                message: format!("Failed to execute library {}: {}", lib_name, e),
-            };
-        }
     }
+}
 
-    // Execute the code
-    let result: Result<Value, _> = sandbox.execute(code);
+/// Run the given code in a sandbox with the universe libraries loaded
+async fn run_sandbox(sandbox: &mut Sandbox, code: &str) -> ServerMessage {
+    let result: Result<Value, _> = sandbox.eval_async(code).await;
 
     match result {
         Ok(value) => {
@@ -1178,7 +1966,7 @@ async fn execute_lua(
 }
 
 /// Convert Lua value to displayable string
-fn lua_value_to_string(value: &Value) -> String {
+pub(super) fn lua_value_to_string(value: &Value) -> String {
     match value {
         Value::Nil => "nil".to_string(),
         Value::Boolean(b) => b.to_string(),
@@ -1291,6 +2079,7 @@ async fn execute_create_command(
             message: format!("Failed to create object: {}", e),
         };
     }
+    lifecycle::fire(state, &obj.id, "on_create", serde_json::json!({})).await;
 
     ServerMessage::Output {
         text: format!(
@@ -1427,62 +2216,175 @@ fn parse_create_args(args: &str) -> Result<CreateParams, String> {
     })
 }
 
-/// Load universe library codes from database
-/// Returns Vec of (lib_name, code) pairs, sorted by name for determinism
-async fn load_universe_lib_codes(
-    state: &AppState,
-    universe_id: &str,
-) -> Result<Vec<(String, String)>, String> {
-    // Get universe config
-    let universe = match state.object_store.get_universe(universe_id).await {
-        Ok(Some(u)) => u,
-        Ok(None) => return Ok(Vec::new()), // No universe = no libs to load
-        Err(e) => return Err(format!("Failed to get universe: {}", e)),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_pool;
+    use crate::objects::Object;
+
+    fn session(
+        player_id: &str,
+        room_id: &str,
+    ) -> (PlayerSession, mpsc::UnboundedReceiver<Outgoing>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let session = PlayerSession {
+            player_id: player_id.to_string(),
+            account_id: String::new(),
+            name: player_id.to_string(),
+            session_id: None,
+            ip_address: None,
+            universe_id: "u1".to_string(),
+            room_id: Some(room_id.to_string()),
+            access_level: AccessLevel::Player,
+            sender,
+            capabilities: Capabilities::default(),
+            last_state: BTreeMap::new(),
+            outbox: Mutex::default(),
+            resume_from: 0,
+            connected_at: std::time::Instant::now(),
+            last_active: std::time::Instant::now(),
+            afk: None,
+            friends: Default::default(),
+            channels: Default::default(),
+            ignored: Default::default(),
+        };
+        (session, receiver)
+    }
 
-    // Get lib_hashes from config
-    let lib_hashes = match universe.config.get("lib_hashes") {
-        Some(hashes) => hashes,
-        None => return Ok(Vec::new()), // No libs defined
-    };
+    #[tokio::test]
+    async fn test_deliver_region_broadcast_excludes_actor() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO accounts (id, username, password_hash, salt) VALUES ('owner', 'owner', '', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let store = ObjectStore::new(pool, None);
+        store
+            .create_universe("u1", "Test", "owner", serde_json::json!({}))
+            .await
+            .unwrap();
+        for (room, region) in [
+            ("/rooms/cave", "/regions/dungeon"),
+            ("/rooms/hall", "/regions/dungeon"),
+            ("/rooms/field", "/regions/meadow"),
+        ] {
+            let mut obj = Object::new(room, "u1", "room").unwrap();
+            obj.set_property("region_id", serde_json::json!(region));
+            store.create(&obj).await.unwrap();
+        }
 
-    // Parse lib_hashes as object
-    let hashes_obj = match lib_hashes.as_object() {
-        Some(obj) => obj,
-        None => return Ok(Vec::new()), // Invalid format, skip
-    };
+        let connections = ConnectionManager::new();
+        let (actor, mut actor_rx) = session("/players/actor", "/rooms/cave");
+        let (near, mut near_rx) = session("/players/near", "/rooms/hall");
+        let (far, mut far_rx) = session("/players/far", "/rooms/field");
+        for s in [actor, near, far] {
+            connections.register(s).await;
+        }
 
-    // Load each library in order (sorted for determinism)
-    let mut lib_names: Vec<_> = hashes_obj.keys().collect();
-    lib_names.sort();
+        connections
+            .deliver(
+                vec![GameMessage::BroadcastRegion {
+                    region_id: "/regions/dungeon".to_string(),
+                    message: "A roar echoes.".to_string(),
+                    exclude_id: Some("/players/actor".to_string()),
+                }],
+                &store,
+            )
+            .await;
 
-    let mut lib_codes = Vec::new();
+        match near_rx.try_recv().unwrap().msg {
+            ServerMessage::Output { text } => assert_eq!(text, "A roar echoes."),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(actor_rx.try_recv().is_err());
+        assert!(far_rx.try_recv().is_err());
+    }
 
-    for lib_name in lib_names {
-        let hash = match hashes_obj.get(lib_name).and_then(|v| v.as_str()) {
-            Some(h) => h,
-            None => continue,
+    #[tokio::test]
+    async fn test_resume_replays_messages_sent_while_away() {
+        let connections = ConnectionManager::new();
+        let say = |text: &str| ServerMessage::Output {
+            text: text.to_string(),
         };
 
-        // Get code from code_store
-        let code = match state.object_store.get_code(hash).await {
-            Ok(Some(c)) => c,
-            Ok(None) => {
-                tracing::warn!(
-                    "Library {} with hash {} not found in code_store",
-                    lib_name,
-                    hash
-                );
-                continue;
-            }
-            Err(e) => {
-                tracing::warn!("Failed to load library {}: {}", lib_name, e);
-                continue;
-            }
-        };
+        let (first, first_rx) = session("/players/alice", "/rooms/hall");
+        connections.register(first).await;
+        connections
+            .send_to_player("/players/alice", say("seen"))
+            .await;
+        drop(first_rx);
+        assert!(!connections.is_connected("/players/alice").await);
 
-        lib_codes.push((lib_name.clone(), code));
+        // Sent while the connection is down
+        connections
+            .send_to_player("/players/alice", say("missed"))
+            .await;
+
+        let (second, mut second_rx) = session("/players/alice", "/rooms/hall");
+        connections.register(second).await;
+        assert!(connections.is_connected("/players/alice").await);
+        connections
+            .send_to_player("/players/alice", say("new"))
+            .await;
+        connections.resume("/players/alice", 1).await;
+
+        let received: Vec<(u64, ServerMessage)> = std::iter::from_fn(|| second_rx.try_recv().ok())
+            .map(|out| (out.seq, out.msg))
+            .collect();
+        assert!(matches!(&received[0], (3, ServerMessage::Output { text }) if text == "new"));
+        assert!(matches!(&received[1], (2, ServerMessage::Output { text }) if text == "missed"));
+        assert!(matches!(
+            received[2],
+            (
+                4,
+                ServerMessage::Resumed {
+                    replayed: 1,
+                    missed: 0
+                }
+            )
+        ));
+        assert_eq!(received.len(), 3);
     }
 
-    Ok(lib_codes)
+    #[tokio::test]
+    async fn test_presence_reaches_friends_and_afk_clears() {
+        let connections = ConnectionManager::new();
+        let (mut alice, mut alice_rx) = session("/players/alice", "/rooms/hall");
+        alice.friends.insert("/players/bob".to_string());
+        let (bob, _bob_rx) = session("/players/bob", "/rooms/cave");
+        let (carol, mut carol_rx) = session("/players/carol", "/rooms/hall");
+        for s in [alice, bob, carol] {
+            connections.register(s).await;
+        }
+
+        let online = ServerMessage::PresenceChanged {
+            player_id: "/players/bob".to_string(),
+            name: "bob".to_string(),
+            status: "online".to_string(),
+        };
+        connections.notify_friends("/players/bob", online).await;
+        match alice_rx.try_recv().unwrap().msg {
+            ServerMessage::Output { text } => assert_eq!(text, "Your friend bob has logged in."),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(carol_rx.try_recv().is_err());
+
+        connections
+            .set_afk("/players/bob", Some("lunch".to_string()))
+            .await;
+        let who = connections.online_players("u1").await;
+        let names: Vec<&str> = who.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["/players/alice", "/players/bob", "/players/carol"]
+        );
+        assert_eq!(who[1].afk.as_deref(), Some("lunch"));
+
+        assert_eq!(
+            connections.touch("/players/bob").await.as_deref(),
+            Some("lunch")
+        );
+        assert_eq!(connections.touch("/players/bob").await, None);
+        assert!(connections.online_players("u2").await.is_empty());
+    }
 }
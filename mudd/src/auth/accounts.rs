@@ -1,11 +1,35 @@
 //! Account management service
 //!
-//! Handles account creation, authentication, and token management.
+//! Handles account creation, authentication, and session management.
+//! Writes go through Raft when a writer is available, so accounts and
+//! sessions are the same on every node.
+//!
+//! Each login starts a session with its own token, so an account can be
+//! signed in on several devices at once. Only a hash of the token is
+//! stored; sessions expire after `SESSION_TTL_DAYS`.
+//!
+//! Accounts can also be bound to an Ethereum wallet and signed in with a
+//! Sign-In with Ethereum message instead of a password.
+
+use std::sync::Arc;
 
+use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
 use thiserror::Error;
+use tracing::warn;
+
+use super::wallet::{self, WalletError};
+use super::{generate_token, hash_password, hash_token, verify_password, PasswordCheck};
+use crate::raft::{write_entry, GameLogEntry, RaftWriter};
+
+/// How long a session stays valid after login
+pub const SESSION_TTL_DAYS: i64 = 30;
 
-use super::{generate_salt, generate_token, hash_password, verify_password};
+/// Minimum time between updates of a session's last-seen time
+const LAST_SEEN_INTERVAL_SECS: i64 = 60;
+
+/// How long a wallet sign-in nonce can be used
+const NONCE_TTL_SECS: i64 = 600;
 
 /// Account data
 #[derive(Debug, Clone)]
@@ -16,39 +40,126 @@ pub struct Account {
     pub created_at: String,
 }
 
+/// Where a session was started from
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// A logged-in session
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    pub account_id: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_seen_at: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+type SessionRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+);
+
+impl From<SessionRow> for Session {
+    fn from(
+        (id, account_id, created_at, expires_at, last_seen_at, user_agent, ip_address): SessionRow,
+    ) -> Self {
+        Self {
+            id,
+            account_id,
+            created_at,
+            expires_at,
+            last_seen_at,
+            user_agent,
+            ip_address,
+        }
+    }
+}
+
+/// id, password_hash, salt, access_level, created_at
+type LoginRow = (String, Option<String>, Option<String>, String, String);
+
 /// Authentication errors
 #[derive(Debug, Error)]
 pub enum AuthError {
     #[error("username already exists")]
     UsernameExists,
 
+    #[error("username is reserved for wallet accounts")]
+    ReservedUsername,
+
     #[error("invalid credentials")]
     InvalidCredentials,
 
     #[error("account not found")]
     AccountNotFound,
 
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+
+    #[error("nonce is invalid or expired")]
+    InvalidNonce,
+
+    #[error("wallet is linked to another account")]
+    WalletInUse,
+
+    #[error("account already has a wallet")]
+    WalletAlreadyLinked,
+
+    #[error("password hashing failed: {0}")]
+    Hashing(#[from] tokio::task::JoinError),
+
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
+
+    #[error("write failed: {0}")]
+    Write(#[from] anyhow::Error),
 }
 
 /// Account service for authentication operations
 pub struct AccountService {
     pool: SqlitePool,
+    raft_writer: Option<Arc<RaftWriter>>,
 }
 
 impl AccountService {
     /// Create a new account service
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+    pub fn new(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Self {
+        Self { pool, raft_writer }
+    }
+
+    /// Apply a log entry through Raft (if available) or directly
+    async fn write(&self, entry: GameLogEntry) -> Result<u64, AuthError> {
+        Ok(write_entry(self.raft_writer.as_deref(), &self.pool, entry).await?)
     }
 
-    /// Create a new account
+    /// Create a new account and start a session on it
     pub async fn create_account(
         &self,
         username: &str,
         password: &str,
+        client: &ClientInfo,
     ) -> Result<(Account, String), AuthError> {
+        let account = self.insert_account(username, password).await?;
+        let token = self.start_session(&account.id, client).await?;
+        Ok((account, token))
+    }
+
+    async fn insert_account(&self, username: &str, password: &str) -> Result<Account, AuthError> {
+        // Wallet accounts are named after their address
+        if wallet::is_address(username) {
+            return Err(AuthError::ReservedUsername);
+        }
+
         // Check if username already exists
         let existing: Option<(String,)> =
             sqlx::query_as("SELECT id FROM accounts WHERE username = ?")
@@ -60,68 +171,79 @@ impl AccountService {
             return Err(AuthError::UsernameExists);
         }
 
-        // Generate credentials
+        // Generate credentials (the salt is part of the Argon2 hash)
         let id = uuid::Uuid::new_v4().to_string();
-        let salt = generate_salt();
-        let password_hash = hash_password(password, &salt);
-        let token = generate_token();
+        let password = password.to_string();
+        let password_hash = tokio::task::spawn_blocking(move || hash_password(&password)).await?;
         let now = chrono::Utc::now().to_rfc3339();
 
         // Insert account
-        sqlx::query(
-            "INSERT INTO accounts (id, username, password_hash, salt, token, access_level, created_at)
-             VALUES (?, ?, ?, ?, ?, 'player', ?)",
-        )
-        .bind(&id)
-        .bind(username)
-        .bind(&password_hash)
-        .bind(&salt)
-        .bind(&token)
-        .bind(&now)
-        .execute(&self.pool)
+        self.write(GameLogEntry::CreateAccount {
+            account_id: id.clone(),
+            username: username.to_string(),
+            password_hash: Some(password_hash),
+            wallet_address: None,
+            created_at: now.clone(),
+        })
         .await?;
 
-        let account = Account {
+        Ok(Account {
             id,
             username: username.to_string(),
             access_level: "player".to_string(),
             created_at: now,
-        };
-
-        Ok((account, token))
+        })
     }
 
-    /// Login with username and password, returns token
+    /// Login with username and password, returns a new session's token
     pub async fn login(
         &self,
         username: &str,
         password: &str,
+        client: &ClientInfo,
     ) -> Result<(Account, String), AuthError> {
         // Get account with password info
-        let row: Option<(String, String, String, String, String)> = sqlx::query_as(
+        let row: Option<LoginRow> = sqlx::query_as(
             "SELECT id, password_hash, salt, access_level, created_at FROM accounts WHERE username = ?",
         )
         .bind(username)
         .fetch_optional(&self.pool)
         .await?;
 
-        let (id, stored_hash, salt, access_level, created_at) =
-            row.ok_or(AuthError::InvalidCredentials)?;
+        // Wallet-only accounts have no password
+        let Some((id, Some(stored_hash), salt, access_level, created_at)) = row else {
+            return Err(AuthError::InvalidCredentials);
+        };
+
+        // Verify password, computing a replacement hash if this one is
+        // legacy or outdated. Both are slow, so run them off the runtime.
+        let password = password.to_string();
+        let hash = stored_hash.clone();
+        let (check, new_hash) = tokio::task::spawn_blocking(move || {
+            let check = verify_password(&password, salt.as_deref(), &hash);
+            let new_hash = (check == PasswordCheck::NeedsRehash).then(|| hash_password(&password));
+            (check, new_hash)
+        })
+        .await?;
 
-        // Verify password
-        if !verify_password(password, &salt, &stored_hash) {
+        if check == PasswordCheck::Invalid {
             return Err(AuthError::InvalidCredentials);
         }
 
-        // Generate new token
-        let token = generate_token();
+        if let Some(new_hash) = new_hash {
+            // Only replace the hash we verified, in case the password
+            // changed in the meantime
+            let rehash = GameLogEntry::RehashPassword {
+                account_id: id.clone(),
+                old_hash: stored_hash,
+                new_hash,
+            };
+            if let Err(e) = self.write(rehash).await {
+                warn!("Failed to upgrade password hash for {}: {}", username, e);
+            }
+        }
 
-        // Update token in database
-        sqlx::query("UPDATE accounts SET token = ? WHERE id = ?")
-            .bind(&token)
-            .bind(&id)
-            .execute(&self.pool)
-            .await?;
+        let token = self.start_session(&id, client).await?;
 
         let account = Account {
             id,
@@ -133,12 +255,294 @@ impl AccountService {
         Ok((account, token))
     }
 
+    /// Start a session for an account, returning its token
+    ///
+    /// Also clears out the account's expired sessions.
+    async fn start_session(
+        &self,
+        account_id: &str,
+        client: &ClientInfo,
+    ) -> Result<String, AuthError> {
+        let token = generate_token();
+        let now = chrono::Utc::now();
+        let created_at = now.to_rfc3339();
+        let expires_at = (now + chrono::Duration::days(SESSION_TTL_DAYS)).to_rfc3339();
+
+        self.write(GameLogEntry::StartSession {
+            session_id: uuid::Uuid::new_v4().to_string(),
+            account_id: account_id.to_string(),
+            token_hash: hash_token(&token),
+            user_agent: client.user_agent.clone(),
+            ip_address: client.ip_address.clone(),
+            created_at,
+            expires_at,
+        })
+        .await?;
+
+        Ok(token)
+    }
+
     /// Validate a token and return the associated account
     pub async fn validate_token(&self, token: &str) -> Result<Option<Account>, AuthError> {
+        Ok(self
+            .validate_session(token)
+            .await?
+            .map(|(account, _)| account))
+    }
+
+    /// Validate a token and return its account and session
+    ///
+    /// Expired sessions are rejected. The session's last-seen time is
+    /// refreshed at most once a minute, on a best-effort basis: a node
+    /// that can't reach the leader still accepts the session.
+    pub async fn validate_session(
+        &self,
+        token: &str,
+    ) -> Result<Option<(Account, Session)>, AuthError> {
+        let now = chrono::Utc::now();
+        let row: Option<SessionRow> = sqlx::query_as(
+            "SELECT id, account_id, created_at, expires_at, last_seen_at, user_agent, ip_address
+             FROM sessions WHERE token_hash = ? AND expires_at > ?",
+        )
+        .bind(hash_token(token))
+        .bind(now.to_rfc3339())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(mut session) = row.map(Session::from) else {
+            return Ok(None);
+        };
+        let Some(account) = self.get_account(&session.account_id).await? else {
+            return Ok(None);
+        };
+
+        let stale_before = (now - chrono::Duration::seconds(LAST_SEEN_INTERVAL_SECS)).to_rfc3339();
+        if session.last_seen_at < stale_before {
+            let refreshed = self
+                .write(GameLogEntry::TouchSession {
+                    session_id: session.id.clone(),
+                    last_seen_at: now.to_rfc3339(),
+                })
+                .await;
+            match refreshed {
+                Ok(_) => session.last_seen_at = now.to_rfc3339(),
+                Err(e) => warn!("Failed to refresh last_seen_at for {}: {}", session.id, e),
+            }
+        }
+
+        Ok(Some((account, session)))
+    }
+
+    /// Logout by ending the token's session
+    pub async fn logout(&self, token: &str) -> Result<bool, AuthError> {
+        let rows = self
+            .write(GameLogEntry::EndSession {
+                token_hash: hash_token(token),
+            })
+            .await?;
+
+        Ok(rows > 0)
+    }
+
+    /// List an account's unexpired sessions, most recently used first
+    pub async fn list_sessions(&self, account_id: &str) -> Result<Vec<Session>, AuthError> {
+        let rows: Vec<SessionRow> = sqlx::query_as(
+            "SELECT id, account_id, created_at, expires_at, last_seen_at, user_agent, ip_address
+             FROM sessions WHERE account_id = ? AND expires_at > ?
+             ORDER BY last_seen_at DESC",
+        )
+        .bind(account_id)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Session::from).collect())
+    }
+
+    /// End one of an account's sessions
+    pub async fn revoke_session(
+        &self,
+        account_id: &str,
+        session_id: &str,
+    ) -> Result<bool, AuthError> {
+        let rows = self
+            .write(GameLogEntry::RevokeSession {
+                account_id: account_id.to_string(),
+                session_id: session_id.to_string(),
+            })
+            .await?;
+
+        Ok(rows > 0)
+    }
+
+    /// End all of an account's sessions, returning how many were ended
+    pub async fn revoke_all_sessions(&self, account_id: &str) -> Result<u64, AuthError> {
+        self.write(GameLogEntry::RevokeSessions {
+            account_id: account_id.to_string(),
+        })
+        .await
+    }
+
+    /// Issue a nonce for a wallet to sign in with
+    ///
+    /// Returns the nonce and when it expires. Also clears out expired nonces.
+    pub async fn wallet_nonce(&self, address: &str) -> Result<(String, String), AuthError> {
+        let address = wallet::checksum_address(address)?;
+        let nonce = wallet::generate_nonce();
+        let now = chrono::Utc::now();
+        let created_at = now.to_rfc3339();
+        let expires_at = (now + chrono::Duration::seconds(NONCE_TTL_SECS)).to_rfc3339();
+
+        self.write(GameLogEntry::IssueNonce {
+            nonce: nonce.clone(),
+            address,
+            created_at,
+            expires_at: expires_at.clone(),
+        })
+        .await?;
+
+        Ok((nonce, expires_at))
+    }
+
+    /// Verify a signed sign-in message, returning the wallet's address
+    ///
+    /// The message must be for `domain`, currently valid, and carry an
+    /// unexpired nonce issued to the signing address. The nonce is used up
+    /// whether or not the caller goes on to succeed.
+    async fn verify_wallet_message(
+        &self,
+        message: &str,
+        signature: &str,
+        domain: &str,
+    ) -> Result<String, AuthError> {
+        let message = wallet::verify(message, signature)?;
+        let now = chrono::Utc::now();
+        message.check(domain, now)?;
+
+        let rows = self
+            .write(GameLogEntry::UseNonce {
+                nonce: message.nonce.clone(),
+                address: message.address.clone(),
+                used_at: now.to_rfc3339(),
+            })
+            .await?;
+        if rows == 0 {
+            return Err(AuthError::InvalidNonce);
+        }
+
+        Ok(message.address)
+    }
+
+    /// Sign in with a wallet, returning a new session's token
+    ///
+    /// A wallet not linked to any account gets a new passwordless account,
+    /// named after its address.
+    pub async fn login_with_wallet(
+        &self,
+        message: &str,
+        signature: &str,
+        domain: &str,
+        client: &ClientInfo,
+    ) -> Result<(Account, String), AuthError> {
+        let address = self
+            .verify_wallet_message(message, signature, domain)
+            .await?;
+
+        let account = match self.get_by_wallet(&address).await? {
+            Some(account) => account,
+            None => self.insert_wallet_account(&address).await?,
+        };
+
+        let token = self.start_session(&account.id, client).await?;
+        Ok((account, token))
+    }
+
+    async fn insert_wallet_account(&self, address: &str) -> Result<Account, AuthError> {
+        if self.get_by_username(address).await?.is_some() {
+            return Err(AuthError::UsernameExists);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = chrono::Utc::now().to_rfc3339();
+
+        self.write(GameLogEntry::CreateAccount {
+            account_id: id.clone(),
+            username: address.to_string(),
+            password_hash: None,
+            wallet_address: Some(address.to_string()),
+            created_at: now.clone(),
+        })
+        .await?;
+
+        Ok(Account {
+            id,
+            username: address.to_string(),
+            access_level: "player".to_string(),
+            created_at: now,
+        })
+    }
+
+    /// Bind a wallet to an existing account, proven by a signed message
+    ///
+    /// Returns the linked address.
+    pub async fn link_wallet(
+        &self,
+        account_id: &str,
+        message: &str,
+        signature: &str,
+        domain: &str,
+    ) -> Result<String, AuthError> {
+        let address = self
+            .verify_wallet_message(message, signature, domain)
+            .await?;
+
+        if let Some(owner) = self.get_by_wallet(&address).await? {
+            if owner.id == account_id {
+                return Ok(address);
+            }
+            return Err(AuthError::WalletInUse);
+        }
+
+        let result = self
+            .write(GameLogEntry::LinkWallet {
+                account_id: account_id.to_string(),
+                wallet_address: address.clone(),
+            })
+            .await;
+        match result {
+            Ok(rows) if rows > 0 => Ok(address),
+            Ok(_) => match self.get_account(account_id).await? {
+                Some(_) => Err(AuthError::WalletAlreadyLinked),
+                None => Err(AuthError::AccountNotFound),
+            },
+            Err(e) => {
+                // Linked to another account since we checked
+                if self.get_by_wallet(&address).await?.is_some() {
+                    return Err(AuthError::WalletInUse);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the wallet address linked to an account
+    pub async fn wallet_address(&self, account_id: &str) -> Result<Option<String>, AuthError> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT wallet_address FROM accounts WHERE id = ?")
+                .bind(account_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.and_then(|(address,)| address))
+    }
+
+    /// Get account by linked wallet address (any case)
+    pub async fn get_by_wallet(&self, address: &str) -> Result<Option<Account>, AuthError> {
+        let address = wallet::checksum_address(address)?;
         let row: Option<(String, String, String, String)> = sqlx::query_as(
-            "SELECT id, username, access_level, created_at FROM accounts WHERE token = ?",
+            "SELECT id, username, access_level, created_at FROM accounts WHERE wallet_address = ?",
         )
-        .bind(token)
+        .bind(&address)
         .fetch_optional(&self.pool)
         .await?;
 
@@ -150,16 +554,6 @@ impl AccountService {
         }))
     }
 
-    /// Logout by clearing the token
-    pub async fn logout(&self, token: &str) -> Result<bool, AuthError> {
-        let result = sqlx::query("UPDATE accounts SET token = NULL WHERE token = ?")
-            .bind(token)
-            .execute(&self.pool)
-            .await?;
-
-        Ok(result.rows_affected() > 0)
-    }
-
     /// Get account by ID
     pub async fn get_account(&self, id: &str) -> Result<Option<Account>, AuthError> {
         let row: Option<(String, String, String, String)> = sqlx::query_as(
@@ -194,21 +588,21 @@ impl AccountService {
         }))
     }
 
-    /// Create account (simpler alias for create_account, returns just Account)
+    /// Create an account without starting a session
     pub async fn create(&self, username: &str, password: &str) -> Result<Account, AuthError> {
-        let (account, _token) = self.create_account(username, password).await?;
-        Ok(account)
+        self.insert_account(username, password).await
     }
 
     /// Set account access level
     pub async fn set_access_level(&self, account_id: &str, level: &str) -> Result<(), AuthError> {
-        let result = sqlx::query("UPDATE accounts SET access_level = ? WHERE id = ?")
-            .bind(level)
-            .bind(account_id)
-            .execute(&self.pool)
+        let rows = self
+            .write(GameLogEntry::SetAccessLevel {
+                account_id: account_id.to_string(),
+                access_level: level.to_string(),
+            })
             .await?;
 
-        if result.rows_affected() == 0 {
+        if rows == 0 {
             return Err(AuthError::AccountNotFound);
         }
 
@@ -224,10 +618,10 @@ mod tests {
     #[tokio::test]
     async fn test_account_create() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         let (account, token) = service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
@@ -239,63 +633,115 @@ mod tests {
     #[tokio::test]
     async fn test_account_create_duplicate() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
-        let result = service.create_account("testuser", "different").await;
+        let result = service
+            .create_account("testuser", "different", &ClientInfo::default())
+            .await;
         assert!(matches!(result, Err(AuthError::UsernameExists)));
     }
 
     #[tokio::test]
     async fn test_login_success() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
-        let (account, token) = service.login("testuser", "password123").await.unwrap();
+        let (account, token) = service
+            .login("testuser", "password123", &ClientInfo::default())
+            .await
+            .unwrap();
 
         assert_eq!(account.username, "testuser");
         assert!(!token.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_login_upgrades_legacy_hash() {
+        use sha2::{Digest, Sha256};
+
+        let pool = test_pool().await;
+        let service = AccountService::new(pool.clone(), None);
+
+        // Account created before Argon2: hex Sha256(salt || password)
+        let legacy_hash = hex::encode(Sha256::digest(b"abcd1234password123"));
+        sqlx::query(
+            "INSERT INTO accounts (id, username, password_hash, salt) VALUES ('old', 'olduser', ?, 'abcd1234')",
+        )
+        .bind(&legacy_hash)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            service
+                .login("olduser", "wrongpassword", &ClientInfo::default())
+                .await,
+            Err(AuthError::InvalidCredentials)
+        ));
+        service
+            .login("olduser", "password123", &ClientInfo::default())
+            .await
+            .unwrap();
+
+        let (hash, salt): (String, Option<String>) =
+            sqlx::query_as("SELECT password_hash, salt FROM accounts WHERE id = 'old'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_eq!(salt, None);
+
+        // The upgraded hash still logs in
+        service
+            .login("olduser", "password123", &ClientInfo::default())
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_login_wrong_password() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
-        let result = service.login("testuser", "wrongpassword").await;
+        let result = service
+            .login("testuser", "wrongpassword", &ClientInfo::default())
+            .await;
         assert!(matches!(result, Err(AuthError::InvalidCredentials)));
     }
 
     #[tokio::test]
     async fn test_login_nonexistent_user() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
-        let result = service.login("nouser", "password").await;
+        let result = service
+            .login("nouser", "password", &ClientInfo::default())
+            .await;
         assert!(matches!(result, Err(AuthError::InvalidCredentials)));
     }
 
     #[tokio::test]
     async fn test_validate_token() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         let (_, token) = service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
@@ -307,7 +753,7 @@ mod tests {
     #[tokio::test]
     async fn test_validate_invalid_token() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         let account = service.validate_token("invalidtoken").await.unwrap();
         assert!(account.is_none());
@@ -316,10 +762,10 @@ mod tests {
     #[tokio::test]
     async fn test_logout() {
         let pool = test_pool().await;
-        let service = AccountService::new(pool);
+        let service = AccountService::new(pool, None);
 
         let (_, token) = service
-            .create_account("testuser", "password123")
+            .create_account("testuser", "password123", &ClientInfo::default())
             .await
             .unwrap();
 
@@ -333,4 +779,329 @@ mod tests {
         // Token should be invalid now
         assert!(service.validate_token(&token).await.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn test_sessions_per_device() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool, None);
+        let laptop = ClientInfo {
+            user_agent: Some("laptop".to_string()),
+            ip_address: Some("10.0.0.1".to_string()),
+        };
+        let phone = ClientInfo {
+            user_agent: Some("phone".to_string()),
+            ip_address: None,
+        };
+
+        let (account, laptop_token) = service
+            .create_account("testuser", "password123", &laptop)
+            .await
+            .unwrap();
+        let (_, phone_token) = service
+            .login("testuser", "password123", &phone)
+            .await
+            .unwrap();
+
+        // Logging in on a second device keeps the first signed in
+        assert!(service
+            .validate_token(&laptop_token)
+            .await
+            .unwrap()
+            .is_some());
+        let (_, phone_session) = service
+            .validate_session(&phone_token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(phone_session.user_agent.as_deref(), Some("phone"));
+
+        let sessions = service.list_sessions(&account.id).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions
+            .iter()
+            .any(|s| s.ip_address.as_deref() == Some("10.0.0.1")));
+
+        // Revoking one session leaves the other
+        assert!(service
+            .revoke_session(&account.id, &phone_session.id)
+            .await
+            .unwrap());
+        assert!(service
+            .validate_token(&phone_token)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(service
+            .validate_token(&laptop_token)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Another account can't revoke this one's sessions
+        assert!(!service
+            .revoke_session("someone-else", &phone_session.id)
+            .await
+            .unwrap());
+
+        assert_eq!(service.revoke_all_sessions(&account.id).await.unwrap(), 1);
+        assert!(service
+            .validate_token(&laptop_token)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// Sign a fresh SIWE message for `key` with a nonce from the service
+    async fn signed_wallet_message(
+        service: &AccountService,
+        key: &k256::ecdsa::SigningKey,
+    ) -> (String, String) {
+        let address = wallet::signer_address(key);
+        let (nonce, _) = service.wallet_nonce(&address).await.unwrap();
+        let now = chrono::Utc::now();
+        let message = wallet::SiweMessage {
+            domain: "mud.example.com".to_string(),
+            address,
+            statement: None,
+            uri: "https://mud.example.com".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+            nonce,
+            issued_at: now,
+            expiration_time: Some(now + chrono::Duration::minutes(5)),
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+        .to_string();
+        let signature = wallet::sign_message(key, &message);
+        (message, signature)
+    }
+
+    #[tokio::test]
+    async fn test_wallet_login_creates_account_once() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool, None);
+        let key = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let client = ClientInfo::default();
+
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        let (account, token) = service
+            .login_with_wallet(&message, &signature, "mud.example.com", &client)
+            .await
+            .unwrap();
+        assert_eq!(account.username, wallet::signer_address(&key));
+        assert!(service.validate_token(&token).await.unwrap().is_some());
+
+        // The nonce can't be replayed
+        assert!(matches!(
+            service
+                .login_with_wallet(&message, &signature, "mud.example.com", &client)
+                .await,
+            Err(AuthError::InvalidNonce)
+        ));
+
+        // A new message signs in to the same account
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        let (again, _) = service
+            .login_with_wallet(&message, &signature, "mud.example.com", &client)
+            .await
+            .unwrap();
+        assert_eq!(again.id, account.id);
+
+        // The wallet account has no password to log in with
+        assert!(matches!(
+            service.login(&account.username, "", &client).await,
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wallet_address_usernames_are_reserved() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool, None);
+        let key = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let client = ClientInfo::default();
+        let address = wallet::signer_address(&key);
+
+        for squatted in [address.clone(), address.to_lowercase()] {
+            assert!(matches!(
+                service
+                    .create_account(&squatted, "password123", &client)
+                    .await,
+                Err(AuthError::ReservedUsername)
+            ));
+        }
+
+        // The wallet's owner can still sign in
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        let (account, _) = service
+            .login_with_wallet(&message, &signature, "mud.example.com", &client)
+            .await
+            .unwrap();
+        assert_eq!(account.username, address);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_login_rejects_bad_messages() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool, None);
+        let key = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let other = k256::ecdsa::SigningKey::from_slice(&[2u8; 32]).unwrap();
+        let client = ClientInfo::default();
+
+        // Signed by a different wallet than the message names
+        let (message, _) = signed_wallet_message(&service, &key).await;
+        let forged = wallet::sign_message(&other, &message);
+        assert!(matches!(
+            service
+                .login_with_wallet(&message, &forged, "mud.example.com", &client)
+                .await,
+            Err(AuthError::Wallet(WalletError::SignatureMismatch))
+        ));
+
+        // Meant for another server
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        assert!(matches!(
+            service
+                .login_with_wallet(&message, &signature, "other.example.com", &client)
+                .await,
+            Err(AuthError::Wallet(WalletError::WrongDomain(_)))
+        ));
+
+        // Nonce issued to a different address
+        let (message, _) = signed_wallet_message(&service, &other).await;
+        let (nonce, _) = service
+            .wallet_nonce(&wallet::signer_address(&key))
+            .await
+            .unwrap();
+        let mut parsed: wallet::SiweMessage = message.parse().unwrap();
+        parsed.nonce = nonce;
+        let message = parsed.to_string();
+        let signature = wallet::sign_message(&other, &message);
+        assert!(matches!(
+            service
+                .login_with_wallet(&message, &signature, "mud.example.com", &client)
+                .await,
+            Err(AuthError::InvalidNonce)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_link_wallet() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool, None);
+        let key = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let address = wallet::signer_address(&key);
+        let client = ClientInfo::default();
+
+        let (account, _) = service
+            .create_account("testuser", "password123", &client)
+            .await
+            .unwrap();
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        assert_eq!(
+            service
+                .link_wallet(&account.id, &message, &signature, "mud.example.com")
+                .await
+                .unwrap(),
+            address
+        );
+        assert_eq!(
+            service.wallet_address(&account.id).await.unwrap(),
+            Some(address.clone())
+        );
+
+        // The wallet now signs in to the password account
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        let (wallet_account, _) = service
+            .login_with_wallet(&message, &signature, "mud.example.com", &client)
+            .await
+            .unwrap();
+        assert_eq!(wallet_account.id, account.id);
+        assert_eq!(
+            service
+                .get_by_wallet(&address.to_lowercase())
+                .await
+                .unwrap()
+                .unwrap()
+                .id,
+            account.id
+        );
+
+        // Another account can't take the wallet
+        let (second, _) = service
+            .create_account("seconduser", "password123", &client)
+            .await
+            .unwrap();
+        let (message, signature) = signed_wallet_message(&service, &key).await;
+        assert!(matches!(
+            service
+                .link_wallet(&second.id, &message, &signature, "mud.example.com")
+                .await,
+            Err(AuthError::WalletInUse)
+        ));
+
+        // And the account can't silently swap to a different wallet
+        let other = k256::ecdsa::SigningKey::from_slice(&[2u8; 32]).unwrap();
+        let (message, signature) = signed_wallet_message(&service, &other).await;
+        assert!(matches!(
+            service
+                .link_wallet(&account.id, &message, &signature, "mud.example.com")
+                .await,
+            Err(AuthError::WalletAlreadyLinked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_session_rejected() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool.clone(), None);
+
+        let (account, token) = service
+            .create_account("testuser", "password123", &ClientInfo::default())
+            .await
+            .unwrap();
+
+        sqlx::query("UPDATE sessions SET expires_at = ? WHERE account_id = ?")
+            .bind((chrono::Utc::now() - chrono::Duration::seconds(1)).to_rfc3339())
+            .bind(&account.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(service.validate_token(&token).await.unwrap().is_none());
+        assert!(service.list_sessions(&account.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_valid_when_last_seen_refresh_fails() {
+        let pool = test_pool().await;
+        let service = AccountService::new(pool.clone(), None);
+
+        let (account, token) = service
+            .create_account("testuser", "password123", &ClientInfo::default())
+            .await
+            .unwrap();
+
+        let stale = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE account_id = ?")
+            .bind(&stale)
+            .bind(&account.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Stand in for a write that can't reach the leader
+        sqlx::query(
+            "CREATE TRIGGER sessions_read_only BEFORE UPDATE ON sessions
+             BEGIN SELECT RAISE(FAIL, 'read only'); END",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let (found, session) = service.validate_session(&token).await.unwrap().unwrap();
+        assert_eq!(found.id, account.id);
+        assert_eq!(session.last_seen_at, stale);
+    }
 }
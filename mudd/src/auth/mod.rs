@@ -1,11 +1,16 @@
 //! Authentication module
 //!
-//! Provides token-based authentication for the mudd server.
+//! Provides token-based authentication for the mudd server, with password
+//! and Ethereum wallet sign-in.
 
 pub mod accounts;
+pub mod wallet;
 
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
 use rand::Rng;
 use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
 /// Generate a secure random token
 pub fn generate_token() -> String {
@@ -21,29 +26,108 @@ pub fn generate_token() -> String {
     hex::encode(hasher.finalize())
 }
 
-/// Generate a random salt for password hashing
-pub fn generate_salt() -> String {
-    let random_bytes: [u8; 16] = rand::rng().random();
-    hex::encode(random_bytes)
+/// Hash a session token for storage
+///
+/// Tokens are random, so a fast hash is enough to keep a copy of the
+/// database from being usable to log in.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
-/// Hash a password with a salt
-pub fn hash_password(password: &str, salt: &str) -> String {
+/// Outcome of checking a password against a stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// The password does not match
+    Invalid,
+    /// The password matches and the hash is current
+    Valid,
+    /// The password matches but the hash is legacy or uses outdated
+    /// parameters, and should be replaced with a fresh `hash_password`
+    NeedsRehash,
+}
+
+/// Argon2id with the current parameters
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+}
+
+/// Hash a password with Argon2id
+///
+/// Returns a PHC string (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`)
+/// carrying its own salt and parameters. This is deliberately slow; call
+/// it from a blocking thread.
+pub fn hash_password(password: &str) -> String {
+    let salt_bytes: [u8; 16] = rand::rng().random();
+    let salt = SaltString::encode_b64(&salt_bytes).expect("16-byte salt is valid");
+    argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default Argon2 parameters are valid")
+        .to_string()
+}
+
+/// Verify a password against a stored hash
+///
+/// Accepts PHC strings from `hash_password` and legacy hex
+/// `Sha256(salt || password)` hashes, which need the account's salt.
+pub fn verify_password(password: &str, salt: Option<&str>, stored_hash: &str) -> PasswordCheck {
+    if !stored_hash.starts_with('$') {
+        return match salt {
+            // Compare in constant time so timing doesn't leak the hash
+            Some(salt)
+                if bool::from(
+                    legacy_hash_password(password, salt)
+                        .as_bytes()
+                        .ct_eq(stored_hash.as_bytes()),
+                ) =>
+            {
+                PasswordCheck::NeedsRehash
+            }
+            _ => PasswordCheck::Invalid,
+        };
+    }
+
+    let Ok(parsed) = PasswordHash::new(stored_hash) else {
+        return PasswordCheck::Invalid;
+    };
+    // Verification uses the parameters stored in the hash
+    if argon2()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+
+    let current = Params::default();
+    let outdated = parsed.algorithm != Algorithm::Argon2id.ident()
+        || Params::try_from(&parsed).map_or(true, |params| {
+            (params.m_cost(), params.t_cost(), params.p_cost())
+                != (current.m_cost(), current.t_cost(), current.p_cost())
+        });
+    if outdated {
+        PasswordCheck::NeedsRehash
+    } else {
+        PasswordCheck::Valid
+    }
+}
+
+/// Hash used before Argon2: a single `Sha256(salt || password)`
+fn legacy_hash_password(password: &str, salt: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(salt.as_bytes());
     hasher.update(password.as_bytes());
     hex::encode(hasher.finalize())
 }
 
-/// Verify a password against a stored hash
-pub fn verify_password(password: &str, salt: &str, hash: &str) -> bool {
-    hash_password(password, salt) == hash
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Generate a random salt for legacy password hashes
+    fn generate_salt() -> String {
+        let random_bytes: [u8; 16] = rand::rng().random();
+        hex::encode(random_bytes)
+    }
+
     #[test]
     fn test_token_generation() {
         let token1 = generate_token();
@@ -71,37 +155,64 @@ mod tests {
     }
 
     #[test]
-    fn test_password_hash_deterministic() {
-        let password = "secret123";
-        let salt = "abcd1234";
+    fn test_password_hash_is_argon2id_phc() {
+        let hash1 = hash_password("secret123");
+        let hash2 = hash_password("secret123");
 
-        let hash1 = hash_password(password, salt);
-        let hash2 = hash_password(password, salt);
+        assert!(hash1.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
 
-        // Same password + salt = same hash
-        assert_eq!(hash1, hash2);
+        // Each hash gets its own salt
+        assert_ne!(hash1, hash2);
     }
 
     #[test]
-    fn test_password_hash_different_salts() {
-        let password = "secret123";
-        let salt1 = "salt1";
-        let salt2 = "salt2";
-
-        let hash1 = hash_password(password, salt1);
-        let hash2 = hash_password(password, salt2);
+    fn test_verify_password() {
+        let password = "mysecret";
+        let hash = hash_password(password);
 
-        // Different salts = different hashes
-        assert_ne!(hash1, hash2);
+        assert_eq!(verify_password(password, None, &hash), PasswordCheck::Valid);
+        assert_eq!(
+            verify_password("wrongpassword", None, &hash),
+            PasswordCheck::Invalid
+        );
     }
 
     #[test]
-    fn test_verify_password() {
+    fn test_verify_legacy_password_needs_rehash() {
         let password = "mysecret";
         let salt = generate_salt();
-        let hash = hash_password(password, &salt);
+        let hash = legacy_hash_password(password, &salt);
+
+        assert_eq!(
+            verify_password(password, Some(&salt), &hash),
+            PasswordCheck::NeedsRehash
+        );
+        assert_eq!(
+            verify_password("wrongpassword", Some(&salt), &hash),
+            PasswordCheck::Invalid
+        );
+        assert_eq!(
+            verify_password(password, None, &hash),
+            PasswordCheck::Invalid
+        );
+    }
 
-        assert!(verify_password(password, &salt, &hash));
-        assert!(!verify_password("wrongpassword", &salt, &hash));
+    #[test]
+    fn test_verify_outdated_params_needs_rehash() {
+        let params = Params::new(8 * 1024, 1, 1, None).unwrap();
+        let salt = SaltString::encode_b64(b"0123456789abcdef").unwrap();
+        let hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"mysecret", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify_password("mysecret", None, &hash),
+            PasswordCheck::NeedsRehash
+        );
+        assert_eq!(
+            verify_password("wrongpassword", None, &hash),
+            PasswordCheck::Invalid
+        );
     }
 }
@@ -0,0 +1,516 @@
+//! Ethereum wallet sign-in (EIP-4361, "Sign-In with Ethereum")
+//!
+//! The client asks for a nonce, builds a SIWE message containing it and
+//! signs the message with its wallet (EIP-191 `personal_sign`). The server
+//! parses the message, recovers the signer's address from the secp256k1
+//! signature and checks it against the address the message names.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use rand::Rng;
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+const URI_TAG: &str = "URI: ";
+const VERSION_TAG: &str = "Version: ";
+const CHAIN_TAG: &str = "Chain ID: ";
+const NONCE_TAG: &str = "Nonce: ";
+const ISSUED_AT_TAG: &str = "Issued At: ";
+const EXPIRATION_TAG: &str = "Expiration Time: ";
+const NOT_BEFORE_TAG: &str = "Not Before: ";
+const REQUEST_ID_TAG: &str = "Request ID: ";
+const RESOURCES_TAG: &str = "Resources:";
+
+/// Wallet sign-in errors
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WalletError {
+    #[error("malformed sign-in message: {0}")]
+    Malformed(&'static str),
+
+    #[error("invalid address")]
+    InvalidAddress,
+
+    #[error("malformed signature")]
+    MalformedSignature,
+
+    #[error("signature does not match address")]
+    SignatureMismatch,
+
+    #[error("message is for {0}, not this server")]
+    WrongDomain(String),
+
+    #[error("message has expired")]
+    Expired,
+
+    #[error("message is not yet valid")]
+    NotYetValid,
+}
+
+/// A parsed EIP-4361 sign-in message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiweMessage {
+    /// Host (and port) of the server asking for the signature
+    pub domain: String,
+    /// Signing address, EIP-55 checksummed
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    /// Check the message was meant for this server and is valid now
+    pub fn check(&self, domain: &str, now: DateTime<Utc>) -> Result<(), WalletError> {
+        if self.domain != domain {
+            return Err(WalletError::WrongDomain(self.domain.clone()));
+        }
+        if self.expiration_time.is_some_and(|exp| now >= exp) {
+            return Err(WalletError::Expired);
+        }
+        if self.not_before.is_some_and(|nbf| now < nbf) {
+            return Err(WalletError::NotYetValid);
+        }
+        Ok(())
+    }
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, WalletError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| WalletError::Malformed("invalid timestamp"))
+}
+
+/// Strip a required tag from a line
+fn tagged<'a>(tag: &str, line: Option<&'a str>) -> Result<&'a str, WalletError> {
+    line.and_then(|l| l.strip_prefix(tag))
+        .ok_or(WalletError::Malformed("missing field"))
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", self.domain, PREAMBLE)?;
+        writeln!(f, "{}", self.address)?;
+        writeln!(f)?;
+        if let Some(ref statement) = self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{}{}", URI_TAG, self.uri)?;
+        writeln!(f, "{}{}", VERSION_TAG, self.version)?;
+        writeln!(f, "{}{}", CHAIN_TAG, self.chain_id)?;
+        writeln!(f, "{}{}", NONCE_TAG, self.nonce)?;
+        write!(f, "{}{}", ISSUED_AT_TAG, format_time(&self.issued_at))?;
+        if let Some(ref exp) = self.expiration_time {
+            write!(f, "\n{}{}", EXPIRATION_TAG, format_time(exp))?;
+        }
+        if let Some(ref nbf) = self.not_before {
+            write!(f, "\n{}{}", NOT_BEFORE_TAG, format_time(nbf))?;
+        }
+        if let Some(ref request_id) = self.request_id {
+            write!(f, "\n{}{}", REQUEST_ID_TAG, request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\n{}", RESOURCES_TAG)?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.split('\n');
+
+        let domain = lines
+            .next()
+            .and_then(|l| l.strip_suffix(PREAMBLE))
+            .filter(|d| !d.is_empty())
+            .ok_or(WalletError::Malformed("missing preamble"))?
+            .to_string();
+
+        let address = lines.next().ok_or(WalletError::InvalidAddress)?;
+        // The message must carry the checksummed form
+        if checksum_address(address)? != address {
+            return Err(WalletError::InvalidAddress);
+        }
+
+        if lines.next() != Some("") {
+            return Err(WalletError::Malformed("expected blank line after address"));
+        }
+        let statement = match lines.next() {
+            Some("") => None,
+            Some(statement) => {
+                if lines.next() != Some("") {
+                    return Err(WalletError::Malformed(
+                        "expected blank line after statement",
+                    ));
+                }
+                Some(statement.to_string())
+            }
+            None => return Err(WalletError::Malformed("message ends after address")),
+        };
+
+        let uri = tagged(URI_TAG, lines.next())?.to_string();
+        let version = tagged(VERSION_TAG, lines.next())?;
+        if version != "1" {
+            return Err(WalletError::Malformed("unsupported version"));
+        }
+        let chain_id = tagged(CHAIN_TAG, lines.next())?
+            .parse()
+            .map_err(|_| WalletError::Malformed("invalid chain ID"))?;
+        let nonce = tagged(NONCE_TAG, lines.next())?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(WalletError::Malformed("invalid nonce"));
+        }
+        let issued_at = parse_time(tagged(ISSUED_AT_TAG, lines.next())?)?;
+
+        let mut message = SiweMessage {
+            domain,
+            address: address.to_string(),
+            statement,
+            uri,
+            version: version.to_string(),
+            chain_id,
+            nonce: nonce.to_string(),
+            issued_at,
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        };
+
+        // Optional fields, in this order
+        let mut line = lines.next();
+        if let Some(exp) = line.and_then(|l| l.strip_prefix(EXPIRATION_TAG)) {
+            message.expiration_time = Some(parse_time(exp)?);
+            line = lines.next();
+        }
+        if let Some(nbf) = line.and_then(|l| l.strip_prefix(NOT_BEFORE_TAG)) {
+            message.not_before = Some(parse_time(nbf)?);
+            line = lines.next();
+        }
+        if let Some(request_id) = line.and_then(|l| l.strip_prefix(REQUEST_ID_TAG)) {
+            message.request_id = Some(request_id.to_string());
+            line = lines.next();
+        }
+        if line == Some(RESOURCES_TAG) {
+            for resource in lines.by_ref() {
+                let resource = resource
+                    .strip_prefix("- ")
+                    .ok_or(WalletError::Malformed("invalid resource"))?;
+                message.resources.push(resource.to_string());
+            }
+            line = None;
+        }
+        if line.is_some() {
+            return Err(WalletError::Malformed("unexpected content"));
+        }
+
+        Ok(message)
+    }
+}
+
+/// Generate a sign-in nonce (alphanumeric, as EIP-4361 requires)
+pub fn generate_nonce() -> String {
+    rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/// Whether `value` has the shape of an address, in any case
+pub fn is_address(value: &str) -> bool {
+    value
+        .strip_prefix("0x")
+        .is_some_and(|h| h.len() == 40 && h.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Normalize an address to its EIP-55 checksummed form
+///
+/// Accepts any case, but a mixed-case address must already carry a
+/// correct checksum.
+pub fn checksum_address(address: &str) -> Result<String, WalletError> {
+    if !is_address(address) {
+        return Err(WalletError::InvalidAddress);
+    }
+    let hex_part = &address[2..];
+
+    let lower = hex_part.to_ascii_lowercase();
+    let hash = Keccak256::digest(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    let mixed_case = hex_part != lower && hex_part != hex_part.to_ascii_uppercase();
+    if mixed_case && hex_part != checksummed {
+        return Err(WalletError::InvalidAddress);
+    }
+    Ok(format!("0x{}", checksummed))
+}
+
+/// Checksummed address of a public key
+fn key_address(key: &VerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    let hash = Keccak256::digest(&point.as_bytes()[1..]);
+    let address = format!("0x{}", hex::encode(&hash[12..]));
+    checksum_address(&address).expect("derived address is well-formed")
+}
+
+/// Hash a message the way `personal_sign` does (EIP-191 version 0x45)
+fn personal_message_hash(message: &str) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+    hasher.update(message.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Recover the checksummed address that signed a message
+///
+/// `signature` is the 65-byte `r || s || v` hex string wallets return,
+/// with v either 0/1 or 27/28.
+pub fn recover_address(message: &str, signature: &str) -> Result<String, WalletError> {
+    let bytes = hex::decode(signature.strip_prefix("0x").unwrap_or(signature))
+        .map_err(|_| WalletError::MalformedSignature)?;
+    if bytes.len() != 65 {
+        return Err(WalletError::MalformedSignature);
+    }
+
+    let signature =
+        Signature::from_slice(&bytes[..64]).map_err(|_| WalletError::MalformedSignature)?;
+    let v = match bytes[64] {
+        27 | 28 => bytes[64] - 27,
+        v => v,
+    };
+    let recovery_id = RecoveryId::from_byte(v).ok_or(WalletError::MalformedSignature)?;
+
+    let key = VerifyingKey::recover_from_prehash(
+        &personal_message_hash(message),
+        &signature,
+        recovery_id,
+    )
+    .map_err(|_| WalletError::SignatureMismatch)?;
+    Ok(key_address(&key))
+}
+
+/// Parse a sign-in message and check it was signed by the address it names
+///
+/// Does not check the domain, times or nonce; see `SiweMessage::check`.
+pub fn verify(message: &str, signature: &str) -> Result<SiweMessage, WalletError> {
+    let parsed: SiweMessage = message.parse()?;
+    if recover_address(message, signature)? != parsed.address {
+        return Err(WalletError::SignatureMismatch);
+    }
+    Ok(parsed)
+}
+
+/// Address of a private key
+pub fn signer_address(key: &SigningKey) -> String {
+    key_address(key.verifying_key())
+}
+
+/// Sign a message as a wallet's `personal_sign` would
+///
+/// Returns the `0x`-prefixed `r || s || v` hex signature, with v as 27/28.
+/// The server never holds wallet keys; this is for tools and tests.
+pub fn sign_message(key: &SigningKey, message: &str) -> String {
+    let (signature, recovery_id) = key
+        .sign_prehash_recoverable(&personal_message_hash(message))
+        .expect("signing a 32-byte hash cannot fail");
+    let mut bytes = signature.to_bytes().to_vec();
+    bytes.push(recovery_id.to_byte() + 27);
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> SigningKey {
+        let secret =
+            hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+                .unwrap();
+        SigningKey::from_slice(&secret).unwrap()
+    }
+
+    fn test_message(address: &str) -> SiweMessage {
+        SiweMessage {
+            domain: "mud.example.com".to_string(),
+            address: address.to_string(),
+            statement: Some("Sign in to HemiMUD".to_string()),
+            uri: "https://mud.example.com".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+            nonce: "abcdEFGH1234".to_string(),
+            issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+            expiration_time: Some("2026-01-01T00:10:00Z".parse().unwrap()),
+            not_before: None,
+            request_id: None,
+            resources: vec!["https://mud.example.com/terms".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_checksum_address() {
+        let expected = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        assert_eq!(checksum_address(expected).unwrap(), expected);
+        assert_eq!(
+            checksum_address(&expected.to_lowercase()).unwrap(),
+            expected
+        );
+        // Mixed case with a bad checksum
+        assert_eq!(
+            checksum_address("0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Err(WalletError::InvalidAddress)
+        );
+        assert!(checksum_address("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_err());
+        assert!(checksum_address("0x1234").is_err());
+        assert!(is_address("0x5AAEB6053f3e94c9b9a09f33669435e7ef1beaed"));
+        assert!(!is_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaeg"));
+        assert!(!is_address("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"));
+    }
+
+    #[test]
+    fn test_known_signature() {
+        // Vector from the web3.js accounts.sign documentation
+        let key = test_key();
+        assert_eq!(
+            signer_address(&key),
+            "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23"
+        );
+        let signature = "0xb91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a0291c";
+        assert_eq!(sign_message(&key, "Some data"), signature);
+        assert_eq!(
+            recover_address("Some data", signature).unwrap(),
+            "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23"
+        );
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = test_message("0x2c7536E3605D9C16a7a3D7b1898e529396a65c23");
+        let text = message.to_string();
+        assert!(text.starts_with(
+            "mud.example.com wants you to sign in with your Ethereum account:\n0x2c75"
+        ));
+        assert_eq!(text.parse::<SiweMessage>().unwrap(), message);
+
+        let bare = SiweMessage {
+            statement: None,
+            expiration_time: None,
+            resources: Vec::new(),
+            ..message
+        };
+        assert!(bare.to_string().contains("a65c23\n\n\nURI: "));
+        assert_eq!(bare.to_string().parse::<SiweMessage>().unwrap(), bare);
+    }
+
+    #[test]
+    fn test_malformed_messages() {
+        let text = test_message("0x2c7536E3605D9C16a7a3D7b1898e529396a65c23").to_string();
+
+        let lowercase = text.replace(
+            "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23",
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23",
+        );
+        assert_eq!(
+            lowercase.parse::<SiweMessage>(),
+            Err(WalletError::InvalidAddress)
+        );
+        assert!(text
+            .replace("Nonce: abcdEFGH1234", "Nonce: abc")
+            .parse::<SiweMessage>()
+            .is_err());
+        assert!(text
+            .replace("Version: 1", "Version: 2")
+            .parse::<SiweMessage>()
+            .is_err());
+        assert!(format!("{}\nExtra: line", text)
+            .parse::<SiweMessage>()
+            .is_err());
+        assert!("hello".parse::<SiweMessage>().is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = test_key();
+        let text = test_message(&signer_address(&key)).to_string();
+        let signature = sign_message(&key, &text);
+
+        let message = verify(&text, &signature).unwrap();
+        assert_eq!(message.nonce, "abcdEFGH1234");
+
+        // Signed by a different key
+        let other = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        assert_eq!(
+            verify(&text, &sign_message(&other, &text)),
+            Err(WalletError::SignatureMismatch)
+        );
+
+        // Message altered after signing
+        let altered = text.replace("Chain ID: 1", "Chain ID: 5");
+        assert_eq!(
+            verify(&altered, &signature),
+            Err(WalletError::SignatureMismatch)
+        );
+
+        assert_eq!(
+            verify(&text, "0x1234"),
+            Err(WalletError::MalformedSignature)
+        );
+    }
+
+    #[test]
+    fn test_check_domain_and_times() {
+        let message = test_message("0x2c7536E3605D9C16a7a3D7b1898e529396a65c23");
+        let during = "2026-01-01T00:05:00Z".parse().unwrap();
+        let after = "2026-01-01T00:10:00Z".parse().unwrap();
+
+        assert!(message.check("mud.example.com", during).is_ok());
+        assert_eq!(
+            message.check("evil.example.com", during),
+            Err(WalletError::WrongDomain("mud.example.com".to_string()))
+        );
+        assert_eq!(
+            message.check("mud.example.com", after),
+            Err(WalletError::Expired)
+        );
+
+        let later = SiweMessage {
+            not_before: Some(after),
+            ..message
+        };
+        assert_eq!(
+            later.check("mud.example.com", during),
+            Err(WalletError::NotYetValid)
+        );
+    }
+}
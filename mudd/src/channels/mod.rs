@@ -0,0 +1,350 @@
+//! Chat channels
+//!
+//! Named channels players join to talk beyond their room:
+//! - `gossip` reaches players in every universe
+//! - `chat` reaches players in the same universe
+//! - `wiz` reaches wizards and above in every universe
+//! - Universes add their own (e.g. one per guild) with the `channels`
+//!   universe setting
+//!
+//! Message history and moderators' mutes are kept in SQLite. Both are
+//! written through Raft so every node can show the same history.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::permissions::AccessLevel;
+use crate::raft::{write_entry, GameLogEntry, RaftWriter};
+
+/// Messages kept per channel
+pub const HISTORY_LIMIT: i64 = 100;
+
+/// Where a channel's messages reach
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Every universe on the server
+    Global,
+    /// One universe
+    Universe,
+}
+
+/// A channel players can join
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelDef {
+    pub name: String,
+    pub scope: Scope,
+    /// Lowest access level that can join and talk
+    pub min_level: AccessLevel,
+    /// Value the player object's `guild` property must have to join
+    pub guild: Option<String>,
+    /// Joined automatically by players who may use it
+    pub auto_join: bool,
+}
+
+impl ChannelDef {
+    fn builtin(name: &str, scope: Scope, min_level: AccessLevel) -> Self {
+        Self {
+            name: name.to_string(),
+            scope,
+            min_level,
+            guild: None,
+            auto_join: true,
+        }
+    }
+
+    /// Access level that can mute players on this channel
+    pub fn moderator_level(&self) -> AccessLevel {
+        self.min_level.max(AccessLevel::Wizard)
+    }
+
+    /// Key separating this channel's history and mutes by universe
+    ///
+    /// Empty for channels shared by every universe.
+    pub fn scope_key<'a>(&self, universe_id: &'a str) -> &'a str {
+        match self.scope {
+            Scope::Global => "",
+            Scope::Universe => universe_id,
+        }
+    }
+}
+
+/// A channel a universe defines, as stored in its `channels` setting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSetting {
+    pub name: String,
+    /// "player", "builder", "wizard", "admin" or "owner"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guild: Option<String>,
+    #[serde(default)]
+    pub auto_join: bool,
+}
+
+impl ChannelSetting {
+    /// Key in universe_settings
+    pub const SETTING_KEY: &'static str = "channels";
+}
+
+/// Channels open to a universe: the built-in ones, then its own
+///
+/// A universe channel can't take the name of a built-in one.
+pub fn channel_defs(settings: &[ChannelSetting]) -> Vec<ChannelDef> {
+    let mut defs = vec![
+        ChannelDef::builtin("gossip", Scope::Global, AccessLevel::Player),
+        ChannelDef::builtin("chat", Scope::Universe, AccessLevel::Player),
+        ChannelDef::builtin("wiz", Scope::Global, AccessLevel::Wizard),
+    ];
+    for setting in settings {
+        let name = setting.name.to_lowercase();
+        if name.is_empty() || defs.iter().any(|d| d.name == name) {
+            continue;
+        }
+        defs.push(ChannelDef {
+            name,
+            scope: Scope::Universe,
+            min_level: setting
+                .min_level
+                .as_deref()
+                .and_then(|level| level.parse().ok())
+                .unwrap_or_default(),
+            guild: setting.guild.clone(),
+            auto_join: setting.auto_join,
+        });
+    }
+    defs
+}
+
+/// A message said on a channel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelPost {
+    pub id: String,
+    pub channel: String,
+    /// Universe for universe channels, empty for global ones
+    pub universe_id: String,
+    pub sender_id: String,
+    pub sender: String,
+    pub text: String,
+    pub created_at: String,
+}
+
+impl ChannelPost {
+    /// A new message, with its ID and time fixed for replication
+    pub fn new(
+        def: &ChannelDef,
+        universe_id: &str,
+        sender_id: &str,
+        sender: &str,
+        text: &str,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            channel: def.name.clone(),
+            universe_id: def.scope_key(universe_id).to_string(),
+            sender_id: sender_id.to_string(),
+            sender: sender.to_string(),
+            text: text.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Channel history and mutes in SQLite
+pub struct ChannelStore {
+    pool: SqlitePool,
+    raft_writer: Option<Arc<RaftWriter>>,
+}
+
+impl ChannelStore {
+    /// Create a store writing through `raft_writer`, or straight to the
+    /// database without one
+    pub fn new(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Self {
+        Self { pool, raft_writer }
+    }
+
+    async fn write(&self, entry: GameLogEntry) -> anyhow::Result<u64> {
+        write_entry(self.raft_writer.as_deref(), &self.pool, entry).await
+    }
+
+    /// Add a message to its channel's history, dropping the oldest beyond
+    /// `HISTORY_LIMIT`
+    pub async fn record(&self, post: &ChannelPost) -> anyhow::Result<()> {
+        self.write(GameLogEntry::RecordChannelMessage {
+            message_id: post.id.clone(),
+            channel: post.channel.clone(),
+            universe_id: post.universe_id.clone(),
+            sender_id: post.sender_id.clone(),
+            sender_name: post.sender.clone(),
+            text: post.text.clone(),
+            created_at: post.created_at.clone(),
+            keep: HISTORY_LIMIT,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// The latest `limit` messages on a channel, oldest first
+    pub async fn history(
+        &self,
+        channel: &str,
+        scope_key: &str,
+        limit: i64,
+    ) -> anyhow::Result<Vec<ChannelPost>> {
+        let rows: Vec<(String, String, String, String, String, String, String)> = sqlx::query_as(
+            "SELECT id, channel, universe_id, sender_id, sender_name, text, created_at
+             FROM channel_messages WHERE channel = ? AND universe_id = ?
+             ORDER BY created_at DESC, id DESC LIMIT ?",
+        )
+        .bind(channel)
+        .bind(scope_key)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .rev()
+            .map(
+                |(id, channel, universe_id, sender_id, sender, text, created_at)| ChannelPost {
+                    id,
+                    channel,
+                    universe_id,
+                    sender_id,
+                    sender,
+                    text,
+                    created_at,
+                },
+            )
+            .collect())
+    }
+
+    /// Stop a player talking on a channel
+    pub async fn mute(
+        &self,
+        channel: &str,
+        scope_key: &str,
+        player_id: &str,
+        muted_by: &str,
+    ) -> anyhow::Result<()> {
+        self.write(GameLogEntry::MuteChannel {
+            channel: channel.to_string(),
+            universe_id: scope_key.to_string(),
+            player_id: player_id.to_string(),
+            muted_by: muted_by.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Let a muted player talk again; returns whether they were muted
+    pub async fn unmute(
+        &self,
+        channel: &str,
+        scope_key: &str,
+        player_id: &str,
+    ) -> anyhow::Result<bool> {
+        let rows = self
+            .write(GameLogEntry::UnmuteChannel {
+                channel: channel.to_string(),
+                universe_id: scope_key.to_string(),
+                player_id: player_id.to_string(),
+            })
+            .await?;
+        Ok(rows > 0)
+    }
+
+    /// Whether a player is muted on a channel
+    pub async fn is_muted(
+        &self,
+        channel: &str,
+        scope_key: &str,
+        player_id: &str,
+    ) -> anyhow::Result<bool> {
+        let row: Option<(i64,)> = sqlx::query_as(
+            "SELECT 1 FROM channel_mutes WHERE channel = ? AND universe_id = ? AND player_id = ?",
+        )
+        .bind(channel)
+        .bind(scope_key)
+        .bind(player_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_universe_channels_follow_builtins() {
+        let settings: Vec<ChannelSetting> = serde_json::from_str(
+            r#"[{"name": "Thieves", "guild": "thieves"}, {"name": "wiz"}, {"name": "council", "min_level": "admin", "auto_join": true}]"#,
+        )
+        .unwrap();
+        let defs = channel_defs(&settings);
+        let names: Vec<&str> = defs.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["gossip", "chat", "wiz", "thieves", "council"]);
+
+        let thieves = &defs[3];
+        assert_eq!(thieves.scope, Scope::Universe);
+        assert_eq!(thieves.guild.as_deref(), Some("thieves"));
+        assert!(!thieves.auto_join);
+        assert_eq!(defs[4].min_level, AccessLevel::Admin);
+        assert_eq!(defs[4].moderator_level(), AccessLevel::Admin);
+        assert_eq!(defs[0].moderator_level(), AccessLevel::Wizard);
+        assert_eq!(defs[0].scope_key("u1"), "");
+        assert_eq!(defs[1].scope_key("u1"), "u1");
+    }
+
+    #[tokio::test]
+    async fn test_history_is_trimmed_and_scoped() {
+        let db = Database::new(None).await.unwrap();
+        let store = ChannelStore::new(db.pool().clone(), None);
+        let defs = channel_defs(&[]);
+        let chat = &defs[1];
+
+        for i in 0..HISTORY_LIMIT + 5 {
+            let mut post = ChannelPost::new(chat, "u1", "/players/a", "alice", &i.to_string());
+            // Distinct, ordered times without waiting
+            post.created_at = format!("2026-01-01T00:00:{:03}Z", i);
+            store.record(&post).await.unwrap();
+        }
+        let other = ChannelPost::new(chat, "u2", "/players/b", "bob", "elsewhere");
+        store.record(&other).await.unwrap();
+
+        let history = store.history("chat", "u1", 1000).await.unwrap();
+        assert_eq!(history.len() as i64, HISTORY_LIMIT);
+        assert_eq!(history[0].text, "5");
+        assert_eq!(
+            history.last().unwrap().text,
+            (HISTORY_LIMIT + 4).to_string()
+        );
+
+        let latest = store.history("chat", "u2", 10).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].sender, "bob");
+    }
+
+    #[tokio::test]
+    async fn test_mutes() {
+        let db = Database::new(None).await.unwrap();
+        let store = ChannelStore::new(db.pool().clone(), None);
+
+        assert!(!store.is_muted("gossip", "", "/players/a").await.unwrap());
+        store
+            .mute("gossip", "", "/players/a", "/players/wiz")
+            .await
+            .unwrap();
+        assert!(store.is_muted("gossip", "", "/players/a").await.unwrap());
+        assert!(!store.is_muted("chat", "u1", "/players/a").await.unwrap());
+
+        assert!(store.unmute("gossip", "", "/players/a").await.unwrap());
+        assert!(!store.unmute("gossip", "", "/players/a").await.unwrap());
+        assert!(!store.is_muted("gossip", "", "/players/a").await.unwrap());
+    }
+}
@@ -14,6 +14,7 @@ use tokio::sync::RwLock;
 use tracing::{debug, warn};
 
 use super::DamageType;
+use crate::raft::{write_entry, EffectRecord, GameLogEntry, RaftWriter};
 
 /// Types of status effects
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -280,10 +281,10 @@ impl EntityEffects {
 }
 
 /// Global effect registry for tracking effects on all entities
-#[derive(Debug)]
 pub struct EffectRegistry {
     entities: RwLock<HashMap<String, EntityEffects>>,
     db_pool: Option<SqlitePool>,
+    raft_writer: Option<Arc<RaftWriter>>,
 }
 
 impl Default for EffectRegistry {
@@ -291,6 +292,7 @@ impl Default for EffectRegistry {
         Self {
             entities: RwLock::new(HashMap::new()),
             db_pool: None,
+            raft_writer: None,
         }
     }
 }
@@ -301,11 +303,12 @@ impl EffectRegistry {
         Self::default()
     }
 
-    /// Create a new effect registry with database pool
-    pub fn with_db(pool: SqlitePool) -> Self {
+    /// Create a new effect registry with database pool and raft writer
+    pub fn with_db(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Self {
         Self {
             entities: RwLock::new(HashMap::new()),
             db_pool: Some(pool),
+            raft_writer,
         }
     }
 
@@ -314,9 +317,9 @@ impl EffectRegistry {
         Arc::new(Self::new())
     }
 
-    /// Create a shared instance with database pool
-    pub fn shared_with_db(pool: SqlitePool) -> Arc<Self> {
-        Arc::new(Self::with_db(pool))
+    /// Create a shared instance with database pool and raft writer
+    pub fn shared_with_db(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Arc<Self> {
+        Arc::new(Self::with_db(pool, raft_writer))
     }
 
     /// Add an effect to an entity
@@ -335,6 +338,12 @@ impl EffectRegistry {
             .add(effect);
     }
 
+    /// Apply a log entry (via Raft if available)
+    async fn write(&self, pool: &SqlitePool, entry: GameLogEntry) -> anyhow::Result<()> {
+        write_entry(self.raft_writer.as_deref(), pool, entry).await?;
+        Ok(())
+    }
+
     /// Persist an effect to database
     async fn persist_effect(
         &self,
@@ -342,41 +351,22 @@ impl EffectRegistry {
         effect: &StatusEffect,
         pool: &SqlitePool,
     ) -> anyhow::Result<()> {
-        let effect_type_str = effect.effect_type.to_string();
-        let damage_type_str = effect.damage_type.map(|dt| format!("{:?}", dt));
-        let id = uuid::Uuid::new_v4().to_string();
-
-        sqlx::query(
-            r#"
-            INSERT INTO active_effects
-            (id, entity_id, effect_type, remaining_ticks, magnitude, damage_type, source_id)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(&id)
-        .bind(entity_id)
-        .bind(&effect_type_str)
-        .bind(effect.remaining_ticks as i32)
-        .bind(effect.magnitude)
-        .bind(&damage_type_str)
-        .bind(&effect.source_id)
-        .execute(pool)
-        .await?;
-        Ok(())
+        let entry = GameLogEntry::AddEffect {
+            entity_id: entity_id.to_string(),
+            effect: effect_record(effect),
+        };
+        self.write(pool, entry).await
     }
 
     /// Remove an effect from an entity
     pub async fn remove_effect(&self, entity_id: &str, effect_type: EffectType) {
         // Remove from database
         if let Some(ref pool) = self.db_pool {
-            let effect_type_str = effect_type.to_string();
-            if let Err(e) =
-                sqlx::query("DELETE FROM active_effects WHERE entity_id = ? AND effect_type = ?")
-                    .bind(entity_id)
-                    .bind(&effect_type_str)
-                    .execute(pool)
-                    .await
-            {
+            let entry = GameLogEntry::RemoveEffect {
+                entity_id: entity_id.to_string(),
+                effect_type: effect_type.to_string(),
+            };
+            if let Err(e) = self.write(pool, entry).await {
                 warn!(
                     "Failed to remove effect {} for {}: {}",
                     effect_type, entity_id, e
@@ -425,19 +415,24 @@ impl EffectRegistry {
 
     /// Sync effects to database after tick
     async fn sync_effects_to_db(&self, entity_id: &str, pool: &SqlitePool) -> anyhow::Result<()> {
-        // Delete all effects for entity and re-insert active ones
-        sqlx::query("DELETE FROM active_effects WHERE entity_id = ?")
-            .bind(entity_id)
-            .execute(pool)
-            .await?;
-
-        let entities = self.entities.read().await;
-        if let Some(entity_effects) = entities.get(entity_id) {
-            for effect in entity_effects.active_effects() {
-                self.persist_effect(entity_id, effect, pool).await?;
-            }
-        }
-        Ok(())
+        // Replace all effects for entity with the active ones, in one write
+        let effects = {
+            let entities = self.entities.read().await;
+            entities
+                .get(entity_id)
+                .map_or_else(Vec::new, |entity_effects| {
+                    entity_effects
+                        .active_effects()
+                        .into_iter()
+                        .map(effect_record)
+                        .collect()
+                })
+        };
+        let entry = GameLogEntry::SetEffects {
+            entity_id: entity_id.to_string(),
+            effects,
+        };
+        self.write(pool, entry).await
     }
 
     /// Load effects from database on startup
@@ -503,11 +498,11 @@ impl EffectRegistry {
     pub async fn clear(&self, entity_id: &str) {
         // Remove from database
         if let Some(ref pool) = self.db_pool {
-            if let Err(e) = sqlx::query("DELETE FROM active_effects WHERE entity_id = ?")
-                .bind(entity_id)
-                .execute(pool)
-                .await
-            {
+            let entry = GameLogEntry::SetEffects {
+                entity_id: entity_id.to_string(),
+                effects: Vec::new(),
+            };
+            if let Err(e) = self.write(pool, entry).await {
                 warn!("Failed to clear effects for {}: {}", entity_id, e);
             }
         }
@@ -517,6 +512,18 @@ impl EffectRegistry {
     }
 }
 
+/// An effect's database row, under a new row ID
+fn effect_record(effect: &StatusEffect) -> EffectRecord {
+    EffectRecord {
+        effect_id: uuid::Uuid::new_v4().to_string(),
+        effect_type: effect.effect_type.to_string(),
+        remaining_ticks: effect.remaining_ticks,
+        magnitude: effect.magnitude,
+        damage_type: effect.damage_type.map(|dt| format!("{:?}", dt)),
+        source_id: effect.source_id.clone(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
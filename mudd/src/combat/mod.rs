@@ -16,4 +16,4 @@ mod state;
 pub use damage::{DamageModifier, DamageResult, DamageType};
 pub use dice::{parse_dice, roll_dice, DiceRoll};
 pub use effects::{EffectRegistry, EffectType, EntityEffects, StatusEffect};
-pub use state::{AttackResult, CombatManager, CombatState, PvpPolicy};
+pub use state::{AttackResult, CombatManager, CombatState, DamageEvent, PvpPolicy};
@@ -7,13 +7,14 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
 use sqlx::SqlitePool;
 use tracing::{debug, warn};
 
 use super::damage::{DamageProfile, DamageResult, DamageType};
 use super::dice::{is_critical, is_fumble, roll_d20};
+use crate::raft::{write_entry, GameLogEntry, RaftWriter};
 
 /// PvP policy for a universe
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -203,8 +204,18 @@ impl AttackResult {
     }
 }
 
+/// Damage an entity took, waiting for the server to run its handlers
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    pub target_id: String,
+    /// The attacker, or None for direct damage
+    pub actor_id: Option<String>,
+    pub damage: DamageResult,
+    /// Whether this damage killed the target
+    pub killed: bool,
+}
+
 /// Combat manager for a universe
-#[derive(Debug)]
 pub struct CombatManager {
     /// Combat states by entity ID
     states: RwLock<BTreeMap<String, CombatState>>,
@@ -212,6 +223,10 @@ pub struct CombatManager {
     pvp_policy: RwLock<PvpPolicy>,
     /// Database pool for persistence
     db_pool: Option<SqlitePool>,
+    /// Raft writer for replicated persistence
+    raft_writer: Option<Arc<RaftWriter>>,
+    /// Damage dealt since the last `take_damage_events`
+    damage_events: Mutex<Vec<DamageEvent>>,
 }
 
 impl Default for CombatManager {
@@ -220,6 +235,8 @@ impl Default for CombatManager {
             states: RwLock::new(BTreeMap::new()),
             pvp_policy: RwLock::new(PvpPolicy::default()),
             db_pool: None,
+            raft_writer: None,
+            damage_events: Mutex::new(Vec::new()),
         }
     }
 }
@@ -230,12 +247,14 @@ impl CombatManager {
         Self::default()
     }
 
-    /// Create a new combat manager with database pool
-    pub fn with_db(pool: SqlitePool) -> Self {
+    /// Create a new combat manager with database pool and raft writer
+    pub fn with_db(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Self {
         Self {
             states: RwLock::new(BTreeMap::new()),
             pvp_policy: RwLock::new(PvpPolicy::default()),
             db_pool: Some(pool),
+            raft_writer,
+            damage_events: Mutex::new(Vec::new()),
         }
     }
 
@@ -244,9 +263,9 @@ impl CombatManager {
         Arc::new(Self::new())
     }
 
-    /// Create a shared instance with database pool
-    pub fn shared_with_db(pool: SqlitePool) -> Arc<Self> {
-        Arc::new(Self::with_db(pool))
+    /// Create a shared instance with database pool and raft writer
+    pub fn shared_with_db(pool: SqlitePool, raft_writer: Option<Arc<RaftWriter>>) -> Arc<Self> {
+        Arc::new(Self::with_db(pool, raft_writer))
     }
 
     /// Set the PvP policy
@@ -292,7 +311,7 @@ impl CombatManager {
             .insert(entity_id.to_string(), state);
     }
 
-    /// Persist combat state to database
+    /// Persist combat state to database (via Raft if available)
     async fn persist_state(
         &self,
         entity_id: &str,
@@ -300,21 +319,15 @@ impl CombatManager {
         state: &CombatState,
         pool: &SqlitePool,
     ) -> anyhow::Result<()> {
-        sqlx::query(
-            r#"
-            INSERT OR REPLACE INTO combat_state
-            (entity_id, universe_id, hp, max_hp, armor_class, attack_bonus)
-            VALUES (?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(entity_id)
-        .bind(universe_id)
-        .bind(state.hp)
-        .bind(state.max_hp)
-        .bind(state.armor_class)
-        .bind(state.attack_bonus)
-        .execute(pool)
-        .await?;
+        let entry = GameLogEntry::SetCombatState {
+            entity_id: entity_id.to_string(),
+            universe_id: universe_id.to_string(),
+            hp: state.hp,
+            max_hp: state.max_hp,
+            armor_class: state.armor_class,
+            attack_bonus: state.attack_bonus,
+        };
+        write_entry(self.raft_writer.as_deref(), pool, entry).await?;
         Ok(())
     }
 
@@ -429,7 +442,14 @@ impl CombatManager {
                 // Calculate and apply damage
                 let is_crit = result.critical;
                 let defender_mut = states.get_mut(defender_id).unwrap();
+                let was_dead = defender_mut.is_dead();
                 let damage_result = defender_mut.take_damage(damage_dice, damage_type, is_crit);
+                self.damage_events.lock().await.push(DamageEvent {
+                    target_id: defender_id.to_string(),
+                    actor_id: Some(attacker_id.to_string()),
+                    damage: damage_result.clone(),
+                    killed: !was_dead && defender_mut.is_dead(),
+                });
                 result = result.with_damage(damage_result);
             }
 
@@ -457,7 +477,15 @@ impl CombatManager {
         let result = {
             let mut states = self.states.write().await;
             let target = states.get_mut(target_id).ok_or("Target not found")?;
-            target.take_damage(amount, damage_type, is_crit)
+            let was_dead = target.is_dead();
+            let result = target.take_damage(amount, damage_type, is_crit);
+            self.damage_events.lock().await.push(DamageEvent {
+                target_id: target_id.to_string(),
+                actor_id: None,
+                damage: result.clone(),
+                killed: !was_dead && target.is_dead(),
+            });
+            result
         };
 
         // Persist HP change
@@ -468,6 +496,13 @@ impl CombatManager {
         Ok(result)
     }
 
+    /// Take the damage dealt since the last call, oldest first
+    ///
+    /// The server fires `on_damage` and `on_death` handlers for each.
+    pub async fn take_damage_events(&self) -> Vec<DamageEvent> {
+        std::mem::take(&mut *self.damage_events.lock().await)
+    }
+
     /// Heal an entity
     pub async fn heal(&self, target_id: &str, amount: i32) -> Result<i32, String> {
         let healed = {
@@ -523,11 +558,10 @@ impl CombatManager {
 
         // Remove from database (cascades to active_effects)
         if let Some(ref pool) = self.db_pool {
-            if let Err(e) = sqlx::query("DELETE FROM combat_state WHERE entity_id = ?")
-                .bind(entity_id)
-                .execute(pool)
-                .await
-            {
+            let entry = GameLogEntry::DeleteCombatState {
+                entity_id: entity_id.to_string(),
+            };
+            if let Err(e) = write_entry(self.raft_writer.as_deref(), pool, entry).await {
                 warn!("Failed to remove combat state for {}: {}", entity_id, e);
             }
         }
@@ -1,18 +1,25 @@
 //! Credit system for in-game currency
 //!
 //! Provides:
-//! - Balance management (get, deduct, grant)
-//! - Transaction logging for auditing
+//! - Balance management (get, deduct, grant, transfer)
+//! - Transaction ledger for auditing
 //! - Persistence in SQLite
+//!
+//! Every balance change is proposed to Raft as a single entry that updates
+//! `credits` and appends to `credit_transactions` in the same transaction,
+//! so the ledger always sums to the stored balance.
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use sqlx::SqlitePool;
 use tokio::sync::RwLock;
 use tracing::{debug, warn};
 
-use crate::raft::RaftWriter;
+use crate::lua::MILLICREDITS_PER_CREDIT;
+use crate::raft::{GameLogEntry, ItemTransfer, RaftWriter};
 
 /// Credit balance for a player in a universe
 #[derive(Debug, Clone)]
@@ -26,24 +33,67 @@ pub struct CreditBalance {
 }
 
 /// Transaction record for auditing
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CreditTransaction {
+    /// Transaction ID
+    pub id: String,
     /// Universe ID
     pub universe_id: String,
     /// Player account ID
     pub account_id: String,
     /// Amount (positive = credit, negative = debit)
     pub amount: i64,
+    /// Balance after this transaction
+    pub balance_after: i64,
     /// Reason for transaction
     pub reason: String,
     /// Timestamp
-    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl CreditTransaction {
+    /// Start a record for a change about to be applied
+    ///
+    /// `balance_after` is filled in once the change has been applied.
+    fn pending(universe_id: &str, account_id: &str, amount: i64, reason: &str) -> Self {
+        Self {
+            // Pre-compute UUID and timestamp for deterministic replication
+            id: uuid::Uuid::new_v4().to_string(),
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            amount,
+            balance_after: 0,
+            reason: reason.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// A stored balance checked against the ledger
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceAudit {
+    /// Universe ID
+    pub universe_id: String,
+    /// Player account ID
+    pub account_id: String,
+    /// Balance stored in `credits`
+    pub balance: i64,
+    /// Sum of all ledger amounts
+    pub ledger_total: i64,
+    /// Number of ledger entries
+    pub transaction_count: i64,
+    /// Whether the balance matches the ledger
+    pub consistent: bool,
 }
 
 /// Credit manager for handling in-game currency
 pub struct CreditManager {
     /// In-memory cache of balances: (universe_id, account_id) -> balance
     balances: RwLock<HashMap<(String, String), i64>>,
+    /// Ledger kept in memory when there is no database (tests)
+    transactions: RwLock<Vec<CreditTransaction>>,
+    /// Usage short of a whole credit, carried to each account's next charge
+    carried: RwLock<HashMap<(String, String), u64>>,
     /// Database pool for persistence
     pool: Option<SqlitePool>,
     /// Raft writer for consensus
@@ -64,6 +114,8 @@ impl CreditManager {
     pub fn new(pool: Option<SqlitePool>, raft_writer: Option<Arc<RaftWriter>>) -> Self {
         Self {
             balances: RwLock::new(HashMap::new()),
+            transactions: RwLock::new(Vec::new()),
+            carried: RwLock::new(HashMap::new()),
             pool,
             raft_writer,
         }
@@ -112,11 +164,7 @@ impl CreditManager {
             return false;
         }
 
-        let key = (universe_id.to_string(), account_id.to_string());
-        let mut balances = self.balances.write().await;
-
-        // Get current balance
-        let current = *balances.get(&key).unwrap_or(&0);
+        let current = self.get_balance(universe_id, account_id).await;
         if current < amount {
             debug!(
                 "Insufficient credits: {} has {} but needs {}",
@@ -125,70 +173,364 @@ impl CreditManager {
             return false;
         }
 
-        // Deduct
-        let new_balance = current - amount;
-        balances.insert(key, new_balance);
-
-        // Persist via Raft
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = self
-                .save_balance(universe_id, account_id, new_balance, raft_writer)
-                .await
-            {
-                warn!("Failed to persist credit balance: {}", e);
-            }
+        // The state machine re-checks the balance, so a concurrent
+        // deduction cannot overdraw the account
+        let transaction = CreditTransaction::pending(universe_id, account_id, -amount, reason);
+        let entry = GameLogEntry::DeductCredits {
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            amount,
+            transaction_id: transaction.id.clone(),
+            reason: reason.to_string(),
+            created_at: transaction.timestamp.to_rfc3339(),
+        };
+        if let Err(e) = self.apply(entry, vec![transaction]).await {
+            debug!(
+                "Failed to deduct {} credits from {}: {}",
+                amount, account_id, e
+            );
+            return false;
         }
 
         debug!(
-            "Deducted {} credits from {}: {} -> {} ({})",
-            amount, account_id, current, new_balance, reason
+            "Deducted {} credits from {} ({})",
+            amount, account_id, reason
         );
         true
     }
 
+    /// Charge metered usage, given in millicredits
+    ///
+    /// Takes the whole credits due, or as many as the balance covers when
+    /// the charge is applied, so concurrent charges can neither overdraw
+    /// the account nor go unpaid while credits remain. What is short of a
+    /// whole credit is carried to the account's next charge. Carried usage
+    /// is kept in memory, so a restart forgives under a credit per account.
+    pub async fn charge_usage(
+        &self,
+        universe_id: &str,
+        account_id: &str,
+        millicredits: u64,
+        reason: &str,
+    ) {
+        let key = (universe_id.to_string(), account_id.to_string());
+        let due = {
+            let mut carried = self.carried.write().await;
+            let carried = carried.entry(key).or_insert(0);
+            let total = *carried + millicredits;
+            *carried = total % MILLICREDITS_PER_CREDIT;
+            (total / MILLICREDITS_PER_CREDIT) as i64
+        };
+        if due == 0 {
+            return;
+        }
+
+        // The state machine caps the charge at the stored balance; the
+        // in-memory ledger needs the cap up front
+        let current = self.get_balance(universe_id, account_id).await;
+        let charged = due.min(current.max(0));
+        let replicated = self.raft_writer.is_some() && self.pool.is_some();
+        if !replicated && charged == 0 {
+            return;
+        }
+
+        let transaction = CreditTransaction::pending(universe_id, account_id, -charged, reason);
+        let entry = GameLogEntry::ChargeCredits {
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            amount: due,
+            transaction_id: transaction.id.clone(),
+            reason: reason.to_string(),
+            created_at: transaction.timestamp.to_rfc3339(),
+        };
+        if let Err(e) = self.apply(entry, vec![transaction]).await {
+            warn!("Failed to charge {} credits to {}: {}", due, account_id, e);
+            return;
+        }
+
+        debug!(
+            "Charged up to {} credits to {} ({})",
+            due, account_id, reason
+        );
+    }
+
     /// Grant credits to a player (admin function)
-    pub async fn grant(&self, universe_id: &str, account_id: &str, amount: i64, reason: &str) {
+    /// Returns true if the credits were added
+    pub async fn grant(
+        &self,
+        universe_id: &str,
+        account_id: &str,
+        amount: i64,
+        reason: &str,
+    ) -> bool {
         if amount <= 0 {
-            return;
+            return false;
         }
 
-        let key = (universe_id.to_string(), account_id.to_string());
-        let mut balances = self.balances.write().await;
+        // Applied as a deposit, so the stored balance is correct even if
+        // the cached one was never loaded
+        let transaction = CreditTransaction::pending(universe_id, account_id, amount, reason);
+        let entry = GameLogEntry::DepositCredits {
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            amount,
+            balance_id: uuid::Uuid::new_v4().to_string(),
+            transaction_id: transaction.id.clone(),
+            reason: reason.to_string(),
+            created_at: transaction.timestamp.to_rfc3339(),
+        };
+        if let Err(e) = self.apply(entry, vec![transaction]).await {
+            warn!("Failed to persist credit grant: {}", e);
+            return false;
+        }
+
+        debug!("Granted {} credits to {} ({})", amount, account_id, reason);
+        true
+    }
 
-        // Get current balance
-        let current = *balances.get(&key).unwrap_or(&0);
-        let new_balance = current + amount;
-        balances.insert(key, new_balance);
+    /// Move credits from one player to another
+    ///
+    /// Both sides are recorded in the ledger with the same reason.
+    pub async fn transfer(
+        &self,
+        universe_id: &str,
+        from_account_id: &str,
+        to_account_id: &str,
+        amount: i64,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        self.transfer_with_item(
+            universe_id,
+            from_account_id,
+            to_account_id,
+            amount,
+            reason,
+            None,
+        )
+        .await
+    }
 
-        // Persist via Raft
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = self
-                .save_balance(universe_id, account_id, new_balance, raft_writer)
-                .await
-            {
-                warn!("Failed to persist credit balance: {}", e);
-            }
+    /// Pay for an object and move it to the buyer in one step
+    ///
+    /// Fails without charging anything if the object is no longer in
+    /// `item.from_parent_id` when the sale is applied.
+    pub async fn purchase(
+        &self,
+        universe_id: &str,
+        buyer_account_id: &str,
+        seller_account_id: &str,
+        price: i64,
+        reason: &str,
+        item: ItemTransfer,
+    ) -> anyhow::Result<()> {
+        if self.pool.is_none() {
+            anyhow::bail!("purchases require a database");
+        }
+        self.transfer_with_item(
+            universe_id,
+            buyer_account_id,
+            seller_account_id,
+            price,
+            reason,
+            Some(item),
+        )
+        .await
+    }
+
+    async fn transfer_with_item(
+        &self,
+        universe_id: &str,
+        from_account_id: &str,
+        to_account_id: &str,
+        amount: i64,
+        reason: &str,
+        item: Option<ItemTransfer>,
+    ) -> anyhow::Result<()> {
+        if amount <= 0 {
+            anyhow::bail!("amount must be positive");
+        }
+        if from_account_id == to_account_id {
+            anyhow::bail!("cannot transfer credits to yourself");
         }
 
+        let current = self.get_balance(universe_id, from_account_id).await;
+        if current < amount {
+            anyhow::bail!("insufficient credits");
+        }
+
+        let debit = CreditTransaction::pending(universe_id, from_account_id, -amount, reason);
+        let credit = CreditTransaction::pending(universe_id, to_account_id, amount, reason);
+        let entry = GameLogEntry::TransferCredits {
+            universe_id: universe_id.to_string(),
+            from_account_id: from_account_id.to_string(),
+            to_account_id: to_account_id.to_string(),
+            amount,
+            balance_id: uuid::Uuid::new_v4().to_string(),
+            debit_transaction_id: debit.id.clone(),
+            credit_transaction_id: credit.id.clone(),
+            reason: reason.to_string(),
+            item,
+            created_at: debit.timestamp.to_rfc3339(),
+        };
+        self.apply(entry, vec![debit, credit]).await?;
+
         debug!(
-            "Granted {} credits to {}: {} -> {} ({})",
-            amount, account_id, current, new_balance, reason
+            "Transferred {} credits from {} to {} ({})",
+            amount, from_account_id, to_account_id, reason
         );
+        Ok(())
     }
 
     /// Set balance directly (used for initialization)
     pub async fn set_balance(&self, universe_id: &str, account_id: &str, balance: i64) {
-        let key = (universe_id.to_string(), account_id.to_string());
-        self.balances.write().await.insert(key, balance);
+        // The state machine records the actual difference; this one is
+        // only used for the in-memory ledger
+        let current = self.get_balance(universe_id, account_id).await;
+        let transaction =
+            CreditTransaction::pending(universe_id, account_id, balance - current, "set_balance");
+        let entry = GameLogEntry::SetCredits {
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            balance,
+            balance_id: uuid::Uuid::new_v4().to_string(),
+            transaction_id: transaction.id.clone(),
+            reason: transaction.reason.clone(),
+            created_at: transaction.timestamp.to_rfc3339(),
+        };
+        if let Err(e) = self.apply(entry, vec![transaction]).await {
+            warn!("Failed to persist credit balance: {}", e);
+        }
+    }
 
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = self
-                .save_balance(universe_id, account_id, balance, raft_writer)
-                .await
-            {
-                warn!("Failed to persist credit balance: {}", e);
+    /// Get a player's transactions, newest first
+    pub async fn get_transactions(
+        &self,
+        universe_id: &str,
+        account_id: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<CreditTransaction>> {
+        let Some(ref pool) = self.pool else {
+            let transactions = self.transactions.read().await;
+            return Ok(transactions
+                .iter()
+                .rev()
+                .filter(|t| t.universe_id == universe_id && t.account_id == account_id)
+                .take(limit as usize)
+                .cloned()
+                .collect());
+        };
+
+        let rows: Vec<(String, i64, i64, String, String)> = sqlx::query_as(
+            "SELECT id, amount, balance_after, reason, created_at FROM credit_transactions WHERE universe_id = ? AND player_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?",
+        )
+        .bind(universe_id)
+        .bind(account_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, amount, balance_after, reason, created_at)| {
+                Ok(CreditTransaction {
+                    id,
+                    universe_id: universe_id.to_string(),
+                    account_id: account_id.to_string(),
+                    amount,
+                    balance_after,
+                    reason,
+                    timestamp: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
+    /// Check a player's stored balance against the sum of their ledger
+    pub async fn audit_balance(
+        &self,
+        universe_id: &str,
+        account_id: &str,
+    ) -> anyhow::Result<BalanceAudit> {
+        let (balance, ledger_total, transaction_count) = match self.pool {
+            Some(ref pool) => {
+                let balance = self.load_balance(universe_id, account_id, pool).await?;
+                let (total, count): (i64, i64) = sqlx::query_as(
+                    "SELECT COALESCE(SUM(amount), 0), COUNT(*) FROM credit_transactions WHERE universe_id = ? AND player_id = ?",
+                )
+                .bind(universe_id)
+                .bind(account_id)
+                .fetch_one(pool)
+                .await?;
+                (balance, total, count)
+            }
+            None => {
+                let balance = self.get_balance(universe_id, account_id).await;
+                let transactions = self.transactions.read().await;
+                let (total, count) = transactions
+                    .iter()
+                    .filter(|t| t.universe_id == universe_id && t.account_id == account_id)
+                    .fold((0, 0), |(total, count), t| (total + t.amount, count + 1));
+                (balance, total, count)
+            }
+        };
+
+        Ok(BalanceAudit {
+            universe_id: universe_id.to_string(),
+            account_id: account_id.to_string(),
+            balance,
+            ledger_total,
+            transaction_count,
+            consistent: balance == ledger_total,
+        })
+    }
+
+    /// Apply a credit entry and update the cached balances
+    ///
+    /// With Raft the entry is replicated and the new balances read back from
+    /// the database. Without it, the transactions are applied in memory,
+    /// all or none.
+    async fn apply(
+        &self,
+        entry: GameLogEntry,
+        transactions: Vec<CreditTransaction>,
+    ) -> anyhow::Result<()> {
+        if let (Some(raft_writer), Some(pool)) = (&self.raft_writer, &self.pool) {
+            raft_writer.propose(entry).await?;
+            for transaction in &transactions {
+                let balance = self
+                    .load_balance(&transaction.universe_id, &transaction.account_id, pool)
+                    .await?;
+                let key = (
+                    transaction.universe_id.clone(),
+                    transaction.account_id.clone(),
+                );
+                self.balances.write().await.insert(key, balance);
             }
+            return Ok(());
         }
+
+        let mut balances = self.balances.write().await;
+        for transaction in &transactions {
+            let key = (
+                transaction.universe_id.clone(),
+                transaction.account_id.clone(),
+            );
+            if balances.get(&key).copied().unwrap_or(0) + transaction.amount < 0 {
+                anyhow::bail!("insufficient credits");
+            }
+        }
+
+        let mut ledger = self.transactions.write().await;
+        for mut transaction in transactions {
+            let key = (
+                transaction.universe_id.clone(),
+                transaction.account_id.clone(),
+            );
+            let balance = balances.entry(key).or_insert(0);
+            *balance += transaction.amount;
+            transaction.balance_after = *balance;
+            ledger.push(transaction);
+        }
+        Ok(())
     }
 
     /// Load balance from database
@@ -207,33 +549,6 @@ impl CreditManager {
 
         Ok(row.map(|(b,)| b).unwrap_or(0))
     }
-
-    /// Save balance to database via Raft
-    async fn save_balance(
-        &self,
-        universe_id: &str,
-        account_id: &str,
-        balance: i64,
-        raft_writer: &RaftWriter,
-    ) -> anyhow::Result<()> {
-        // Pre-compute UUID for deterministic replication
-        let id = uuid::Uuid::new_v4().to_string();
-
-        raft_writer
-            .execute(
-                "INSERT INTO credits (id, universe_id, player_id, balance) VALUES (?, ?, ?, ?) ON CONFLICT(universe_id, player_id) DO UPDATE SET balance = ?",
-                vec![
-                    serde_json::json!(&id),
-                    serde_json::json!(universe_id),
-                    serde_json::json!(account_id),
-                    serde_json::json!(balance),
-                    serde_json::json!(balance),
-                ],
-            )
-            .await?;
-
-        Ok(())
-    }
 }
 
 #[cfg(test)]
@@ -310,6 +625,137 @@ mod tests {
         assert_eq!(balance, 100);
     }
 
+    #[tokio::test]
+    async fn test_charge_usage_carries_fractions_and_caps() {
+        let manager = CreditManager::new(None, None);
+        manager.set_balance("u1", "player1", 10).await;
+
+        // Under a credit is carried, not dropped
+        manager.charge_usage("u1", "player1", 600, "eval").await;
+        assert_eq!(manager.get_balance("u1", "player1").await, 10);
+        manager.charge_usage("u1", "player1", 600, "eval").await;
+        assert_eq!(manager.get_balance("u1", "player1").await, 9);
+
+        // More than the balance takes what is left
+        manager.charge_usage("u1", "player1", 50_000, "eval").await;
+        assert_eq!(manager.get_balance("u1", "player1").await, 0);
+
+        let audit = manager.audit_balance("u1", "player1").await.unwrap();
+        assert!(audit.consistent);
+    }
+
+    #[tokio::test]
+    async fn test_transactions_recorded() {
+        let manager = CreditManager::new(None, None);
+        manager.grant("u1", "player1", 100, "quest reward").await;
+        assert!(manager.deduct("u1", "player1", 30, "potion").await);
+        assert!(!manager.deduct("u1", "player1", 500, "castle").await);
+
+        let transactions = manager.get_transactions("u1", "player1", 10).await.unwrap();
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|t| (t.amount, t.balance_after, t.reason.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(-30, 70, "potion"), (100, 100, "quest reward")]
+        );
+
+        let audit = manager.audit_balance("u1", "player1").await.unwrap();
+        assert_eq!(audit.balance, 70);
+        assert_eq!(audit.transaction_count, 2);
+        assert!(audit.consistent);
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let manager = CreditManager::new(None, None);
+        manager.set_balance("u1", "alice", 100).await;
+
+        manager
+            .transfer("u1", "alice", "bob", 40, "gift")
+            .await
+            .unwrap();
+        assert_eq!(manager.get_balance("u1", "alice").await, 60);
+        assert_eq!(manager.get_balance("u1", "bob").await, 40);
+
+        // Overdrafts, self-transfers and non-positive amounts change nothing
+        assert!(manager
+            .transfer("u1", "alice", "bob", 61, "too much")
+            .await
+            .is_err());
+        assert!(manager
+            .transfer("u1", "alice", "alice", 10, "self")
+            .await
+            .is_err());
+        assert!(manager
+            .transfer("u1", "bob", "alice", 0, "zero")
+            .await
+            .is_err());
+        assert_eq!(manager.get_balance("u1", "alice").await, 60);
+        assert_eq!(manager.get_balance("u1", "bob").await, 40);
+
+        let received = manager.get_transactions("u1", "bob", 10).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!((received[0].amount, received[0].balance_after), (40, 40));
+        assert!(
+            manager
+                .audit_balance("u1", "alice")
+                .await
+                .unwrap()
+                .consistent
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ledger_persisted_through_raft() {
+        use crate::db::test_utils::test_pool;
+        use std::time::Duration;
+
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO accounts (id, username, password_hash, salt) VALUES ('player1', 'player1', '', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO universes (id, name, owner_id) VALUES ('u1', 'Test', 'player1')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let writer = RaftWriter::single_node(pool.clone(), 1, 19104, "/tmp/test.db")
+            .await
+            .unwrap();
+        writer
+            .wait_for_leader(Duration::from_secs(5))
+            .await
+            .unwrap();
+        let manager = CreditManager::new(Some(pool.clone()), Some(Arc::new(writer)));
+
+        manager.grant("u1", "player1", 50, "welcome bonus").await;
+        assert!(manager.deduct("u1", "player1", 20, "sword").await);
+        assert!(!manager.deduct("u1", "player1", 40, "armor").await);
+        manager.set_balance("u1", "player1", 100).await;
+        assert_eq!(manager.get_balance("u1", "player1").await, 100);
+
+        let transactions = manager.get_transactions("u1", "player1", 2).await.unwrap();
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|t| (t.amount, t.balance_after, t.reason.as_str()))
+            .collect();
+        assert_eq!(summary, vec![(70, 100, "set_balance"), (-20, 30, "sword")]);
+
+        // A balance edited outside the ledger no longer verifies
+        let audit = manager.audit_balance("u1", "player1").await.unwrap();
+        assert!(audit.consistent);
+        sqlx::query("UPDATE credits SET balance = 999")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let audit = manager.audit_balance("u1", "player1").await.unwrap();
+        assert_eq!((audit.balance, audit.ledger_total), (999, 100));
+        assert!(!audit.consistent);
+    }
+
     #[tokio::test]
     async fn test_multiple_universes() {
         let manager = CreditManager::new(None, None);
@@ -7,7 +7,43 @@ use anyhow::{bail, Result};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use std::path::Path;
 use std::str::FromStr;
-use tracing::info;
+use tracing::{info, warn};
+
+/// Class tables, per universe; the built-in classes live only in the
+/// ClassRegistry. Properties and handlers are normalized from JSON blobs.
+const CLASS_TABLES: [&str; 3] = [
+    r#"
+        CREATE TABLE IF NOT EXISTS classes (
+            name TEXT NOT NULL,
+            universe_id TEXT NOT NULL REFERENCES universes(id),
+            parent TEXT,
+            code_hash TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (universe_id, name)
+        )
+    "#,
+    r#"
+        CREATE TABLE IF NOT EXISTS class_properties (
+            class_name TEXT NOT NULL,
+            universe_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (universe_id, class_name, key),
+            FOREIGN KEY (universe_id, class_name)
+                REFERENCES classes(universe_id, name) ON DELETE CASCADE
+        )
+    "#,
+    r#"
+        CREATE TABLE IF NOT EXISTS class_handlers (
+            class_name TEXT NOT NULL,
+            universe_id TEXT NOT NULL,
+            handler TEXT NOT NULL,
+            PRIMARY KEY (universe_id, class_name, handler),
+            FOREIGN KEY (universe_id, class_name)
+                REFERENCES classes(universe_id, name) ON DELETE CASCADE
+        )
+    "#,
+];
 
 /// Database handle wrapping SQLite connection pool
 pub struct Database {
@@ -171,6 +207,86 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Login sessions, one per device (token_hash is SHA-256 of the token)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                account_id TEXT NOT NULL REFERENCES accounts(id),
+                token_hash TEXT UNIQUE NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                last_seen_at TEXT NOT NULL,
+                user_agent TEXT,
+                ip_address TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_sessions_account ON sessions(account_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Move tokens from the old single-token column into sessions (migration)
+        let legacy_tokens: Vec<(String, String)> =
+            sqlx::query_as("SELECT id, token FROM accounts WHERE token IS NOT NULL")
+                .fetch_all(&self.pool)
+                .await?;
+        if !legacy_tokens.is_empty() {
+            let now = chrono::Utc::now();
+            let expires_at = now + chrono::Duration::days(crate::auth::accounts::SESSION_TTL_DAYS);
+            for (account_id, token) in &legacy_tokens {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO sessions (id, account_id, token_hash, created_at, expires_at, last_seen_at) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(uuid::Uuid::new_v4().to_string())
+                .bind(account_id)
+                .bind(crate::auth::hash_token(token))
+                .bind(now.to_rfc3339())
+                .bind(expires_at.to_rfc3339())
+                .bind(now.to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+            }
+            sqlx::query("UPDATE accounts SET token = NULL")
+                .execute(&self.pool)
+                .await?;
+            info!("Migrated {} login tokens to sessions", legacy_tokens.len());
+        }
+
+        // Ethereum wallet bound to an account (migration), EIP-55 checksummed
+        let has_wallet: Option<(String,)> = sqlx::query_as(
+            "SELECT name FROM pragma_table_info('accounts') WHERE name = 'wallet_address'",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        if has_wallet.is_none() {
+            sqlx::query("ALTER TABLE accounts ADD COLUMN wallet_address TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_accounts_wallet ON accounts(wallet_address)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Outstanding wallet sign-in nonces, each usable once
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS wallet_nonces (
+                nonce TEXT PRIMARY KEY,
+                address TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS universes (
@@ -233,49 +349,41 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // Classes table (for class definitions)
+        // Credit transactions (ledger of every balance change)
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS classes (
-                name TEXT PRIMARY KEY,
+            CREATE TABLE IF NOT EXISTS credit_transactions (
+                id TEXT PRIMARY KEY,
                 universe_id TEXT NOT NULL REFERENCES universes(id),
-                parent TEXT REFERENCES classes(name),
-                code_hash TEXT,
-                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                player_id TEXT NOT NULL REFERENCES accounts(id),
+                amount INTEGER NOT NULL,
+                balance_after INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                created_at TEXT NOT NULL
             )
             "#,
         )
         .execute(&self.pool)
         .await?;
 
-        // Class properties table (normalized from JSON blob)
         sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS class_properties (
-                class_name TEXT NOT NULL REFERENCES classes(name) ON DELETE CASCADE,
-                universe_id TEXT NOT NULL REFERENCES universes(id),
-                key TEXT NOT NULL,
-                value TEXT NOT NULL,
-                PRIMARY KEY (class_name, key)
-            )
-            "#,
+            "CREATE INDEX IF NOT EXISTS idx_credit_tx_player ON credit_transactions(universe_id, player_id, created_at)",
         )
         .execute(&self.pool)
         .await?;
 
-        // Class handlers table (normalized from JSON blob)
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS class_handlers (
-                class_name TEXT NOT NULL REFERENCES classes(name) ON DELETE CASCADE,
-                universe_id TEXT NOT NULL REFERENCES universes(id),
-                handler TEXT NOT NULL,
-                PRIMARY KEY (class_name, handler)
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+        // Classes used to be keyed by name alone, so every universe shared
+        // one namespace. Rebuild those tables keyed by (universe_id, name).
+        let class_keys: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('classes') WHERE pk > 0")
+                .fetch_all(&self.pool)
+                .await?;
+        if class_keys.len() == 1 {
+            self.migrate_legacy_classes().await?;
+        }
+        for sql in CLASS_TABLES {
+            sqlx::query(sql).execute(&self.pool).await?;
+        }
 
         // Timers table (for call_out)
         sqlx::query(
@@ -413,6 +521,7 @@ impl Database {
             CREATE TABLE IF NOT EXISTS raft_log (
                 log_index INTEGER PRIMARY KEY,
                 term INTEGER NOT NULL,
+                node_id INTEGER NOT NULL DEFAULT 0,
                 entry_type TEXT NOT NULL,
                 payload TEXT,
                 created_at INTEGER NOT NULL DEFAULT (unixepoch())
@@ -422,6 +531,18 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Migration: record the leader node of each log entry (part of the LogId)
+        let has_log_node_id: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('raft_log') WHERE name = 'node_id'")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        if has_log_node_id.is_none() {
+            sqlx::query("ALTER TABLE raft_log ADD COLUMN node_id INTEGER NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+        }
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS raft_vote (
@@ -474,6 +595,40 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Chat channel history ('' universe_id for channels shared by all
+        // universes)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS channel_messages (
+                id TEXT PRIMARY KEY,
+                channel TEXT NOT NULL,
+                universe_id TEXT NOT NULL DEFAULT '',
+                sender_id TEXT NOT NULL,
+                sender_name TEXT NOT NULL,
+                text TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Players muted on a channel by a moderator
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS channel_mutes (
+                channel TEXT NOT NULL,
+                universe_id TEXT NOT NULL DEFAULT '',
+                player_id TEXT NOT NULL,
+                muted_by TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (channel, universe_id, player_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         // Create indexes
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_objects_universe ON objects(universe_id)")
             .execute(&self.pool)
@@ -497,11 +652,82 @@ impl Database {
         )
         .execute(&self.pool)
         .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_channel_messages ON channel_messages(channel, universe_id, created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
 
         info!("Database migrations complete");
         Ok(())
     }
 
+    /// Move classes from the tables shared by all universes into
+    /// per-universe ones
+    ///
+    /// Runs in one transaction, so an interrupted migration leaves the old
+    /// tables as they were. Classes without a real universe can't be
+    /// placed in one and are dropped.
+    async fn migrate_legacy_classes(&self) -> Result<()> {
+        info!("Migrating classes to per-universe namespaces");
+        let mut tx = self.pool.begin().await?;
+        for table in ["classes", "class_properties", "class_handlers"] {
+            sqlx::query(&format!("ALTER TABLE {table} RENAME TO legacy_{table}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        for sql in CLASS_TABLES {
+            sqlx::query(sql).execute(&mut *tx).await?;
+        }
+
+        let copied = sqlx::query(
+            "INSERT OR IGNORE INTO classes (name, universe_id, parent, code_hash, created_at)
+             SELECT name, universe_id, parent, code_hash, created_at FROM legacy_classes
+             WHERE universe_id IN (SELECT id FROM universes)",
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query(
+            "INSERT OR IGNORE INTO class_properties (class_name, universe_id, key, value)
+             SELECT p.class_name, p.universe_id, p.key, p.value FROM legacy_class_properties p
+             JOIN classes c ON c.universe_id = p.universe_id AND c.name = p.class_name",
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO class_handlers (class_name, universe_id, handler)
+             SELECT h.class_name, h.universe_id, h.handler FROM legacy_class_handlers h
+             JOIN classes c ON c.universe_id = h.universe_id AND c.name = h.class_name",
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM legacy_classes")
+            .fetch_one(&mut *tx)
+            .await?;
+        if total > copied as i64 {
+            warn!(
+                "Dropped {} classes with no universe while migrating",
+                total - copied as i64
+            );
+        }
+
+        for table in [
+            "legacy_class_handlers",
+            "legacy_class_properties",
+            "legacy_classes",
+        ] {
+            sqlx::query(&format!("DROP TABLE {table}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        info!("Migrated {} classes to per-universe namespaces", copied);
+        Ok(())
+    }
+
     /// Get the connection pool
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
@@ -535,4 +761,138 @@ mod tests {
             .unwrap();
         assert_eq!(result.0, 0);
     }
+
+    /// Create a database at `path` whose class tables are keyed by name
+    /// alone, as they used to be
+    async fn create_legacy_class_db(path: &str) {
+        let db = Database::new(Some(path)).await.unwrap();
+        let mut conn = db.pool().acquire().await.unwrap();
+        for sql in [
+            "PRAGMA foreign_keys = OFF",
+            "DROP TABLE class_handlers",
+            "DROP TABLE class_properties",
+            "DROP TABLE classes",
+            "CREATE TABLE classes (name TEXT PRIMARY KEY, universe_id TEXT NOT NULL REFERENCES universes(id),
+                parent TEXT REFERENCES classes(name), code_hash TEXT,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')))",
+            "CREATE TABLE class_properties (class_name TEXT NOT NULL REFERENCES classes(name) ON DELETE CASCADE,
+                universe_id TEXT NOT NULL REFERENCES universes(id), key TEXT NOT NULL, value TEXT NOT NULL,
+                PRIMARY KEY (class_name, key))",
+            "CREATE TABLE class_handlers (class_name TEXT NOT NULL REFERENCES classes(name) ON DELETE CASCADE,
+                universe_id TEXT NOT NULL REFERENCES universes(id), handler TEXT NOT NULL,
+                PRIMARY KEY (class_name, handler))",
+            "INSERT INTO accounts (id, username) VALUES ('a1', 'owner')",
+            "INSERT INTO universes (id, name, owner_id) VALUES ('u1', 'One', 'a1')",
+            "INSERT INTO classes (name, universe_id, parent) VALUES ('dragon', 'u1', 'npc')",
+            "INSERT INTO classes (name, universe_id, parent) VALUES ('orphan', '', 'thing')",
+            "INSERT INTO class_properties (class_name, universe_id, key, value) VALUES ('dragon', 'u1', 'breath', '\"fire\"')",
+            "INSERT INTO class_properties (class_name, universe_id, key, value) VALUES ('orphan', '', 'lost', 'true')",
+            "INSERT INTO class_handlers (class_name, universe_id, handler) VALUES ('dragon', 'u1', 'on_roar')",
+        ] {
+            sqlx::query(sql).execute(&mut *conn).await.unwrap();
+        }
+        drop(conn);
+        db.pool().close().await;
+    }
+
+    #[tokio::test]
+    async fn test_classes_migrated_to_universe_namespaces() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("old.db");
+        let path = path.to_str().unwrap();
+
+        create_legacy_class_db(path).await;
+
+        let db = Database::open_for_migration(path).await.unwrap();
+        let classes: Vec<(String, String)> =
+            sqlx::query_as("SELECT universe_id, name FROM classes")
+                .fetch_all(db.pool())
+                .await
+                .unwrap();
+        assert_eq!(classes, vec![("u1".to_string(), "dragon".to_string())]);
+        let props: Vec<(String, String)> =
+            sqlx::query_as("SELECT universe_id, key FROM class_properties")
+                .fetch_all(db.pool())
+                .await
+                .unwrap();
+        assert_eq!(props, vec![("u1".to_string(), "breath".to_string())]);
+
+        // Another universe can now define a class with the same name
+        sqlx::query("INSERT INTO universes (id, name, owner_id) VALUES ('u2', 'Two', 'a1')")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO classes (name, universe_id, parent) VALUES ('dragon', 'u2', 'living')",
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let legacy: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM sqlite_master WHERE name LIKE 'legacy_%'")
+                .fetch_optional(db.pool())
+                .await
+                .unwrap();
+        assert!(legacy.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_class_migration_leaves_old_tables() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("old.db");
+        let path = path.to_str().unwrap();
+        create_legacy_class_db(path).await;
+
+        // The last rename collides, after the first two have run
+        let pool = SqlitePool::connect(&format!("sqlite:{}", path))
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE legacy_class_handlers (x)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        assert!(Database::open_for_migration(path).await.is_err());
+
+        let pool = SqlitePool::connect(&format!("sqlite:{}", path))
+            .await
+            .unwrap();
+        let tables: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '%class%' ORDER BY name",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let tables: Vec<&str> = tables.iter().map(|(name,)| name.as_str()).collect();
+        assert_eq!(
+            tables,
+            vec![
+                "class_handlers",
+                "class_properties",
+                "classes",
+                "legacy_class_handlers"
+            ]
+        );
+        let (keys,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM pragma_table_info('classes') WHERE pk > 0")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(keys, 1);
+
+        // With the obstacle gone the migration runs from the start
+        sqlx::query("DROP TABLE legacy_class_handlers")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        let db = Database::open_for_migration(path).await.unwrap();
+        let classes: Vec<(String, String)> =
+            sqlx::query_as("SELECT universe_id, name FROM classes")
+                .fetch_all(db.pool())
+                .await
+                .unwrap();
+        assert_eq!(classes, vec![("u1".to_string(), "dragon".to_string())]);
+    }
 }
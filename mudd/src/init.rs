@@ -10,6 +10,7 @@ use tracing::info;
 
 use crate::auth::accounts::AccountService;
 use crate::db::Database;
+use crate::raft::{log_entry, write_entry, GameLogEntry};
 
 /// Initialize or upgrade a game server database (idempotent)
 ///
@@ -59,7 +60,7 @@ pub async fn init_database(
         let db = Database::new(Some(path.to_str().unwrap())).await?;
 
         // Create admin account
-        let service = AccountService::new(db.pool().clone());
+        let service = AccountService::new(db.pool().clone(), None);
         let account = service.create(admin_username, admin_password).await?;
         info!(
             "Created admin account '{}' ({})",
@@ -128,19 +129,19 @@ pub async fn init_database(
 }
 
 /// Store code in the code_store table (content-addressed)
+///
+/// mudd_init runs before any Raft node, so writes apply locally through
+/// the same log entries the state machine uses.
 async fn store_code(pool: &sqlx::SqlitePool, source: &str) -> Result<String> {
-    use sha2::{Digest, Sha256};
-
-    let mut hasher = Sha256::new();
-    hasher.update(source.as_bytes());
-    let hash = hex::encode(hasher.finalize());
+    let hash = log_entry::hash_code(source);
 
     // Insert or ignore (content-addressed)
-    sqlx::query("INSERT OR IGNORE INTO code_store (hash, source) VALUES (?, ?)")
-        .bind(&hash)
-        .bind(source)
-        .execute(pool)
-        .await?;
+    let entry = GameLogEntry::StoreCode {
+        hash: hash.clone(),
+        source: source.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    write_entry(None, pool, entry).await?;
 
     Ok(hash)
 }
@@ -149,12 +150,15 @@ async fn store_code(pool: &sqlx::SqlitePool, source: &str) -> Result<String> {
 async fn store_core_lib_hash(pool: &sqlx::SqlitePool, name: &str, hash: &str) -> Result<()> {
     let updated_at = chrono::Utc::now().to_rfc3339();
 
-    sqlx::query("INSERT OR REPLACE INTO core_lib_hashes (name, hash, updated_at) VALUES (?, ?, ?)")
-        .bind(name)
-        .bind(hash)
-        .bind(&updated_at)
-        .execute(pool)
-        .await?;
+    let entry = GameLogEntry::statement(
+        "INSERT OR REPLACE INTO core_lib_hashes (name, hash, updated_at) VALUES (?, ?, ?)",
+        vec![
+            serde_json::json!(name),
+            serde_json::json!(hash),
+            serde_json::json!(&updated_at),
+        ],
+    );
+    write_entry(None, pool, entry).await?;
 
     Ok(())
 }
@@ -184,7 +188,7 @@ mod tests {
 
         // Verify admin account exists with correct level
         let db = Database::open(db_path.to_str().unwrap()).await.unwrap();
-        let service = AccountService::new(db.pool().clone());
+        let service = AccountService::new(db.pool().clone(), None);
         let account = service.get_by_username("admin").await.unwrap().unwrap();
         assert_eq!(account.access_level, "admin");
     }
@@ -211,7 +215,7 @@ mod tests {
 
         // Verify admin account still exists
         let db = Database::open(db_path.to_str().unwrap()).await.unwrap();
-        let service = AccountService::new(db.pool().clone());
+        let service = AccountService::new(db.pool().clone(), None);
         let account = service.get_by_username("admin").await.unwrap().unwrap();
         assert_eq!(account.access_level, "admin");
     }
@@ -4,6 +4,7 @@
 
 pub mod api;
 pub mod auth;
+pub mod channels;
 pub mod combat;
 pub mod credits;
 pub mod db;
@@ -27,8 +28,9 @@ use anyhow::Result;
 use axum::Router;
 use tokio::net::TcpListener;
 use tokio::sync::watch;
-use tracing::info;
+use tracing::{error, info};
 
+use api::LoginLimits;
 use db::Database;
 use raft::{RaftNodeConfig, RaftWriter};
 
@@ -44,6 +46,16 @@ pub struct Config {
     pub raft_port: u16,
     /// Cluster peers (format: "1=host1:9000,2=host2:9001"). None = single-node.
     pub peers: Option<String>,
+    /// Join an existing cluster: serve Raft RPCs but don't wait for a leader,
+    /// since the node only learns of one once an admin adds it as a learner
+    pub join: bool,
+    /// Address for the telnet gateway. None = no telnet listener.
+    pub telnet_addr: Option<SocketAddr>,
+    /// Domain clients reach the server at, which wallet sign-in messages
+    /// must name. None = the bind address.
+    pub public_domain: Option<String>,
+    /// Login and registration limits for the whole server
+    pub login_limits: LoginLimits,
 }
 
 impl Default for Config {
@@ -54,6 +66,10 @@ impl Default for Config {
             node_id: 1,
             raft_port: 9000,
             peers: None,
+            join: false,
+            telnet_addr: None,
+            public_domain: None,
+            login_limits: LoginLimits::default(),
         }
     }
 }
@@ -73,6 +89,11 @@ impl Server {
     pub async fn new(config: Config) -> Result<Self> {
         let db = Database::open(&config.db_path).await?;
         let db = Arc::new(db);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        if config.join && config.peers.is_none() {
+            anyhow::bail!("Joining a cluster requires peers (at least this node's address)");
+        }
 
         // Initialize RaftWriter
         let raft_writer = if let Some(ref peers_str) = config.peers {
@@ -80,7 +101,21 @@ impl Server {
             let peers = RaftNodeConfig::parse_peers(peers_str);
             let raft_config = RaftNodeConfig::cluster(config.node_id, config.raft_port, peers)
                 .with_db_path(&config.db_path);
-            RaftWriter::cluster(db.pool().clone(), raft_config, &config.db_path).await?
+            let raft_writer =
+                RaftWriter::cluster(db.pool().clone(), raft_config, &config.db_path).await?;
+
+            // Peers must be able to reach us before a leader can be elected
+            let raft_addr = SocketAddr::new(config.bind_addr.ip(), config.raft_port);
+            let listener = TcpListener::bind(raft_addr).await?;
+            let raft = raft_writer.raft().clone();
+            let rpc_shutdown = shutdown_rx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = raft::rpc::serve(listener, raft, rpc_shutdown).await {
+                    error!("Raft RPC server failed: {}", e);
+                }
+            });
+
+            raft_writer
         } else {
             // Single-node mode
             RaftWriter::single_node(
@@ -93,10 +128,16 @@ impl Server {
         };
 
         // Wait for leader election
-        raft_writer.wait_for_leader(Duration::from_secs(10)).await?;
+        if config.join {
+            info!(
+                "Node {} waiting to be added to the cluster (raft port {})",
+                config.node_id, config.raft_port
+            );
+        } else {
+            raft_writer.wait_for_leader(Duration::from_secs(10)).await?;
+        }
 
         let raft_writer = Arc::new(raft_writer);
-        let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
         Ok(Self {
             config,
@@ -118,8 +159,16 @@ impl Server {
     }
 
     /// Build the router
-    async fn router(&self) -> Router {
-        api::router(self.db.clone(), self.raft_writer.clone()).await
+    async fn router(&self, telnet: Option<TcpListener>, public_domain: String) -> Router {
+        api::router(
+            self.db.clone(),
+            self.raft_writer.clone(),
+            self.shutdown_rx.clone(),
+            telnet,
+            public_domain,
+            self.config.login_limits.clone(),
+        )
+        .await
     }
 
     /// Run the server until shutdown
@@ -128,14 +177,32 @@ impl Server {
         let local_addr = listener.local_addr()?;
         info!("mudd listening on {}", local_addr);
 
-        let router = self.router().await;
+        let telnet = match self.config.telnet_addr {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!("telnet gateway listening on {}", listener.local_addr()?);
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let public_domain = self
+            .config
+            .public_domain
+            .clone()
+            .unwrap_or_else(|| local_addr.to_string());
+        let router = self.router(telnet, public_domain).await;
         let mut shutdown_rx = self.shutdown_rx.clone();
 
-        axum::serve(listener, router)
-            .with_graceful_shutdown(async move {
-                shutdown_rx.changed().await.ok();
-            })
-            .await?;
+        // Peer addresses are recorded on login sessions
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move {
+            shutdown_rx.changed().await.ok();
+        })
+        .await?;
 
         info!("mudd shutdown complete");
         Ok(())
@@ -2,8 +2,11 @@
 //!
 //! Objects can register actions (verbs) that players can use when in context.
 //! For example, a lever might add a "pull" action when a player enters the room.
+//!
+//! Actions added from an object's `on_init` handler belong to the living the
+//! handler ran for, LPC style, and last while the two share an environment.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -26,6 +29,8 @@ pub struct ActionRegistry {
     room_actions: RwLock<BTreeMap<String, BTreeMap<String, Vec<Action>>>>,
     /// Object-scoped actions: object_id -> verb -> action (for inventory items)
     object_actions: RwLock<BTreeMap<String, BTreeMap<String, Action>>>,
+    /// Actions given to a living by init(): living_id -> verb -> list of actions
+    living_actions: RwLock<BTreeMap<String, BTreeMap<String, Vec<Action>>>>,
 }
 
 impl ActionRegistry {
@@ -92,6 +97,55 @@ impl ActionRegistry {
         actions.get(object_id).and_then(|o| o.get(verb)).cloned()
     }
 
+    /// Give an action to a living (from an object's init)
+    pub async fn add_living_action(&self, living_id: &str, action: Action) {
+        let mut actions = self.living_actions.write().await;
+        let living_entry = actions.entry(living_id.to_string()).or_default();
+        let verb_entry = living_entry.entry(action.verb.clone()).or_default();
+
+        // Don't add duplicate
+        if !verb_entry.iter().any(|a| a.object_id == action.object_id) {
+            verb_entry.push(action);
+        }
+    }
+
+    /// Take an action back from a living
+    pub async fn remove_living_action(&self, living_id: &str, verb: &str, object_id: &str) {
+        let mut actions = self.living_actions.write().await;
+        if let Some(living_entry) = actions.get_mut(living_id) {
+            if let Some(verb_entry) = living_entry.get_mut(verb) {
+                verb_entry.retain(|a| a.object_id != object_id);
+            }
+        }
+    }
+
+    /// Get the actions a living has for a verb
+    pub async fn get_living_actions(&self, living_id: &str, verb: &str) -> Vec<Action> {
+        let actions = self.living_actions.read().await;
+        actions
+            .get(living_id)
+            .and_then(|l| l.get(verb))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Forget what an object's init() gave and got after it moves
+    ///
+    /// Every living loses the actions the object gave it, and the object
+    /// keeps only the actions from `keep` (the things it carries). Whatever
+    /// it shares an environment with now gives them back through init().
+    pub async fn object_moved(&self, object_id: &str, keep: &BTreeSet<String>) {
+        let mut actions = self.living_actions.write().await;
+        for (living_id, living_entry) in actions.iter_mut() {
+            for verb_entry in living_entry.values_mut() {
+                verb_entry.retain(|a| {
+                    a.object_id != object_id
+                        && (living_id != object_id || keep.contains(&a.object_id))
+                });
+            }
+        }
+    }
+
     /// Clear all actions for a room (e.g., when room is destroyed)
     pub async fn clear_room(&self, room_id: &str) {
         let mut actions = self.room_actions.write().await;
@@ -112,6 +166,16 @@ impl ActionRegistry {
         // Remove object actions
         let mut obj_actions = self.object_actions.write().await;
         obj_actions.remove(object_id);
+        drop(obj_actions);
+
+        // Remove actions it gave to livings, and any it was given
+        let mut living_actions = self.living_actions.write().await;
+        living_actions.remove(object_id);
+        for living_entry in living_actions.values_mut() {
+            for verb_entry in living_entry.values_mut() {
+                verb_entry.retain(|a| a.object_id != object_id);
+            }
+        }
     }
 }
 
@@ -183,4 +247,55 @@ mod tests {
         let actions = registry.get_room_actions("room_1", "pull").await;
         assert!(actions.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_living_actions_follow_moves() {
+        let registry = ActionRegistry::new();
+        let action = |verb: &str, object_id: &str| Action {
+            verb: verb.to_string(),
+            object_id: object_id.to_string(),
+            method: format!("on_{}", verb),
+        };
+
+        registry
+            .add_living_action("/players/alice", action("pull", "/items/lever"))
+            .await;
+        registry
+            .add_living_action("/players/alice", action("light", "/items/lamp"))
+            .await;
+        registry
+            .add_living_action("/players/bob", action("pull", "/items/lever"))
+            .await;
+
+        // Alice walks off carrying the lamp: she keeps its verb only
+        let carried = BTreeSet::from(["/items/lamp".to_string()]);
+        registry.object_moved("/players/alice", &carried).await;
+        assert!(registry
+            .get_living_actions("/players/alice", "pull")
+            .await
+            .is_empty());
+        assert_eq!(
+            registry
+                .get_living_actions("/players/alice", "light")
+                .await
+                .len(),
+            1
+        );
+        assert_eq!(
+            registry
+                .get_living_actions("/players/bob", "pull")
+                .await
+                .len(),
+            1
+        );
+
+        // The lever is carried off: bob loses its verb too
+        registry
+            .object_moved("/items/lever", &BTreeSet::new())
+            .await;
+        assert!(registry
+            .get_living_actions("/players/bob", "pull")
+            .await
+            .is_empty());
+    }
 }
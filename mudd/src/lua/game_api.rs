@@ -3,17 +3,22 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use mlua::{Function, Lua, Result as LuaResult, Table, Value};
+use mlua::{Lua, Result as LuaResult, Table, Value};
 use parking_lot::Mutex;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use tokio::sync::RwLock;
 
 use super::actions::{Action, ActionRegistry};
+use super::call_async;
+use super::handlers;
 use super::messaging::MessageQueue;
+use super::metering::{Billing, Metering};
+use crate::api::ConnectionManager;
 use crate::credits::CreditManager;
 use crate::objects::{ClassRegistry, Object, ObjectStore};
 use crate::permissions::{AccessLevel, Action as PermAction, ObjectContext, PermissionManager};
+use crate::raft::ItemTransfer;
 use crate::timers::{HeartBeat, Timer, TimerManager};
 use crate::venice::{ChatMessage, ImageSize, ImageStyle, ModelTier, VeniceClient};
 
@@ -23,22 +28,83 @@ pub struct GameApi {
     store: Arc<ObjectStore>,
     classes: Arc<RwLock<ClassRegistry>>,
     actions: Arc<ActionRegistry>,
-    messages: Arc<MessageQueue>,
     permissions: Arc<PermissionManager>,
     timers: Arc<TimerManager>,
     credits: Arc<CreditManager>,
     venice: Arc<VeniceClient>,
     image_store: Arc<crate::images::ImageStore>,
+    /// Connected players, for `game.online_players` (None outside a server)
+    connections: Option<Arc<ConnectionManager>>,
     universe_id: String,
-    current_room_id: Option<String>,
-    current_user_id: Option<String>,
-    current_object_id: Option<String>,
+    /// Who and what this execution runs for
+    execution: Execution,
+}
+
+/// Per-execution context of the game API
+#[derive(Clone)]
+struct Execution {
+    messages: Arc<MessageQueue>,
+    room_id: Option<String>,
+    user_id: Option<String>,
+    object_id: Option<String>,
+    /// The current user typed the code or command being run
+    player_input: bool,
+    /// Resource usage of the current execution
+    metering: Metering,
+    /// Pricing and balance, if this execution is billed
+    billing: Option<Billing>,
     /// Time override for testing (milliseconds since epoch, 0 = use real time)
     time_override: Arc<AtomicU64>,
     /// RNG for dice rolls - seeded for reproducibility in tests
     rng: Arc<Mutex<StdRng>>,
 }
 
+/// The execution a state's game functions work for
+///
+/// Shared by the functions and kept in the state's app data, so
+/// `GameApi::enter` can switch a registered state to a new execution
+/// without replacing functions its libraries may hold.
+#[derive(Clone)]
+struct Current(Arc<Mutex<Execution>>);
+
+impl Current {
+    fn messages(&self) -> Arc<MessageQueue> {
+        self.0.lock().messages.clone()
+    }
+
+    fn room_id(&self) -> Option<String> {
+        self.0.lock().room_id.clone()
+    }
+
+    fn user_id(&self) -> Option<String> {
+        self.0.lock().user_id.clone()
+    }
+
+    fn object_id(&self) -> Option<String> {
+        self.0.lock().object_id.clone()
+    }
+
+    fn player_input(&self) -> bool {
+        self.0.lock().player_input
+    }
+
+    fn metering(&self) -> Metering {
+        self.0.lock().metering.clone()
+    }
+
+    fn billing(&self) -> Option<Billing> {
+        self.0.lock().billing.clone()
+    }
+
+    fn time_override(&self) -> Arc<AtomicU64> {
+        self.0.lock().time_override.clone()
+    }
+
+    fn rng(&self) -> Arc<Mutex<StdRng>> {
+        self.0.lock().rng.clone()
+    }
+}
+
 impl GameApi {
     /// Create a new game API for a universe
     #[allow(clippy::too_many_arguments)]
@@ -58,24 +124,30 @@ impl GameApi {
             store,
             classes,
             actions,
-            messages,
             permissions,
             timers,
             credits,
             venice,
             image_store,
+            connections: None,
             universe_id: universe_id.to_string(),
-            current_room_id: None,
-            current_user_id: None,
-            current_object_id: None,
-            time_override: Arc::new(AtomicU64::new(0)),
-            rng: Arc::new(Mutex::new(StdRng::from_rng(&mut rand::rng()))),
+            execution: Execution {
+                messages,
+                room_id: None,
+                user_id: None,
+                object_id: None,
+                player_input: false,
+                metering: Metering::new(),
+                billing: None,
+                time_override: Arc::new(AtomicU64::new(0)),
+                rng: Arc::new(Mutex::new(StdRng::from_rng(&mut rand::rng()))),
+            },
         }
     }
 
     /// Set the current user context for permission checks
     pub fn set_user_context(&mut self, user_id: Option<String>) {
-        self.current_user_id = user_id;
+        self.execution.user_id = user_id;
     }
 
     /// Get the permission manager
@@ -85,12 +157,39 @@ impl GameApi {
 
     /// Set the current room context for action registration
     pub fn set_room_context(&mut self, room_id: Option<String>) {
-        self.current_room_id = room_id;
+        self.execution.room_id = room_id;
     }
 
     /// Set the current object context for timer registration
     pub fn set_object_context(&mut self, object_id: Option<String>) {
-        self.current_object_id = object_id;
+        self.execution.object_id = object_id;
+    }
+
+    /// Mark this execution as the current user's own eval or command
+    ///
+    /// Only such executions may spend the user's credits.
+    pub fn set_player_input(&mut self, player_input: bool) {
+        self.execution.player_input = player_input;
+    }
+
+    /// Let scripts see who is connected
+    pub fn set_connections(&mut self, connections: Arc<ConnectionManager>) {
+        self.connections = Some(connections);
+    }
+
+    /// Bill this execution, refusing Venice calls the balance can't cover
+    pub fn set_billing(&mut self, billing: Option<Billing>) {
+        self.execution.billing = billing;
+    }
+
+    /// Get the billing for this execution, if any
+    pub fn billing(&self) -> Option<&Billing> {
+        self.execution.billing.as_ref()
+    }
+
+    /// Get the metering shared with the sandbox
+    pub fn metering(&self) -> &Metering {
+        &self.execution.metering
     }
 
     /// Get the timer manager
@@ -100,7 +199,7 @@ impl GameApi {
 
     /// Get the message queue for draining after execution
     pub fn message_queue(&self) -> Arc<MessageQueue> {
-        self.messages.clone()
+        self.execution.messages.clone()
     }
 
     /// Get the action registry
@@ -108,24 +207,32 @@ impl GameApi {
         self.actions.clone()
     }
 
-    /// Register the game API in a Lua state
+    /// Register the game API in a Lua state, working for this execution
+    ///
+    /// A state only needs registering once: the functions look up the
+    /// execution when called, so later executions switch to themselves with
+    /// `enter`.
     pub fn register(&self, lua: &Lua) -> LuaResult<()> {
+        let current = Current(Arc::new(Mutex::new(self.execution.clone())));
+        lua.set_app_data(current.clone());
         let globals = lua.globals();
 
-        // Create the 'game' table
-        let game = lua.create_table()?;
+        let game = match globals.get::<Value>("game")? {
+            Value::Table(game) => game,
+            _ => lua.create_table()?,
+        };
 
         // Register functions
-        self.register_object_functions(lua, &game)?;
-        self.register_class_functions(lua, &game)?;
-        self.register_query_functions(lua, &game)?;
-        self.register_action_functions(lua, &game)?;
-        self.register_message_functions(lua, &game)?;
-        self.register_permission_functions(lua, &game)?;
-        self.register_timer_functions(lua, &game)?;
-        self.register_credit_functions(lua, &game)?;
-        self.register_venice_functions(lua, &game)?;
-        self.register_utility_functions(lua, &game)?;
+        self.register_object_functions(lua, &game, &current)?;
+        self.register_class_functions(lua, &game, &current)?;
+        self.register_query_functions(lua, &game, &current)?;
+        self.register_action_functions(lua, &game, &current)?;
+        self.register_message_functions(lua, &game, &current)?;
+        self.register_permission_functions(lua, &game, &current)?;
+        self.register_timer_functions(lua, &game, &current)?;
+        self.register_credit_functions(lua, &game, &current)?;
+        self.register_venice_functions(lua, &game, &current)?;
+        self.register_utility_functions(lua, &game, &current)?;
 
         globals.set("game", game)?;
 
@@ -133,71 +240,67 @@ impl GameApi {
         // This is a global function, not on the game table
         let classes = self.classes.clone();
         let store = self.store.clone();
-        let parent_fn = lua.create_function(
-            move |lua, (class_name, handler_name, args): (String, String, Table)| {
+        let universe_id = self.universe_id.clone();
+        let parent_fn = lua.create_async_function(
+            move |lua, (first, handler_name, args): (Value, Option<String>, Option<Table>)| {
                 let classes = classes.clone();
                 let store = store.clone();
-
-                // Get the parent class name
-                let parent_class = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(async {
-                        let registry = classes.read().await;
-                        let class = registry.get_class(&class_name);
-                        class.and_then(|c| c.parent.clone())
-                    })
-                });
-
-                let parent_class_name = match parent_class {
-                    Some(p) => p,
-                    None => return Ok(Value::Nil), // No parent class
-                };
-
-                // Get the parent class's code hash (if it has one)
-                let parent_code = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(async {
-                        let registry = classes.read().await;
-                        let parent = registry.get_class(&parent_class_name);
-                        match parent {
-                            Some(class_def) => {
-                                // Classes may store their code hash in handlers list
-                                // For now, we look for a code_hash in properties
-                                if let Some(serde_json::Value::String(hash)) =
-                                    class_def.properties.get("code_hash")
-                                {
-                                    store.get_code(hash).await.ok().flatten()
-                                } else {
-                                    None
-                                }
-                            }
-                            None => None,
-                        }
-                    })
-                });
-
-                match parent_code {
-                    Some(code) => {
-                        // Execute parent code to get handlers
-                        let chunk = lua.load(&code);
-                        let handlers: LuaResult<Table> = chunk.eval();
-
-                        match handlers {
-                            Ok(handler_table) => {
-                                if let Ok(handler) =
-                                    handler_table.get::<Function>(handler_name.as_str())
-                                {
-                                    // Call parent handler with same args
-                                    match handler.call::<Value>(args) {
-                                        Ok(result) => Ok(result),
-                                        Err(e) => Err(e),
+                let universe_id = universe_id.clone();
+                async move {
+                    match first {
+                        // parent(ctx): the running handler's next definition up the chain
+                        Value::Table(ctx) => {
+                            let object_id: String = ctx.get("object_id")?;
+                            let Some(handler) = ctx.get::<Option<String>>("handler")? else {
+                                return Err(mlua::Error::runtime(
+                                    "parent(ctx) must be called from a handler",
+                                ));
+                            };
+                            let class: Option<String> = ctx.get("class")?;
+                            let sources =
+                                match store.get(&object_id).await.map_err(mlua::Error::external)? {
+                                    Some(object) => {
+                                        handlers::object_sources(&store, &classes, &object)
+                                            .await
+                                            .map_err(mlua::Error::external)?
                                     }
-                                } else {
-                                    Ok(Value::Nil)
+                                    None => Vec::new(),
+                                };
+                            let rest = handlers::after(&sources, class.as_deref());
+                            Ok(handlers::call(&lua, None, rest, &handler, ctx)
+                                .await?
+                                .unwrap_or(Value::Nil))
+                        }
+                        // parent(class_name, handler_name, args): the named class's parent's handler
+                        Value::String(class_name) => {
+                            let class_name = class_name.to_str()?.to_string();
+                            let (Some(handler), Some(args)) = (handler_name, args) else {
+                                return Err(mlua::Error::runtime(
+                                    "usage: parent(class_name, handler_name, args)",
+                                ));
+                            };
+                            let parent_class = {
+                                let registry = classes.read().await;
+                                registry
+                                    .get_class(&universe_id, &class_name)
+                                    .and_then(|c| c.parent.clone())
+                            };
+                            let sources = match parent_class {
+                                Some(parent) => {
+                                    handlers::class_sources(&store, &classes, &universe_id, &parent)
+                                        .await
+                                        .map_err(mlua::Error::external)?
                                 }
-                            }
-                            Err(_) => Ok(Value::Nil),
+                                None => Vec::new(),
+                            };
+                            Ok(handlers::call(&lua, None, &sources, &handler, args)
+                                .await?
+                                .unwrap_or(Value::Nil))
                         }
+                        _ => Err(mlua::Error::runtime(
+                            "parent expects the handler's ctx table",
+                        )),
                     }
-                    None => Ok(Value::Nil),
                 }
             },
         )?;
@@ -206,16 +309,35 @@ impl GameApi {
         Ok(())
     }
 
-    fn register_object_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    /// Point a state registered by another execution's game API at this one
+    pub fn enter(&self, lua: &Lua) -> LuaResult<()> {
+        let current = lua
+            .app_data_ref::<Current>()
+            .ok_or_else(|| mlua::Error::runtime("game API is not registered"))?;
+        *current.0.lock() = self.execution.clone();
+        Ok(())
+    }
+
+    fn register_object_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let store = self.store.clone();
+        let classes = self.classes.clone();
+        let actions = self.actions.clone();
         let universe_id = self.universe_id.clone();
 
         // game.create_object(path, class, parent_id, props)
-        // Actually creates object in database with current user as owner
+        // Actually creates object in database with current user as owner, then
+        // runs its on_create handler
         // Returns object on success, or {error = "message"} on path validation failure
         let store_clone = store.clone();
+        let classes_clone = classes.clone();
         let universe_clone = universe_id.clone();
-        let create_object = lua.create_function(
+        let exec = current.clone();
+        let create_object = lua.create_async_function(
             move |lua,
                   (path, class, parent_id, props): (
                 String,
@@ -224,45 +346,44 @@ impl GameApi {
                 Option<Table>,
             )| {
                 let store = store_clone.clone();
+                let classes = classes_clone.clone();
                 let universe_id = universe_clone.clone();
-
-                // Get current user for ownership
-                let globals = lua.globals();
-                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-                let owner_id = actor_override;
-
-                // Create object with path validation
-                let mut obj = match Object::new(&path, &universe_id, &class) {
-                    Ok(obj) => obj,
-                    Err(e) => {
-                        // Return error table for path validation failure
-                        let error_table = lua.create_table()?;
-                        error_table.set("error", e.to_string())?;
-                        return Ok(Value::Table(error_table));
-                    }
-                };
-                obj.parent_id = parent_id;
-                obj.owner_id = owner_id; // Set creator as owner
-
-                // Copy properties from Lua table if provided
-                if let Some(props_table) = props {
-                    for pair in props_table.pairs::<String, Value>() {
-                        let (k, v) = pair?;
-                        let json_val = lua_to_json(v)?;
-                        obj.properties.insert(k, json_val);
+                exec.metering().record_db_write();
+
+                async move {
+                    // Get current user for ownership
+                    let globals = lua.globals();
+                    let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                    let owner_id = actor_override;
+
+                    // Create object with path validation
+                    let mut obj = match Object::new(&path, &universe_id, &class) {
+                        Ok(obj) => obj,
+                        Err(e) => {
+                            // Return error table for path validation failure
+                            let error_table = lua.create_table()?;
+                            error_table.set("error", e.to_string())?;
+                            return Ok(Value::Table(error_table));
+                        }
+                    };
+                    obj.parent_id = parent_id;
+                    obj.owner_id = owner_id; // Set creator as owner
+
+                    // Copy properties from Lua table if provided
+                    if let Some(props_table) = props {
+                        for pair in props_table.pairs::<String, Value>() {
+                            let (k, v) = pair?;
+                            let json_val = lua_to_json(v)?;
+                            obj.properties.insert(k, json_val);
+                        }
                     }
-                }
 
-                // Save to database
-                let obj_clone = obj.clone();
-                let result = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current()
-                        .block_on(async { store.create(&obj_clone).await })
-                });
+                    // Save to database
+                    store.create(&obj).await.map_err(mlua::Error::external)?;
 
-                match result {
-                    Ok(()) => Ok(Value::Table(object_to_lua(lua, &obj)?)),
-                    Err(e) => Err(mlua::Error::external(e)),
+                    let ctx = lua.create_table()?;
+                    handlers::fire(&lua, &store, &classes, &obj.id, "on_create", ctx).await?;
+                    Ok(Value::Table(object_to_lua(&lua, &obj)?))
                 }
             },
         )?;
@@ -271,17 +392,17 @@ impl GameApi {
         // game.get_object(id)
         // Actually fetches from database
         let store_clone = store.clone();
-        let get_object = lua.create_function(move |lua, id: String| {
+        let exec = current.clone();
+        let get_object = lua.create_async_function(move |lua, id: String| {
             let store = store_clone.clone();
+            exec.metering().record_db_read();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async { store.get(&id).await })
-            });
-
-            match result {
-                Ok(Some(obj)) => Ok(Value::Table(object_to_lua(lua, &obj)?)),
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(mlua::Error::external(e)),
+            async move {
+                match store.get(&id).await {
+                    Ok(Some(obj)) => Ok(Value::Table(object_to_lua(&lua, &obj)?)),
+                    Ok(None) => Ok(Value::Nil),
+                    Err(e) => Err(mlua::Error::external(e)),
+                }
             }
         })?;
         game.set("get_object", get_object)?;
@@ -289,98 +410,133 @@ impl GameApi {
         // game.update_object(id, changes)
         // Actually updates object in database
         let store_clone = store.clone();
-        let update_object = lua.create_function(move |_, (id, changes): (String, Table)| {
-            let store = store_clone.clone();
+        let exec = current.clone();
+        let update_object =
+            lua.create_async_function(move |_, (id, changes): (String, Table)| {
+                let store = store_clone.clone();
+                exec.metering().record_db_read();
+                exec.metering().record_db_write();
 
-            // Collect changes into a vec first (outside async)
-            let mut changes_vec = Vec::new();
-            for pair in changes.pairs::<String, Value>() {
-                let (k, v) = pair?;
-                let json_val = lua_to_json(v)?;
-                changes_vec.push((k, json_val));
-            }
+                async move {
+                    let mut changes_vec = Vec::new();
+                    for pair in changes.pairs::<String, Value>() {
+                        let (k, v) = pair?;
+                        let json_val = lua_to_json(v)?;
+                        changes_vec.push((k, json_val));
+                    }
 
-            let result: anyhow::Result<bool> = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
-                    // First get the existing object
-                    let obj_result = store.get(&id).await?;
-                    match obj_result {
-                        Some(mut obj) => {
-                            // Apply changes
-                            for (k, v) in changes_vec {
-                                obj.properties.insert(k, v);
+                    let result: anyhow::Result<bool> = async {
+                        // First get the existing object
+                        let obj_result = store.get(&id).await?;
+                        match obj_result {
+                            Some(mut obj) => {
+                                // Apply changes
+                                for (k, v) in changes_vec {
+                                    obj.properties.insert(k, v);
+                                }
+                                store.update(&obj).await?;
+                                Ok(true)
                             }
-                            store.update(&obj).await?;
-                            Ok(true)
+                            None => Ok(false),
                         }
-                        None => Ok(false),
                     }
-                })
-            });
+                    .await;
 
-            match result {
-                Ok(success) => Ok(success),
-                Err(e) => Err(mlua::Error::external(e)),
-            }
-        })?;
+                    result.map_err(mlua::Error::external)
+                }
+            })?;
         game.set("update_object", update_object)?;
 
         // game.delete_object(id)
-        // Actually deletes from database
+        // Runs the object's on_destroy handler, then deletes it from the database
+        // along with the actions it gave and got
         let store_clone = store.clone();
-        let delete_object = lua.create_function(move |_, id: String| {
+        let classes_clone = classes.clone();
+        let actions_clone = actions.clone();
+        let exec = current.clone();
+        let delete_object = lua.create_async_function(move |lua, id: String| {
             let store = store_clone.clone();
+            let classes = classes_clone.clone();
+            let actions = actions_clone.clone();
+            exec.metering().record_db_write();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async { store.delete(&id).await })
-            });
+            async move {
+                let ctx = lua.create_table()?;
+                handlers::fire(&lua, &store, &classes, &id, "on_destroy", ctx).await?;
 
-            match result {
-                Ok(deleted) => Ok(deleted),
-                Err(e) => Err(mlua::Error::external(e)),
+                let deleted = store.delete(&id).await.map_err(mlua::Error::external)?;
+                actions.clear_by_object(&id).await;
+                Ok(deleted)
             }
         })?;
         game.set("delete_object", delete_object)?;
 
         // game.move_object(id, new_parent_id)
-        // Actually moves object in database
+        // Actually moves object in database, then runs on_leave on the old
+        // container, on_move on the object, on_enter on the new container and
+        // on_init for whatever the object now shares an environment with
         let store_clone = store.clone();
-        let move_object =
-            lua.create_function(move |_, (id, new_parent_id): (String, Option<String>)| {
+        let classes_clone = classes.clone();
+        let actions_clone = actions.clone();
+        let exec = current.clone();
+        let move_object = lua.create_async_function(
+            move |lua, (id, new_parent_id): (String, Option<String>)| {
                 let store = store_clone.clone();
-
-                let result = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current()
-                        .block_on(async { store.move_object(&id, new_parent_id.as_deref()).await })
-                });
-
-                match result {
-                    Ok(()) => Ok(true),
-                    Err(e) => Err(mlua::Error::external(e)),
+                let classes = classes_clone.clone();
+                let actions = actions_clone.clone();
+                exec.metering().record_db_read();
+                exec.metering().record_db_write();
+
+                async move {
+                    let result: anyhow::Result<Option<String>> = async {
+                        let old_parent_id = store.get(&id).await?.and_then(|o| o.parent_id);
+                        store.move_object(&id, new_parent_id.as_deref()).await?;
+                        Ok(old_parent_id)
+                    }
+                    .await;
+                    let old_parent_id = result.map_err(mlua::Error::external)?;
+
+                    handlers::moved(
+                        &lua,
+                        &store,
+                        &classes,
+                        &actions,
+                        &id,
+                        old_parent_id.as_deref(),
+                        new_parent_id.as_deref(),
+                    )
+                    .await?;
+                    Ok(true)
                 }
-            })?;
+            },
+        )?;
         game.set("move_object", move_object)?;
 
         // game.clone_object(id, new_path, new_parent_id)
-        // Actually clones object in database
+        // Actually clones object in database, then runs the clone's on_create
+        // handler with source_id set to the original
         // Returns object on success, nil if not found, or {error = "message"} on path validation failure
         let store_clone = store.clone();
-        let clone_object = lua.create_function(
+        let exec = current.clone();
+        let clone_object = lua.create_async_function(
             move |lua, (id, new_path, new_parent_id): (String, String, Option<String>)| {
                 let store = store_clone.clone();
+                let classes = classes.clone();
+                exec.metering().record_db_read();
+                exec.metering().record_db_write();
+
+                async move {
+                    // Validate new path first
+                    let validated_path = match crate::objects::validate_object_path(&new_path) {
+                        Ok(path) => path,
+                        Err(e) => {
+                            let error_table = lua.create_table()?;
+                            error_table.set("error", e.to_string())?;
+                            return Ok(Value::Table(error_table));
+                        }
+                    };
 
-                // Validate new path first
-                let validated_path = match crate::objects::validate_object_path(&new_path) {
-                    Ok(path) => path,
-                    Err(e) => {
-                        let error_table = lua.create_table()?;
-                        error_table.set("error", e.to_string())?;
-                        return Ok(Value::Table(error_table));
-                    }
-                };
-
-                let result: anyhow::Result<Option<Object>> = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(async {
+                    let result: anyhow::Result<Option<Object>> = async {
                         let obj_result = store.get(&id).await?;
                         match obj_result {
                             Some(original) => {
@@ -394,13 +550,20 @@ impl GameApi {
                             }
                             None => Ok(None),
                         }
-                    })
-                });
-
-                match result {
-                    Ok(Some(obj)) => Ok(Value::Table(object_to_lua(lua, &obj)?)),
-                    Ok(None) => Ok(Value::Nil),
-                    Err(e) => Err(mlua::Error::external(e)),
+                    }
+                    .await;
+
+                    match result {
+                        Ok(Some(obj)) => {
+                            let ctx = lua.create_table()?;
+                            ctx.set("source_id", id.as_str())?;
+                            handlers::fire(&lua, &store, &classes, &obj.id, "on_create", ctx)
+                                .await?;
+                            Ok(Value::Table(object_to_lua(&lua, &obj)?))
+                        }
+                        Ok(None) => Ok(Value::Nil),
+                        Err(e) => Err(mlua::Error::external(e)),
+                    }
                 }
             },
         )?;
@@ -408,71 +571,69 @@ impl GameApi {
 
         // game.store_code(source) - returns hash
         let store_clone = store.clone();
-        let store_code = lua.create_function(move |_, source: String| {
+        let exec = current.clone();
+        let store_code = lua.create_async_function(move |_, source: String| {
             let store = store_clone.clone();
+            exec.metering().record_db_write();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.store_code(&source).await })
-            });
-
-            match result {
-                Ok(hash) => Ok(hash),
-                Err(e) => Err(mlua::Error::external(e)),
+            async move {
+                store
+                    .store_code(&source)
+                    .await
+                    .map_err(mlua::Error::external)
             }
         })?;
         game.set("store_code", store_code)?;
 
         // game.get_code(hash) - returns source
         let store_clone = store.clone();
-        let get_code = lua.create_function(move |lua, hash: String| {
+        let exec = current.clone();
+        let get_code = lua.create_async_function(move |lua, hash: String| {
             let store = store_clone.clone();
+            exec.metering().record_db_read();
 
-            let result: anyhow::Result<Option<String>> = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async { store.get_code(&hash).await })
-            });
-
-            match result {
-                Ok(Some(source)) => Ok(Value::String(lua.create_string(&source)?)),
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(mlua::Error::external(e)),
+            async move {
+                match store.get_code(&hash).await {
+                    Ok(Some(source)) => Ok(Value::String(lua.create_string(&source)?)),
+                    Ok(None) => Ok(Value::Nil),
+                    Err(e) => Err(mlua::Error::external(e)),
+                }
             }
         })?;
         game.set("get_code", get_code)?;
 
         // game.get_children(parent_id, filter) - returns array of objects
         let store_clone = store;
+        let exec = current.clone();
         let get_children =
-            lua.create_function(move |lua, (parent_id, filter): (String, Option<Table>)| {
+            lua.create_async_function(move |lua, (parent_id, filter): (String, Option<Table>)| {
                 let store = store_clone.clone();
+                exec.metering().record_db_read();
 
-                let result = tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current()
-                        .block_on(async { store.get_contents(&parent_id).await })
-                });
+                async move {
+                    let objects = store
+                        .get_contents(&parent_id)
+                        .await
+                        .map_err(mlua::Error::external)?;
 
-                match result {
-                    Ok(objects) => {
-                        let table = lua.create_table()?;
-                        let mut idx = 1;
+                    let table = lua.create_table()?;
+                    let mut idx = 1;
 
-                        // Optional class filter
-                        let class_filter: Option<String> =
-                            filter.as_ref().and_then(|f| f.get::<String>("class").ok());
+                    // Optional class filter
+                    let class_filter: Option<String> =
+                        filter.as_ref().and_then(|f| f.get::<String>("class").ok());
 
-                        for obj in objects {
-                            // Apply filter if specified
-                            if let Some(ref class) = class_filter {
-                                if &obj.class != class {
-                                    continue;
-                                }
+                    for obj in objects {
+                        // Apply filter if specified
+                        if let Some(ref class) = class_filter {
+                            if &obj.class != class {
+                                continue;
                             }
-                            table.set(idx, object_to_lua(lua, &obj)?)?;
-                            idx += 1;
                         }
-                        Ok(table)
+                        table.set(idx, object_to_lua(&lua, &obj)?)?;
+                        idx += 1;
                     }
-                    Err(e) => Err(mlua::Error::external(e)),
+                    Ok(table)
                 }
             })?;
         game.set("get_children", get_children)?;
@@ -480,81 +641,139 @@ impl GameApi {
         Ok(())
     }
 
-    fn register_class_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_class_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let classes = self.classes.clone();
         let store = self.store.clone();
+        let universe_id = self.universe_id.clone();
 
         // game.define_class(name, definition)
-        // Registers a new class with parent and properties
+        // Registers a new class with parent, properties and handler code
         let classes_clone = classes.clone();
-        let define_class = lua.create_function(move |_, (name, definition): (String, Table)| {
-            let classes = classes_clone.clone();
+        let store_clone = store.clone();
+        let universe_clone = universe_id.clone();
+        let exec = current.clone();
+        let define_class =
+            lua.create_async_function(move |lua, (name, definition): (String, Table)| {
+                let classes = classes_clone.clone();
+                let store = store_clone.clone();
+                let universe_id = universe_clone.clone();
+                let metering = exec.metering();
+
+                async move {
+                    // Extract parent from definition
+                    let parent: Option<String> = definition.get("parent").ok();
+
+                    // Extract properties
+                    let properties: Option<Table> = definition.get("properties").ok();
+                    let mut props_map = std::collections::HashMap::new();
+                    if let Some(props) = properties {
+                        for (prop_name, prop_def) in props.pairs::<String, Table>().flatten() {
+                            let prop_type: String = prop_def
+                                .get("type")
+                                .unwrap_or_else(|_| "string".to_string());
+                            let default_val = prop_def.get::<Value>("default").ok();
+                            let json_default = default_val
+                                .map(|v| lua_to_json(v).unwrap_or(serde_json::Value::Null))
+                                .unwrap_or(serde_json::Value::Null);
+                            props_map.insert(prop_name, (prop_type, json_default));
+                        }
+                    }
 
-            // Extract parent from definition
-            let parent: Option<String> = definition.get("parent").ok();
-
-            // Extract properties
-            let properties: Option<Table> = definition.get("properties").ok();
-            let mut props_map = std::collections::HashMap::new();
-            if let Some(props) = properties {
-                for (prop_name, prop_def) in props.pairs::<String, Table>().flatten() {
-                    let prop_type: String = prop_def
-                        .get("type")
-                        .unwrap_or_else(|_| "string".to_string());
-                    let default_val = prop_def.get::<Value>("default").ok();
-                    let json_default = default_val
-                        .map(|v| lua_to_json(v).unwrap_or(serde_json::Value::Null))
-                        .unwrap_or(serde_json::Value::Null);
-                    props_map.insert(prop_name, (prop_type, json_default));
-                }
-            }
+                    // Handler code returns a table of handler functions, whose names
+                    // the class records
+                    let code: Option<String> = definition.get("code")?;
+                    let mut handler_names = Vec::new();
+                    if let Some(ref code) = code {
+                        let class_error = |e: mlua::Error| {
+                            mlua::Error::runtime(format!(
+                                "class {} code must return a table of handlers: {}",
+                                name, e
+                            ))
+                        };
+                        let chunk = lua
+                            .load(code)
+                            .set_name(format!("=class {}", name))
+                            .into_function()
+                            .map_err(class_error)?;
+                        let handlers: Table =
+                            call_async(&lua, chunk, ()).await.map_err(class_error)?;
+                        for pair in handlers.pairs::<String, Value>() {
+                            let (key, value) = pair?;
+                            if matches!(value, Value::Function(_)) {
+                                handler_names.push(key);
+                            }
+                        }
+                        handler_names.sort();
+                    }
 
-            tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
-                    let mut registry = classes.write().await;
-                    registry.define_class(&name, parent.as_deref(), props_map);
-                })
-            });
+                    let result: anyhow::Result<()> = async {
+                        let code_hash = match code {
+                            Some(ref code) => {
+                                metering.record_db_write();
+                                Some(store.store_code(code).await?)
+                            }
+                            None => None,
+                        };
+                        let mut registry = classes.write().await;
+                        registry.define_class(
+                            &universe_id,
+                            &name,
+                            parent.as_deref(),
+                            props_map,
+                            code_hash,
+                            handler_names,
+                        )
+                    }
+                    .await;
+                    result.map_err(mlua::Error::external)?;
 
-            Ok(true)
-        })?;
+                    Ok(true)
+                }
+            })?;
         game.set("define_class", define_class)?;
 
         // game.get_class(name)
         // Returns class definition as table
         let classes_clone = classes.clone();
-        let get_class = lua.create_function(move |lua, name: String| {
+        let universe_clone = universe_id.clone();
+        let get_class = lua.create_async_function(move |lua, name: String| {
             let classes = classes_clone.clone();
+            let universe_id = universe_clone.clone();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
+            async move {
+                let result = {
                     let registry = classes.read().await;
-                    registry.get_class(&name).cloned()
-                })
-            });
+                    registry.get_class(&universe_id, &name).cloned()
+                };
 
-            match result {
-                Some(class_def) => {
-                    let table = lua.create_table()?;
-                    table.set("name", class_def.name.as_str())?;
-                    if let Some(parent) = &class_def.parent {
-                        table.set("parent", parent.as_str())?;
-                    }
-                    // Add properties (simplified - just name -> default value)
-                    let props_table = lua.create_table()?;
-                    for (prop_name, default_val) in &class_def.properties {
-                        props_table.set(prop_name.as_str(), json_to_lua(lua, default_val)?)?;
-                    }
-                    table.set("properties", props_table)?;
-                    // Add handlers
-                    let handlers_table = lua.create_table()?;
-                    for (i, handler) in class_def.handlers.iter().enumerate() {
-                        handlers_table.set(i + 1, handler.as_str())?;
+                match result {
+                    Some(class_def) => {
+                        let table = lua.create_table()?;
+                        table.set("name", class_def.name.as_str())?;
+                        if let Some(parent) = &class_def.parent {
+                            table.set("parent", parent.as_str())?;
+                        }
+                        // Add properties (simplified - just name -> default value)
+                        let props_table = lua.create_table()?;
+                        for (prop_name, default_val) in &class_def.properties {
+                            props_table.set(prop_name.as_str(), json_to_lua(&lua, default_val)?)?;
+                        }
+                        table.set("properties", props_table)?;
+                        // Add handlers
+                        let handlers_table = lua.create_table()?;
+                        for (i, handler) in class_def.handlers.iter().enumerate() {
+                            handlers_table.set(i + 1, handler.as_str())?;
+                        }
+                        table.set("handlers", handlers_table)?;
+                        Ok(Value::Table(table))
                     }
-                    table.set("handlers", handlers_table)?;
-                    Ok(Value::Table(table))
+                    None => Ok(Value::Nil),
                 }
-                None => Ok(Value::Nil),
             }
         })?;
         game.set("get_class", get_class)?;
@@ -563,71 +782,74 @@ impl GameApi {
         // Checks if object is of class or inherits from it
         let store_clone = store.clone();
         let classes_clone = classes.clone();
-        let is_a = lua.create_function(move |_, (obj_id, class_name): (String, String)| {
-            let store = store_clone.clone();
-            let classes = classes_clone.clone();
+        let exec = current.clone();
+        let universe_clone = universe_id.clone();
+        let is_a =
+            lua.create_async_function(move |_, (obj_id, class_name): (String, String)| {
+                let store = store_clone.clone();
+                exec.metering().record_db_read();
+                let classes = classes_clone.clone();
+                let universe_id = universe_clone.clone();
 
-            let result: anyhow::Result<bool> = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
-                    let obj = store.get(&obj_id).await?;
+                async move {
+                    let obj = store.get(&obj_id).await.map_err(mlua::Error::external)?;
                     match obj {
                         Some(o) => {
                             let registry = classes.read().await;
-                            Ok(registry.is_a(&o.class, &class_name))
+                            Ok(registry.is_a(&universe_id, &o.class, &class_name))
                         }
                         None => Ok(false),
                     }
-                })
-            });
-
-            match result {
-                Ok(is_match) => Ok(is_match),
-                Err(e) => Err(mlua::Error::external(e)),
-            }
-        })?;
+                }
+            })?;
         game.set("is_a", is_a)?;
 
         // game.get_class_chain(class_name)
         // Returns inheritance chain as array
-        let get_class_chain = lua.create_function(move |lua, name: String| {
+        let get_class_chain = lua.create_async_function(move |lua, name: String| {
             let classes = classes.clone();
+            let universe_id = universe_id.clone();
 
-            let chain = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
+            async move {
+                let chain = {
                     let registry = classes.read().await;
-                    registry.get_inheritance_chain(&name)
-                })
-            });
+                    registry.get_inheritance_chain(&universe_id, &name)
+                };
 
-            let table = lua.create_table()?;
-            for (i, class_name) in chain.iter().enumerate() {
-                table.set(i + 1, class_name.as_str())?;
+                let table = lua.create_table()?;
+                for (i, class_name) in chain.iter().enumerate() {
+                    table.set(i + 1, class_name.as_str())?;
+                }
+                Ok(table)
             }
-            Ok(table)
         })?;
         game.set("get_class_chain", get_class_chain)?;
 
         Ok(())
     }
 
-    fn register_query_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_query_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let store = self.store.clone();
 
         // game.environment(obj_id)
         // Returns the parent object (container/room)
         let store_clone = store.clone();
-        let environment = lua.create_function(move |lua, obj_id: String| {
+        let exec = current.clone();
+        let environment = lua.create_async_function(move |lua, obj_id: String| {
             let store = store_clone.clone();
+            exec.metering().record_db_read();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.get_environment(&obj_id).await })
-            });
-
-            match result {
-                Ok(Some(obj)) => Ok(Value::Table(object_to_lua(lua, &obj)?)),
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(mlua::Error::external(e)),
+            async move {
+                match store.get_environment(&obj_id).await {
+                    Ok(Some(obj)) => Ok(Value::Table(object_to_lua(&lua, &obj)?)),
+                    Ok(None) => Ok(Value::Nil),
+                    Err(e) => Err(mlua::Error::external(e)),
+                }
             }
         })?;
         game.set("environment", environment)?;
@@ -635,23 +857,21 @@ impl GameApi {
         // game.all_inventory(obj_id)
         // Returns all contents of an object
         let store_clone = store.clone();
-        let all_inventory = lua.create_function(move |lua, obj_id: String| {
+        let exec = current.clone();
+        let all_inventory = lua.create_async_function(move |lua, obj_id: String| {
             let store = store_clone.clone();
-
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.get_contents(&obj_id).await })
-            });
-
-            match result {
-                Ok(objects) => {
-                    let table = lua.create_table()?;
-                    for (i, obj) in objects.iter().enumerate() {
-                        table.set(i + 1, object_to_lua(lua, obj)?)?;
-                    }
-                    Ok(table)
+            exec.metering().record_db_read();
+
+            async move {
+                let objects = store
+                    .get_contents(&obj_id)
+                    .await
+                    .map_err(mlua::Error::external)?;
+                let table = lua.create_table()?;
+                for (i, obj) in objects.iter().enumerate() {
+                    table.set(i + 1, object_to_lua(&lua, obj)?)?;
                 }
-                Err(e) => Err(mlua::Error::external(e)),
+                Ok(table)
             }
         })?;
         game.set("all_inventory", all_inventory)?;
@@ -659,88 +879,117 @@ impl GameApi {
         // game.present(name, env_id)
         // Find object by name in a location
         let store_clone = store.clone();
-        let present = lua.create_function(move |lua, (name, env_id): (String, String)| {
+        let exec = current.clone();
+        let present = lua.create_async_function(move |lua, (name, env_id): (String, String)| {
             let store = store_clone.clone();
+            exec.metering().record_db_read();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.find_by_name(&env_id, &name).await })
-            });
-
-            match result {
-                Ok(Some(obj)) => Ok(Value::Table(object_to_lua(lua, &obj)?)),
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(mlua::Error::external(e)),
+            async move {
+                match store.find_by_name(&env_id, &name).await {
+                    Ok(Some(obj)) => Ok(Value::Table(object_to_lua(&lua, &obj)?)),
+                    Ok(None) => Ok(Value::Nil),
+                    Err(e) => Err(mlua::Error::external(e)),
+                }
             }
         })?;
         game.set("present", present)?;
 
         // game.get_living_in(env_id)
         // Returns living entities (players, npcs) in a location
-        let get_living_in = lua.create_function(move |lua, env_id: String| {
+        let exec = current.clone();
+        let get_living_in = lua.create_async_function(move |lua, env_id: String| {
             let store = store.clone();
+            exec.metering().record_db_read();
+
+            async move {
+                let objects = store
+                    .get_living_in(&env_id)
+                    .await
+                    .map_err(mlua::Error::external)?;
+                let table = lua.create_table()?;
+                for (i, obj) in objects.iter().enumerate() {
+                    table.set(i + 1, object_to_lua(&lua, obj)?)?;
+                }
+                Ok(table)
+            }
+        })?;
+        game.set("get_living_in", get_living_in)?;
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.get_living_in(&env_id).await })
-            });
+        // game.online_players()
+        // Returns the players connected to this universe
+        let connections = self.connections.clone();
+        let universe_id = self.universe_id.clone();
+        let online_players = lua.create_async_function(move |lua, ()| {
+            let connections = connections.clone();
+            let universe_id = universe_id.clone();
+
+            async move {
+                let players = match connections {
+                    Some(connections) => connections.online_players(&universe_id).await,
+                    None => Vec::new(),
+                };
 
-            match result {
-                Ok(objects) => {
-                    let table = lua.create_table()?;
-                    for (i, obj) in objects.iter().enumerate() {
-                        table.set(i + 1, object_to_lua(lua, obj)?)?;
-                    }
-                    Ok(table)
+                let table = lua.create_table()?;
+                for (i, player) in players.iter().enumerate() {
+                    let entry = lua.create_table()?;
+                    entry.set("id", player.player_id.as_str())?;
+                    entry.set("name", player.name.as_str())?;
+                    entry.set("access_level", player.access_level.as_str())?;
+                    entry.set("idle_secs", player.idle_secs)?;
+                    entry.set("online_secs", player.online_secs)?;
+                    entry.set("afk", player.afk.as_deref())?;
+                    table.set(i + 1, entry)?;
                 }
-                Err(e) => Err(mlua::Error::external(e)),
+                Ok(table)
             }
         })?;
-        game.set("get_living_in", get_living_in)?;
+        game.set("online_players", online_players)?;
 
         Ok(())
     }
 
-    fn register_action_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_action_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let actions = self.actions.clone();
-        let room_id = self.current_room_id.clone();
 
         // game.add_action(verb, object_id, method)
         // Adds a contextual action for the current room
         let actions_clone = actions.clone();
-        let room_id_clone = room_id.clone();
-        let add_action = lua.create_function(
-            move |_, (verb, object_id, method): (String, String, String)| {
+        let exec = current.clone();
+        let add_action = lua.create_async_function(
+            move |lua, (verb, object_id, method): (String, String, String)| {
                 let actions = actions_clone.clone();
-                let room_id = room_id_clone.clone();
-
-                // Use blocking task since we're in sync Lua context
-                // In production, this would be handled differently
-                let action = Action {
-                    verb: verb.clone(),
-                    object_id,
-                    method,
-                };
+                let room_id = exec.room_id();
 
-                // Store action - for now we'll use the object_id as the scope
-                // The caller should set up proper room context before execution
-                std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        if let Some(rid) = room_id {
-                            actions.add_room_action(&rid, action).await;
-                        } else {
-                            // If no room context, add as object action
-                            actions
-                                .add_object_action(&action.object_id, action.clone())
-                                .await;
-                        }
-                    });
-                })
-                .join()
-                .ok();
+                async move {
+                    // Inside on_init the verb goes to the living it runs for
+                    let init_actor = handlers::current(&lua).and_then(|f| f.init_actor);
 
-                Ok(true)
+                    let action = Action {
+                        verb: verb.clone(),
+                        object_id,
+                        method,
+                    };
+
+                    // Store action - for now we'll use the object_id as the scope
+                    // The caller should set up proper room context before execution
+                    if let Some(actor_id) = init_actor {
+                        actions.add_living_action(&actor_id, action).await;
+                    } else if let Some(rid) = room_id {
+                        actions.add_room_action(&rid, action).await;
+                    } else {
+                        // If no room context, add as object action
+                        actions
+                            .add_object_action(&action.object_id, action.clone())
+                            .await;
+                    }
+
+                    Ok(true)
+                }
             },
         )?;
         game.set("add_action", add_action)?;
@@ -748,98 +997,112 @@ impl GameApi {
         // game.remove_action(verb, object_id)
         // Removes a contextual action
         let actions_clone = actions.clone();
-        let room_id_clone = room_id;
+        let exec = current.clone();
         let remove_action =
-            lua.create_function(move |_, (verb, object_id): (String, String)| {
+            lua.create_async_function(move |lua, (verb, object_id): (String, String)| {
                 let actions = actions_clone.clone();
-                let room_id = room_id_clone.clone();
-
-                std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        if let Some(rid) = room_id {
-                            actions.remove_room_action(&rid, &verb, &object_id).await;
-                        } else {
-                            actions.remove_object_action(&object_id, &verb).await;
-                        }
-                    });
-                })
-                .join()
-                .ok();
+                let room_id = exec.room_id();
 
-                Ok(true)
+                async move {
+                    let init_actor = handlers::current(&lua).and_then(|f| f.init_actor);
+
+                    if let Some(actor_id) = init_actor {
+                        actions
+                            .remove_living_action(&actor_id, &verb, &object_id)
+                            .await;
+                    } else if let Some(rid) = room_id {
+                        actions.remove_room_action(&rid, &verb, &object_id).await;
+                    } else {
+                        actions.remove_object_action(&object_id, &verb).await;
+                    }
+
+                    Ok(true)
+                }
             })?;
         game.set("remove_action", remove_action)?;
 
         Ok(())
     }
 
-    fn register_message_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
-        let messages = self.messages.clone();
-
+    fn register_message_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         // game.send(target_id, message)
         // Send a private message to a player
-        let messages_clone = messages.clone();
-        let send = lua.create_function(move |_, (target_id, message): (String, String)| {
-            let messages = messages_clone.clone();
+        let exec = current.clone();
+        let send =
+            lua.create_async_function(move |_, (target_id, message): (String, String)| {
+                let messages = exec.messages();
 
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
+                async move {
                     messages.send(&target_id, &message).await;
-                });
-            })
-            .join()
-            .ok();
-
-            Ok(true)
-        })?;
+                    Ok(true)
+                }
+            })?;
         game.set("send", send)?;
 
-        // game.broadcast(room_id, message)
-        // Broadcast a message to all players in a room
-        let messages_clone = messages.clone();
-        let broadcast = lua.create_function(move |_, (room_id, message): (String, String)| {
-            let messages = messages_clone.clone();
-
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    messages.broadcast(&room_id, &message).await;
-                });
-            })
-            .join()
-            .ok();
-
-            Ok(true)
-        })?;
+        // game.broadcast(room_id, message, exclude_id?)
+        // Broadcast a message to all players in a room, optionally skipping one
+        let exec = current.clone();
+        let broadcast = lua.create_async_function(
+            move |_, (room_id, message, exclude_id): (String, String, Option<String>)| {
+                let messages = exec.messages();
+
+                async move {
+                    messages
+                        .broadcast_except(&room_id, &message, exclude_id.as_deref())
+                        .await;
+                    Ok(true)
+                }
+            },
+        )?;
         game.set("broadcast", broadcast)?;
 
-        // game.broadcast_region(region_id, message)
-        // Broadcast a message to all players in a region
-        let broadcast_region =
-            lua.create_function(move |_, (region_id, message): (String, String)| {
-                let messages = messages.clone();
-
-                std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        messages.broadcast_region(&region_id, &message).await;
-                    });
-                })
-                .join()
-                .ok();
+        // game.say(room_id, speaker_id, message)
+        // Speak in a room; everyone there but the speaker hears it
+        let exec = current.clone();
+        let say = lua.create_async_function(
+            move |_, (room_id, speaker_id, message): (String, String, String)| {
+                let messages = exec.messages();
 
-                Ok(true)
-            })?;
+                async move {
+                    messages.say(&room_id, &speaker_id, &message).await;
+                    Ok(true)
+                }
+            },
+        )?;
+        game.set("say", say)?;
+
+        // game.broadcast_region(region_id, message, exclude_id?)
+        // Broadcast a message to all players in a region, optionally skipping one
+        let exec = current.clone();
+        let broadcast_region = lua.create_async_function(
+            move |_, (region_id, message, exclude_id): (String, String, Option<String>)| {
+                let messages = exec.messages();
+
+                async move {
+                    messages
+                        .broadcast_region_except(&region_id, &message, exclude_id.as_deref())
+                        .await;
+                    Ok(true)
+                }
+            },
+        )?;
         game.set("broadcast_region", broadcast_region)?;
 
         Ok(())
     }
 
-    fn register_permission_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_permission_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let permissions = self.permissions.clone();
-        let current_user = self.current_user_id.clone();
         let universe_id = self.universe_id.clone();
 
         // game.set_actor(actor_id)
@@ -867,9 +1130,9 @@ impl GameApi {
         // game.check_permission(action, target_id, is_fixed, owner_id)
         // Returns {allowed: bool, error?: string}
         let permissions_clone = permissions.clone();
-        let current_user_clone = current_user.clone();
+        let exec = current.clone();
         let universe_clone = universe_id.clone();
-        let check_permission = lua.create_function(
+        let check_permission = lua.create_async_function(
             move |lua,
                   (action_str, target_id, is_fixed, owner_id): (
                 String,
@@ -878,62 +1141,56 @@ impl GameApi {
                 Option<String>,
             )| {
                 let permissions = permissions_clone.clone();
-                let current_user = current_user_clone.clone();
+                let current_user = exec.user_id();
                 let universe_id = universe_clone.clone();
 
-                // Parse action string
-                let action = match action_str.as_str() {
-                    "read" => PermAction::Read,
-                    "modify" => PermAction::Modify,
-                    "move" => PermAction::Move,
-                    "delete" => PermAction::Delete,
-                    "create" => PermAction::Create,
-                    "execute" => PermAction::Execute,
-                    "store_code" => PermAction::StoreCode,
-                    "admin_config" => PermAction::AdminConfig,
-                    "grant_credits" => PermAction::GrantCredits,
-                    _ => {
-                        let result = lua.create_table()?;
-                        result.set("allowed", false)?;
-                        result.set("error", format!("Unknown action: {}", action_str))?;
-                        return Ok(result);
-                    }
-                };
+                async move {
+                    // Parse action string
+                    let action = match action_str.as_str() {
+                        "read" => PermAction::Read,
+                        "modify" => PermAction::Modify,
+                        "move" => PermAction::Move,
+                        "delete" => PermAction::Delete,
+                        "create" => PermAction::Create,
+                        "execute" => PermAction::Execute,
+                        "store_code" => PermAction::StoreCode,
+                        "admin_config" => PermAction::AdminConfig,
+                        "grant_credits" => PermAction::GrantCredits,
+                        _ => {
+                            let result = lua.create_table()?;
+                            result.set("allowed", false)?;
+                            result.set("error", format!("Unknown action: {}", action_str))?;
+                            return Ok(result);
+                        }
+                    };
 
-                // Get user context - prefer _current_actor_id from Lua globals if set
-                let globals = lua.globals();
-                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-                let user_id = actor_override
-                    .or(current_user)
-                    .unwrap_or_else(|| "anonymous".to_string());
+                    // Get user context - prefer _current_actor_id from Lua globals if set
+                    let globals = lua.globals();
+                    let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                    let user_id = actor_override
+                        .or(current_user)
+                        .unwrap_or_else(|| "anonymous".to_string());
+
+                    let user_ctx = permissions.get_user_context(&user_id, &universe_id).await;
+                    let obj_ctx = ObjectContext {
+                        object_id: target_id,
+                        owner_id,
+                        is_fixed: is_fixed.unwrap_or(false),
+                    };
+                    let result_data = permissions.check_permission(&user_ctx, action, &obj_ctx);
 
-                // Build contexts synchronously using thread spawn
-                let result_data = std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        let user_ctx = permissions.get_user_context(&user_id, &universe_id).await;
-                        let obj_ctx = ObjectContext {
-                            object_id: target_id,
-                            owner_id,
-                            is_fixed: is_fixed.unwrap_or(false),
-                        };
-                        permissions.check_permission(&user_ctx, action, &obj_ctx)
-                    })
-                })
-                .join()
-                .expect("Thread panicked");
-
-                let result = lua.create_table()?;
-                match result_data {
-                    crate::permissions::PermissionResult::Allowed => {
-                        result.set("allowed", true)?;
-                    }
-                    crate::permissions::PermissionResult::Denied(reason) => {
-                        result.set("allowed", false)?;
-                        result.set("error", reason)?;
+                    let result = lua.create_table()?;
+                    match result_data {
+                        crate::permissions::PermissionResult::Allowed => {
+                            result.set("allowed", true)?;
+                        }
+                        crate::permissions::PermissionResult::Denied(reason) => {
+                            result.set("allowed", false)?;
+                            result.set("error", reason)?;
+                        }
                     }
+                    Ok(result)
                 }
-                Ok(result)
             },
         )?;
         game.set("check_permission", check_permission)?;
@@ -941,55 +1198,45 @@ impl GameApi {
         // game.can_access_path(path)
         // Returns true if the current user can access the given path
         let permissions_clone = permissions.clone();
-        let current_user_clone = current_user.clone();
+        let exec = current.clone();
         let universe_clone = universe_id.clone();
-        let can_access_path = lua.create_function(move |lua, path: String| {
+        let can_access_path = lua.create_async_function(move |lua, path: String| {
             let permissions = permissions_clone.clone();
-            let current_user = current_user_clone.clone();
+            let current_user = exec.user_id();
             let universe_id = universe_clone.clone();
 
-            let globals = lua.globals();
-            let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-            let user_id = actor_override
-                .or(current_user)
-                .unwrap_or_else(|| "anonymous".to_string());
-
-            let result = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    permissions
-                        .can_access_path(&user_id, &universe_id, &path)
-                        .await
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                let globals = lua.globals();
+                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                let user_id = actor_override
+                    .or(current_user)
+                    .unwrap_or_else(|| "anonymous".to_string());
 
-            Ok(result)
+                Ok(permissions
+                    .can_access_path(&user_id, &universe_id, &path)
+                    .await)
+            }
         })?;
         game.set("can_access_path", can_access_path)?;
 
         // game.get_access_level(account_id)
         // Returns the access level of a user as a string
         let permissions_clone = permissions.clone();
-        let get_access_level = lua.create_function(move |_, account_id: String| {
+        let get_access_level = lua.create_async_function(move |_, account_id: String| {
             let permissions = permissions_clone.clone();
 
-            let level = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async { permissions.get_access_level(&account_id).await })
-            })
-            .join()
-            .expect("Thread panicked");
-
-            let level_str = match level {
-                AccessLevel::Player => "player",
-                AccessLevel::Builder => "builder",
-                AccessLevel::Wizard => "wizard",
-                AccessLevel::Admin => "admin",
-                AccessLevel::Owner => "owner",
-            };
-            Ok(level_str.to_string())
+            async move {
+                let level = permissions.get_access_level(&account_id).await;
+
+                let level_str = match level {
+                    AccessLevel::Player => "player",
+                    AccessLevel::Builder => "builder",
+                    AccessLevel::Wizard => "wizard",
+                    AccessLevel::Admin => "admin",
+                    AccessLevel::Owner => "owner",
+                };
+                Ok(level_str.to_string())
+            }
         })?;
         game.set("get_access_level", get_access_level)?;
 
@@ -997,84 +1244,70 @@ impl GameApi {
         // Sets a user's access level (requires admin)
         let permissions_clone = permissions.clone();
         let set_access_level =
-            lua.create_function(move |_, (account_id, level_str): (String, String)| {
+            lua.create_async_function(move |_, (account_id, level_str): (String, String)| {
                 let permissions = permissions_clone.clone();
 
-                let level = match level_str.as_str() {
-                    "player" => AccessLevel::Player,
-                    "builder" => AccessLevel::Builder,
-                    "wizard" => AccessLevel::Wizard,
-                    "admin" => AccessLevel::Admin,
-                    "owner" => AccessLevel::Owner,
-                    _ => return Ok(false),
-                };
-
-                std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        permissions.set_access_level(&account_id, level).await;
-                    });
-                })
-                .join()
-                .ok();
+                async move {
+                    let level = match level_str.as_str() {
+                        "player" => AccessLevel::Player,
+                        "builder" => AccessLevel::Builder,
+                        "wizard" => AccessLevel::Wizard,
+                        "admin" => AccessLevel::Admin,
+                        "owner" => AccessLevel::Owner,
+                        _ => return Ok(false),
+                    };
 
-                Ok(true)
+                    permissions.set_access_level(&account_id, level).await;
+                    Ok(true)
+                }
             })?;
         game.set("set_access_level", set_access_level)?;
 
         // game.grant_path(grantee_id, path_prefix, can_delegate)
         // Grant path access to a user. Returns grant info table or {error: string}
         let permissions_clone = permissions.clone();
-        let current_user_clone = current_user.clone();
+        let exec = current.clone();
         let universe_clone = universe_id.clone();
-        let grant_path = lua.create_function(
+        let grant_path = lua.create_async_function(
             move |lua, (grantee_id, path_prefix, can_delegate): (String, String, Option<bool>)| {
                 let permissions = permissions_clone.clone();
-                let current_user = current_user_clone.clone();
+                let current_user = exec.user_id();
                 let universe_id = universe_clone.clone();
 
-                let globals = lua.globals();
-                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-                let user_id = actor_override
-                    .or(current_user)
-                    .unwrap_or_else(|| "anonymous".to_string());
-
-                let result: Result<crate::permissions::PathGrant, String> =
-                    std::thread::spawn(move || {
-                        let rt = tokio::runtime::Runtime::new().unwrap();
-                        rt.block_on(async {
-                            let grantor_ctx =
-                                permissions.get_user_context(&user_id, &universe_id).await;
-                            permissions
-                                .grant_path(
-                                    &grantor_ctx,
-                                    &grantee_id,
-                                    &universe_id,
-                                    &path_prefix,
-                                    can_delegate.unwrap_or(false),
-                                )
-                                .await
-                                .map_err(|e| e.to_string())
-                        })
-                    })
-                    .join()
-                    .expect("Thread panicked");
-
-                match result {
-                    Ok(grant) => {
-                        let table = lua.create_table()?;
-                        table.set("id", grant.id.as_str())?;
-                        table.set("grantee_id", grant.grantee_id.as_str())?;
-                        table.set("path_prefix", grant.path_prefix.as_str())?;
-                        table.set("can_delegate", grant.can_delegate)?;
-                        table.set("granted_by", grant.granted_by.as_str())?;
-                        table.set("granted_at", grant.granted_at.as_str())?;
-                        Ok(Value::Table(table))
-                    }
-                    Err(e) => {
-                        let table = lua.create_table()?;
-                        table.set("error", e)?;
-                        Ok(Value::Table(table))
+                async move {
+                    let globals = lua.globals();
+                    let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                    let user_id = actor_override
+                        .or(current_user)
+                        .unwrap_or_else(|| "anonymous".to_string());
+
+                    let grantor_ctx = permissions.get_user_context(&user_id, &universe_id).await;
+                    let result = permissions
+                        .grant_path(
+                            &grantor_ctx,
+                            &grantee_id,
+                            &universe_id,
+                            &path_prefix,
+                            can_delegate.unwrap_or(false),
+                        )
+                        .await;
+
+                    match result {
+                        Ok(grant) => {
+                            let table = lua.create_table()?;
+                            table.set("id", grant.id.as_str())?;
+                            table.set("grantee_id", grant.grantee_id.as_str())?;
+                            table.set("path_prefix", grant.path_prefix.as_str())?;
+                            table.set("can_delegate", grant.can_delegate)?;
+                            table.set("granted_by", grant.granted_by.as_str())?;
+                            table.set("granted_at", grant.granted_at.as_str())?;
+                            Ok(Value::Table(table))
+                        }
+                        Err(e) => {
+                            let table = lua.create_table()?;
+                            table.set("error", e.to_string())?;
+                            Ok(Value::Table(table))
+                        }
                     }
                 }
             },
@@ -1084,38 +1317,32 @@ impl GameApi {
         // game.revoke_path(grant_id)
         // Revoke a path grant. Returns true if revoked, false if not found, or {error: string}
         let permissions_clone = permissions.clone();
-        let current_user_clone = current_user.clone();
+        let exec = current.clone();
         let universe_clone = universe_id.clone();
-        let revoke_path = lua.create_function(move |lua, grant_id: String| {
+        let revoke_path = lua.create_async_function(move |lua, grant_id: String| {
             let permissions = permissions_clone.clone();
-            let current_user = current_user_clone.clone();
+            let current_user = exec.user_id();
             let universe_id = universe_clone.clone();
 
-            let globals = lua.globals();
-            let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-            let user_id = actor_override
-                .or(current_user)
-                .unwrap_or_else(|| "anonymous".to_string());
-
-            let result: Result<bool, String> = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    let revoker_ctx = permissions.get_user_context(&user_id, &universe_id).await;
-                    permissions
-                        .revoke_path(&revoker_ctx, &grant_id, &universe_id)
-                        .await
-                        .map_err(|e| e.to_string())
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                let globals = lua.globals();
+                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                let user_id = actor_override
+                    .or(current_user)
+                    .unwrap_or_else(|| "anonymous".to_string());
 
-            match result {
-                Ok(revoked) => Ok(Value::Boolean(revoked)),
-                Err(e) => {
-                    let table = lua.create_table()?;
-                    table.set("error", e)?;
-                    Ok(Value::Table(table))
+                let revoker_ctx = permissions.get_user_context(&user_id, &universe_id).await;
+                let result = permissions
+                    .revoke_path(&revoker_ctx, &grant_id, &universe_id)
+                    .await;
+
+                match result {
+                    Ok(revoked) => Ok(Value::Boolean(revoked)),
+                    Err(e) => {
+                        let table = lua.create_table()?;
+                        table.set("error", e.to_string())?;
+                        Ok(Value::Table(table))
+                    }
                 }
             }
         })?;
@@ -1125,69 +1352,65 @@ impl GameApi {
         // Get all path grants for a user. Returns array of grant info tables.
         let permissions_clone = permissions;
         let universe_clone = universe_id;
-        let get_path_grants = lua.create_function(move |lua, account_id: String| {
+        let get_path_grants = lua.create_async_function(move |lua, account_id: String| {
             let permissions = permissions_clone.clone();
             let universe_id = universe_clone.clone();
 
-            let grants = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async { permissions.get_path_grants(&account_id, &universe_id).await })
-            })
-            .join()
-            .expect("Thread panicked");
-
-            let table = lua.create_table()?;
-            for (i, grant) in grants.iter().enumerate() {
-                let grant_table = lua.create_table()?;
-                grant_table.set("id", grant.id.as_str())?;
-                grant_table.set("grantee_id", grant.grantee_id.as_str())?;
-                grant_table.set("path_prefix", grant.path_prefix.as_str())?;
-                grant_table.set("can_delegate", grant.can_delegate)?;
-                grant_table.set("granted_by", grant.granted_by.as_str())?;
-                grant_table.set("granted_at", grant.granted_at.as_str())?;
-                table.set(i + 1, grant_table)?;
+            async move {
+                let grants = permissions.get_path_grants(&account_id, &universe_id).await;
+
+                let table = lua.create_table()?;
+                for (i, grant) in grants.iter().enumerate() {
+                    let grant_table = lua.create_table()?;
+                    grant_table.set("id", grant.id.as_str())?;
+                    grant_table.set("grantee_id", grant.grantee_id.as_str())?;
+                    grant_table.set("path_prefix", grant.path_prefix.as_str())?;
+                    grant_table.set("can_delegate", grant.can_delegate)?;
+                    grant_table.set("granted_by", grant.granted_by.as_str())?;
+                    grant_table.set("granted_at", grant.granted_at.as_str())?;
+                    table.set(i + 1, grant_table)?;
+                }
+                Ok(table)
             }
-            Ok(table)
         })?;
         game.set("get_path_grants", get_path_grants)?;
 
         Ok(())
     }
 
-    fn register_timer_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_timer_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let timers = self.timers.clone();
         let universe_id = self.universe_id.clone();
-        let current_object = self.current_object_id.clone();
 
         // game.call_out(delay_secs, method, ...)
         // Schedule a one-shot timer to call a method after delay
         // Returns timer_id
         let timers_clone = timers.clone();
         let universe_clone = universe_id.clone();
-        let object_clone = current_object.clone();
-        let call_out = lua.create_function(
+        let exec = current.clone();
+        let call_out = lua.create_async_function(
             move |_, (delay_secs, method, args): (f64, String, Option<String>)| {
                 let timers = timers_clone.clone();
                 let universe_id = universe_clone.clone();
-                let object_id = object_clone.clone();
+                let object_id = exec.object_id();
 
                 let delay_ms = (delay_secs * 1000.0) as u64;
 
-                let timer_id = std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        if let Some(obj_id) = object_id {
-                            let timer = Timer::new(&universe_id, &obj_id, &method, delay_ms, args);
-                            timers.add_timer(timer).await
-                        } else {
-                            String::new()
-                        }
-                    })
-                })
-                .join()
-                .expect("Thread panicked");
+                async move {
+                    let timer_id = if let Some(obj_id) = object_id {
+                        let timer = Timer::new(&universe_id, &obj_id, &method, delay_ms, args);
+                        timers.add_timer(timer).await
+                    } else {
+                        String::new()
+                    };
 
-                Ok(timer_id)
+                    Ok(timer_id)
+                }
             },
         )?;
         game.set("call_out", call_out)?;
@@ -1195,17 +1418,10 @@ impl GameApi {
         // game.remove_call_out(timer_id)
         // Cancel a scheduled timer
         let timers_clone = timers.clone();
-        let remove_call_out = lua.create_function(move |_, timer_id: String| {
+        let remove_call_out = lua.create_async_function(move |_, timer_id: String| {
             let timers = timers_clone.clone();
 
-            let removed = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async { timers.remove_timer(&timer_id).await })
-            })
-            .join()
-            .expect("Thread panicked");
-
-            Ok(removed)
+            async move { Ok(timers.remove_timer(&timer_id).await) }
         })?;
         game.set("remove_call_out", remove_call_out)?;
 
@@ -1213,161 +1429,330 @@ impl GameApi {
         // Set a recurring heartbeat for the current object
         let timers_clone = timers.clone();
         let universe_clone = universe_id.clone();
-        let object_clone = current_object.clone();
-        let set_heart_beat = lua.create_function(move |_, interval_ms: u64| {
+        let exec = current.clone();
+        let set_heart_beat = lua.create_async_function(move |_, interval_ms: u64| {
             let timers = timers_clone.clone();
             let universe_id = universe_clone.clone();
-            let object_id = object_clone.clone();
-
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(obj_id) = object_id {
-                        let hb = HeartBeat::new(&universe_id, &obj_id, interval_ms);
-                        timers.set_heartbeat(hb).await;
-                    }
-                });
-            })
-            .join()
-            .ok();
+            let object_id = exec.object_id();
 
-            Ok(true)
+            async move {
+                if let Some(obj_id) = object_id {
+                    let hb = HeartBeat::new(&universe_id, &obj_id, interval_ms);
+                    timers.set_heartbeat(hb).await;
+                }
+
+                Ok(true)
+            }
         })?;
         game.set("set_heart_beat", set_heart_beat)?;
 
         // game.remove_heart_beat()
         // Remove the heartbeat for the current object
-        let object_clone = current_object;
-        let remove_heart_beat = lua.create_function(move |_, ()| {
+        let exec = current.clone();
+        let remove_heart_beat = lua.create_async_function(move |_, ()| {
             let timers = timers.clone();
-            let object_id = object_clone.clone();
+            let object_id = exec.object_id();
 
-            let removed = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(obj_id) = object_id {
-                        timers.remove_heartbeat(&obj_id).await
-                    } else {
-                        false
-                    }
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                let removed = if let Some(obj_id) = object_id {
+                    timers.remove_heartbeat(&obj_id).await
+                } else {
+                    false
+                };
 
-            Ok(removed)
+                Ok(removed)
+            }
         })?;
         game.set("remove_heart_beat", remove_heart_beat)?;
 
         Ok(())
     }
 
-    fn register_credit_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_credit_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let credits = self.credits.clone();
         let universe_id = self.universe_id.clone();
-        let current_user = self.current_user_id.clone();
         let permissions = self.permissions.clone();
 
         // game.get_credits()
         // Get the current player's credit balance
         let credits_clone = credits.clone();
         let universe_clone = universe_id.clone();
-        let user_clone = current_user.clone();
-        let get_credits = lua.create_function(move |_, ()| {
+        let exec = current.clone();
+        let get_credits = lua.create_async_function(move |_, ()| {
             let credits = credits_clone.clone();
             let universe_id = universe_clone.clone();
-            let user_id = user_clone.clone();
+            let user_id = exec.user_id();
 
-            let balance = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(uid) = user_id {
-                        credits.get_balance(&universe_id, &uid).await
-                    } else {
-                        0
-                    }
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                let balance = if let Some(uid) = user_id {
+                    credits.get_balance(&universe_id, &uid).await
+                } else {
+                    0
+                };
 
-            Ok(balance)
+                Ok(balance)
+            }
         })?;
         game.set("get_credits", get_credits)?;
 
+        // game.get_transactions(account_id?, limit?)
+        // Get credit history, newest first. Defaults to the current player;
+        // other accounts require wizard+
+        let credits_clone = credits.clone();
+        let universe_clone = universe_id.clone();
+        let exec = current.clone();
+        let permissions_clone = permissions.clone();
+        let get_transactions = lua.create_async_function(
+            move |lua, (account_id, limit): (Option<String>, Option<u32>)| {
+                let credits = credits_clone.clone();
+                let universe_id = universe_clone.clone();
+                let user_id = exec.user_id();
+                let permissions = permissions_clone.clone();
+
+                async move {
+                    let Some(uid) = user_id else {
+                        return Err(mlua::Error::external("No current player"));
+                    };
+                    let account_id = account_id.unwrap_or_else(|| uid.clone());
+                    let limit = limit.unwrap_or(20).min(100);
+
+                    let result: anyhow::Result<_> = async {
+                        if account_id != uid
+                            && permissions.get_access_level(&uid).await < AccessLevel::Wizard
+                        {
+                            anyhow::bail!(
+                                "Permission denied: cannot view another player's credits"
+                            );
+                        }
+                        credits
+                            .get_transactions(&universe_id, &account_id, limit)
+                            .await
+                    }
+                    .await;
+                    let result = result.map_err(|e| mlua::Error::external(e.to_string()))?;
+
+                    let list = lua.create_table()?;
+                    for (i, transaction) in result.into_iter().enumerate() {
+                        let entry = lua.create_table()?;
+                        entry.set("id", transaction.id)?;
+                        entry.set("amount", transaction.amount)?;
+                        entry.set("balance_after", transaction.balance_after)?;
+                        entry.set("reason", transaction.reason)?;
+                        entry.set("timestamp", transaction.timestamp.to_rfc3339())?;
+                        list.set(i + 1, entry)?;
+                    }
+                    Ok(list)
+                }
+            },
+        )?;
+        game.set("get_transactions", get_transactions)?;
+
         // game.deduct_credits(amount, reason)
         // Deduct credits from the current player
         // Returns true if successful, false if insufficient funds
         let credits_clone = credits.clone();
         let universe_clone = universe_id.clone();
-        let user_clone = current_user.clone();
-        let deduct_credits = lua.create_function(move |_, (amount, reason): (i64, String)| {
-            let credits = credits_clone.clone();
-            let universe_id = universe_clone.clone();
-            let user_id = user_clone.clone();
+        let exec = current.clone();
+        let deduct_credits =
+            lua.create_async_function(move |_, (amount, reason): (i64, String)| {
+                let credits = credits_clone.clone();
+                let universe_id = universe_clone.clone();
+                let user_id = exec.user_id();
 
-            let result = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(uid) = user_id {
+                async move {
+                    let result = if let Some(uid) = user_id {
                         credits.deduct(&universe_id, &uid, amount, &reason).await
                     } else {
                         false
+                    };
+
+                    Ok(result)
+                }
+            })?;
+        game.set("deduct_credits", deduct_credits)?;
+
+        // game.transfer_credits(to_account_id, amount, reason)
+        // Pay another player from the current player's balance. Only the
+        // player's own eval or typed command may pay: object handlers never
+        // can, even on objects the player owns, since whoever wrote an
+        // object's code need not be its owner.
+        // Returns true, or false and an error message
+        let credits_clone = credits.clone();
+        let universe_clone = universe_id.clone();
+        let exec = current.clone();
+        let transfer_credits = lua.create_async_function(
+            move |lua, (to_account_id, amount, reason): (String, i64, Option<String>)| {
+                let credits = credits_clone.clone();
+                let universe_id = universe_clone.clone();
+                let user_id = exec.user_id();
+                let metering = exec.metering();
+                let player_input = exec.player_input();
+
+                async move {
+                    let Some(uid) = user_id else {
+                        return Ok((false, Some("No current player".to_string())));
+                    };
+                    if let Some(frame) = handlers::current(&lua) {
+                        return Ok((
+                            false,
+                            Some(format!(
+                                "Permission denied: {} can't spend player credits",
+                                frame.object_id
+                            )),
+                        ));
+                    }
+                    if !player_input {
+                        return Ok((
+                            false,
+                            Some("Permission denied: not the player's own input".to_string()),
+                        ));
+                    }
+                    metering.record_db_write();
+                    let reason = reason.unwrap_or_else(|| "transfer".to_string());
+
+                    let result = credits
+                        .transfer(&universe_id, &uid, &to_account_id, amount, &reason)
+                        .await;
+
+                    match result {
+                        Ok(()) => Ok((true, None)),
+                        Err(e) => Ok((false, Some(e.to_string()))),
+                    }
+                }
+            },
+        )?;
+        game.set("transfer_credits", transfer_credits)?;
+
+        // game.sell(item_id, buyer_id)
+        // Sell an item held by the current object to the current player.
+        // The item's "price" property is paid to the owner of the item and
+        // the item moves into the buyer's inventory in one step. Only code
+        // running on an object owned by the item's owner can sell it.
+        // Returns true, or false and an error message
+        let credits_clone = credits.clone();
+        let store = self.store.clone();
+        let universe_clone = universe_id.clone();
+        let exec = current.clone();
+        let sell = lua.create_async_function(move |_, (item_id, buyer_id): (String, String)| {
+            let credits = credits_clone.clone();
+            let store = store.clone();
+            let universe_id = universe_clone.clone();
+            let user_id = exec.user_id();
+            let object_id = exec.object_id();
+            let metering = exec.metering();
+
+            async move {
+                let (Some(uid), Some(vendor_id)) = (user_id, object_id) else {
+                    return Ok((false, Some("No current player or object".to_string())));
+                };
+                metering.record_db_read();
+                metering.record_db_read();
+                metering.record_db_read();
+                metering.record_db_write();
+
+                let result: anyhow::Result<()> = async {
+                    let vendor = store.get(&vendor_id).await?;
+                    let item = store.get(&item_id).await?;
+                    let buyer = store.get(&buyer_id).await?;
+                    let (Some(vendor), Some(item), Some(buyer)) = (vendor, item, buyer) else {
+                        anyhow::bail!("Object not found");
+                    };
+
+                    if item.parent_id.as_deref() != Some(vendor.id.as_str()) {
+                        anyhow::bail!("{} is not for sale here", item.id);
+                    }
+                    let price = match item.get_i64("price") {
+                        Some(price) if price > 0 => price,
+                        _ => anyhow::bail!("{} has no price", item.id),
+                    };
+                    let seller = match (&vendor.owner_id, &item.owner_id) {
+                        (Some(vendor_owner), Some(item_owner)) if vendor_owner == item_owner => {
+                            item_owner.clone()
+                        }
+                        _ => anyhow::bail!("Permission denied: vendor does not own {}", item.id),
+                    };
+                    if buyer.owner_id.as_deref() != Some(uid.as_str()) {
+                        anyhow::bail!("Permission denied: buyer must be the current player");
                     }
-                })
-            })
-            .join()
-            .expect("Thread panicked");
 
-            Ok(result)
+                    credits
+                        .purchase(
+                            &universe_id,
+                            &uid,
+                            &seller,
+                            price,
+                            &format!("buy {}", item.id),
+                            ItemTransfer {
+                                object_id: item.id.clone(),
+                                from_parent_id: vendor.id.clone(),
+                                to_parent_id: buyer.id.clone(),
+                            },
+                        )
+                        .await
+                }
+                .await;
+
+                match result {
+                    Ok(()) => Ok((true, None)),
+                    Err(e) => Ok((false, Some(e.to_string()))),
+                }
+            }
         })?;
-        game.set("deduct_credits", deduct_credits)?;
+        game.set("sell", sell)?;
 
         // game.admin_grant_credits(account_id, amount)
         // Grant credits to a player (wizard+ only)
         let credits_clone = credits;
         let universe_clone = universe_id;
-        let user_clone = current_user;
+        let exec = current.clone();
         let admin_grant_credits =
-            lua.create_function(move |_, (account_id, amount): (String, i64)| {
+            lua.create_async_function(move |_, (account_id, amount): (String, i64)| {
                 let credits = credits_clone.clone();
                 let universe_id = universe_clone.clone();
-                let user_id = user_clone.clone();
+                let user_id = exec.user_id();
                 let permissions = permissions.clone();
 
-                let result = std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        // Check if current user is wizard+
-                        if let Some(ref uid) = user_id {
-                            let level = permissions.get_access_level(uid).await;
-                            if level < AccessLevel::Wizard {
-                                return false;
-                            }
-                        } else {
-                            return false;
+                async move {
+                    // Check if current user is wizard+
+                    if let Some(ref uid) = user_id {
+                        let level = permissions.get_access_level(uid).await;
+                        if level < AccessLevel::Wizard {
+                            return Ok(false);
                         }
+                    } else {
+                        return Ok(false);
+                    }
 
-                        credits
-                            .grant(&universe_id, &account_id, amount, "admin_grant")
-                            .await;
-                        true
-                    })
-                })
-                .join()
-                .expect("Thread panicked");
-
-                Ok(result)
+                    Ok(credits
+                        .grant(&universe_id, &account_id, amount, "admin_grant")
+                        .await)
+                }
             })?;
         game.set("admin_grant_credits", admin_grant_credits)?;
 
         Ok(())
     }
 
-    fn register_venice_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
+    fn register_venice_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let venice = self.venice.clone();
         let image_store = self.image_store.clone();
-        let current_user = self.current_user_id.clone();
+
+        // Error table returned in place of a result
+        fn error_table(lua: &Lua, message: impl Into<String>) -> LuaResult<Value> {
+            let result = lua.create_table()?;
+            result.set("error", message.into())?;
+            Ok(Value::Table(result))
+        }
 
         // game.llm_chat(messages, tier)
         // Send a chat completion request to Venice AI
@@ -1375,44 +1760,45 @@ impl GameApi {
         // tier: "fast", "balanced", or "quality"
         // Returns response text or nil on error
         let venice_clone = venice.clone();
-        let user_clone = current_user.clone();
-        let llm_chat = lua.create_function(
+        let exec = current.clone();
+        let llm_chat = lua.create_async_function(
             move |lua, (messages_table, tier_str): (Table, Option<String>)| {
                 let venice = venice_clone.clone();
-                let user_id = user_clone.clone();
-
-                // Parse messages from Lua table
-                let mut messages = Vec::new();
-                for pair in messages_table.sequence_values::<Table>() {
-                    let msg_table = pair?;
-                    let role: String = msg_table.get("role")?;
-                    let content: String = msg_table.get("content")?;
-                    messages.push(ChatMessage { role, content });
-                }
+                let user_id = exec.user_id();
+                let metering = exec.metering();
+                let billing = exec.billing();
+
+                async move {
+                    // Parse messages from Lua table
+                    let mut messages = Vec::new();
+                    for pair in messages_table.sequence_values::<Table>() {
+                        let msg_table = pair?;
+                        let role: String = msg_table.get("role")?;
+                        let content: String = msg_table.get("content")?;
+                        messages.push(ChatMessage { role, content });
+                    }
 
-                // Parse tier
-                let tier = tier_str
-                    .as_deref()
-                    .and_then(ModelTier::parse)
-                    .unwrap_or(ModelTier::Balanced);
+                    // Parse tier
+                    let tier = tier_str
+                        .as_deref()
+                        .and_then(ModelTier::parse)
+                        .unwrap_or(ModelTier::Balanced);
 
-                let result = std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        let account_id = user_id.as_deref().unwrap_or("anonymous");
-                        venice.chat(account_id, messages, tier).await
-                    })
-                })
-                .join()
-                .expect("Thread panicked");
+                    if let Some(ref billing) = billing {
+                        if !billing.can_afford(&metering, billing.costs.llm_chat(tier)) {
+                            return error_table(&lua, "Insufficient credits");
+                        }
+                    }
 
-                match result {
-                    Ok(response) => Ok(Value::String(lua.create_string(&response)?)),
-                    Err(e) => {
-                        // Return nil and error message
-                        let result = lua.create_table()?;
-                        result.set("error", e)?;
-                        Ok(Value::Table(result))
+                    let account_id = user_id.as_deref().unwrap_or("anonymous");
+                    let result = venice.chat(account_id, messages, tier).await;
+
+                    match result {
+                        Ok(response) => {
+                            metering.record_llm_chat(tier);
+                            Ok(Value::String(lua.create_string(&response)?))
+                        }
+                        Err(e) => error_table(&lua, e),
                     }
                 }
             },
@@ -1425,26 +1811,33 @@ impl GameApi {
         // style: "realistic", "anime", "digital", "painterly"
         // size: "small", "medium", "large"
         // Returns image hash string (for use with /images/{hash}) or error table
-        let user_clone = current_user;
-        let llm_image = lua.create_function(
+        let exec = current.clone();
+        let llm_image = lua.create_async_function(
             move |lua, (prompt, style_str, size_str): (String, Option<String>, Option<String>)| {
                 let venice = venice.clone();
                 let image_store = image_store.clone();
-                let user_id = user_clone.clone();
-
-                // Parse style and size
-                let style = style_str
-                    .as_deref()
-                    .and_then(ImageStyle::parse)
-                    .unwrap_or(ImageStyle::Realistic);
-                let size = size_str
-                    .as_deref()
-                    .and_then(ImageSize::parse)
-                    .unwrap_or(ImageSize::Medium);
-
-                let result = std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
+                let user_id = exec.user_id();
+                let metering = exec.metering();
+                let billing = exec.billing();
+
+                async move {
+                    // Parse style and size
+                    let style = style_str
+                        .as_deref()
+                        .and_then(ImageStyle::parse)
+                        .unwrap_or(ImageStyle::Realistic);
+                    let size = size_str
+                        .as_deref()
+                        .and_then(ImageSize::parse)
+                        .unwrap_or(ImageSize::Medium);
+
+                    if let Some(ref billing) = billing {
+                        if !billing.can_afford(&metering, billing.costs.llm_image) {
+                            return error_table(&lua, "Insufficient credits");
+                        }
+                    }
+
+                    let result: Result<String, String> = async {
                         let account_id = user_id.as_deref().unwrap_or("anonymous");
                         // Generate image (returns raw binary bytes)
                         let image_bytes = venice
@@ -1454,18 +1847,15 @@ impl GameApi {
                         image_store
                             .store(&image_bytes, "image/png", "llm_image")
                             .await
-                    })
-                })
-                .join()
-                .expect("Thread panicked");
+                    }
+                    .await;
 
-                match result {
-                    Ok(hash) => Ok(Value::String(lua.create_string(&hash)?)),
-                    Err(e) => {
-                        // Return nil and error message
-                        let result = lua.create_table()?;
-                        result.set("error", e)?;
-                        Ok(Value::Table(result))
+                    match result {
+                        Ok(hash) => {
+                            metering.record_llm_image();
+                            Ok(Value::String(lua.create_string(&hash)?))
+                        }
+                        Err(e) => error_table(&lua, e),
                     }
                 }
             },
@@ -1475,20 +1865,22 @@ impl GameApi {
         Ok(())
     }
 
-    fn register_utility_functions(&self, lua: &Lua, game: &Table) -> LuaResult<()> {
-        let time_override = self.time_override.clone();
-        let rng = self.rng.clone();
+    fn register_utility_functions(
+        &self,
+        lua: &Lua,
+        game: &Table,
+        current: &Current,
+    ) -> LuaResult<()> {
         let permissions = self.permissions.clone();
-        let current_user = self.current_user_id.clone();
         let store = self.store.clone();
         let universe_id = self.universe_id.clone();
 
         // game.time()
         // Returns current time in milliseconds since epoch
         // If time is overridden (for testing), returns the override value
-        let time_override_clone = time_override.clone();
+        let exec = current.clone();
         let get_time = lua.create_function(move |_, ()| {
-            let override_val = time_override_clone.load(Ordering::Relaxed);
+            let override_val = exec.time_override().load(Ordering::Relaxed);
             if override_val > 0 {
                 Ok(override_val)
             } else {
@@ -1504,78 +1896,66 @@ impl GameApi {
         // game.set_time(t)
         // Override current time for testing (wizard+ only)
         // Set to 0 to return to real time
-        let time_override_clone = time_override.clone();
         let permissions_clone = permissions.clone();
-        let user_clone = current_user.clone();
-        let set_time = lua.create_function(move |lua, time_ms: u64| {
+        let exec = current.clone();
+        let set_time = lua.create_async_function(move |lua, time_ms: u64| {
             let permissions = permissions_clone.clone();
-            let user_id = user_clone.clone();
-            let time_override = time_override_clone.clone();
+            let user_id = exec.user_id();
+            let time_override = exec.time_override();
 
-            // Check for actor override from game.set_actor()
-            let globals = lua.globals();
-            let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-            let effective_user = actor_override.or(user_id);
-
-            // Check wizard+ permission
-            let allowed = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(ref uid) = effective_user {
-                        let level = permissions.get_access_level(uid).await;
-                        level >= AccessLevel::Wizard
-                    } else {
-                        false
-                    }
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                // Check for actor override from game.set_actor()
+                let globals = lua.globals();
+                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                let effective_user = actor_override.or(user_id);
 
-            if allowed {
-                time_override.store(time_ms, Ordering::Relaxed);
-                Ok(true)
-            } else {
-                Ok(false)
+                // Check wizard+ permission
+                let allowed = if let Some(ref uid) = effective_user {
+                    let level = permissions.get_access_level(uid).await;
+                    level >= AccessLevel::Wizard
+                } else {
+                    false
+                };
+
+                if allowed {
+                    time_override.store(time_ms, Ordering::Relaxed);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
             }
         })?;
         game.set("set_time", set_time)?;
 
         // game.set_rng_seed(seed)
         // Set RNG seed for reproducible testing (wizard+ only)
-        let rng_clone = rng.clone();
         let permissions_clone = permissions.clone();
-        let user_clone = current_user.clone();
-        let set_rng_seed = lua.create_function(move |lua, seed: u64| {
+        let exec = current.clone();
+        let set_rng_seed = lua.create_async_function(move |lua, seed: u64| {
             let permissions = permissions_clone.clone();
-            let user_id = user_clone.clone();
-            let rng = rng_clone.clone();
+            let user_id = exec.user_id();
+            let rng = exec.rng();
 
-            // Check for actor override from game.set_actor()
-            let globals = lua.globals();
-            let actor_override: Option<String> = globals.get("_current_actor_id").ok();
-            let effective_user = actor_override.or(user_id);
-
-            // Check wizard+ permission
-            let allowed = std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Some(ref uid) = effective_user {
-                        let level = permissions.get_access_level(uid).await;
-                        level >= AccessLevel::Wizard
-                    } else {
-                        false
-                    }
-                })
-            })
-            .join()
-            .expect("Thread panicked");
+            async move {
+                // Check for actor override from game.set_actor()
+                let globals = lua.globals();
+                let actor_override: Option<String> = globals.get("_current_actor_id").ok();
+                let effective_user = actor_override.or(user_id);
 
-            if allowed {
-                *rng.lock() = StdRng::seed_from_u64(seed);
-                Ok(true)
-            } else {
-                Ok(false)
+                // Check wizard+ permission
+                let allowed = if let Some(ref uid) = effective_user {
+                    let level = permissions.get_access_level(uid).await;
+                    level >= AccessLevel::Wizard
+                } else {
+                    false
+                };
+
+                if allowed {
+                    *rng.lock() = StdRng::seed_from_u64(seed);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
             }
         })?;
         game.set("set_rng_seed", set_rng_seed)?;
@@ -1583,8 +1963,9 @@ impl GameApi {
         // game.roll_dice(dice_str)
         // Parse and roll dice notation like "2d6+3", "1d20-2"
         // Returns total roll result
+        let exec = current.clone();
         let roll_dice = lua.create_function(move |_, dice_str: String| {
-            let rng = rng.clone();
+            let rng = exec.rng();
 
             // Parse dice notation: NdM[+/-K]
             let result = parse_and_roll_dice(&dice_str, &rng);
@@ -1596,11 +1977,12 @@ impl GameApi {
         game.set("roll_dice", roll_dice)?;
 
         // game.use_object(obj_id, actor_id, verb, target_id)
-        // Invoke an object's handler method
+        // Invoke an object's handler method, from its own code or its classes'
         // Returns the result from the handler, or nil if not found
         let store_clone = store.clone();
+        let classes = self.classes.clone();
         let use_object =
-            lua.create_function(
+            lua.create_async_function(
                 move |lua,
                       (obj_id, actor_id, verb, target_id): (
                     String,
@@ -1609,73 +1991,29 @@ impl GameApi {
                     Option<String>,
                 )| {
                     let store = store_clone.clone();
+                    let classes = classes.clone();
+
+                    async move {
+                        // Map verb to handler name
+                        let handler_name = match verb.as_str() {
+                            "use" => "on_use",
+                            "hit" => "on_hit",
+                            "look" => "on_look",
+                            "init" => "on_init",
+                            _ => verb.as_str(), // Use verb directly if no mapping
+                        };
 
-                    // Get the object
-                    let result: anyhow::Result<Option<(String, String)>> =
-                        tokio::task::block_in_place(|| {
-                            tokio::runtime::Handle::current().block_on(async {
-                                let obj = store.get(&obj_id).await?;
-                                match obj {
-                                    Some(o) => {
-                                        if let Some(hash) = o.code_hash {
-                                            let code = store.get_code(&hash).await?;
-                                            Ok(code.map(|c| (hash, c)))
-                                        } else {
-                                            Ok(None)
-                                        }
-                                    }
-                                    None => Ok(None),
-                                }
-                            })
-                        });
-
-                    let (code_hash, code) = match result {
-                        Ok(Some((h, c))) => (h, c),
-                        Ok(None) => return Ok(Value::Nil),
-                        Err(e) => return Err(mlua::Error::external(e)),
-                    };
-
-                    // Create a minimal sub-environment to execute the object code
-                    // First, evaluate the code to get the object's function table
-                    let chunk = lua.load(&code);
-                    let handlers: LuaResult<Table> = chunk.eval();
-
-                    match handlers {
-                        Ok(handler_table) => {
-                            // Map verb to handler name
-                            let handler_name = match verb.as_str() {
-                                "use" => "on_use",
-                                "hit" => "on_hit",
-                                "look" => "on_look",
-                                "init" => "on_init",
-                                _ => verb.as_str(), // Use verb directly if no mapping
-                            };
-
-                            // Try to get the handler function
-                            if let Ok(handler) = handler_table.get::<Function>(handler_name) {
-                                // Call the handler with context
-                                let args_table = lua.create_table()?;
-                                args_table.set("object_id", obj_id.as_str())?;
-                                args_table.set("actor_id", actor_id.as_str())?;
-                                args_table.set("verb", verb.as_str())?;
-                                args_table.set("code_hash", code_hash.as_str())?;
-                                if let Some(ref tid) = target_id {
-                                    args_table.set("target_id", tid.as_str())?;
-                                }
-
-                                match handler.call::<Value>(args_table) {
-                                    Ok(result) => Ok(result),
-                                    Err(e) => Err(e),
-                                }
-                            } else {
-                                // No handler for this verb
-                                Ok(Value::Nil)
-                            }
-                        }
-                        Err(_) => {
-                            // Code didn't return a table, try calling it as a function
-                            Ok(Value::Nil)
+                        let ctx = lua.create_table()?;
+                        ctx.set("actor_id", actor_id.as_str())?;
+                        ctx.set("verb", verb.as_str())?;
+                        if let Some(ref tid) = target_id {
+                            ctx.set("target_id", tid.as_str())?;
                         }
+
+                        let result =
+                            handlers::fire(&lua, &store, &classes, &obj_id, handler_name, ctx)
+                                .await?;
+                        Ok(result.unwrap_or(Value::Nil))
                     }
                 },
             )?;
@@ -1685,27 +2023,24 @@ impl GameApi {
         // Returns universe info as table {id, name, owner_id, config, created_at}
         let store_clone = store.clone();
         let universe_clone = universe_id.clone();
-        let get_universe = lua.create_function(move |lua, ()| {
+        let get_universe = lua.create_async_function(move |lua, ()| {
             let store = store_clone.clone();
             let universe_id = universe_clone.clone();
 
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current()
-                    .block_on(async { store.get_universe(&universe_id).await })
-            });
-
-            match result {
-                Ok(Some(info)) => {
-                    let table = lua.create_table()?;
-                    table.set("id", info.id.as_str())?;
-                    table.set("name", info.name.as_str())?;
-                    table.set("owner_id", info.owner_id.as_str())?;
-                    table.set("config", json_to_lua(lua, &info.config)?)?;
-                    table.set("created_at", info.created_at.as_str())?;
-                    Ok(Value::Table(table))
+            async move {
+                match store.get_universe(&universe_id).await {
+                    Ok(Some(info)) => {
+                        let table = lua.create_table()?;
+                        table.set("id", info.id.as_str())?;
+                        table.set("name", info.name.as_str())?;
+                        table.set("owner_id", info.owner_id.as_str())?;
+                        table.set("config", json_to_lua(&lua, &info.config)?)?;
+                        table.set("created_at", info.created_at.as_str())?;
+                        Ok(Value::Table(table))
+                    }
+                    Ok(None) => Ok(Value::Nil),
+                    Err(e) => Err(mlua::Error::external(e)),
                 }
-                Ok(None) => Ok(Value::Nil),
-                Err(e) => Err(mlua::Error::external(e)),
             }
         })?;
         game.set("get_universe", get_universe)?;
@@ -1716,36 +2051,31 @@ impl GameApi {
         let store_clone = store;
         let universe_clone = universe_id;
         let permissions_clone = permissions.clone();
-        let user_clone = current_user.clone();
-        let update_universe = lua.create_function(move |_, config: Table| {
+        let exec = current.clone();
+        let update_universe = lua.create_async_function(move |_, config: Table| {
             let store = store_clone.clone();
             let universe_id = universe_clone.clone();
             let permissions = permissions_clone.clone();
-            let user_id = user_clone.clone();
+            let user_id = exec.user_id();
 
-            // Convert Lua table to JSON
-            let config_json = lua_to_json(Value::Table(config))?;
+            async move {
+                // Convert Lua table to JSON
+                let config_json = lua_to_json(Value::Table(config))?;
 
-            // Check wizard+ permission and update
-            let result: Result<bool, anyhow::Error> = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(async {
-                    // Check permission
-                    if let Some(ref uid) = user_id {
-                        let level = permissions.get_access_level(uid).await;
-                        if level < AccessLevel::Wizard {
-                            return Ok(false);
-                        }
-                    } else {
+                // Check wizard+ permission
+                if let Some(ref uid) = user_id {
+                    let level = permissions.get_access_level(uid).await;
+                    if level < AccessLevel::Wizard {
                         return Ok(false);
                     }
+                } else {
+                    return Ok(false);
+                }
 
-                    store.update_universe(&universe_id, config_json).await
-                })
-            });
-
-            match result {
-                Ok(success) => Ok(success),
-                Err(e) => Err(mlua::Error::external(e)),
+                store
+                    .update_universe(&universe_id, config_json)
+                    .await
+                    .map_err(mlua::Error::external)
             }
         })?;
         game.set("update_universe", update_universe)?;
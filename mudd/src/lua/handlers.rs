@@ -0,0 +1,376 @@
+//! Handler resolution - find and run an object's handlers
+//!
+//! An object's handlers come from its own code, then the code of each class
+//! in its inheritance chain, most specific first. Each piece of code returns
+//! a table of handler functions, and a handler runs from the first table
+//! that defines it. The handler's context table records which class it came
+//! from, so `parent(ctx)` can carry on up the chain from there.
+//!
+//! Scripts can write anything into a context table, so what a handler may
+//! do is decided by a `Frame` kept on the Rust side instead: the object the
+//! handler runs for and, inside `on_init`, the living it runs for.
+
+use mlua::{Function, Lua, Result as LuaResult, Table, Value};
+use tokio::sync::RwLock;
+
+use super::init::{self, INIT_HANDLER};
+use super::pool;
+use super::{call_async, ActionRegistry};
+use crate::objects::{ClassRegistry, Object, ObjectStore};
+
+/// Code that may define handlers for an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandlerSource {
+    /// Class the code belongs to, or None for the object's own code
+    pub class: Option<String>,
+    /// Hash of the code in code_store
+    pub hash: Option<String>,
+    pub code: String,
+}
+
+/// An object's handler code: its own, then its classes', child first
+pub async fn object_sources(
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    object: &Object,
+) -> anyhow::Result<Vec<HandlerSource>> {
+    let mut sources = Vec::new();
+    if let Some(ref hash) = object.code_hash {
+        if let Some(code) = store.get_code(hash).await? {
+            sources.push(HandlerSource {
+                class: None,
+                hash: Some(hash.clone()),
+                code,
+            });
+        }
+    }
+    sources.extend(class_sources(store, classes, &object.universe_id, &object.class).await?);
+    Ok(sources)
+}
+
+/// Handler code for a class and its ancestors, child first
+pub async fn class_sources(
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    universe_id: &str,
+    class: &str,
+) -> anyhow::Result<Vec<HandlerSource>> {
+    let hashes: Vec<(String, String)> = {
+        let registry = classes.read().await;
+        registry
+            .get_chain(universe_id, class)
+            .into_iter()
+            .filter_map(|name| {
+                let hash = registry.get(universe_id, &name)?.code_hash.clone()?;
+                Some((name, hash))
+            })
+            .collect()
+    };
+
+    let mut sources = Vec::new();
+    for (class, hash) in hashes {
+        if let Some(code) = store.get_code(&hash).await? {
+            sources.push(HandlerSource {
+                class: Some(class),
+                hash: Some(hash),
+                code,
+            });
+        }
+    }
+    Ok(sources)
+}
+
+/// The object a running handler belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub object_id: String,
+    /// The living an `on_init` handler runs for; `game.add_action` gives
+    /// verbs to it
+    pub init_actor: Option<String>,
+}
+
+impl Frame {
+    pub fn new(object_id: impl Into<String>) -> Self {
+        Self {
+            object_id: object_id.into(),
+            init_actor: None,
+        }
+    }
+
+    pub fn init(object_id: impl Into<String>, actor_id: impl Into<String>) -> Self {
+        Self {
+            object_id: object_id.into(),
+            init_actor: Some(actor_id.into()),
+        }
+    }
+}
+
+/// Frames of the handlers running in a sandbox, innermost last
+#[derive(Default)]
+struct Frames(Vec<Frame>);
+
+/// Pops a frame when its handler finishes, even if it was cancelled
+struct FrameGuard(Lua);
+
+impl FrameGuard {
+    fn push(lua: &Lua, frame: Frame) -> Self {
+        if lua.app_data_ref::<Frames>().is_none() {
+            lua.set_app_data(Frames::default());
+        }
+        if let Some(mut frames) = lua.app_data_mut::<Frames>() {
+            frames.0.push(frame);
+        }
+        Self(lua.clone())
+    }
+}
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        if let Some(mut frames) = self.0.app_data_mut::<Frames>() {
+            frames.0.pop();
+        }
+    }
+}
+
+/// The innermost running handler's frame, or None outside any handler
+pub fn current(lua: &Lua) -> Option<Frame> {
+    lua.app_data_ref::<Frames>()?.0.last().cloned()
+}
+
+/// Run `handler` from the first of `sources` that defines it
+///
+/// Sets `ctx.handler` and `ctx.class` for the call, restoring `ctx.class`
+/// afterwards. The handler runs in `frame`, or in the caller's frame when
+/// None, as `parent()` does. Returns None when no source defines the
+/// handler.
+pub async fn call(
+    lua: &Lua,
+    frame: Option<Frame>,
+    sources: &[HandlerSource],
+    handler: &str,
+    ctx: Table,
+) -> LuaResult<Option<Value>> {
+    for source in sources {
+        let name = match source.class {
+            Some(ref class) => format!("=class {}", class),
+            None => "=object".to_string(),
+        };
+        let chunk = pool::load_code(lua, source.hash.as_deref(), &source.code, &name)?;
+        let Value::Table(handlers) = call_async::<Value>(lua, chunk, ()).await? else {
+            continue;
+        };
+        let Ok(function) = handlers.get::<Function>(handler) else {
+            continue;
+        };
+
+        let caller_class: Value = ctx.get("class")?;
+        ctx.set("handler", handler)?;
+        ctx.set("class", source.class.as_deref())?;
+        let guard = frame.map(|frame| FrameGuard::push(lua, frame));
+        let result = call_async::<Value>(lua, function, ctx.clone()).await;
+        drop(guard);
+        ctx.set("class", caller_class)?;
+        return result.map(Some);
+    }
+    Ok(None)
+}
+
+/// Sources after the one a running handler came from
+///
+/// `class` is the running handler's `ctx.class`: None for the object's own
+/// code.
+pub fn after<'a>(sources: &'a [HandlerSource], class: Option<&str>) -> &'a [HandlerSource] {
+    match sources.iter().position(|s| s.class.as_deref() == class) {
+        Some(index) => &sources[index + 1..],
+        None => &[],
+    }
+}
+
+/// Run `handler` on an object from inside a running sandbox
+///
+/// `ctx` gets the object's id and universe. Returns None when nothing
+/// defines the handler or the object doesn't exist.
+pub async fn fire(
+    lua: &Lua,
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    object_id: &str,
+    handler: &str,
+    ctx: Table,
+) -> LuaResult<Option<Value>> {
+    fire_in(lua, store, classes, Frame::new(object_id), handler, ctx).await
+}
+
+/// Run `handler` on the frame's object from inside a running sandbox
+async fn fire_in(
+    lua: &Lua,
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    frame: Frame,
+    handler: &str,
+    ctx: Table,
+) -> LuaResult<Option<Value>> {
+    let object_id = frame.object_id.as_str();
+    let Some(object) = store.get(object_id).await.map_err(mlua::Error::external)? else {
+        return Ok(None);
+    };
+    let sources = object_sources(store, classes, &object)
+        .await
+        .map_err(mlua::Error::external)?;
+    if sources.is_empty() {
+        return Ok(None);
+    }
+
+    ctx.set("object_id", object_id)?;
+    ctx.set("universe_id", object.universe_id.as_str())?;
+    call(lua, Some(frame), &sources, handler, ctx).await
+}
+
+/// Run the handlers for an object that moved from one container to another
+///
+/// The old container gets `on_leave`, the object `on_move` and the new
+/// container `on_enter`. Each gets a fresh context naming the object as
+/// `actor_id` and the other end of the move as `from_id` or `to_id`. Then
+/// the object and what it now shares an environment with run `on_init`;
+/// see `lua::init`.
+pub async fn moved(
+    lua: &Lua,
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    actions: &ActionRegistry,
+    object_id: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> LuaResult<()> {
+    init::forget(store, actions, object_id)
+        .await
+        .map_err(mlua::Error::external)?;
+    let init_calls = match to {
+        Some(to) => init::calls(store, classes, object_id, to)
+            .await
+            .map_err(mlua::Error::external)?,
+        None => Vec::new(),
+    };
+
+    if let Some(from) = from {
+        let ctx = lua.create_table()?;
+        ctx.set("actor_id", object_id)?;
+        ctx.set("to_id", to)?;
+        fire(lua, store, classes, from, "on_leave", ctx).await?;
+    }
+
+    let ctx = lua.create_table()?;
+    ctx.set("from_id", from)?;
+    ctx.set("to_id", to)?;
+    fire(lua, store, classes, object_id, "on_move", ctx).await?;
+
+    if let Some(to) = to {
+        let ctx = lua.create_table()?;
+        ctx.set("actor_id", object_id)?;
+        ctx.set("from_id", from)?;
+        fire(lua, store, classes, to, "on_enter", ctx).await?;
+    }
+
+    for call in init_calls {
+        let ctx = lua.create_table()?;
+        ctx.set("actor_id", call.actor_id.as_str())?;
+        let frame = Frame::init(call.object_id, call.actor_id);
+        fire_in(lua, store, classes, frame, INIT_HANDLER, ctx).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(class: Option<&str>, code: &str) -> HandlerSource {
+        HandlerSource {
+            class: class.map(str::to_string),
+            hash: None,
+            code: code.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_uses_most_specific_handler() {
+        let lua = Lua::new();
+        let sources = vec![
+            source(None, "return { on_look = function(ctx) return 'own' end }"),
+            source(
+                Some("sword"),
+                "return { on_use = function(ctx) return 'sword ' .. ctx.class end }",
+            ),
+            source(
+                Some("weapon"),
+                "return { on_use = function(ctx) return 'weapon' end }",
+            ),
+        ];
+        let ctx = lua.create_table().unwrap();
+
+        let used = call(&lua, None, &sources, "on_use", ctx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            used.unwrap().as_string().unwrap().to_str().unwrap(),
+            "sword sword"
+        );
+        assert!(ctx.get::<Value>("class").unwrap().is_nil());
+        assert_eq!(ctx.get::<String>("handler").unwrap(), "on_use");
+
+        let rest = after(&sources, Some("sword"));
+        let used = call(&lua, None, rest, "on_use", ctx.clone()).await.unwrap();
+        assert_eq!(
+            used.unwrap().as_string().unwrap().to_str().unwrap(),
+            "weapon"
+        );
+
+        assert!(call(&lua, None, &sources, "on_drop", ctx)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(after(&sources, None).len(), 2);
+        assert!(after(&sources, Some("thing")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_call_keeps_the_frame_out_of_lua() {
+        let lua = Lua::new();
+        let frame = lua
+            .create_function(|lua, ()| {
+                let frame = current(lua).unwrap();
+                Ok((frame.object_id, frame.init_actor))
+            })
+            .unwrap();
+        lua.globals().set("frame", frame).unwrap();
+        let sources = vec![source(
+            None,
+            r#"return {
+                on_init = function(ctx)
+                    _init_actor_id = "forged"
+                    ctx.object_id = "forged"
+                    local object_id, actor_id = frame()
+                    return object_id .. " " .. actor_id
+                end,
+                on_fail = function(ctx) error("boom") end
+            }"#,
+        )];
+        let ctx = lua.create_table().unwrap();
+
+        let frame = Frame::init("bell", "player");
+        let used = call(&lua, Some(frame), &sources, "on_init", ctx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            used.unwrap().as_string().unwrap().to_str().unwrap(),
+            "bell player"
+        );
+        assert!(current(&lua).is_none());
+
+        let frame = Frame::new("bell");
+        assert!(call(&lua, Some(frame), &sources, "on_fail", ctx)
+            .await
+            .is_err());
+        assert!(current(&lua).is_none());
+    }
+}
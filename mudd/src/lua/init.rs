@@ -0,0 +1,150 @@
+//! LPC-style init() - contextual actions between objects that meet
+//!
+//! When an object moves, each pair of objects that now share an environment
+//! with a living on one side runs `on_init`, the way LPC's `move_object`
+//! calls `init()`. The handler's `ctx.actor_id` is the living (LPC's
+//! `this_player()`), and any `game.add_action` it makes gives that living
+//! the verb. Actions an object gave or got before the move are forgotten
+//! first, except those from the things it carries.
+
+use std::collections::BTreeSet;
+
+use tokio::sync::RwLock;
+
+use super::ActionRegistry;
+use crate::objects::{ClassRegistry, ObjectStore};
+
+/// The handler that runs when objects meet
+pub const INIT_HANDLER: &str = "on_init";
+
+/// One init() call: `object_id`'s on_init with `actor_id` as the living
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitCall {
+    pub object_id: String,
+    pub actor_id: String,
+}
+
+impl InitCall {
+    fn new(object_id: &str, actor_id: &str) -> Self {
+        Self {
+            object_id: object_id.to_string(),
+            actor_id: actor_id.to_string(),
+        }
+    }
+}
+
+/// Forget the actions an object gave and got before it moved
+pub async fn forget(
+    store: &ObjectStore,
+    actions: &ActionRegistry,
+    object_id: &str,
+) -> anyhow::Result<()> {
+    let carried: BTreeSet<String> = store
+        .get_contents(object_id)
+        .await?
+        .into_iter()
+        .map(|o| o.id)
+        .collect();
+    actions.object_moved(object_id, &carried).await;
+    Ok(())
+}
+
+/// The init() calls for an object that has just moved into `to`
+///
+/// Following LPC: if the object is living, its new environment and
+/// everything else there run init for it; it runs init for every living
+/// there; and if the environment is itself living, the object runs init
+/// for it.
+pub async fn calls(
+    store: &ObjectStore,
+    classes: &RwLock<ClassRegistry>,
+    object_id: &str,
+    to: &str,
+) -> anyhow::Result<Vec<InitCall>> {
+    let (Some(object), Some(dest)) = (store.get(object_id).await?, store.get(to).await?) else {
+        return Ok(Vec::new());
+    };
+    let others: Vec<_> = store
+        .get_contents(to)
+        .await?
+        .into_iter()
+        .filter(|o| o.id != object.id)
+        .collect();
+
+    let registry = classes.read().await;
+    let living = |class: &str| registry.is_a(&object.universe_id, class, "living");
+    let object_living = living(&object.class);
+
+    let mut calls = Vec::new();
+    if object_living {
+        calls.push(InitCall::new(&dest.id, &object.id));
+    }
+    for other in &others {
+        if object_living {
+            calls.push(InitCall::new(&other.id, &object.id));
+        }
+        if living(&other.class) {
+            calls.push(InitCall::new(&object.id, &other.id));
+        }
+    }
+    if living(&dest.class) {
+        calls.push(InitCall::new(&object.id, &dest.id));
+    }
+    Ok(calls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_pool;
+    use crate::objects::Object;
+
+    #[tokio::test]
+    async fn test_calls_follow_move_object() {
+        let pool = test_pool().await;
+        sqlx::query("INSERT INTO accounts (id, username, password_hash, salt) VALUES ('owner', 'owner', '', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let store = ObjectStore::new(pool, None);
+        let classes = RwLock::new(ClassRegistry::new());
+        store
+            .create_universe("u1", "Test", "owner", serde_json::json!({}))
+            .await
+            .unwrap();
+
+        let room = Object::new("/rooms/hall", "u1", "room").unwrap();
+        store.create(&room).await.unwrap();
+        for (id, class) in [
+            ("/items/lever", "item"),
+            ("/npcs/guard", "npc"),
+            ("/players/alice", "player"),
+        ] {
+            let mut obj = Object::new(id, "u1", class).unwrap();
+            obj.parent_id = Some(room.id.clone());
+            store.create(&obj).await.unwrap();
+        }
+
+        // A player arriving meets the room, everything in it and the guard
+        let arrived = calls(&store, &classes, "/players/alice", &room.id)
+            .await
+            .unwrap();
+        let expected = [
+            InitCall::new("/rooms/hall", "/players/alice"),
+            InitCall::new("/items/lever", "/players/alice"),
+            InitCall::new("/npcs/guard", "/players/alice"),
+            InitCall::new("/players/alice", "/npcs/guard"),
+        ];
+        assert_eq!(arrived.len(), expected.len(), "{:?}", arrived);
+        for call in &expected {
+            assert!(arrived.contains(call), "missing {:?}", call);
+        }
+
+        // An item dropped in the room only meets the livings there
+        let dropped = calls(&store, &classes, "/items/lever", &room.id)
+            .await
+            .unwrap();
+        assert_eq!(dropped.len(), 2, "{:?}", dropped);
+        assert!(dropped.iter().all(|c| c.object_id == "/items/lever"));
+    }
+}
@@ -10,10 +10,24 @@ use tokio::sync::RwLock;
 pub enum GameMessage {
     /// Send to a specific player
     Send { target_id: String, message: String },
-    /// Broadcast to all players in a room
-    Broadcast { room_id: String, message: String },
-    /// Broadcast to all players in a region
-    BroadcastRegion { region_id: String, message: String },
+    /// Broadcast to all players in a room, optionally skipping one (the actor)
+    Broadcast {
+        room_id: String,
+        message: String,
+        exclude_id: Option<String>,
+    },
+    /// Broadcast to all players in a region, optionally skipping one (the actor)
+    BroadcastRegion {
+        region_id: String,
+        message: String,
+        exclude_id: Option<String>,
+    },
+    /// Speech heard by everyone in a room but the speaker
+    Say {
+        room_id: String,
+        speaker_id: String,
+        message: String,
+    },
 }
 
 /// Queue for messages generated during Lua execution
@@ -44,19 +58,46 @@ impl MessageQueue {
 
     /// Queue a broadcast to a room
     pub async fn broadcast(&self, room_id: &str, message: &str) {
+        self.broadcast_except(room_id, message, None).await;
+    }
+
+    /// Queue a broadcast to a room that skips one player
+    pub async fn broadcast_except(&self, room_id: &str, message: &str, exclude_id: Option<&str>) {
         let mut messages = self.messages.write().await;
         messages.push(GameMessage::Broadcast {
             room_id: room_id.to_string(),
             message: message.to_string(),
+            exclude_id: exclude_id.map(str::to_string),
         });
     }
 
     /// Queue a broadcast to a region
     pub async fn broadcast_region(&self, region_id: &str, message: &str) {
+        self.broadcast_region_except(region_id, message, None).await;
+    }
+
+    /// Queue a broadcast to a region that skips one player
+    pub async fn broadcast_region_except(
+        &self,
+        region_id: &str,
+        message: &str,
+        exclude_id: Option<&str>,
+    ) {
         let mut messages = self.messages.write().await;
         messages.push(GameMessage::BroadcastRegion {
             region_id: region_id.to_string(),
             message: message.to_string(),
+            exclude_id: exclude_id.map(str::to_string),
+        });
+    }
+
+    /// Queue speech to a room
+    pub async fn say(&self, room_id: &str, speaker_id: &str, message: &str) {
+        let mut messages = self.messages.write().await;
+        messages.push(GameMessage::Say {
+            room_id: room_id.to_string(),
+            speaker_id: speaker_id.to_string(),
+            message: message.to_string(),
         });
     }
 
@@ -102,9 +143,14 @@ mod tests {
         }
 
         match &messages[1] {
-            GameMessage::Broadcast { room_id, message } => {
+            GameMessage::Broadcast {
+                room_id,
+                message,
+                exclude_id,
+            } => {
                 assert_eq!(room_id, "room_1");
                 assert_eq!(message, "Someone arrived.");
+                assert_eq!(exclude_id, &None);
             }
             _ => panic!("Expected Broadcast message"),
         }
@@ -1,8 +1,19 @@
 //! Resource metering for Lua execution
+//!
+//! `Metering` counts what an execution used; a universe's `CostTable`
+//! prices that usage in millicredits (1 credit = 1000 millicredits).
 
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+use serde::{Deserialize, Serialize};
+
+use super::SandboxConfig;
+use crate::venice::ModelTier;
+
+/// Millicredits per credit
+pub const MILLICREDITS_PER_CREDIT: u64 = 1000;
+
 /// Tracks resource usage during Lua execution
 #[derive(Debug, Clone)]
 pub struct Metering {
@@ -19,6 +30,10 @@ struct MeteringInner {
     db_writes: AtomicU64,
     /// Number of Venice API calls
     venice_calls: AtomicU64,
+    /// Venice chat calls by tier (fast, balanced, quality)
+    llm_chat_calls: [AtomicU64; 3],
+    /// Venice image generations
+    llm_image_calls: AtomicU64,
     /// Memory usage in bytes
     memory_bytes: AtomicU64,
 }
@@ -38,6 +53,8 @@ impl Metering {
                 db_reads: AtomicU64::new(0),
                 db_writes: AtomicU64::new(0),
                 venice_calls: AtomicU64::new(0),
+                llm_chat_calls: Default::default(),
+                llm_image_calls: AtomicU64::new(0),
                 memory_bytes: AtomicU64::new(0),
             }),
         }
@@ -83,6 +100,28 @@ impl Metering {
         self.inner.venice_calls.load(Ordering::Relaxed)
     }
 
+    /// Record a Venice chat completion
+    pub fn record_llm_chat(&self, tier: ModelTier) {
+        self.record_venice_call();
+        self.inner.llm_chat_calls[tier_index(tier)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get Venice chat call count for a tier
+    pub fn llm_chat_calls(&self, tier: ModelTier) -> u64 {
+        self.inner.llm_chat_calls[tier_index(tier)].load(Ordering::Relaxed)
+    }
+
+    /// Record a Venice image generation
+    pub fn record_llm_image(&self) {
+        self.record_venice_call();
+        self.inner.llm_image_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get Venice image generation count
+    pub fn llm_image_calls(&self) -> u64 {
+        self.inner.llm_image_calls.load(Ordering::Relaxed)
+    }
+
     /// Set current memory usage
     pub fn set_memory(&self, bytes: u64) {
         self.inner.memory_bytes.store(bytes, Ordering::Relaxed);
@@ -93,19 +132,18 @@ impl Metering {
         self.inner.memory_bytes.load(Ordering::Relaxed)
     }
 
-    /// Calculate estimated cost in credits (millicredits)
-    /// Based on design.md pricing
-    pub fn estimated_cost_millicredits(&self) -> u64 {
-        // Instructions: $0.0001 per 1M = 0.1 millicredits per 1M
-        let instr_cost = self.instructions() / 10_000_000;
-        // DB reads: $0.001 each = 1 millicredit
-        let read_cost = self.db_reads();
-        // DB writes: $0.01 each = 10 millicredits
-        let write_cost = self.db_writes() * 10;
-        // Venice calls: ~$0.02-$0.20 each, use average $0.10 = 100 millicredits
-        let venice_cost = self.venice_calls() * 100;
+    /// Price the usage so far in millicredits
+    pub fn cost_millicredits(&self, costs: &CostTable) -> u64 {
+        let instr_cost = self.instructions() * costs.instructions_per_million / 1_000_000;
+        let read_cost = self.db_reads() * costs.db_read;
+        let write_cost = self.db_writes() * costs.db_write;
+        let chat_cost = [ModelTier::Fast, ModelTier::Balanced, ModelTier::Quality]
+            .into_iter()
+            .map(|tier| self.llm_chat_calls(tier) * costs.llm_chat(tier))
+            .sum::<u64>();
+        let image_cost = self.llm_image_calls() * costs.llm_image;
 
-        instr_cost + read_cost + write_cost + venice_cost
+        instr_cost + read_cost + write_cost + chat_cost + image_cost
     }
 
     /// Reset all counters
@@ -114,10 +152,100 @@ impl Metering {
         self.inner.db_reads.store(0, Ordering::Relaxed);
         self.inner.db_writes.store(0, Ordering::Relaxed);
         self.inner.venice_calls.store(0, Ordering::Relaxed);
+        for calls in &self.inner.llm_chat_calls {
+            calls.store(0, Ordering::Relaxed);
+        }
+        self.inner.llm_image_calls.store(0, Ordering::Relaxed);
         self.inner.memory_bytes.store(0, Ordering::Relaxed);
     }
 }
 
+fn tier_index(tier: ModelTier) -> usize {
+    match tier {
+        ModelTier::Fast => 0,
+        ModelTier::Balanced => 1,
+        ModelTier::Quality => 2,
+    }
+}
+
+/// Per-universe prices for metered resources, in millicredits
+///
+/// Stored as JSON in `universe_settings` under `costs`. Universes without
+/// the setting are not billed; missing fields take the defaults, which
+/// follow design.md (1 credit = 1 cent).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostTable {
+    /// Per million Lua instructions
+    pub instructions_per_million: u64,
+    /// Per database read
+    pub db_read: u64,
+    /// Per database write
+    pub db_write: u64,
+    /// Per fast-tier chat completion
+    pub llm_fast: u64,
+    /// Per balanced-tier chat completion
+    pub llm_balanced: u64,
+    /// Per quality-tier chat completion
+    pub llm_quality: u64,
+    /// Per generated image
+    pub llm_image: u64,
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self {
+            instructions_per_million: 10,
+            db_read: 100,
+            db_write: 1_000,
+            llm_fast: 2_000,
+            llm_balanced: 10_000,
+            llm_quality: 20_000,
+            llm_image: 5_000,
+        }
+    }
+}
+
+impl CostTable {
+    /// Key in universe_settings
+    pub const SETTING_KEY: &'static str = "costs";
+
+    /// Price of one chat completion at a tier
+    pub fn llm_chat(&self, tier: ModelTier) -> u64 {
+        match tier {
+            ModelTier::Fast => self.llm_fast,
+            ModelTier::Balanced => self.llm_balanced,
+            ModelTier::Quality => self.llm_quality,
+        }
+    }
+
+    /// Worst-case compute cost of an execution under a sandbox config
+    pub fn execution_estimate(&self, config: &SandboxConfig) -> u64 {
+        config.max_instructions * self.instructions_per_million / 1_000_000
+    }
+}
+
+/// Pricing and spending limit for a billed execution
+#[derive(Debug, Clone)]
+pub struct Billing {
+    /// The universe's prices
+    pub costs: CostTable,
+    /// Credits available when the execution started
+    pub balance: i64,
+}
+
+impl Billing {
+    /// Whether usage so far plus `extra` millicredits stays within balance
+    pub fn can_afford(&self, metering: &Metering, extra: u64) -> bool {
+        to_credits(metering.cost_millicredits(&self.costs) + extra) <= self.balance
+    }
+}
+
+/// Convert millicredits to whole credits, rounding down
+pub fn to_credits(millicredits: u64) -> i64 {
+    (millicredits / MILLICREDITS_PER_CREDIT) as i64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,4 +287,41 @@ mod tests {
         assert_eq!(m.db_reads(), 0);
         assert_eq!(m.db_writes(), 0);
     }
+
+    #[test]
+    fn test_cost_millicredits() {
+        let m = Metering::new();
+        m.add_instructions(2_000_000);
+        m.record_db_read();
+        m.record_db_write();
+        m.record_llm_chat(ModelTier::Fast);
+        m.record_llm_image();
+        assert_eq!(m.venice_calls(), 2);
+
+        let costs = CostTable::default();
+        assert_eq!(
+            m.cost_millicredits(&costs),
+            20 + 100 + 1_000 + 2_000 + 5_000
+        );
+        assert_eq!(to_credits(m.cost_millicredits(&costs)), 8);
+    }
+
+    #[test]
+    fn test_cost_table_partial_json() {
+        let costs: CostTable = serde_json::from_str(r#"{"llm_fast": 500}"#).unwrap();
+        assert_eq!(costs.llm_chat(ModelTier::Fast), 500);
+        assert_eq!(costs.db_write, CostTable::default().db_write);
+    }
+
+    #[test]
+    fn test_billing_can_afford() {
+        let billing = Billing {
+            costs: CostTable::default(),
+            balance: 10,
+        };
+        let m = Metering::new();
+        m.record_llm_image();
+        assert!(billing.can_afford(&m, 5_000));
+        assert!(!billing.can_afford(&m, 6_000));
+    }
 }
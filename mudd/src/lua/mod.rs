@@ -2,12 +2,15 @@
 
 mod actions;
 mod game_api;
+pub mod handlers;
+pub mod init;
 mod messaging;
 mod metering;
+pub mod pool;
 mod sandbox;
 
 pub use actions::{Action, ActionRegistry};
 pub use game_api::GameApi;
 pub use messaging::{GameMessage, MessageQueue};
-pub use metering::Metering;
-pub use sandbox::{Sandbox, SandboxConfig, SandboxError};
+pub use metering::{to_credits, Billing, CostTable, Metering, MILLICREDITS_PER_CREDIT};
+pub use sandbox::{call_async, Sandbox, SandboxConfig, SandboxError};
@@ -0,0 +1,364 @@
+//! Sandbox pool - warm Lua states with universe libraries loaded
+//!
+//! Creating a sandbox and running every mudlib source in it costs more than
+//! most commands do. The pool keeps idle sandboxes per universe with the
+//! libraries already run, keyed by the libraries' code hashes so that a
+//! changed mudlib gets fresh states. Compiled chunks are cached by code hash
+//! and shared by every state.
+//!
+//! A sandbox's globals, and the contents of every table reachable from them,
+//! are put back the way its libraries left them when it returns to the pool.
+//! Upvalues of library functions are not, so anything meant to last belongs
+//! in objects rather than library state.
+//!
+//! The game API is registered once, when a sandbox is created; each later
+//! checkout only switches it to the new execution's user, object and
+//! metering, so libraries holding `game` or its functions see whoever is
+//! running them now.
+
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, RwLock};
+
+use mlua::{ChunkMode, Function, Lua, Result as LuaResult};
+
+use super::{Sandbox, SandboxConfig, SandboxError};
+
+/// Most idle sandboxes kept per universe
+const MAX_IDLE: usize = 8;
+
+/// Most compiled chunks kept before the cache starts over
+const MAX_CHUNKS: usize = 4096;
+
+/// A library by name and code hash
+pub type LibHash = (String, String);
+
+/// Bytecode for code_store entries, by hash
+#[derive(Debug, Default)]
+pub struct BytecodeCache {
+    chunks: RwLock<HashMap<String, Arc<[u8]>>>,
+}
+
+impl BytecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile `code` as a function, reusing the bytecode cached for `hash`
+    pub fn load(&self, lua: &Lua, hash: &str, code: &str, name: &str) -> LuaResult<Function> {
+        let cached = self.chunks.read().unwrap().get(hash).cloned();
+        if let Some(bytecode) = cached {
+            return lua
+                .load(&bytecode[..])
+                .set_name(name)
+                .set_mode(ChunkMode::Binary)
+                .into_function();
+        }
+
+        // Stored code is always source, never trusted bytecode
+        let function = lua
+            .load(code)
+            .set_name(name)
+            .set_mode(ChunkMode::Text)
+            .into_function()?;
+        let mut chunks = self.chunks.write().unwrap();
+        if chunks.len() >= MAX_CHUNKS {
+            chunks.clear();
+        }
+        chunks.insert(hash.to_string(), function.dump(false).into());
+        Ok(function)
+    }
+
+    /// Number of cached chunks
+    pub fn len(&self) -> usize {
+        self.chunks.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Compile stored code, through the state's bytecode cache if it has one
+pub fn load_code(lua: &Lua, hash: Option<&str>, code: &str, name: &str) -> LuaResult<Function> {
+    if let (Some(hash), Some(cache)) = (hash, lua.app_data_ref::<Arc<BytecodeCache>>()) {
+        return cache.load(lua, hash, code, name);
+    }
+    lua.load(code)
+        .set_name(name)
+        .set_mode(ChunkMode::Text)
+        .into_function()
+}
+
+/// Idle sandboxes for one universe
+struct Idle {
+    libs: Vec<LibHash>,
+    sandboxes: Vec<Sandbox>,
+}
+
+/// Warm sandboxes per universe
+#[derive(Default)]
+pub struct SandboxPool {
+    idle: Mutex<HashMap<String, Idle>>,
+    bytecode: Arc<BytecodeCache>,
+}
+
+impl SandboxPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytecode cache shared by the pool's sandboxes
+    pub fn bytecode(&self) -> &Arc<BytecodeCache> {
+        &self.bytecode
+    }
+
+    /// Take an idle sandbox that has run exactly these libraries
+    ///
+    /// Idle sandboxes for an older set of libraries are dropped.
+    pub fn take(self: &Arc<Self>, universe_id: &str, libs: &[LibHash]) -> Option<PooledSandbox> {
+        let mut idle = self.idle.lock().unwrap();
+        let entry = idle.get_mut(universe_id)?;
+        if entry.libs != libs {
+            idle.remove(universe_id);
+            return None;
+        }
+        let sandbox = entry.sandboxes.pop()?;
+        Some(PooledSandbox {
+            sandbox: Some(sandbox),
+            pool: self.clone(),
+            universe_id: universe_id.to_string(),
+            libs: libs.to_vec(),
+            ready: true,
+        })
+    }
+
+    /// Create a sandbox for these libraries
+    ///
+    /// Run the libraries with `run_library`, then call `ready` so the
+    /// sandbox returns to the pool after use.
+    pub fn create(
+        self: &Arc<Self>,
+        universe_id: &str,
+        libs: &[LibHash],
+        config: SandboxConfig,
+    ) -> Result<PooledSandbox, SandboxError> {
+        let sandbox = Sandbox::new(config)?;
+        sandbox.lua().set_app_data(self.bytecode.clone());
+        Ok(PooledSandbox {
+            sandbox: Some(sandbox),
+            pool: self.clone(),
+            universe_id: universe_id.to_string(),
+            libs: libs.to_vec(),
+            ready: false,
+        })
+    }
+
+    /// Number of idle sandboxes for a universe
+    pub fn idle_count(&self, universe_id: &str) -> usize {
+        self.idle
+            .lock()
+            .unwrap()
+            .get(universe_id)
+            .map_or(0, |entry| entry.sandboxes.len())
+    }
+
+    /// Return a used sandbox, unless it can't be trusted to start clean
+    fn give_back(&self, universe_id: String, libs: Vec<LibHash>, sandbox: Sandbox) {
+        if sandbox.limit_exceeded() || sandbox.restore_globals().is_err() {
+            return;
+        }
+        if sandbox.lua().gc_collect().is_err()
+            || sandbox.memory_used() > sandbox.config().max_memory / 2
+        {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap();
+        let entry = idle.entry(universe_id).or_insert_with(|| Idle {
+            libs: libs.clone(),
+            sandboxes: Vec::new(),
+        });
+        if entry.libs != libs {
+            // Libraries changed while this one was out
+            *entry = Idle {
+                libs,
+                sandboxes: Vec::new(),
+            };
+        }
+        if entry.sandboxes.len() < MAX_IDLE {
+            entry.sandboxes.push(sandbox);
+        }
+    }
+}
+
+/// A sandbox checked out of the pool, returned to it when dropped
+pub struct PooledSandbox {
+    sandbox: Option<Sandbox>,
+    pool: Arc<SandboxPool>,
+    universe_id: String,
+    libs: Vec<LibHash>,
+    /// Libraries are loaded and globals saved
+    ready: bool,
+}
+
+impl PooledSandbox {
+    /// Run one of the sandbox's libraries
+    pub async fn run_library(
+        &mut self,
+        name: &str,
+        hash: &str,
+        code: &str,
+    ) -> Result<(), SandboxError> {
+        let chunk = self
+            .pool
+            .bytecode
+            .load(self.lua(), hash, code, &format!("=lib {}", name))?;
+        self.call_async::<_, ()>(chunk, ()).await
+    }
+
+    /// Libraries are loaded: keep the globals as they are now
+    pub fn ready(&mut self) -> LuaResult<()> {
+        self.save_globals()?;
+        self.ready = true;
+        Ok(())
+    }
+}
+
+impl Deref for PooledSandbox {
+    type Target = Sandbox;
+
+    fn deref(&self) -> &Sandbox {
+        self.sandbox.as_ref().expect("sandbox present until drop")
+    }
+}
+
+impl DerefMut for PooledSandbox {
+    fn deref_mut(&mut self) -> &mut Sandbox {
+        self.sandbox.as_mut().expect("sandbox present until drop")
+    }
+}
+
+impl Drop for PooledSandbox {
+    fn drop(&mut self) {
+        if let (true, Some(sandbox)) = (self.ready, self.sandbox.take()) {
+            let universe_id = std::mem::take(&mut self.universe_id);
+            let libs = std::mem::take(&mut self.libs);
+            self.pool.give_back(universe_id, libs, sandbox);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn libs(hash: &str) -> Vec<LibHash> {
+        vec![("commands".to_string(), hash.to_string())]
+    }
+
+    async fn warm(pool: &Arc<SandboxPool>, hash: &str) -> PooledSandbox {
+        let mut sandbox = pool
+            .create("u1", &libs(hash), SandboxConfig::default())
+            .unwrap();
+        sandbox
+            .run_library(
+                "commands",
+                hash,
+                "Commands = { look = function() return 'ok' end }",
+            )
+            .await
+            .unwrap();
+        sandbox.ready().unwrap();
+        sandbox
+    }
+
+    #[tokio::test]
+    async fn test_sandboxes_are_reused_clean() {
+        let pool = Arc::new(SandboxPool::new());
+        let mut sandbox = warm(&pool, "h1").await;
+        let _: () = sandbox
+            .eval_async("leaked = Commands.look()")
+            .await
+            .unwrap();
+        drop(sandbox);
+        assert_eq!(pool.idle_count("u1"), 1);
+
+        let mut sandbox = pool.take("u1", &libs("h1")).unwrap();
+        assert!(!sandbox.global_exists("leaked"));
+        let looked: String = sandbox.eval_async("return Commands.look()").await.unwrap();
+        assert_eq!(looked, "ok");
+        assert_eq!(pool.idle_count("u1"), 0);
+        drop(sandbox);
+
+        // New libraries retire the old states
+        assert!(pool.take("u1", &libs("h2")).is_none());
+        assert_eq!(pool.idle_count("u1"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_library_tables_are_reset() {
+        let pool = Arc::new(SandboxPool::new());
+        let mut sandbox = warm(&pool, "h1").await;
+        let _: () = sandbox
+            .eval_async(
+                "string.upper = function() return 'x' end; string.extra = 1
+                 Commands.look = function() return 'bad' end; Commands.extra = 1",
+            )
+            .await
+            .unwrap();
+        drop(sandbox);
+
+        let mut sandbox = pool.take("u1", &libs("h1")).unwrap();
+        let clean: bool = sandbox
+            .eval_async(
+                "return string.upper('a') == 'A' and string.extra == nil
+                    and Commands.look() == 'ok' and Commands.extra == nil",
+            )
+            .await
+            .unwrap();
+        assert!(clean);
+    }
+
+    #[tokio::test]
+    async fn test_unready_and_exhausted_sandboxes_are_dropped() {
+        let pool = Arc::new(SandboxPool::new());
+        drop(
+            pool.create("u1", &libs("h1"), SandboxConfig::default())
+                .unwrap(),
+        );
+        assert_eq!(pool.idle_count("u1"), 0);
+
+        let mut sandbox = warm(&pool, "h1").await;
+        sandbox
+            .set_config(SandboxConfig {
+                max_instructions: 100,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(sandbox
+            .eval_async::<()>("for i = 1, 1000000 do end")
+            .await
+            .is_err());
+        drop(sandbox);
+        assert_eq!(pool.idle_count("u1"), 0);
+    }
+
+    #[test]
+    fn test_bytecode_is_shared_by_hash() {
+        let cache = Arc::new(BytecodeCache::new());
+        let first = Lua::new();
+        let second = Lua::new();
+        second.set_app_data(cache.clone());
+
+        let code = "return 40 + 2";
+        let f = cache.load(&first, "h", code, "=test").unwrap();
+        assert_eq!(f.call::<i64>(()).unwrap(), 42);
+        assert_eq!(cache.len(), 1);
+
+        // The second state loads the cached bytecode, not the source
+        let f = load_code(&second, Some("h"), "return 0", "=test").unwrap();
+        assert_eq!(f.call::<i64>(()).unwrap(), 42);
+        assert_eq!(cache.len(), 1);
+    }
+}
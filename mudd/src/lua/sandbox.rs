@@ -1,14 +1,30 @@
 //! Lua sandbox - secure execution environment
-
+//!
+//! Scripts run on coroutines so that async `game.*` functions can yield to
+//! the runtime while they wait. The instruction count and deadline are
+//! shared by every coroutine an execution starts, so they hold across those
+//! yields and across handlers that call back into Lua.
+
+use std::collections::HashSet;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::task::Poll;
 use std::time::{Duration, Instant};
 
-use mlua::{Function, HookTriggers, Lua, Result as LuaResult, StdLib, Value, VmState};
+use mlua::{
+    FromLuaMulti, Function, HookTriggers, IntoLuaMulti, Lua, Result as LuaResult, StdLib, Table,
+    Thread, Value, VmState,
+};
+use parking_lot::Mutex;
 use thiserror::Error;
 
 use super::Metering;
 
+/// Instructions between hook calls
+const HOOK_INTERVAL: u64 = 1000;
+
 /// Sandbox configuration
 #[derive(Debug, Clone)]
 pub struct SandboxConfig {
@@ -58,14 +74,118 @@ pub enum SandboxError {
     LuaError(#[from] mlua::Error),
 }
 
+/// Limits shared by the hooks on every coroutine of a sandbox
+#[derive(Debug)]
+struct Limits {
+    instructions: AtomicU64,
+    max_instructions: AtomicU64,
+    exceeded: AtomicBool,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl Limits {
+    fn new(config: &SandboxConfig) -> Self {
+        Self {
+            instructions: AtomicU64::new(0),
+            max_instructions: AtomicU64::new(config.max_instructions),
+            exceeded: AtomicBool::new(false),
+            deadline: Mutex::new(None),
+        }
+    }
+
+    /// Start counting for a new execution
+    fn start(&self, timeout: Duration) {
+        self.instructions.store(0, Ordering::Relaxed);
+        self.exceeded.store(false, Ordering::Relaxed);
+        *self.deadline.lock() = Some(Instant::now() + timeout);
+    }
+
+    /// Count instructions on `thread` until a limit is hit
+    fn hook(self: &Arc<Self>, thread: &Thread) {
+        let limits = self.clone();
+        thread.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INTERVAL as u32),
+            move |_lua, _debug| limits.tick(),
+        );
+    }
+
+    fn tick(&self) -> LuaResult<VmState> {
+        let current = self
+            .instructions
+            .fetch_add(HOOK_INTERVAL, Ordering::Relaxed)
+            + HOOK_INTERVAL;
+        let out_of_time = self.deadline.lock().is_some_and(|d| Instant::now() > d);
+        if current <= self.max_instructions.load(Ordering::Relaxed) && !out_of_time {
+            return Ok(VmState::Continue);
+        }
+        self.exceeded.store(true, Ordering::Relaxed);
+
+        // A yield reaches call_async, which ends the execution. Lua can't
+        // yield inside C calls such as table.sort, so alternate with an
+        // error, which pcall can't swallow for good either.
+        if (current / HOOK_INTERVAL).is_multiple_of(2) {
+            Ok(VmState::Yield)
+        } else {
+            Err(mlua::Error::runtime("execution limit exceeded"))
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
+
+/// Call a Lua function on its own coroutine, within the sandbox's limits
+///
+/// mlua runs a hook only on the thread it was set for, so each call gets a
+/// thread with the sandbox's hook, and the calling thread gets the hook back
+/// afterwards. Rust code that calls back into Lua from an async `game.*`
+/// function, such as a handler, goes through here. Outside a sandbox this
+/// is a plain `call_async`.
+pub async fn call_async<R>(lua: &Lua, function: Function, args: impl IntoLuaMulti) -> LuaResult<R>
+where
+    R: FromLuaMulti,
+{
+    let limits = lua
+        .app_data_ref::<Arc<Limits>>()
+        .map(|limits| limits.clone());
+    let Some(limits) = limits else {
+        return function.call_async(args).await;
+    };
+
+    let args = args.into_lua_multi(lua)?;
+    let caller = lua.current_thread();
+    let thread = lua.create_thread(function)?;
+    limits.hook(&thread);
+    let mut execution = pin!(thread.into_async::<R>(args));
+    let result = poll_fn(|cx| match execution.as_mut().poll(cx) {
+        _ if limits.exceeded() => {
+            Poll::Ready(Err(mlua::Error::runtime("execution limit exceeded")))
+        }
+        poll => poll,
+    })
+    .await;
+    limits.hook(&caller);
+    result
+}
+
 /// A sandboxed Lua execution environment
 pub struct Sandbox {
     lua: Lua,
     config: SandboxConfig,
     metering: Metering,
-    instruction_count: Arc<AtomicU64>,
-    exceeded: Arc<AtomicBool>,
-    start_time: Option<Instant>,
+    limits: Arc<Limits>,
+    /// Shared by every string, so scripts can reach it without globals
+    string_metatable: Option<Table>,
+    /// Tables to return to between executions, once saved
+    saved_tables: Option<Vec<SavedTable>>,
+}
+
+/// A table's contents and metatable as `save_globals` found them
+struct SavedTable {
+    table: Table,
+    entries: Vec<(Value, Value)>,
+    metatable: Option<Table>,
 }
 
 impl Sandbox {
@@ -80,26 +200,9 @@ impl Sandbox {
         // Set memory limit
         lua.set_memory_limit(config.max_memory)?;
 
-        let instruction_count = Arc::new(AtomicU64::new(0));
-        let exceeded = Arc::new(AtomicBool::new(false));
-
-        // Set up instruction counting hook
-        let count_clone = instruction_count.clone();
-        let exceeded_clone = exceeded.clone();
-        let max_instructions = config.max_instructions;
-
-        lua.set_hook(
-            HookTriggers::new().every_nth_instruction(1000),
-            move |_lua, _debug| {
-                let current = count_clone.fetch_add(1000, Ordering::Relaxed) + 1000;
-                if current > max_instructions {
-                    exceeded_clone.store(true, Ordering::Relaxed);
-                    Ok(VmState::Yield)
-                } else {
-                    Ok(VmState::Continue)
-                }
-            },
-        );
+        // Instruction counting hooks go on each coroutine in call_async
+        let limits = Arc::new(Limits::new(&config));
+        lua.set_app_data(limits.clone());
 
         // Remove dangerous globals
         Self::remove_dangerous_globals(&lua)?;
@@ -107,13 +210,15 @@ impl Sandbox {
         // Add safe utility functions
         Self::add_safe_globals(&lua)?;
 
+        let string_metatable = lua.load("return getmetatable('')").eval()?;
+
         Ok(Self {
             lua,
             config,
             metering: Metering::new(),
-            instruction_count,
-            exceeded,
-            start_time: None,
+            limits,
+            string_metatable,
+            saved_tables: None,
         })
     }
 
@@ -162,71 +267,140 @@ impl Sandbox {
         &self.metering
     }
 
+    /// Record into a shared metering instance
+    ///
+    /// Lets the game API's database and Venice counts land in the same
+    /// place as this sandbox's instruction count.
+    pub fn set_metering(&mut self, metering: Metering) {
+        self.metering = metering;
+    }
+
     /// Get the sandbox configuration
     pub fn config(&self) -> &SandboxConfig {
         &self.config
     }
 
-    /// Execute Lua code and return the result
-    pub fn execute<R>(&mut self, code: &str) -> Result<R, SandboxError>
-    where
-        R: mlua::FromLuaMulti,
-    {
-        // Reset counters for this execution
-        self.instruction_count.store(0, Ordering::Relaxed);
-        self.exceeded.store(false, Ordering::Relaxed);
-        self.metering.reset();
-        self.start_time = Some(Instant::now());
-
-        // Compile and execute
-        let chunk = self.lua.load(code);
-        let result: LuaResult<R> = chunk.eval();
-
-        // Update metering
-        let instr = self.instruction_count.load(Ordering::Relaxed);
-        self.metering.add_instructions(instr);
-        self.metering.set_memory(self.lua.used_memory() as u64);
+    /// Apply new limits to an existing sandbox
+    pub fn set_config(&mut self, config: SandboxConfig) -> Result<(), SandboxError> {
+        self.lua.set_memory_limit(config.max_memory)?;
+        self.limits
+            .max_instructions
+            .store(config.max_instructions, Ordering::Relaxed);
+        self.config = config;
+        Ok(())
+    }
 
-        // Check if we exceeded limits
-        if self.exceeded.load(Ordering::Relaxed) {
-            return Err(SandboxError::InstructionLimitExceeded(
-                instr,
-                self.config.max_instructions,
-            ));
+    /// Remember the current globals to restore between executions
+    ///
+    /// Every table reachable from the globals or the string metatable is
+    /// saved along with its contents and metatable.
+    pub fn save_globals(&mut self) -> LuaResult<()> {
+        let mut pending = vec![self.lua.globals()];
+        pending.extend(self.string_metatable.clone());
+        let mut seen = HashSet::new();
+        let mut saved = Vec::new();
+        while let Some(table) = pending.pop() {
+            if !seen.insert(table.to_pointer()) {
+                continue;
+            }
+            let mut entries = Vec::new();
+            for pair in table.pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                for v in [&key, &value] {
+                    if let Value::Table(t) = v {
+                        pending.push(t.clone());
+                    }
+                }
+                entries.push((key, value));
+            }
+            let metatable = table.metatable();
+            pending.extend(metatable.clone());
+            saved.push(SavedTable {
+                table,
+                entries,
+                metatable,
+            });
         }
+        self.saved_tables = Some(saved);
+        Ok(())
+    }
 
-        // Check timeout
-        if let Some(start) = self.start_time {
-            if start.elapsed() > self.config.timeout {
-                return Err(SandboxError::Timeout(self.config.timeout));
+    /// Put the saved tables back as `save_globals` found them
+    ///
+    /// Keys added since are removed, replaced ones restored and metatables
+    /// reset, in the globals and every library table. State kept in
+    /// closures' upvalues is not undone.
+    pub fn restore_globals(&self) -> LuaResult<()> {
+        let Some(ref saved) = self.saved_tables else {
+            return Ok(());
+        };
+        for saved in saved {
+            saved.table.clear()?;
+            for (key, value) in &saved.entries {
+                saved.table.raw_set(key.clone(), value.clone())?;
             }
+            saved.table.set_metatable(saved.metatable.clone());
         }
+        Ok(())
+    }
 
-        result.map_err(SandboxError::from)
+    /// Whether the last execution ran out of instructions or time
+    pub fn limit_exceeded(&self) -> bool {
+        self.limits.exceeded()
     }
 
-    /// Execute a Lua function with arguments
-    pub fn call<A, R>(&mut self, func: Function, args: A) -> Result<R, SandboxError>
+    /// Evaluate Lua code and return the result
+    pub async fn eval_async<R>(&mut self, code: &str) -> Result<R, SandboxError>
     where
-        A: mlua::IntoLuaMulti,
-        R: mlua::FromLuaMulti,
+        R: FromLuaMulti,
     {
-        self.instruction_count.store(0, Ordering::Relaxed);
-        self.exceeded.store(false, Ordering::Relaxed);
-        self.start_time = Some(Instant::now());
+        self.metering.reset();
 
-        let result: LuaResult<R> = func.call(args);
+        // Compile as an expression if possible, like Chunk::eval
+        let function = match self.lua.load(format!("return {}", code)).into_function() {
+            Ok(function) => function,
+            Err(_) => self.lua.load(code).into_function()?,
+        };
+        let result = self.call_async(function, ()).await;
+        self.metering.set_memory(self.lua.used_memory() as u64);
+        result
+    }
 
-        let instr = self.instruction_count.load(Ordering::Relaxed);
+    /// Call a Lua function with arguments
+    pub async fn call_async<A, R>(&mut self, func: Function, args: A) -> Result<R, SandboxError>
+    where
+        A: IntoLuaMulti,
+        R: FromLuaMulti,
+    {
+        let start = Instant::now();
+        self.limits.start(self.config.timeout);
+
+        // The hook only runs while Lua does, so bound awaited game functions
+        let result =
+            tokio::time::timeout(self.config.timeout, call_async::<R>(&self.lua, func, args)).await;
+
+        let instr = self.limits.instructions.load(Ordering::Relaxed);
         self.metering.add_instructions(instr);
 
-        if self.exceeded.load(Ordering::Relaxed) {
+        let Ok(result) = result else {
+            // The abandoned coroutine leaves the state mid-call
+            self.limits.exceeded.store(true, Ordering::Relaxed);
+            return Err(SandboxError::Timeout(self.config.timeout));
+        };
+
+        // Check if we exceeded limits
+        if instr > self.config.max_instructions {
             return Err(SandboxError::InstructionLimitExceeded(
                 instr,
                 self.config.max_instructions,
             ));
         }
 
+        // Check timeout, including time spent waiting on game functions
+        if self.limits.exceeded() || start.elapsed() > self.config.timeout {
+            return Err(SandboxError::Timeout(self.config.timeout));
+        }
+
         result.map_err(SandboxError::from)
     }
 
@@ -241,7 +415,7 @@ impl Sandbox {
 
     /// Get the current instruction count
     pub fn instruction_count(&self) -> u64 {
-        self.instruction_count.load(Ordering::Relaxed)
+        self.limits.instructions.load(Ordering::Relaxed)
     }
 
     /// Get current memory usage
@@ -288,15 +462,15 @@ mod tests {
         assert!(sandbox.global_exists("print")); // Safe version
     }
 
-    #[test]
-    fn test_simple_execution() {
+    #[tokio::test]
+    async fn test_simple_execution() {
         let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
-        let result: i64 = sandbox.execute("return 1 + 2").unwrap();
+        let result: i64 = sandbox.eval_async("return 1 + 2").await.unwrap();
         assert_eq!(result, 3);
     }
 
-    #[test]
-    fn test_instruction_limit() {
+    #[tokio::test]
+    async fn test_instruction_limit() {
         let config = SandboxConfig {
             max_instructions: 100, // Very low limit
             ..Default::default()
@@ -304,15 +478,17 @@ mod tests {
         let mut sandbox = Sandbox::new(config).unwrap();
 
         // This loop should exceed the instruction limit
-        let result: Result<(), _> = sandbox.execute(
-            r#"
+        let result: Result<(), _> = sandbox
+            .eval_async(
+                r#"
             local sum = 0
             for i = 1, 1000000 do
                 sum = sum + i
             end
             return sum
             "#,
-        );
+            )
+            .await;
 
         assert!(matches!(
             result,
@@ -320,8 +496,8 @@ mod tests {
         ));
     }
 
-    #[test]
-    fn test_memory_limit() {
+    #[tokio::test]
+    async fn test_memory_limit() {
         let config = SandboxConfig {
             max_memory: 1024 * 1024, // 1MB limit
             ..Default::default()
@@ -329,25 +505,27 @@ mod tests {
         let mut sandbox = Sandbox::new(config).unwrap();
 
         // Try to allocate a large table
-        let result: Result<(), _> = sandbox.execute(
-            r#"
+        let result: Result<(), _> = sandbox
+            .eval_async(
+                r#"
             local t = {}
             for i = 1, 10000000 do
                 t[i] = string.rep("x", 1000)
             end
             "#,
-        );
+            )
+            .await;
 
         // Should fail with memory error
         assert!(result.is_err());
     }
 
-    #[test]
-    fn test_metering() {
+    #[tokio::test]
+    async fn test_metering() {
         let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
 
         let _: () = sandbox
-            .execute(
+            .eval_async(
                 r#"
             local sum = 0
             for i = 1, 1000 do
@@ -355,37 +533,186 @@ mod tests {
             end
             "#,
             )
+            .await
             .unwrap();
 
         // Should have recorded some instructions
         assert!(sandbox.metering().instructions() > 0);
     }
 
-    #[test]
-    fn test_string_operations() {
+    #[tokio::test]
+    async fn test_string_operations() {
         let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
-        let result: String = sandbox.execute(r#"return string.upper("hello")"#).unwrap();
+        let result: String = sandbox
+            .eval_async(r#"return string.upper("hello")"#)
+            .await
+            .unwrap();
         assert_eq!(result, "HELLO");
     }
 
-    #[test]
-    fn test_table_operations() {
+    #[tokio::test]
+    async fn test_table_operations() {
         let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
         let result: i64 = sandbox
-            .execute(
+            .eval_async(
                 r#"
             local t = {1, 2, 3, 4, 5}
             return #t
             "#,
             )
+            .await
             .unwrap();
         assert_eq!(result, 5);
     }
 
-    #[test]
-    fn test_math_operations() {
+    #[tokio::test]
+    async fn test_math_operations() {
         let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
-        let result: f64 = sandbox.execute("return math.sqrt(16)").unwrap();
+        let result: f64 = sandbox.eval_async("return math.sqrt(16)").await.unwrap();
         assert!((result - 4.0).abs() < 0.001);
     }
+
+    #[tokio::test]
+    async fn test_restore_globals() {
+        let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
+        let _: () = sandbox
+            .eval_async("Lib = { name = 'lib', nested = { n = 1 } }")
+            .await
+            .unwrap();
+        sandbox.save_globals().unwrap();
+
+        let _: () = sandbox
+            .eval_async(
+                "local lib = Lib; Lib = nil; leaked = true; string = nil
+                 lib.nested.n = 2; setmetatable(lib, { __index = function() return 'x' end })
+                 getmetatable('').__index = {}",
+            )
+            .await
+            .unwrap();
+        sandbox.restore_globals().unwrap();
+
+        assert!(!sandbox.global_exists("leaked"));
+        let name: String = sandbox
+            .eval_async("return Lib.name .. string.upper('!') .. Lib.nested.n .. ('s'):upper()")
+            .await
+            .unwrap();
+        assert_eq!(name, "lib!1S");
+        let missing: Option<String> = sandbox.eval_async("return Lib.missing").await.unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[tokio::test]
+    async fn test_set_config_changes_limits() {
+        let mut sandbox = Sandbox::new(SandboxConfig::default()).unwrap();
+        sandbox
+            .set_config(SandboxConfig {
+                max_instructions: 100,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let result: Result<(), _> = sandbox.eval_async("for i = 1, 1000000 do end").await;
+        assert!(matches!(
+            result,
+            Err(SandboxError::InstructionLimitExceeded(_, 100))
+        ));
+        assert!(sandbox.limit_exceeded());
+    }
+
+    #[tokio::test]
+    async fn test_limits_stop_endless_loops() {
+        let config = SandboxConfig {
+            max_instructions: 100_000,
+            ..Default::default()
+        };
+        let mut sandbox = Sandbox::new(config).unwrap();
+
+        let result: Result<(), _> = sandbox.eval_async("while true do end").await;
+        assert!(matches!(
+            result,
+            Err(SandboxError::InstructionLimitExceeded(_, _))
+        ));
+
+        // pcall can't keep catching the limit
+        let result: Result<(), _> = sandbox
+            .eval_async("while true do pcall(function() while true do end end) end")
+            .await;
+        assert!(matches!(
+            result,
+            Err(SandboxError::InstructionLimitExceeded(_, _))
+        ));
+
+        let result: i64 = sandbox.eval_async("return 6 * 7").await.unwrap();
+        assert_eq!(result, 42);
+    }
+
+    #[tokio::test]
+    async fn test_limits_hold_across_async_calls() {
+        let config = SandboxConfig {
+            max_instructions: 100_000,
+            timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut sandbox = Sandbox::new(config).unwrap();
+        let lua = sandbox.lua();
+        let sleep = lua
+            .create_async_function(|_, ms: u64| async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                Ok(())
+            })
+            .unwrap();
+        // Calls back into Lua the way handlers do
+        let call = lua
+            .create_async_function(
+                |lua, f: Function| async move { call_async::<()>(&lua, f, ()).await },
+            )
+            .unwrap();
+        lua.globals().set("sleep", sleep).unwrap();
+        lua.globals().set("call", call).unwrap();
+
+        let result: Result<(), _> = sandbox
+            .eval_async("sleep(1); call(function() sleep(1) end); while true do end")
+            .await;
+        assert!(matches!(
+            result,
+            Err(SandboxError::InstructionLimitExceeded(_, _))
+        ));
+
+        let result: Result<(), _> = sandbox
+            .eval_async("call(function() while true do end end)")
+            .await;
+        assert!(matches!(
+            result,
+            Err(SandboxError::InstructionLimitExceeded(_, _))
+        ));
+
+        // Time spent waiting counts toward the timeout
+        let result: Result<(), _> = sandbox
+            .eval_async("sleep(200); for i = 1, 10000 do end")
+            .await;
+        assert!(matches!(result, Err(SandboxError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_ends_slow_async_call() {
+        let config = SandboxConfig {
+            timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut sandbox = Sandbox::new(config).unwrap();
+        let sleep = sandbox
+            .lua()
+            .create_async_function(|_, ms: u64| async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                Ok(())
+            })
+            .unwrap();
+        sandbox.lua().globals().set("sleep", sleep).unwrap();
+
+        let start = Instant::now();
+        let result: Result<(), _> = sandbox.eval_async("sleep(10000)").await;
+        assert!(matches!(result, Err(SandboxError::Timeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(sandbox.limit_exceeded());
+    }
 }
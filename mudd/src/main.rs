@@ -2,8 +2,9 @@
 
 use std::net::SocketAddr;
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use mudd::api::LoginLimits;
 use mudd::{Config, Server};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -11,13 +12,16 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 #[derive(Parser, Debug)]
 #[command(name = "mudd", version, about)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Address to bind to
     #[arg(short, long, default_value = "127.0.0.1:8080")]
     bind: SocketAddr,
 
     /// Database file path (required - must be pre-initialized with mudd_init)
     #[arg(short, long)]
-    database: String,
+    database: Option<String>,
 
     /// Raft node ID (unique within cluster)
     #[arg(long, default_value = "1")]
@@ -30,6 +34,81 @@ struct Args {
     /// Cluster peers (format: "1=host1:9000,2=host2:9001"). Omit for single-node.
     #[arg(long)]
     peers: Option<String>,
+
+    /// Start as a new node that an admin will add to a running cluster
+    #[arg(long, requires = "peers")]
+    join: bool,
+
+    /// Also accept telnet MUD clients on this address (e.g. 127.0.0.1:4000)
+    #[arg(long)]
+    telnet: Option<SocketAddr>,
+
+    /// Domain clients reach the server at (e.g. mud.example.com), named in
+    /// wallet sign-in messages. Defaults to the bind address.
+    #[arg(long)]
+    public_domain: Option<String>,
+
+    /// Login attempts a minute per username (0 = unlimited)
+    #[arg(long, default_value = "10")]
+    logins_per_minute: u32,
+
+    /// Login and registration attempts a minute per IP address (0 = unlimited)
+    #[arg(long, default_value = "30")]
+    ip_logins_per_minute: u32,
+
+    /// Failed logins in a row that lock a username out (0 = never)
+    #[arg(long, default_value = "5")]
+    max_failed_logins: u32,
+
+    /// Failed logins in a row that lock an IP address out (0 = never)
+    #[arg(long, default_value = "20")]
+    max_ip_failed_logins: u32,
+
+    /// How long a lockout lasts, in seconds
+    #[arg(long, default_value = "300")]
+    lockout_secs: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage Raft cluster membership of a running server
+    ///
+    /// Admin credentials are read from MUDD_ADMIN_USERNAME and MUDD_ADMIN_PASSWORD.
+    Cluster {
+        /// HTTP address of the cluster leader
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+
+        #[command(subcommand)]
+        action: ClusterAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ClusterAction {
+    /// Show voters, learners and known node addresses
+    Members,
+    /// Add a node as a non-voting learner
+    AddLearner {
+        /// Node ID of the new node
+        #[arg(long)]
+        node_id: u64,
+        /// Raft address of the new node (host:port)
+        #[arg(long)]
+        addr: String,
+    },
+    /// Promote a learner to a voting member
+    Promote {
+        /// Node ID of the learner
+        #[arg(long)]
+        node_id: u64,
+    },
+    /// Remove a voter or learner from the cluster
+    Remove {
+        /// Node ID to remove
+        #[arg(long)]
+        node_id: u64,
+    },
 }
 
 #[tokio::main]
@@ -46,13 +125,31 @@ async fn main() -> Result<()> {
     // Parse CLI arguments
     let args = Args::parse();
 
+    if let Some(Command::Cluster { server, action }) = args.command {
+        return run_cluster_command(&server, action).await;
+    }
+
+    let Some(db_path) = args.database else {
+        bail!("--database is required to run the server");
+    };
+
     // Build config from CLI args
     let config = Config {
         bind_addr: args.bind,
-        db_path: args.database,
+        db_path,
         node_id: args.node_id,
         raft_port: args.raft_port,
         peers: args.peers,
+        join: args.join,
+        telnet_addr: args.telnet,
+        public_domain: args.public_domain,
+        login_limits: LoginLimits {
+            logins_per_minute: args.logins_per_minute,
+            ip_logins_per_minute: args.ip_logins_per_minute,
+            max_failed_logins: args.max_failed_logins,
+            max_ip_failed_logins: args.max_ip_failed_logins,
+            lockout_secs: args.lockout_secs,
+        },
     };
 
     // Create and run server
@@ -61,3 +158,56 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Log in as admin and issue a cluster membership request
+async fn run_cluster_command(server: &str, action: ClusterAction) -> Result<()> {
+    let username = std::env::var("MUDD_ADMIN_USERNAME")
+        .map_err(|_| anyhow::anyhow!("MUDD_ADMIN_USERNAME environment variable required"))?;
+    let password = std::env::var("MUDD_ADMIN_PASSWORD")
+        .map_err(|_| anyhow::anyhow!("MUDD_ADMIN_PASSWORD environment variable required"))?;
+
+    let server = server.trim_end_matches('/');
+    let client = reqwest::Client::new();
+
+    let login: serde_json::Value = client
+        .post(format!("{}/auth/login", server))
+        .json(&serde_json::json!({ "username": username, "password": password }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let Some(token) = login["token"].as_str() else {
+        bail!(
+            "Login failed: {}",
+            login["error"].as_str().unwrap_or("unknown error")
+        );
+    };
+
+    let request = match action {
+        ClusterAction::Members => client.get(format!("{}/cluster/members", server)),
+        ClusterAction::AddLearner { node_id, addr } => client
+            .post(format!("{}/cluster/learner", server))
+            .json(&serde_json::json!({ "node_id": node_id, "addr": addr })),
+        ClusterAction::Promote { node_id } => client
+            .post(format!("{}/cluster/voter", server))
+            .json(&serde_json::json!({ "node_id": node_id })),
+        ClusterAction::Remove { node_id } => client
+            .post(format!("{}/cluster/remove", server))
+            .json(&serde_json::json!({ "node_id": node_id })),
+    };
+
+    let response = request.bearer_auth(token).send().await?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await?;
+
+    if !status.is_success() {
+        bail!(
+            "Cluster request failed ({}): {}",
+            status,
+            body["error"].as_str().unwrap_or("unknown error")
+        );
+    }
+
+    println!("{}", serde_json::to_string_pretty(&body)?);
+    Ok(())
+}
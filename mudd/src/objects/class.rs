@@ -3,6 +3,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use tracing::{debug, warn};
@@ -55,9 +56,17 @@ impl ClassDef {
     }
 }
 
-/// Registry of all class definitions
+/// Registry of class definitions
+///
+/// The built-in classes are a read-only base layer shared by every
+/// universe. Classes defined with `define_class` belong to one universe, so
+/// two universes can each have their own `dragon`; lookups in a universe
+/// try its classes first, then the built-ins.
 pub struct ClassRegistry {
-    classes: HashMap<String, ClassDef>,
+    /// Built-in classes
+    base: HashMap<String, ClassDef>,
+    /// Classes defined by each universe, keyed by universe id
+    universes: HashMap<String, HashMap<String, ClassDef>>,
     db_pool: Option<SqlitePool>,
     raft_writer: Option<Arc<RaftWriter>>,
 }
@@ -72,7 +81,8 @@ impl ClassRegistry {
     /// Create a new registry with base classes
     pub fn new() -> Self {
         let mut registry = Self {
-            classes: HashMap::new(),
+            base: HashMap::new(),
+            universes: HashMap::new(),
             db_pool: None,
             raft_writer: None,
         };
@@ -83,7 +93,8 @@ impl ClassRegistry {
     /// Create a new registry with database pool and raft writer
     pub fn with_db(pool: SqlitePool, raft_writer: Arc<RaftWriter>) -> Self {
         let mut registry = Self {
-            classes: HashMap::new(),
+            base: HashMap::new(),
+            universes: HashMap::new(),
             db_pool: Some(pool),
             raft_writer: Some(raft_writer),
         };
@@ -100,7 +111,7 @@ impl ClassRegistry {
         thing.add_handler("on_create");
         thing.add_handler("on_destroy");
         thing.add_handler("on_init");
-        self.register(thing);
+        self.register_base(thing);
 
         // item - inherits from thing
         let mut item = ClassDef::new("item", Some("thing"));
@@ -109,7 +120,7 @@ impl ClassRegistry {
         item.set_property("fixed", serde_json::json!(false));
         item.add_handler("on_move");
         item.add_handler("on_use");
-        self.register(item);
+        self.register_base(item);
 
         // living - mixin for combat
         let mut living = ClassDef::new("living", Some("thing"));
@@ -121,7 +132,7 @@ impl ClassRegistry {
         living.add_handler("heart_beat");
         living.add_handler("on_damage");
         living.add_handler("on_death");
-        self.register(living);
+        self.register_base(living);
 
         // room - inherits from thing
         let mut room = ClassDef::new("room", Some("thing"));
@@ -130,39 +141,39 @@ impl ClassRegistry {
         room.set_property("region_id", serde_json::json!(null));
         room.add_handler("on_enter");
         room.add_handler("on_leave");
-        self.register(room);
+        self.register_base(room);
 
         // region - inherits from thing
         let mut region = ClassDef::new("region", Some("thing"));
         region.set_property("environment_type", serde_json::json!("dungeon"));
         region.set_property("danger_level", serde_json::json!(1));
         region.set_property("ambient_sounds", serde_json::json!([]));
-        self.register(region);
+        self.register_base(region);
 
         // weapon - inherits from item
         let mut weapon = ClassDef::new("weapon", Some("item"));
         weapon.set_property("damage_dice", serde_json::json!("1d6"));
         weapon.set_property("damage_bonus", serde_json::json!(0));
         weapon.set_property("damage_type", serde_json::json!("physical"));
-        self.register(weapon);
+        self.register_base(weapon);
 
         // armor - inherits from item
         let mut armor = ClassDef::new("armor", Some("item"));
         armor.set_property("armor_value", serde_json::json!(0));
         armor.set_property("slot", serde_json::json!("body"));
-        self.register(armor);
+        self.register_base(armor);
 
         // container - inherits from item
         let mut container = ClassDef::new("container", Some("item"));
         container.set_property("capacity", serde_json::json!(10));
         container.set_property("locked", serde_json::json!(false));
-        self.register(container);
+        self.register_base(container);
 
         // player - inherits from living
         let mut player = ClassDef::new("player", Some("living"));
         player.set_property("wallet_address", serde_json::json!(null));
         player.set_property("access_level", serde_json::json!("player"));
-        self.register(player);
+        self.register_base(player);
 
         // npc - inherits from living
         let mut npc = ClassDef::new("npc", Some("living"));
@@ -170,30 +181,55 @@ impl ClassRegistry {
         npc.set_property("respawn_time", serde_json::json!(null));
         npc.add_handler("ai_idle_tick");
         npc.add_handler("ai_combat_tick");
-        self.register(npc);
+        self.register_base(npc);
     }
 
-    /// Register a class definition
-    pub fn register(&mut self, class: ClassDef) {
-        self.classes.insert(class.name.clone(), class);
+    fn register_base(&mut self, class: ClassDef) {
+        self.base.insert(class.name.clone(), class);
     }
 
-    /// Get a class definition by name
-    pub fn get(&self, name: &str) -> Option<&ClassDef> {
-        self.classes.get(name)
+    /// Whether a class is one of the built-ins shared by every universe
+    pub fn is_builtin(&self, name: &str) -> bool {
+        self.base.contains_key(name)
     }
 
-    /// Check if a class exists
-    pub fn exists(&self, name: &str) -> bool {
-        self.classes.contains_key(name)
+    /// Register a class definition in a universe
+    ///
+    /// Built-in classes can't be replaced.
+    pub fn register(&mut self, universe_id: &str, class: ClassDef) -> anyhow::Result<()> {
+        if self.is_builtin(&class.name) {
+            bail!("cannot redefine built-in class '{}'", class.name);
+        }
+        self.universes
+            .entry(universe_id.to_string())
+            .or_default()
+            .insert(class.name.clone(), class);
+        Ok(())
+    }
+
+    /// Get a class definition by name, as seen from a universe
+    pub fn get(&self, universe_id: &str, name: &str) -> Option<&ClassDef> {
+        self.universes
+            .get(universe_id)
+            .and_then(|classes| classes.get(name))
+            .or_else(|| self.base.get(name))
+    }
+
+    /// Check if a class exists in a universe
+    pub fn exists(&self, universe_id: &str, name: &str) -> bool {
+        self.get(universe_id, name).is_some()
     }
 
     /// Get the inheritance chain for a class (child -> ... -> root)
-    pub fn get_chain(&self, name: &str) -> Vec<String> {
+    pub fn get_chain(&self, universe_id: &str, name: &str) -> Vec<String> {
         let mut chain = Vec::new();
         let mut current = name.to_string();
 
-        while let Some(class) = self.classes.get(&current) {
+        while let Some(class) = self.get(universe_id, &current) {
+            // Universe classes can name each other as parents in a loop
+            if chain.contains(&class.name) {
+                break;
+            }
             chain.push(class.name.clone());
             match &class.parent {
                 Some(parent) => current = parent.clone(),
@@ -205,19 +241,19 @@ impl ClassRegistry {
     }
 
     /// Check if a class is a descendant of another class
-    pub fn is_a(&self, child: &str, ancestor: &str) -> bool {
-        let chain = self.get_chain(child);
+    pub fn is_a(&self, universe_id: &str, child: &str, ancestor: &str) -> bool {
+        let chain = self.get_chain(universe_id, child);
         chain.contains(&ancestor.to_string())
     }
 
     /// Resolve all properties for a class (includes inherited)
-    pub fn resolve_properties(&self, name: &str) -> Properties {
-        let chain = self.get_chain(name);
+    pub fn resolve_properties(&self, universe_id: &str, name: &str) -> Properties {
+        let chain = self.get_chain(universe_id, name);
         let mut props = Properties::new();
 
         // Apply from root to child (so child overrides parent)
         for class_name in chain.into_iter().rev() {
-            if let Some(class) = self.classes.get(&class_name) {
+            if let Some(class) = self.get(universe_id, &class_name) {
                 for (k, v) in &class.properties {
                     props.insert(k.clone(), v.clone());
                 }
@@ -228,12 +264,12 @@ impl ClassRegistry {
     }
 
     /// Resolve all handlers for a class (includes inherited)
-    pub fn resolve_handlers(&self, name: &str) -> Vec<String> {
-        let chain = self.get_chain(name);
+    pub fn resolve_handlers(&self, universe_id: &str, name: &str) -> Vec<String> {
+        let chain = self.get_chain(universe_id, name);
         let mut handlers = Vec::new();
 
         for class_name in chain {
-            if let Some(class) = self.classes.get(&class_name) {
+            if let Some(class) = self.get(universe_id, &class_name) {
                 for h in &class.handlers {
                     if !handlers.contains(h) {
                         handlers.push(h.clone());
@@ -246,32 +282,40 @@ impl ClassRegistry {
     }
 
     /// Get a class definition (alias for get, for Lua API)
-    pub fn get_class(&self, name: &str) -> Option<&ClassDef> {
-        self.get(name)
+    pub fn get_class(&self, universe_id: &str, name: &str) -> Option<&ClassDef> {
+        self.get(universe_id, name)
     }
 
     /// Get inheritance chain (alias for get_chain, for Lua API)
-    pub fn get_inheritance_chain(&self, name: &str) -> Vec<String> {
-        self.get_chain(name)
+    pub fn get_inheritance_chain(&self, universe_id: &str, name: &str) -> Vec<String> {
+        self.get_chain(universe_id, name)
     }
 
-    /// Define a class from Lua with typed properties
+    /// Define a class from Lua with typed properties and handler code
     /// props_map contains (property_name -> (type_name, default_value))
     pub fn define_class(
         &mut self,
+        universe_id: &str,
         name: &str,
         parent: Option<&str>,
         props_map: std::collections::HashMap<String, (String, serde_json::Value)>,
-    ) {
+        code_hash: Option<String>,
+        handlers: Vec<String>,
+    ) -> anyhow::Result<()> {
         let mut class = ClassDef::new(name, parent);
         for (prop_name, (_type_name, default_val)) in props_map.iter() {
             class.set_property(prop_name, default_val.clone());
         }
-        self.register(class.clone());
+        class.code_hash = code_hash;
+        for handler in &handlers {
+            class.add_handler(handler);
+        }
+        self.register(universe_id, class.clone())?;
 
         // Persist to database via Raft (async called via spawn)
         if let Some(ref raft_writer) = self.raft_writer {
             let raft_writer = raft_writer.clone();
+            let universe_id = universe_id.to_string();
             let name = name.to_string();
             let parent = parent.map(|s| s.to_string());
             let code_hash = class.code_hash.clone();
@@ -287,9 +331,10 @@ impl ClassRegistry {
 
                 // 1. INSERT OR REPLACE into classes
                 statements.push((
-                    "INSERT OR REPLACE INTO classes (name, universe_id, parent, code_hash) VALUES (?, '', ?, ?)".to_string(),
+                    "INSERT OR REPLACE INTO classes (name, universe_id, parent, code_hash) VALUES (?, ?, ?, ?)".to_string(),
                     vec![
                         serde_json::json!(&name),
+                        serde_json::json!(&universe_id),
                         serde_json::json!(&parent),
                         serde_json::json!(&code_hash),
                     ],
@@ -297,16 +342,18 @@ impl ClassRegistry {
 
                 // 2. DELETE old properties
                 statements.push((
-                    "DELETE FROM class_properties WHERE class_name = ?".to_string(),
-                    vec![serde_json::json!(&name)],
+                    "DELETE FROM class_properties WHERE universe_id = ? AND class_name = ?"
+                        .to_string(),
+                    vec![serde_json::json!(&universe_id), serde_json::json!(&name)],
                 ));
 
                 // 3. INSERT properties
                 for (key, value) in &properties {
                     statements.push((
-                        "INSERT INTO class_properties (class_name, universe_id, key, value) VALUES (?, '', ?, ?)".to_string(),
+                        "INSERT INTO class_properties (class_name, universe_id, key, value) VALUES (?, ?, ?, ?)".to_string(),
                         vec![
                             serde_json::json!(&name),
+                            serde_json::json!(&universe_id),
                             serde_json::json!(key),
                             serde_json::json!(value),
                         ],
@@ -315,16 +362,18 @@ impl ClassRegistry {
 
                 // 4. DELETE old handlers
                 statements.push((
-                    "DELETE FROM class_handlers WHERE class_name = ?".to_string(),
-                    vec![serde_json::json!(&name)],
+                    "DELETE FROM class_handlers WHERE universe_id = ? AND class_name = ?"
+                        .to_string(),
+                    vec![serde_json::json!(&universe_id), serde_json::json!(&name)],
                 ));
 
                 // 5. INSERT handlers
                 for handler in &handlers {
                     statements.push((
-                        "INSERT INTO class_handlers (class_name, universe_id, handler) VALUES (?, '', ?)".to_string(),
+                        "INSERT INTO class_handlers (class_name, universe_id, handler) VALUES (?, ?, ?)".to_string(),
                         vec![
                             serde_json::json!(&name),
+                            serde_json::json!(&universe_id),
                             serde_json::json!(handler),
                         ],
                     ));
@@ -336,6 +385,7 @@ impl ClassRegistry {
                 }
             });
         }
+        Ok(())
     }
 
     /// Load custom classes from database on startup
@@ -345,27 +395,27 @@ impl ClassRegistry {
         };
 
         // 1. Load class definitions
-        let class_rows: Vec<(String, Option<String>, Option<String>)> =
-            sqlx::query_as("SELECT name, parent, code_hash FROM classes")
+        let class_rows: Vec<(String, String, Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT universe_id, name, parent, code_hash FROM classes")
                 .fetch_all(pool)
                 .await?;
 
         // 2. Load properties
-        let prop_rows: Vec<(String, String, String)> =
-            sqlx::query_as("SELECT class_name, key, value FROM class_properties")
+        let prop_rows: Vec<(String, String, String, String)> =
+            sqlx::query_as("SELECT universe_id, class_name, key, value FROM class_properties")
                 .fetch_all(pool)
                 .await?;
 
         // 3. Load handlers
-        let handler_rows: Vec<(String, String)> =
-            sqlx::query_as("SELECT class_name, handler FROM class_handlers")
+        let handler_rows: Vec<(String, String, String)> =
+            sqlx::query_as("SELECT universe_id, class_name, handler FROM class_handlers")
                 .fetch_all(pool)
                 .await?;
 
-        // Group properties and handlers by class name
-        let mut props_by_class: HashMap<String, Properties> = HashMap::new();
-        for (class_name, key, value) in prop_rows {
-            let props = props_by_class.entry(class_name).or_default();
+        // Group properties and handlers by universe and class name
+        let mut props_by_class: HashMap<(String, String), Properties> = HashMap::new();
+        for (universe_id, class_name, key, value) in prop_rows {
+            let props = props_by_class.entry((universe_id, class_name)).or_default();
             match serde_json::from_str(&value) {
                 Ok(v) => {
                     props.insert(key, v);
@@ -376,36 +426,43 @@ impl ClassRegistry {
             }
         }
 
-        let mut handlers_by_class: HashMap<String, Vec<String>> = HashMap::new();
-        for (class_name, handler) in handler_rows {
+        let mut handlers_by_class: HashMap<(String, String), Vec<String>> = HashMap::new();
+        for (universe_id, class_name, handler) in handler_rows {
             handlers_by_class
-                .entry(class_name)
+                .entry((universe_id, class_name))
                 .or_default()
                 .push(handler);
         }
 
         // Reconstruct ClassDef objects
-        for (name, parent, code_hash) in class_rows {
-            // Skip base classes that are already registered
-            if self.classes.contains_key(&name) {
+        let mut loaded = 0;
+        for (universe_id, name, parent, code_hash) in class_rows {
+            // Built-in classes can't be shadowed
+            if self.is_builtin(&name) {
+                warn!(
+                    "Ignoring stored class {} in universe {}: it is built in",
+                    name, universe_id
+                );
                 continue;
             }
 
             let mut class = ClassDef::new(&name, parent.as_deref());
             class.code_hash = code_hash;
 
-            if let Some(props) = props_by_class.remove(&name) {
+            let key = (universe_id, name);
+            if let Some(props) = props_by_class.remove(&key) {
                 class.properties = props;
             }
 
-            if let Some(handlers) = handlers_by_class.remove(&name) {
+            if let Some(handlers) = handlers_by_class.remove(&key) {
                 class.handlers = handlers;
             }
 
-            self.classes.insert(name, class);
+            self.register(&key.0, class)?;
+            loaded += 1;
         }
 
-        debug!("Loaded {} classes from database", self.classes.len());
+        debug!("Loaded {} classes from database", loaded);
         Ok(())
     }
 }
@@ -425,23 +482,23 @@ mod tests {
     fn test_registry_base_classes() {
         let registry = ClassRegistry::new();
 
-        assert!(registry.exists("thing"));
-        assert!(registry.exists("item"));
-        assert!(registry.exists("living"));
-        assert!(registry.exists("room"));
-        assert!(registry.exists("weapon"));
-        assert!(registry.exists("player"));
-        assert!(registry.exists("npc"));
+        assert!(registry.exists("u1", "thing"));
+        assert!(registry.exists("u1", "item"));
+        assert!(registry.exists("u1", "living"));
+        assert!(registry.exists("u1", "room"));
+        assert!(registry.exists("u1", "weapon"));
+        assert!(registry.exists("u1", "player"));
+        assert!(registry.exists("u1", "npc"));
     }
 
     #[test]
     fn test_inheritance_chain() {
         let registry = ClassRegistry::new();
 
-        let chain = registry.get_chain("weapon");
+        let chain = registry.get_chain("u1", "weapon");
         assert_eq!(chain, vec!["weapon", "item", "thing"]);
 
-        let chain = registry.get_chain("player");
+        let chain = registry.get_chain("u1", "player");
         assert_eq!(chain, vec!["player", "living", "thing"]);
     }
 
@@ -449,19 +506,19 @@ mod tests {
     fn test_is_a() {
         let registry = ClassRegistry::new();
 
-        assert!(registry.is_a("weapon", "item"));
-        assert!(registry.is_a("weapon", "thing"));
-        assert!(registry.is_a("player", "living"));
-        assert!(registry.is_a("player", "thing"));
-        assert!(!registry.is_a("weapon", "living"));
-        assert!(!registry.is_a("player", "item"));
+        assert!(registry.is_a("u1", "weapon", "item"));
+        assert!(registry.is_a("u1", "weapon", "thing"));
+        assert!(registry.is_a("u1", "player", "living"));
+        assert!(registry.is_a("u1", "player", "thing"));
+        assert!(!registry.is_a("u1", "weapon", "living"));
+        assert!(!registry.is_a("u1", "player", "item"));
     }
 
     #[test]
     fn test_resolve_properties() {
         let registry = ClassRegistry::new();
 
-        let props = registry.resolve_properties("weapon");
+        let props = registry.resolve_properties("u1", "weapon");
 
         // Should have thing properties
         assert!(props.contains_key("name"));
@@ -481,7 +538,7 @@ mod tests {
     fn test_resolve_handlers() {
         let registry = ClassRegistry::new();
 
-        let handlers = registry.resolve_handlers("player");
+        let handlers = registry.resolve_handlers("u1", "player");
 
         // Should have living handlers
         assert!(handlers.contains(&"heart_beat".to_string()));
@@ -500,28 +557,73 @@ mod tests {
         // Create deep chain: sword -> weapon -> item -> thing
         let mut sword = ClassDef::new("sword", Some("weapon"));
         sword.set_property("blade_type", serde_json::json!("longsword"));
-        registry.register(sword);
+        registry.register("u1", sword).unwrap();
 
         // fire_sword -> sword
         let mut fire_sword = ClassDef::new("fire_sword", Some("sword"));
         fire_sword.set_property("elemental_damage", serde_json::json!("fire"));
         fire_sword.set_property("fire_damage", serde_json::json!("1d6"));
-        registry.register(fire_sword);
+        registry.register("u1", fire_sword).unwrap();
 
-        let chain = registry.get_chain("fire_sword");
+        let chain = registry.get_chain("u1", "fire_sword");
         assert_eq!(
             chain,
             vec!["fire_sword", "sword", "weapon", "item", "thing"]
         );
 
-        assert!(registry.is_a("fire_sword", "weapon"));
-        assert!(registry.is_a("fire_sword", "thing"));
+        assert!(registry.is_a("u1", "fire_sword", "weapon"));
+        assert!(registry.is_a("u1", "fire_sword", "thing"));
 
-        let props = registry.resolve_properties("fire_sword");
+        let props = registry.resolve_properties("u1", "fire_sword");
         assert!(props.contains_key("elemental_damage"));
         assert!(props.contains_key("blade_type"));
         assert!(props.contains_key("damage_dice"));
         assert!(props.contains_key("weight"));
         assert!(props.contains_key("name"));
     }
+
+    #[test]
+    fn test_universes_have_separate_classes() {
+        let mut registry = ClassRegistry::new();
+
+        let mut red = ClassDef::new("dragon", Some("npc"));
+        red.set_property("breath", serde_json::json!("fire"));
+        registry.register("u1", red).unwrap();
+
+        let mut white = ClassDef::new("dragon", Some("living"));
+        white.set_property("breath", serde_json::json!("frost"));
+        registry.register("u2", white).unwrap();
+
+        assert_eq!(
+            registry.resolve_properties("u1", "dragon")["breath"],
+            "fire"
+        );
+        assert_eq!(
+            registry.resolve_properties("u2", "dragon")["breath"],
+            "frost"
+        );
+        assert!(registry.is_a("u1", "dragon", "npc"));
+        assert!(!registry.is_a("u2", "dragon", "npc"));
+        assert!(!registry.exists("u3", "dragon"));
+
+        // Built-ins are shared and can't be replaced
+        assert!(registry.exists("u3", "room"));
+        assert!(registry
+            .register("u1", ClassDef::new("room", Some("thing")))
+            .is_err());
+        assert_eq!(registry.get_chain("u1", "room"), vec!["room", "thing"]);
+    }
+
+    #[test]
+    fn test_parent_cycle_ends_chain() {
+        let mut registry = ClassRegistry::new();
+        registry
+            .register("u1", ClassDef::new("egg", Some("chicken")))
+            .unwrap();
+        registry
+            .register("u1", ClassDef::new("chicken", Some("egg")))
+            .unwrap();
+
+        assert_eq!(registry.get_chain("u1", "egg"), vec!["egg", "chicken"]);
+    }
 }
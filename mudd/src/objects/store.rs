@@ -3,11 +3,10 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 
 use super::{Object, Properties};
-use crate::raft::RaftWriter;
+use crate::raft::{log_entry, write_entry, GameLogEntry, RaftWriter};
 
 /// Object storage with database backing
 pub struct ObjectStore {
@@ -21,52 +20,30 @@ impl ObjectStore {
         Self { pool, raft_writer }
     }
 
+    /// Apply a log entry either through Raft (if available) or directly
+    async fn execute_entry(&self, entry: GameLogEntry) -> Result<u64> {
+        write_entry(self.raft_writer.as_deref(), &self.pool, entry).await
+    }
+
     /// Execute a write operation either through Raft (if available) or directly
     async fn execute_write(&self, sql: &str, params: Vec<serde_json::Value>) -> Result<u64> {
-        if let Some(ref raft_writer) = self.raft_writer {
-            let result = raft_writer.execute(sql, params).await?;
-            Ok(result.rows_affected)
-        } else {
-            // Direct execution fallback (for tests)
-            let mut query = sqlx::query(sql);
-            for param in &params {
-                match param {
-                    serde_json::Value::String(s) => query = query.bind(s.clone()),
-                    serde_json::Value::Number(n) => {
-                        if let Some(i) = n.as_i64() {
-                            query = query.bind(i);
-                        } else if let Some(f) = n.as_f64() {
-                            query = query.bind(f);
-                        }
-                    }
-                    serde_json::Value::Bool(b) => query = query.bind(*b),
-                    serde_json::Value::Null => query = query.bind(Option::<String>::None),
-                    _ => query = query.bind(param.to_string()),
-                }
-            }
-            let result = query.execute(&self.pool).await?;
-            Ok(result.rows_affected())
-        }
+        self.execute_entry(GameLogEntry::statement(sql, params))
+            .await
     }
 
     /// Create a new object in the database
     pub async fn create(&self, obj: &Object) -> Result<()> {
-        let properties = serde_json::to_string(&obj.properties)?;
-
-        self.execute_write(
-            "INSERT INTO objects (id, universe_id, class, parent_id, properties, code_hash, owner_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            vec![
-                serde_json::json!(&obj.id),
-                serde_json::json!(&obj.universe_id),
-                serde_json::json!(&obj.class),
-                serde_json::json!(&obj.parent_id),
-                serde_json::json!(&properties),
-                serde_json::json!(&obj.code_hash),
-                serde_json::json!(&obj.owner_id),
-                serde_json::json!(&obj.created_at),
-                serde_json::json!(&obj.updated_at),
-            ],
-        )
+        self.execute_entry(GameLogEntry::CreateObject {
+            object_id: obj.id.clone(),
+            universe_id: obj.universe_id.clone(),
+            class: obj.class.clone(),
+            parent_id: obj.parent_id.clone(),
+            properties: serde_json::to_value(&obj.properties)?,
+            code_hash: obj.code_hash.clone(),
+            owner_id: obj.owner_id.clone(),
+            created_at: obj.created_at.clone(),
+            updated_at: obj.updated_at.clone(),
+        })
         .await?;
 
         Ok(())
@@ -92,21 +69,17 @@ impl ObjectStore {
 
     /// Update an existing object
     pub async fn update(&self, obj: &Object) -> Result<()> {
-        let properties = serde_json::to_string(&obj.properties)?;
         // Pre-compute timestamp for deterministic replication
         let updated_at = chrono::Utc::now().to_rfc3339();
 
-        self.execute_write(
-            "UPDATE objects SET class = ?, parent_id = ?, properties = ?, code_hash = ?, updated_at = ? WHERE id = ?",
-            vec![
-                serde_json::json!(&obj.class),
-                serde_json::json!(&obj.parent_id),
-                serde_json::json!(&properties),
-                serde_json::json!(&obj.code_hash),
-                serde_json::json!(&updated_at),
-                serde_json::json!(&obj.id),
-            ],
-        )
+        self.execute_entry(GameLogEntry::UpdateObject {
+            object_id: obj.id.clone(),
+            class: obj.class.clone(),
+            parent_id: obj.parent_id.clone(),
+            properties: serde_json::to_value(&obj.properties)?,
+            code_hash: obj.code_hash.clone(),
+            updated_at,
+        })
         .await?;
 
         Ok(())
@@ -115,10 +88,9 @@ impl ObjectStore {
     /// Delete an object
     pub async fn delete(&self, id: &str) -> Result<bool> {
         let rows = self
-            .execute_write(
-                "DELETE FROM objects WHERE id = ?",
-                vec![serde_json::json!(id)],
-            )
+            .execute_entry(GameLogEntry::DeleteObject {
+                object_id: id.to_string(),
+            })
             .await?;
 
         Ok(rows > 0)
@@ -141,17 +113,23 @@ impl ObjectStore {
 
     /// Move an object to a new parent
     pub async fn move_object(&self, id: &str, new_parent_id: Option<&str>) -> Result<()> {
+        self.move_objects(&[id.to_string()], new_parent_id).await
+    }
+
+    /// Move several objects to a new parent in one atomic write
+    pub async fn move_objects(&self, ids: &[String], new_parent_id: Option<&str>) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
         // Pre-compute timestamp for deterministic replication
         let updated_at = chrono::Utc::now().to_rfc3339();
 
-        self.execute_write(
-            "UPDATE objects SET parent_id = ?, updated_at = ? WHERE id = ?",
-            vec![
-                serde_json::json!(new_parent_id),
-                serde_json::json!(&updated_at),
-                serde_json::json!(id),
-            ],
-        )
+        self.execute_entry(GameLogEntry::MoveObjects {
+            object_ids: ids.to_vec(),
+            parent_id: new_parent_id.map(str::to_string),
+            updated_at,
+        })
         .await?;
 
         Ok(())
@@ -180,14 +158,11 @@ impl ObjectStore {
         let created_at = chrono::Utc::now().to_rfc3339();
 
         // Insert or ignore if already exists
-        self.execute_write(
-            "INSERT OR IGNORE INTO code_store (hash, source, created_at) VALUES (?, ?, ?)",
-            vec![
-                serde_json::json!(&hash),
-                serde_json::json!(source),
-                serde_json::json!(&created_at),
-            ],
-        )
+        self.execute_entry(GameLogEntry::StoreCode {
+            hash: hash.clone(),
+            source: source.to_string(),
+            created_at,
+        })
         .await?;
 
         Ok(hash)
@@ -205,9 +180,7 @@ impl ObjectStore {
 
     /// Compute SHA-256 hash of code
     pub fn hash_code(source: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(source.as_bytes());
-        hex::encode(hasher.finalize())
+        log_entry::hash_code(source)
     }
 
     /// Find object by name property in a given location.
@@ -390,20 +363,31 @@ impl ObjectStore {
         owner_id: &str,
         config: serde_json::Value,
     ) -> Result<()> {
-        let config_str = serde_json::to_string(&config)?;
+        self.create_universe_with_code(id, name, owner_id, config, &[])
+            .await
+    }
+
+    /// Create a new universe and store its library code in one atomic write.
+    /// Either the universe and all its code are stored, or nothing is.
+    pub async fn create_universe_with_code(
+        &self,
+        id: &str,
+        name: &str,
+        owner_id: &str,
+        config: serde_json::Value,
+        sources: &[String],
+    ) -> Result<()> {
         // Pre-compute timestamp for deterministic replication
         let created_at = chrono::Utc::now().to_rfc3339();
 
-        self.execute_write(
-            "INSERT INTO universes (id, name, owner_id, config, created_at) VALUES (?, ?, ?, ?, ?)",
-            vec![
-                serde_json::json!(id),
-                serde_json::json!(name),
-                serde_json::json!(owner_id),
-                serde_json::json!(&config_str),
-                serde_json::json!(&created_at),
-            ],
-        )
+        self.execute_entry(GameLogEntry::CreateUniverse {
+            universe_id: id.to_string(),
+            name: name.to_string(),
+            owner_id: owner_id.to_string(),
+            config,
+            code: sources.to_vec(),
+            created_at,
+        })
         .await?;
 
         Ok(())
@@ -22,7 +22,7 @@ use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 
-use crate::raft::RaftWriter;
+use crate::raft::{write_entry, GameLogEntry, RaftWriter};
 
 /// Access levels for MUD users
 #[derive(
@@ -59,6 +59,17 @@ impl FromStr for AccessLevel {
 }
 
 impl AccessLevel {
+    /// Lowercase name, as stored on accounts
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessLevel::Player => "player",
+            AccessLevel::Builder => "builder",
+            AccessLevel::Wizard => "wizard",
+            AccessLevel::Admin => "admin",
+            AccessLevel::Owner => "owner",
+        }
+    }
+
     /// Check if this level can perform builder actions
     pub fn can_build(&self) -> bool {
         *self >= AccessLevel::Builder
@@ -327,6 +338,16 @@ impl PermissionManager {
         Arc::new(Self::with_db(db_pool, raft_writer))
     }
 
+    /// Apply a write via Raft if available, otherwise directly (for tests)
+    ///
+    /// Without a database pool permissions are kept in memory only.
+    async fn write(&self, entry: GameLogEntry) -> anyhow::Result<()> {
+        if let Some(ref pool) = self.db_pool {
+            write_entry(self.raft_writer.as_deref(), pool, entry).await?;
+        }
+        Ok(())
+    }
+
     /// Set a user's access level
     pub async fn set_access_level(&self, account_id: &str, level: AccessLevel) {
         let level_str = match level {
@@ -337,27 +358,14 @@ impl PermissionManager {
             AccessLevel::Owner => "owner",
         };
 
-        // Persist via Raft if available, otherwise direct SQL
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = raft_writer
-                .execute(
-                    "UPDATE accounts SET access_level = ? WHERE id = ?",
-                    vec![serde_json::json!(level_str), serde_json::json!(account_id)],
-                )
-                .await
-            {
-                tracing::warn!("Failed to persist access level for {}: {}", account_id, e);
-            }
-        } else if let Some(ref pool) = self.db_pool {
-            // Direct SQL fallback (for tests)
-            if let Err(e) = sqlx::query("UPDATE accounts SET access_level = ? WHERE id = ?")
-                .bind(level_str)
-                .bind(account_id)
-                .execute(pool)
-                .await
-            {
-                tracing::warn!("Failed to persist access level for {}: {}", account_id, e);
-            }
+        if let Err(e) = self
+            .write(GameLogEntry::SetAccessLevel {
+                account_id: account_id.to_string(),
+                access_level: level_str.to_string(),
+            })
+            .await
+        {
+            tracing::warn!("Failed to persist access level for {}: {}", account_id, e);
         }
 
         self.user_levels
@@ -425,36 +433,16 @@ impl PermissionManager {
             &grantor.account_id,
         );
 
-        // Persist via Raft if available, otherwise direct SQL
-        if let Some(ref raft_writer) = self.raft_writer {
-            raft_writer
-                .execute(
-                    "INSERT OR REPLACE INTO path_grants (id, universe_id, grantee_id, path_prefix, can_delegate, granted_by, granted_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                    vec![
-                        serde_json::json!(&grant.id),
-                        serde_json::json!(&grant.universe_id),
-                        serde_json::json!(&grant.grantee_id),
-                        serde_json::json!(&grant.path_prefix),
-                        serde_json::json!(grant.can_delegate),
-                        serde_json::json!(&grant.granted_by),
-                        serde_json::json!(&grant.granted_at),
-                    ],
-                )
-                .await?;
-        } else if let Some(ref pool) = self.db_pool {
-            sqlx::query(
-                "INSERT OR REPLACE INTO path_grants (id, universe_id, grantee_id, path_prefix, can_delegate, granted_by, granted_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
-            )
-            .bind(&grant.id)
-            .bind(&grant.universe_id)
-            .bind(&grant.grantee_id)
-            .bind(&grant.path_prefix)
-            .bind(grant.can_delegate)
-            .bind(&grant.granted_by)
-            .bind(&grant.granted_at)
-            .execute(pool)
-            .await?;
-        }
+        self.write(GameLogEntry::GrantPath {
+            grant_id: grant.id.clone(),
+            universe_id: grant.universe_id.clone(),
+            grantee_id: grant.grantee_id.clone(),
+            path_prefix: grant.path_prefix.clone(),
+            can_delegate: grant.can_delegate,
+            granted_by: grant.granted_by.clone(),
+            granted_at: grant.granted_at.clone(),
+        })
+        .await?;
 
         // Update in-memory cache
         let mut grants = self.path_grants.write().await;
@@ -496,20 +484,11 @@ impl PermissionManager {
         }
 
         // Delete from database
-        if let Some(ref raft_writer) = self.raft_writer {
-            raft_writer
-                .execute(
-                    "DELETE FROM path_grants WHERE id = ? AND universe_id = ?",
-                    vec![serde_json::json!(grant_id), serde_json::json!(universe_id)],
-                )
-                .await?;
-        } else if let Some(ref pool) = self.db_pool {
-            sqlx::query("DELETE FROM path_grants WHERE id = ? AND universe_id = ?")
-                .bind(grant_id)
-                .bind(universe_id)
-                .execute(pool)
-                .await?;
-        }
+        self.write(GameLogEntry::RevokePath {
+            grant_id: grant_id.to_string(),
+            universe_id: universe_id.to_string(),
+        })
+        .await?;
 
         // Update in-memory cache
         let mut grants = self.path_grants.write().await;
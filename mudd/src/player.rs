@@ -126,6 +126,20 @@ impl PlayerManager {
         Ok(())
     }
 
+    /// Set a property on a player's object, if it exists
+    pub async fn set_property(
+        &self,
+        player_id: &str,
+        key: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        if let Some(mut player) = self.object_store.get(player_id).await? {
+            player.set_property(key, value);
+            self.object_store.update(&player).await?;
+        }
+        Ok(())
+    }
+
     /// Handle player disconnect.
     /// If not in a safe zone, drops all inventory to the current room.
     pub async fn handle_disconnect(&self, player_id: &str) -> Result<()> {
@@ -236,19 +250,23 @@ impl PlayerManager {
     }
 
     /// Drop all inventory items from a player to a room.
+    /// All items move in one atomic write, so none are lost on failure.
     async fn drop_inventory(&self, player_id: &str, room_id: &str) -> Result<()> {
         let items = self.object_store.get_contents(player_id).await?;
 
-        for item in items {
-            // Skip items that shouldn't be dropped (though they shouldn't be in inventory anyway)
-            if item.get_bool("fixed").unwrap_or(false) {
-                continue;
-            }
+        // Skip items that shouldn't be dropped (though they shouldn't be in inventory anyway)
+        let item_ids: Vec<String> = items
+            .into_iter()
+            .filter(|item| !item.get_bool("fixed").unwrap_or(false))
+            .map(|item| item.id)
+            .collect();
+
+        self.object_store
+            .move_objects(&item_ids, Some(room_id))
+            .await?;
 
-            self.object_store
-                .move_object(&item.id, Some(room_id))
-                .await?;
-            info!("Dropped {} from {} to {}", item.id, player_id, room_id);
+        for item_id in &item_ids {
+            info!("Dropped {} from {} to {}", item_id, player_id, room_id);
         }
 
         Ok(())
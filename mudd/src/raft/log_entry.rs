@@ -0,0 +1,2003 @@
+//! Typed Raft log entries
+//!
+//! Every write proposed to Raft is a `GameLogEntry` wrapped in a versioned
+//! `Request`. Entries describe *what* happened in domain terms; the state
+//! machine turns them into SQL against the current schema when applying.
+//! That keeps the log auditable and lets old logs replay after migrations.
+//!
+//! Entries written before typed entries existed were bare `{sql, params}`
+//! objects (or arrays of them). They decode as version 0 `Mutations`.
+//! Version 1 deposits predate the credit ledger; see `upgrade_v1`.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::{SqliteArguments, SqliteConnection};
+use sqlx::Sqlite;
+use thiserror::Error;
+use tracing::{debug, error};
+
+use super::types::Statement;
+
+/// Current log entry format version
+///
+/// Version 2 added ledger fields to `DepositCredits`. Version 3 added
+/// account, session, permission, channel, timer and combat entries.
+pub const LOG_ENTRY_VERSION: u32 = 3;
+
+/// Timestamp given to upgraded entries that recorded none
+const EPOCH: &str = "1970-01-01T00:00:00+00:00";
+
+/// A domain-level change to the game database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameLogEntry {
+    /// Create a universe together with its library code
+    CreateUniverse {
+        universe_id: String,
+        name: String,
+        owner_id: String,
+        config: serde_json::Value,
+        /// Lua sources stored alongside the universe (content-addressed)
+        #[serde(default)]
+        code: Vec<String>,
+        created_at: String,
+    },
+    /// Raw SQL mutations, applied together in one transaction
+    Mutations {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        universe_id: Option<String>,
+        statements: Vec<Statement>,
+    },
+    /// Add credits to an account's balance in a universe
+    DepositCredits {
+        universe_id: String,
+        account_id: String,
+        amount: i64,
+        /// Row ID used if the account has no balance row yet
+        balance_id: String,
+        /// Ledger row ID for this change (missing before version 2)
+        #[serde(default)]
+        transaction_id: String,
+        reason: String,
+        #[serde(default)]
+        created_at: String,
+    },
+    /// Remove credits from an account, rejected if the balance is too low
+    DeductCredits {
+        universe_id: String,
+        account_id: String,
+        amount: i64,
+        transaction_id: String,
+        reason: String,
+        created_at: String,
+    },
+    /// Remove up to `amount` credits, as many as the balance covers
+    ///
+    /// Used for usage charged after the fact, which can't be refused.
+    ChargeCredits {
+        universe_id: String,
+        account_id: String,
+        amount: i64,
+        transaction_id: String,
+        reason: String,
+        created_at: String,
+    },
+    /// Set an account's balance outright, recording the difference
+    SetCredits {
+        universe_id: String,
+        account_id: String,
+        balance: i64,
+        balance_id: String,
+        transaction_id: String,
+        reason: String,
+        created_at: String,
+    },
+    /// Move credits from one account to another in one step
+    ///
+    /// Rejected if the sender cannot cover the amount. When `item` is set
+    /// the object changes hands in the same transaction, so a sale either
+    /// completes fully or not at all.
+    TransferCredits {
+        universe_id: String,
+        from_account_id: String,
+        to_account_id: String,
+        amount: i64,
+        /// Row ID used if the recipient has no balance row yet
+        balance_id: String,
+        /// Ledger row ID for the sender's debit
+        debit_transaction_id: String,
+        /// Ledger row ID for the recipient's credit
+        credit_transaction_id: String,
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        item: Option<ItemTransfer>,
+        created_at: String,
+    },
+    /// Create an object
+    CreateObject {
+        object_id: String,
+        universe_id: String,
+        class: String,
+        parent_id: Option<String>,
+        properties: serde_json::Value,
+        code_hash: Option<String>,
+        owner_id: Option<String>,
+        created_at: String,
+        updated_at: String,
+    },
+    /// Replace an object's class, container, properties and code
+    UpdateObject {
+        object_id: String,
+        class: String,
+        parent_id: Option<String>,
+        properties: serde_json::Value,
+        code_hash: Option<String>,
+        updated_at: String,
+    },
+    /// Delete an object
+    DeleteObject { object_id: String },
+    /// Move objects into a container (or out of any) together
+    MoveObjects {
+        object_ids: Vec<String>,
+        parent_id: Option<String>,
+        updated_at: String,
+    },
+    /// Store content-addressed code
+    StoreCode {
+        hash: String,
+        source: String,
+        created_at: String,
+    },
+    /// Create an account, with a password hash, a wallet or both
+    CreateAccount {
+        account_id: String,
+        username: String,
+        password_hash: Option<String>,
+        wallet_address: Option<String>,
+        created_at: String,
+    },
+    /// Replace an account's password hash, if it is still `old_hash`
+    RehashPassword {
+        account_id: String,
+        old_hash: String,
+        new_hash: String,
+    },
+    /// Set an account's access level
+    SetAccessLevel {
+        account_id: String,
+        access_level: String,
+    },
+    /// Link a wallet to an account that has none
+    LinkWallet {
+        account_id: String,
+        wallet_address: String,
+    },
+    /// Start a session, clearing out the account's expired ones
+    StartSession {
+        session_id: String,
+        account_id: String,
+        token_hash: String,
+        user_agent: Option<String>,
+        ip_address: Option<String>,
+        created_at: String,
+        expires_at: String,
+    },
+    /// Record that a session was used
+    TouchSession {
+        session_id: String,
+        last_seen_at: String,
+    },
+    /// End the session holding a token
+    EndSession { token_hash: String },
+    /// End one of an account's sessions
+    RevokeSession {
+        account_id: String,
+        session_id: String,
+    },
+    /// End all of an account's sessions
+    RevokeSessions { account_id: String },
+    /// Issue a wallet sign-in nonce, clearing out expired ones
+    IssueNonce {
+        nonce: String,
+        address: String,
+        created_at: String,
+        expires_at: String,
+    },
+    /// Use up a nonce, if it was issued to `address` and unexpired at `used_at`
+    UseNonce {
+        nonce: String,
+        address: String,
+        used_at: String,
+    },
+    /// Grant an account a path in a universe
+    GrantPath {
+        grant_id: String,
+        universe_id: String,
+        grantee_id: String,
+        path_prefix: String,
+        can_delegate: bool,
+        granted_by: String,
+        granted_at: String,
+    },
+    /// Revoke a path grant
+    RevokePath {
+        grant_id: String,
+        universe_id: String,
+    },
+    /// Add a message to a channel's history, keeping only the latest `keep`
+    RecordChannelMessage {
+        message_id: String,
+        channel: String,
+        /// Universe, or the global scope key for cross-universe channels
+        universe_id: String,
+        sender_id: String,
+        sender_name: String,
+        text: String,
+        created_at: String,
+        keep: i64,
+    },
+    /// Stop a player talking on a channel
+    MuteChannel {
+        channel: String,
+        universe_id: String,
+        player_id: String,
+        muted_by: String,
+        created_at: String,
+    },
+    /// Let a muted player talk on a channel again
+    UnmuteChannel {
+        channel: String,
+        universe_id: String,
+        player_id: String,
+    },
+    /// Create or replace a timer
+    SetTimer {
+        timer_id: String,
+        universe_id: String,
+        object_id: String,
+        method: String,
+        /// Unix timestamp (ms)
+        fire_at: i64,
+        args: Option<String>,
+    },
+    /// Delete a timer
+    DeleteTimer { timer_id: String },
+    /// Create or replace an entity's persisted combat stats
+    SetCombatState {
+        entity_id: String,
+        universe_id: String,
+        hp: i32,
+        max_hp: i32,
+        armor_class: i32,
+        attack_bonus: i32,
+    },
+    /// Delete an entity's combat stats and, with them, its effects
+    DeleteCombatState { entity_id: String },
+    /// Add a status effect to an entity
+    AddEffect {
+        entity_id: String,
+        effect: EffectRecord,
+    },
+    /// Remove an entity's effects of one type
+    RemoveEffect {
+        entity_id: String,
+        effect_type: String,
+    },
+    /// Replace all of an entity's effects
+    SetEffects {
+        entity_id: String,
+        effects: Vec<EffectRecord>,
+    },
+}
+
+/// An object handed over as part of a credit transfer
+///
+/// The object goes to the paying account, which becomes its owner.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemTransfer {
+    pub object_id: String,
+    /// Where the object must still be for the transfer to go through
+    pub from_parent_id: String,
+    pub to_parent_id: String,
+}
+
+/// A status effect on an entity, as stored in `active_effects`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectRecord {
+    pub effect_id: String,
+    pub effect_type: String,
+    pub remaining_ticks: u32,
+    pub magnitude: i32,
+    pub damage_type: Option<String>,
+    pub source_id: Option<String>,
+}
+
+impl GameLogEntry {
+    /// Mutations entry from a single statement
+    pub fn statement(sql: impl Into<String>, params: Vec<serde_json::Value>) -> Self {
+        Self::Mutations {
+            universe_id: None,
+            statements: vec![Statement::new(sql, params)],
+        }
+    }
+
+    /// Fill in the fields a version 1 entry lacks
+    ///
+    /// Version 1 deposits had no ledger row ID or timestamp. The row ID is
+    /// derived from the entry's log index, so every node replaying it
+    /// records the same ledger row, and the timestamp is the epoch.
+    pub fn upgrade_v1(mut self, index: u64) -> Self {
+        if let Self::DepositCredits {
+            ref mut transaction_id,
+            ref mut created_at,
+            ..
+        } = self
+        {
+            if transaction_id.is_empty() {
+                *transaction_id = format!("raft-log-{}", index);
+            }
+            if created_at.is_empty() {
+                *created_at = EPOCH.to_string();
+            }
+        }
+        self
+    }
+
+    /// Short name of the entry type, for logging and auditing
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CreateUniverse { .. } => "create_universe",
+            Self::Mutations { .. } => "mutations",
+            Self::DepositCredits { .. } => "deposit_credits",
+            Self::DeductCredits { .. } => "deduct_credits",
+            Self::ChargeCredits { .. } => "charge_credits",
+            Self::SetCredits { .. } => "set_credits",
+            Self::TransferCredits { .. } => "transfer_credits",
+            Self::CreateObject { .. } => "create_object",
+            Self::UpdateObject { .. } => "update_object",
+            Self::DeleteObject { .. } => "delete_object",
+            Self::MoveObjects { .. } => "move_objects",
+            Self::StoreCode { .. } => "store_code",
+            Self::CreateAccount { .. } => "create_account",
+            Self::RehashPassword { .. } => "rehash_password",
+            Self::SetAccessLevel { .. } => "set_access_level",
+            Self::LinkWallet { .. } => "link_wallet",
+            Self::StartSession { .. } => "start_session",
+            Self::TouchSession { .. } => "touch_session",
+            Self::EndSession { .. } => "end_session",
+            Self::RevokeSession { .. } => "revoke_session",
+            Self::RevokeSessions { .. } => "revoke_sessions",
+            Self::IssueNonce { .. } => "issue_nonce",
+            Self::UseNonce { .. } => "use_nonce",
+            Self::GrantPath { .. } => "grant_path",
+            Self::RevokePath { .. } => "revoke_path",
+            Self::RecordChannelMessage { .. } => "record_channel_message",
+            Self::MuteChannel { .. } => "mute_channel",
+            Self::UnmuteChannel { .. } => "unmute_channel",
+            Self::SetTimer { .. } => "set_timer",
+            Self::DeleteTimer { .. } => "delete_timer",
+            Self::SetCombatState { .. } => "set_combat_state",
+            Self::DeleteCombatState { .. } => "delete_combat_state",
+            Self::AddEffect { .. } => "add_effect",
+            Self::RemoveEffect { .. } => "remove_effect",
+            Self::SetEffects { .. } => "set_effects",
+        }
+    }
+
+    /// Apply this entry within an open transaction
+    ///
+    /// Returns the number of rows affected.
+    pub async fn apply(&self, conn: &mut SqliteConnection) -> Result<u64, ApplyError> {
+        debug!("Applying {} entry", self.kind());
+
+        match self {
+            Self::CreateUniverse {
+                universe_id,
+                name,
+                owner_id,
+                config,
+                code,
+                created_at,
+            } => {
+                let mut rows = 0;
+                for source in code {
+                    rows += store_code(conn, &hash_code(source), source, created_at).await?;
+                }
+
+                rows += sqlx::query(
+                    "INSERT INTO universes (id, name, owner_id, config, created_at) VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(universe_id)
+                .bind(name)
+                .bind(owner_id)
+                .bind(config.to_string())
+                .bind(created_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::Mutations { statements, .. } => {
+                let mut rows = 0;
+                for stmt in statements {
+                    rows += bind_params(sqlx::query(&stmt.sql), &stmt.params)
+                        .execute(&mut *conn)
+                        .await?
+                        .rows_affected();
+                }
+                Ok(rows)
+            }
+            Self::DepositCredits {
+                universe_id,
+                account_id,
+                amount,
+                balance_id,
+                transaction_id,
+                reason,
+                created_at,
+            } => {
+                if *amount <= 0 {
+                    return Err(ApplyError::Invalid(format!(
+                        "deposit amount must be positive, got {}",
+                        amount
+                    )));
+                }
+
+                let mut rows = sqlx::query(
+                    "INSERT INTO credits (id, universe_id, player_id, balance) VALUES (?, ?, ?, ?) ON CONFLICT(universe_id, player_id) DO UPDATE SET balance = balance + excluded.balance",
+                )
+                .bind(balance_id)
+                .bind(universe_id)
+                .bind(account_id)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                rows += record_transaction(
+                    conn,
+                    transaction_id,
+                    universe_id,
+                    account_id,
+                    *amount,
+                    reason,
+                    created_at,
+                )
+                .await?;
+                Ok(rows)
+            }
+            Self::DeductCredits {
+                universe_id,
+                account_id,
+                amount,
+                transaction_id,
+                reason,
+                created_at,
+            } => {
+                if *amount <= 0 {
+                    return Err(ApplyError::Invalid(format!(
+                        "deduction amount must be positive, got {}",
+                        amount
+                    )));
+                }
+
+                let mut rows = sqlx::query(
+                    "UPDATE credits SET balance = balance - ? WHERE universe_id = ? AND player_id = ? AND balance >= ?",
+                )
+                .bind(amount)
+                .bind(universe_id)
+                .bind(account_id)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                if rows == 0 {
+                    return Err(ApplyError::Rejected(format!(
+                        "insufficient credits for {} to deduct {}",
+                        account_id, amount
+                    )));
+                }
+
+                rows += record_transaction(
+                    conn,
+                    transaction_id,
+                    universe_id,
+                    account_id,
+                    -amount,
+                    reason,
+                    created_at,
+                )
+                .await?;
+                Ok(rows)
+            }
+            Self::ChargeCredits {
+                universe_id,
+                account_id,
+                amount,
+                transaction_id,
+                reason,
+                created_at,
+            } => {
+                if *amount <= 0 {
+                    return Err(ApplyError::Invalid(format!(
+                        "charge amount must be positive, got {}",
+                        amount
+                    )));
+                }
+
+                let balance: Option<(i64,)> = sqlx::query_as(
+                    "SELECT balance FROM credits WHERE universe_id = ? AND player_id = ?",
+                )
+                .bind(universe_id)
+                .bind(account_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+                let charged = (*amount).min(balance.map_or(0, |(b,)| b.max(0)));
+                if charged == 0 {
+                    return Ok(0);
+                }
+
+                let mut rows = sqlx::query(
+                    "UPDATE credits SET balance = balance - ? WHERE universe_id = ? AND player_id = ?",
+                )
+                .bind(charged)
+                .bind(universe_id)
+                .bind(account_id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                rows += record_transaction(
+                    conn,
+                    transaction_id,
+                    universe_id,
+                    account_id,
+                    -charged,
+                    reason,
+                    created_at,
+                )
+                .await?;
+                Ok(rows)
+            }
+            Self::SetCredits {
+                universe_id,
+                account_id,
+                balance,
+                balance_id,
+                transaction_id,
+                reason,
+                created_at,
+            } => {
+                if *balance < 0 {
+                    return Err(ApplyError::Invalid(format!(
+                        "balance must not be negative, got {}",
+                        balance
+                    )));
+                }
+
+                let current: Option<(i64,)> = sqlx::query_as(
+                    "SELECT balance FROM credits WHERE universe_id = ? AND player_id = ?",
+                )
+                .bind(universe_id)
+                .bind(account_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+                let delta = balance - current.map(|(b,)| b).unwrap_or(0);
+
+                let mut rows = sqlx::query(
+                    "INSERT INTO credits (id, universe_id, player_id, balance) VALUES (?, ?, ?, ?) ON CONFLICT(universe_id, player_id) DO UPDATE SET balance = excluded.balance",
+                )
+                .bind(balance_id)
+                .bind(universe_id)
+                .bind(account_id)
+                .bind(balance)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                rows += record_transaction(
+                    conn,
+                    transaction_id,
+                    universe_id,
+                    account_id,
+                    delta,
+                    reason,
+                    created_at,
+                )
+                .await?;
+                Ok(rows)
+            }
+            Self::TransferCredits {
+                universe_id,
+                from_account_id,
+                to_account_id,
+                amount,
+                balance_id,
+                debit_transaction_id,
+                credit_transaction_id,
+                reason,
+                item,
+                created_at,
+            } => {
+                if *amount <= 0 {
+                    return Err(ApplyError::Invalid(format!(
+                        "transfer amount must be positive, got {}",
+                        amount
+                    )));
+                }
+                if from_account_id == to_account_id {
+                    return Err(ApplyError::Invalid(format!(
+                        "cannot transfer from {} to itself",
+                        from_account_id
+                    )));
+                }
+
+                let mut rows = sqlx::query(
+                    "UPDATE credits SET balance = balance - ? WHERE universe_id = ? AND player_id = ? AND balance >= ?",
+                )
+                .bind(amount)
+                .bind(universe_id)
+                .bind(from_account_id)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                if rows == 0 {
+                    return Err(ApplyError::Rejected(format!(
+                        "insufficient credits for {} to transfer {}",
+                        from_account_id, amount
+                    )));
+                }
+
+                rows += sqlx::query(
+                    "INSERT INTO credits (id, universe_id, player_id, balance) VALUES (?, ?, ?, ?) ON CONFLICT(universe_id, player_id) DO UPDATE SET balance = balance + excluded.balance",
+                )
+                .bind(balance_id)
+                .bind(universe_id)
+                .bind(to_account_id)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                rows += record_transaction(
+                    conn,
+                    debit_transaction_id,
+                    universe_id,
+                    from_account_id,
+                    -amount,
+                    reason,
+                    created_at,
+                )
+                .await?;
+                rows += record_transaction(
+                    conn,
+                    credit_transaction_id,
+                    universe_id,
+                    to_account_id,
+                    *amount,
+                    reason,
+                    created_at,
+                )
+                .await?;
+
+                if let Some(item) = item {
+                    let moved = sqlx::query(
+                        "UPDATE objects SET parent_id = ?, owner_id = ?, updated_at = ? WHERE id = ? AND universe_id = ? AND parent_id = ?",
+                    )
+                    .bind(&item.to_parent_id)
+                    .bind(from_account_id)
+                    .bind(created_at)
+                    .bind(&item.object_id)
+                    .bind(universe_id)
+                    .bind(&item.from_parent_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                    if moved == 0 {
+                        return Err(ApplyError::Rejected(format!(
+                            "{} is no longer in {}",
+                            item.object_id, item.from_parent_id
+                        )));
+                    }
+                    rows += moved;
+                }
+                Ok(rows)
+            }
+            Self::CreateObject {
+                object_id,
+                universe_id,
+                class,
+                parent_id,
+                properties,
+                code_hash,
+                owner_id,
+                created_at,
+                updated_at,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT INTO objects (id, universe_id, class, parent_id, properties, code_hash, owner_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(object_id)
+                .bind(universe_id)
+                .bind(class)
+                .bind(parent_id)
+                .bind(properties.to_string())
+                .bind(code_hash)
+                .bind(owner_id)
+                .bind(created_at)
+                .bind(updated_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::UpdateObject {
+                object_id,
+                class,
+                parent_id,
+                properties,
+                code_hash,
+                updated_at,
+            } => {
+                let rows = sqlx::query(
+                    "UPDATE objects SET class = ?, parent_id = ?, properties = ?, code_hash = ?, updated_at = ? WHERE id = ?",
+                )
+                .bind(class)
+                .bind(parent_id)
+                .bind(properties.to_string())
+                .bind(code_hash)
+                .bind(updated_at)
+                .bind(object_id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::DeleteObject { object_id } => {
+                let rows = sqlx::query("DELETE FROM objects WHERE id = ?")
+                    .bind(object_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::MoveObjects {
+                object_ids,
+                parent_id,
+                updated_at,
+            } => {
+                let mut rows = 0;
+                for object_id in object_ids {
+                    rows += sqlx::query(
+                        "UPDATE objects SET parent_id = ?, updated_at = ? WHERE id = ?",
+                    )
+                    .bind(parent_id)
+                    .bind(updated_at)
+                    .bind(object_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+                }
+                Ok(rows)
+            }
+            Self::StoreCode {
+                hash,
+                source,
+                created_at,
+            } => {
+                if *hash != hash_code(source) {
+                    return Err(ApplyError::Invalid(format!(
+                        "code hash {} does not match source",
+                        hash
+                    )));
+                }
+                store_code(conn, hash, source, created_at).await
+            }
+            Self::CreateAccount {
+                account_id,
+                username,
+                password_hash,
+                wallet_address,
+                created_at,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT INTO accounts (id, username, password_hash, wallet_address, access_level, created_at) VALUES (?, ?, ?, ?, 'player', ?)",
+                )
+                .bind(account_id)
+                .bind(username)
+                .bind(password_hash)
+                .bind(wallet_address)
+                .bind(created_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::RehashPassword {
+                account_id,
+                old_hash,
+                new_hash,
+            } => {
+                let rows = sqlx::query(
+                    "UPDATE accounts SET password_hash = ?, salt = NULL WHERE id = ? AND password_hash = ?",
+                )
+                .bind(new_hash)
+                .bind(account_id)
+                .bind(old_hash)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::SetAccessLevel {
+                account_id,
+                access_level,
+            } => {
+                let rows = sqlx::query("UPDATE accounts SET access_level = ? WHERE id = ?")
+                    .bind(access_level)
+                    .bind(account_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::LinkWallet {
+                account_id,
+                wallet_address,
+            } => {
+                let rows = sqlx::query(
+                    "UPDATE accounts SET wallet_address = ? WHERE id = ? AND wallet_address IS NULL",
+                )
+                .bind(wallet_address)
+                .bind(account_id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::StartSession {
+                session_id,
+                account_id,
+                token_hash,
+                user_agent,
+                ip_address,
+                created_at,
+                expires_at,
+            } => {
+                let mut rows =
+                    sqlx::query("DELETE FROM sessions WHERE account_id = ? AND expires_at <= ?")
+                        .bind(account_id)
+                        .bind(created_at)
+                        .execute(&mut *conn)
+                        .await?
+                        .rows_affected();
+
+                rows += sqlx::query(
+                    "INSERT INTO sessions (id, account_id, token_hash, created_at, expires_at, last_seen_at, user_agent, ip_address) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(session_id)
+                .bind(account_id)
+                .bind(token_hash)
+                .bind(created_at)
+                .bind(expires_at)
+                .bind(created_at)
+                .bind(user_agent)
+                .bind(ip_address)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::TouchSession {
+                session_id,
+                last_seen_at,
+            } => {
+                let rows = sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ?")
+                    .bind(last_seen_at)
+                    .bind(session_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::EndSession { token_hash } => {
+                let rows = sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+                    .bind(token_hash)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::RevokeSession {
+                account_id,
+                session_id,
+            } => {
+                let rows = sqlx::query("DELETE FROM sessions WHERE id = ? AND account_id = ?")
+                    .bind(session_id)
+                    .bind(account_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::RevokeSessions { account_id } => {
+                let rows = sqlx::query("DELETE FROM sessions WHERE account_id = ?")
+                    .bind(account_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::IssueNonce {
+                nonce,
+                address,
+                created_at,
+                expires_at,
+            } => {
+                let mut rows = sqlx::query("DELETE FROM wallet_nonces WHERE expires_at <= ?")
+                    .bind(created_at)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                rows += sqlx::query(
+                    "INSERT INTO wallet_nonces (nonce, address, created_at, expires_at) VALUES (?, ?, ?, ?)",
+                )
+                .bind(nonce)
+                .bind(address)
+                .bind(created_at)
+                .bind(expires_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::UseNonce {
+                nonce,
+                address,
+                used_at,
+            } => {
+                let rows = sqlx::query(
+                    "DELETE FROM wallet_nonces WHERE nonce = ? AND address = ? AND expires_at > ?",
+                )
+                .bind(nonce)
+                .bind(address)
+                .bind(used_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::GrantPath {
+                grant_id,
+                universe_id,
+                grantee_id,
+                path_prefix,
+                can_delegate,
+                granted_by,
+                granted_at,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT OR REPLACE INTO path_grants (id, universe_id, grantee_id, path_prefix, can_delegate, granted_by, granted_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(grant_id)
+                .bind(universe_id)
+                .bind(grantee_id)
+                .bind(path_prefix)
+                .bind(can_delegate)
+                .bind(granted_by)
+                .bind(granted_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::RevokePath {
+                grant_id,
+                universe_id,
+            } => {
+                let rows = sqlx::query("DELETE FROM path_grants WHERE id = ? AND universe_id = ?")
+                    .bind(grant_id)
+                    .bind(universe_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::RecordChannelMessage {
+                message_id,
+                channel,
+                universe_id,
+                sender_id,
+                sender_name,
+                text,
+                created_at,
+                keep,
+            } => {
+                let mut rows = sqlx::query(
+                    "INSERT INTO channel_messages (id, channel, universe_id, sender_id, sender_name, text, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(message_id)
+                .bind(channel)
+                .bind(universe_id)
+                .bind(sender_id)
+                .bind(sender_name)
+                .bind(text)
+                .bind(created_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                rows += sqlx::query(
+                    "DELETE FROM channel_messages WHERE channel = ? AND universe_id = ? AND id NOT IN (SELECT id FROM channel_messages WHERE channel = ? AND universe_id = ? ORDER BY created_at DESC, id DESC LIMIT ?)",
+                )
+                .bind(channel)
+                .bind(universe_id)
+                .bind(channel)
+                .bind(universe_id)
+                .bind(keep)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::MuteChannel {
+                channel,
+                universe_id,
+                player_id,
+                muted_by,
+                created_at,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT OR REPLACE INTO channel_mutes (channel, universe_id, player_id, muted_by, created_at) VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(channel)
+                .bind(universe_id)
+                .bind(player_id)
+                .bind(muted_by)
+                .bind(created_at)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::UnmuteChannel {
+                channel,
+                universe_id,
+                player_id,
+            } => {
+                let rows = sqlx::query(
+                    "DELETE FROM channel_mutes WHERE channel = ? AND universe_id = ? AND player_id = ?",
+                )
+                .bind(channel)
+                .bind(universe_id)
+                .bind(player_id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::SetTimer {
+                timer_id,
+                universe_id,
+                object_id,
+                method,
+                fire_at,
+                args,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT OR REPLACE INTO timers (id, universe_id, object_id, method, fire_at, args) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(timer_id)
+                .bind(universe_id)
+                .bind(object_id)
+                .bind(method)
+                .bind(fire_at)
+                .bind(args)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::DeleteTimer { timer_id } => {
+                let rows = sqlx::query("DELETE FROM timers WHERE id = ?")
+                    .bind(timer_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::SetCombatState {
+                entity_id,
+                universe_id,
+                hp,
+                max_hp,
+                armor_class,
+                attack_bonus,
+            } => {
+                let rows = sqlx::query(
+                    "INSERT OR REPLACE INTO combat_state (entity_id, universe_id, hp, max_hp, armor_class, attack_bonus) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(entity_id)
+                .bind(universe_id)
+                .bind(hp)
+                .bind(max_hp)
+                .bind(armor_class)
+                .bind(attack_bonus)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::DeleteCombatState { entity_id } => {
+                let rows = sqlx::query("DELETE FROM combat_state WHERE entity_id = ?")
+                    .bind(entity_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                Ok(rows)
+            }
+            Self::AddEffect { entity_id, effect } => insert_effect(conn, entity_id, effect).await,
+            Self::RemoveEffect {
+                entity_id,
+                effect_type,
+            } => {
+                let rows = sqlx::query(
+                    "DELETE FROM active_effects WHERE entity_id = ? AND effect_type = ?",
+                )
+                .bind(entity_id)
+                .bind(effect_type)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+
+                Ok(rows)
+            }
+            Self::SetEffects { entity_id, effects } => {
+                let mut rows = sqlx::query("DELETE FROM active_effects WHERE entity_id = ?")
+                    .bind(entity_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+
+                for effect in effects {
+                    rows += insert_effect(conn, entity_id, effect).await?;
+                }
+                Ok(rows)
+            }
+        }
+    }
+}
+
+/// Errors from applying a log entry
+#[derive(Debug, Error)]
+pub enum ApplyError {
+    #[error("unsupported log entry version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("invalid log entry: {0}")]
+    Invalid(String),
+
+    /// The entry is well-formed but the current state does not allow it
+    #[error("entry rejected: {0}")]
+    Rejected(String),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Compute the content hash used by code_store
+pub fn hash_code(source: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+async fn store_code(
+    conn: &mut SqliteConnection,
+    hash: &str,
+    source: &str,
+    created_at: &str,
+) -> Result<u64, ApplyError> {
+    let rows =
+        sqlx::query("INSERT OR IGNORE INTO code_store (hash, source, created_at) VALUES (?, ?, ?)")
+            .bind(hash)
+            .bind(source)
+            .bind(created_at)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+    Ok(rows)
+}
+
+/// Insert an effect's `active_effects` row
+async fn insert_effect(
+    conn: &mut SqliteConnection,
+    entity_id: &str,
+    effect: &EffectRecord,
+) -> Result<u64, ApplyError> {
+    let rows = sqlx::query(
+        "INSERT INTO active_effects (id, entity_id, effect_type, remaining_ticks, magnitude, damage_type, source_id) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&effect.effect_id)
+    .bind(entity_id)
+    .bind(&effect.effect_type)
+    .bind(effect.remaining_ticks)
+    .bind(effect.magnitude)
+    .bind(&effect.damage_type)
+    .bind(&effect.source_id)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    Ok(rows)
+}
+
+/// Append a ledger row for a credit change that was just applied
+///
+/// Records the balance after the change so each row can be checked
+/// against its predecessor.
+async fn record_transaction(
+    conn: &mut SqliteConnection,
+    transaction_id: &str,
+    universe_id: &str,
+    account_id: &str,
+    amount: i64,
+    reason: &str,
+    created_at: &str,
+) -> Result<u64, ApplyError> {
+    let rows = sqlx::query(
+        "INSERT INTO credit_transactions (id, universe_id, player_id, amount, balance_after, reason, created_at) SELECT ?, ?, ?, ?, balance, ?, ? FROM credits WHERE universe_id = ? AND player_id = ?",
+    )
+    .bind(transaction_id)
+    .bind(universe_id)
+    .bind(account_id)
+    .bind(amount)
+    .bind(reason)
+    .bind(created_at)
+    .bind(universe_id)
+    .bind(account_id)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    Ok(rows)
+}
+
+/// Bind JSON parameters to a query
+///
+/// Strings with a "blob:" prefix carry base64-encoded binary data.
+pub fn bind_params<'q>(
+    mut query: sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>,
+    params: &'q [serde_json::Value],
+) -> sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    for param in params {
+        query = match param {
+            serde_json::Value::Null => query.bind(Option::<String>::None),
+            serde_json::Value::Bool(b) => query.bind(*b),
+            serde_json::Value::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    query.bind(i)
+                } else if let Some(f) = n.as_f64() {
+                    query.bind(f)
+                } else {
+                    query.bind(n.to_string())
+                }
+            }
+            serde_json::Value::String(s) => {
+                // Handle "blob:" prefix for binary data
+                if let Some(b64_data) = s.strip_prefix("blob:") {
+                    match BASE64.decode(b64_data) {
+                        Ok(bytes) => query.bind(bytes),
+                        Err(e) => {
+                            error!("Failed to decode base64 blob: {}", e);
+                            query.bind(s.as_str())
+                        }
+                    }
+                } else {
+                    query.bind(s.as_str())
+                }
+            }
+            _ => query.bind(param.to_string()),
+        };
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_pool;
+    use crate::raft::types::Request;
+
+    async fn apply(pool: &sqlx::SqlitePool, entry: &GameLogEntry) -> Result<u64, ApplyError> {
+        let mut tx = pool.begin().await.unwrap();
+        let rows = entry.apply(&mut tx).await?;
+        tx.commit().await.unwrap();
+        Ok(rows)
+    }
+
+    async fn create_account(pool: &sqlx::SqlitePool, id: &str) {
+        sqlx::query(
+            "INSERT INTO accounts (id, username, password_hash, salt) VALUES (?, ?, '', '')",
+        )
+        .bind(id)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_entry_serde_is_tagged() {
+        let entry = GameLogEntry::StoreCode {
+            hash: "abc".to_string(),
+            source: "return 1".to_string(),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        };
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["type"], "store_code");
+
+        let parsed: GameLogEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, entry);
+    }
+
+    #[tokio::test]
+    async fn test_store_code_verifies_hash() {
+        let pool = test_pool().await;
+
+        let good = GameLogEntry::StoreCode {
+            hash: hash_code("return 1"),
+            source: "return 1".to_string(),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        };
+        assert_eq!(apply(&pool, &good).await.unwrap(), 1);
+
+        let bad = GameLogEntry::StoreCode {
+            hash: "not-the-hash".to_string(),
+            source: "return 2".to_string(),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        };
+        assert!(matches!(
+            apply(&pool, &bad).await,
+            Err(ApplyError::Invalid(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_universe_with_code() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+
+        let entry = GameLogEntry::CreateUniverse {
+            universe_id: "test-universe".to_string(),
+            name: "Test".to_string(),
+            owner_id: "owner".to_string(),
+            config: serde_json::json!({"a": 1}),
+            code: vec!["return 1".to_string()],
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+        };
+        assert_eq!(apply(&pool, &entry).await.unwrap(), 2);
+
+        let config: (String,) =
+            sqlx::query_as("SELECT config FROM universes WHERE id = 'test-universe'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(config.0, r#"{"a":1}"#);
+
+        let code: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM code_store WHERE hash = ?")
+            .bind(hash_code("return 1"))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(code.0, 1);
+    }
+
+    #[tokio::test]
+    async fn test_deposit_credits_accumulates() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        apply(
+            &pool,
+            &GameLogEntry::CreateUniverse {
+                universe_id: "test-universe".to_string(),
+                name: "Test".to_string(),
+                owner_id: "owner".to_string(),
+                config: serde_json::json!({}),
+                code: vec![],
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        for (balance_id, transaction_id) in [("b1", "t1"), ("b2", "t2")] {
+            let entry = GameLogEntry::DepositCredits {
+                universe_id: "test-universe".to_string(),
+                account_id: "owner".to_string(),
+                amount: 25,
+                balance_id: balance_id.to_string(),
+                transaction_id: transaction_id.to_string(),
+                reason: "test".to_string(),
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            };
+            apply(&pool, &entry).await.unwrap();
+        }
+
+        let balance: (i64,) = sqlx::query_as(
+            "SELECT balance FROM credits WHERE universe_id = 'test-universe' AND player_id = 'owner'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(balance.0, 50);
+    }
+
+    #[tokio::test]
+    async fn test_version_1_deposit_still_applies() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        apply(
+            &pool,
+            &GameLogEntry::CreateUniverse {
+                universe_id: "test-universe".to_string(),
+                name: "Test".to_string(),
+                owner_id: "owner".to_string(),
+                config: serde_json::json!({}),
+                code: vec![],
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        // As written by version 1, before the ledger existed
+        let request: Request = serde_json::from_str(
+            r#"{"version": 1, "entry": {"type": "deposit_credits", "universe_id": "test-universe", "account_id": "owner", "amount": 25, "balance_id": "b1", "reason": "purchase"}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.version, 1);
+
+        let mut tx = pool.begin().await.unwrap();
+        request.apply(&mut tx, 7).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let ledger: (String, i64, i64, String) =
+            sqlx::query_as("SELECT id, amount, balance_after, created_at FROM credit_transactions")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            ledger,
+            ("raft-log-7".to_string(), 25, 25, EPOCH.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_credit_entries_write_ledger() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        apply(
+            &pool,
+            &GameLogEntry::CreateUniverse {
+                universe_id: "test-universe".to_string(),
+                name: "Test".to_string(),
+                owner_id: "owner".to_string(),
+                config: serde_json::json!({}),
+                code: vec![],
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let deduct = |transaction_id: &str, amount| GameLogEntry::DeductCredits {
+            universe_id: "test-universe".to_string(),
+            account_id: "owner".to_string(),
+            amount,
+            transaction_id: transaction_id.to_string(),
+            reason: "purchase".to_string(),
+            created_at: "2025-01-01T00:00:02Z".to_string(),
+        };
+
+        apply(
+            &pool,
+            &GameLogEntry::DepositCredits {
+                universe_id: "test-universe".to_string(),
+                account_id: "owner".to_string(),
+                amount: 50,
+                balance_id: "b1".to_string(),
+                transaction_id: "t1".to_string(),
+                reason: "grant".to_string(),
+                created_at: "2025-01-01T00:00:01Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        apply(&pool, &deduct("t2", 20)).await.unwrap();
+
+        // Overdrawing is rejected without touching balance or ledger
+        assert!(matches!(
+            apply(&pool, &deduct("t3", 100)).await,
+            Err(ApplyError::Rejected(_))
+        ));
+
+        apply(
+            &pool,
+            &GameLogEntry::SetCredits {
+                universe_id: "test-universe".to_string(),
+                account_id: "owner".to_string(),
+                balance: 10,
+                balance_id: "b2".to_string(),
+                transaction_id: "t4".to_string(),
+                reason: "reset".to_string(),
+                created_at: "2025-01-01T00:00:03Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let ledger: Vec<(String, i64, i64)> = sqlx::query_as(
+            "SELECT id, amount, balance_after FROM credit_transactions ORDER BY created_at",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            ledger,
+            vec![
+                ("t1".to_string(), 50, 50),
+                ("t2".to_string(), -20, 30),
+                ("t4".to_string(), -20, 10),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_charge_credits_takes_what_the_balance_covers() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        apply(
+            &pool,
+            &GameLogEntry::CreateUniverse {
+                universe_id: "test-universe".to_string(),
+                name: "Test".to_string(),
+                owner_id: "owner".to_string(),
+                config: serde_json::json!({}),
+                code: vec![],
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let charge = |transaction_id: &str| GameLogEntry::ChargeCredits {
+            universe_id: "test-universe".to_string(),
+            account_id: "owner".to_string(),
+            amount: 30,
+            transaction_id: transaction_id.to_string(),
+            reason: "eval".to_string(),
+            created_at: "2025-01-01T00:00:01Z".to_string(),
+        };
+
+        // No balance row yet: nothing to take
+        assert_eq!(apply(&pool, &charge("c0")).await.unwrap(), 0);
+
+        apply(
+            &pool,
+            &GameLogEntry::DepositCredits {
+                universe_id: "test-universe".to_string(),
+                account_id: "owner".to_string(),
+                amount: 50,
+                balance_id: "b1".to_string(),
+                transaction_id: "t1".to_string(),
+                reason: "test".to_string(),
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        apply(&pool, &charge("c1")).await.unwrap();
+        apply(&pool, &charge("c2")).await.unwrap();
+        assert_eq!(apply(&pool, &charge("c3")).await.unwrap(), 0);
+
+        let ledger: Vec<(String, i64, i64)> = sqlx::query_as(
+            "SELECT id, amount, balance_after FROM credit_transactions ORDER BY rowid",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            ledger,
+            vec![
+                ("t1".to_string(), 50, 50),
+                ("c1".to_string(), -30, 20),
+                ("c2".to_string(), -20, 0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_with_item_is_atomic() {
+        let pool = test_pool().await;
+        create_account(&pool, "buyer").await;
+        create_account(&pool, "seller").await;
+        sqlx::query("INSERT INTO universes (id, name, owner_id) VALUES ('u1', 'Test', 'seller')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (id, parent) in [
+            ("/npcs/vendor", None),
+            ("/players/buyer", None),
+            ("/items/lamp", Some("/npcs/vendor")),
+        ] {
+            sqlx::query(
+                "INSERT INTO objects (id, universe_id, class, parent_id) VALUES (?, 'u1', 'item', ?)",
+            )
+            .bind(id)
+            .bind(parent)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        apply(
+            &pool,
+            &GameLogEntry::DepositCredits {
+                universe_id: "u1".to_string(),
+                account_id: "buyer".to_string(),
+                amount: 50,
+                balance_id: "b1".to_string(),
+                transaction_id: "t1".to_string(),
+                reason: "grant".to_string(),
+                created_at: "2025-01-01T00:00:01Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let sale = |n: u32, amount| GameLogEntry::TransferCredits {
+            universe_id: "u1".to_string(),
+            from_account_id: "buyer".to_string(),
+            to_account_id: "seller".to_string(),
+            amount,
+            balance_id: format!("b{}", n),
+            debit_transaction_id: format!("d{}", n),
+            credit_transaction_id: format!("c{}", n),
+            reason: "buy lamp".to_string(),
+            item: Some(ItemTransfer {
+                object_id: "/items/lamp".to_string(),
+                from_parent_id: "/npcs/vendor".to_string(),
+                to_parent_id: "/players/buyer".to_string(),
+            }),
+            created_at: "2025-01-01T00:00:02Z".to_string(),
+        };
+
+        // Too expensive: nothing moves
+        assert!(matches!(
+            apply(&pool, &sale(2, 80)).await,
+            Err(ApplyError::Rejected(_))
+        ));
+        apply(&pool, &sale(3, 30)).await.unwrap();
+        // Already sold: the second buyer is not charged
+        assert!(matches!(
+            apply(&pool, &sale(4, 10)).await,
+            Err(ApplyError::Rejected(_))
+        ));
+
+        let moved: (String, String) =
+            sqlx::query_as("SELECT parent_id, owner_id FROM objects WHERE id = '/items/lamp'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(moved, ("/players/buyer".to_string(), "buyer".to_string()));
+
+        let balances: Vec<(String, i64)> =
+            sqlx::query_as("SELECT player_id, balance FROM credits ORDER BY player_id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            balances,
+            vec![("buyer".to_string(), 20), ("seller".to_string(), 30)]
+        );
+
+        let ledger: Vec<(String, i64, i64)> = sqlx::query_as(
+            "SELECT id, amount, balance_after FROM credit_transactions ORDER BY rowid",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            ledger,
+            vec![
+                ("t1".to_string(), 50, 50),
+                ("d3".to_string(), -30, 20),
+                ("c3".to_string(), 30, 30),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_object_entries() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        apply(
+            &pool,
+            &GameLogEntry::CreateUniverse {
+                universe_id: "test-universe".to_string(),
+                name: "Test".to_string(),
+                owner_id: "owner".to_string(),
+                config: serde_json::json!({}),
+                code: vec![],
+                created_at: "2025-01-01T00:00:00Z".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let create = |object_id: &str, class: &str| GameLogEntry::CreateObject {
+            object_id: object_id.to_string(),
+            universe_id: "test-universe".to_string(),
+            class: class.to_string(),
+            parent_id: None,
+            properties: serde_json::json!({"name": "thing"}),
+            code_hash: None,
+            owner_id: Some("owner".to_string()),
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+            updated_at: "2025-01-01T00:00:00Z".to_string(),
+        };
+        apply(&pool, &create("/rooms/hall", "room")).await.unwrap();
+        apply(&pool, &create("/items/lamp", "item")).await.unwrap();
+
+        let moved = GameLogEntry::MoveObjects {
+            object_ids: vec!["/items/lamp".to_string()],
+            parent_id: Some("/rooms/hall".to_string()),
+            updated_at: "2025-01-01T00:00:01Z".to_string(),
+        };
+        assert_eq!(apply(&pool, &moved).await.unwrap(), 1);
+
+        let update = GameLogEntry::UpdateObject {
+            object_id: "/items/lamp".to_string(),
+            class: "item".to_string(),
+            parent_id: Some("/rooms/hall".to_string()),
+            properties: serde_json::json!({"name": "lamp"}),
+            code_hash: None,
+            updated_at: "2025-01-01T00:00:02Z".to_string(),
+        };
+        assert_eq!(apply(&pool, &update).await.unwrap(), 1);
+
+        let lamp: (String, String, String) = sqlx::query_as(
+            "SELECT parent_id, properties, updated_at FROM objects WHERE id = '/items/lamp'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            lamp,
+            (
+                "/rooms/hall".to_string(),
+                r#"{"name":"lamp"}"#.to_string(),
+                "2025-01-01T00:00:02Z".to_string()
+            )
+        );
+
+        let delete = GameLogEntry::DeleteObject {
+            object_id: "/items/lamp".to_string(),
+        };
+        assert_eq!(apply(&pool, &delete).await.unwrap(), 1);
+        assert_eq!(apply(&pool, &delete).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_account_and_session_entries() {
+        let pool = test_pool().await;
+        let at = |minute: u32| format!("2025-01-01T00:{:02}:00+00:00", minute);
+
+        let create = GameLogEntry::CreateAccount {
+            account_id: "a1".to_string(),
+            username: "hero".to_string(),
+            password_hash: Some("old".to_string()),
+            wallet_address: None,
+            created_at: at(0),
+        };
+        assert_eq!(apply(&pool, &create).await.unwrap(), 1);
+
+        // Only the hash that was verified is replaced
+        let rehash = |old: &str| GameLogEntry::RehashPassword {
+            account_id: "a1".to_string(),
+            old_hash: old.to_string(),
+            new_hash: "new".to_string(),
+        };
+        assert_eq!(apply(&pool, &rehash("stale")).await.unwrap(), 0);
+        assert_eq!(apply(&pool, &rehash("old")).await.unwrap(), 1);
+
+        let link = GameLogEntry::LinkWallet {
+            account_id: "a1".to_string(),
+            wallet_address: "0xabc".to_string(),
+        };
+        assert_eq!(apply(&pool, &link).await.unwrap(), 1);
+        assert_eq!(apply(&pool, &link).await.unwrap(), 0);
+
+        let start = |session_id: &str, created: u32, expires: u32| GameLogEntry::StartSession {
+            session_id: session_id.to_string(),
+            account_id: "a1".to_string(),
+            token_hash: format!("hash-{}", session_id),
+            user_agent: None,
+            ip_address: Some("10.0.0.1".to_string()),
+            created_at: at(created),
+            expires_at: at(expires),
+        };
+        apply(&pool, &start("s1", 0, 5)).await.unwrap();
+        apply(&pool, &start("s2", 1, 30)).await.unwrap();
+        // Starting a session clears out the account's expired ones
+        assert_eq!(apply(&pool, &start("s3", 10, 30)).await.unwrap(), 2);
+
+        let touch = GameLogEntry::TouchSession {
+            session_id: "s2".to_string(),
+            last_seen_at: at(12),
+        };
+        assert_eq!(apply(&pool, &touch).await.unwrap(), 1);
+        let end = GameLogEntry::EndSession {
+            token_hash: "hash-s2".to_string(),
+        };
+        assert_eq!(apply(&pool, &end).await.unwrap(), 1);
+        let revoke = GameLogEntry::RevokeSession {
+            account_id: "other".to_string(),
+            session_id: "s3".to_string(),
+        };
+        assert_eq!(apply(&pool, &revoke).await.unwrap(), 0);
+        let revoke_all = GameLogEntry::RevokeSessions {
+            account_id: "a1".to_string(),
+        };
+        assert_eq!(apply(&pool, &revoke_all).await.unwrap(), 1);
+
+        let account: (String, Option<String>, Option<String>) = sqlx::query_as(
+            "SELECT password_hash, salt, wallet_address FROM accounts WHERE id = 'a1'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            account,
+            ("new".to_string(), None, Some("0xabc".to_string()))
+        );
+
+        let issue = |nonce: &str, created: u32, expires: u32| GameLogEntry::IssueNonce {
+            nonce: nonce.to_string(),
+            address: "0xabc".to_string(),
+            created_at: at(created),
+            expires_at: at(expires),
+        };
+        let use_nonce = |nonce: &str, address: &str, used: u32| GameLogEntry::UseNonce {
+            nonce: nonce.to_string(),
+            address: address.to_string(),
+            used_at: at(used),
+        };
+        apply(&pool, &issue("n1", 0, 5)).await.unwrap();
+        apply(&pool, &issue("n2", 1, 10)).await.unwrap();
+        assert_eq!(apply(&pool, &use_nonce("n1", "0xabc", 6)).await.unwrap(), 0);
+        assert_eq!(apply(&pool, &use_nonce("n2", "0xdef", 2)).await.unwrap(), 0);
+        assert_eq!(apply(&pool, &use_nonce("n2", "0xabc", 2)).await.unwrap(), 1);
+        assert_eq!(apply(&pool, &use_nonce("n2", "0xabc", 2)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_channel_timer_and_combat_entries() {
+        let pool = test_pool().await;
+        create_account(&pool, "owner").await;
+        sqlx::query("INSERT INTO universes (id, name, owner_id) VALUES ('u1', 'Test', 'owner')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let at = |second: u32| format!("2025-01-01T00:00:{:02}+00:00", second);
+
+        let record = |n: u32| GameLogEntry::RecordChannelMessage {
+            message_id: format!("m{}", n),
+            channel: "ooc".to_string(),
+            universe_id: "u1".to_string(),
+            sender_id: "p1".to_string(),
+            sender_name: "Hero".to_string(),
+            text: format!("message {}", n),
+            created_at: at(n),
+            keep: 2,
+        };
+        for n in 1..=3 {
+            apply(&pool, &record(n)).await.unwrap();
+        }
+        let kept: Vec<(String,)> = sqlx::query_as("SELECT id FROM channel_messages ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(kept, vec![("m2".to_string(),), ("m3".to_string(),)]);
+
+        let set_timer = GameLogEntry::SetTimer {
+            timer_id: "t1".to_string(),
+            universe_id: "u1".to_string(),
+            object_id: "/items/clock".to_string(),
+            method: "on_tick".to_string(),
+            fire_at: 1000,
+            args: None,
+        };
+        assert_eq!(apply(&pool, &set_timer).await.unwrap(), 1);
+        let delete_timer = GameLogEntry::DeleteTimer {
+            timer_id: "t1".to_string(),
+        };
+        assert_eq!(apply(&pool, &delete_timer).await.unwrap(), 1);
+
+        let set_state = GameLogEntry::SetCombatState {
+            entity_id: "/npcs/troll".to_string(),
+            universe_id: "u1".to_string(),
+            hp: 20,
+            max_hp: 30,
+            armor_class: 12,
+            attack_bonus: 2,
+        };
+        apply(&pool, &set_state).await.unwrap();
+        let effect = |id: &str, effect_type: &str| EffectRecord {
+            effect_id: id.to_string(),
+            effect_type: effect_type.to_string(),
+            remaining_ticks: 3,
+            magnitude: 2,
+            damage_type: Some("Poison".to_string()),
+            source_id: None,
+        };
+        let add = GameLogEntry::AddEffect {
+            entity_id: "/npcs/troll".to_string(),
+            effect: effect("e1", "poisoned"),
+        };
+        apply(&pool, &add).await.unwrap();
+        let set = GameLogEntry::SetEffects {
+            entity_id: "/npcs/troll".to_string(),
+            effects: vec![effect("e2", "poisoned"), effect("e3", "stunned")],
+        };
+        assert_eq!(apply(&pool, &set).await.unwrap(), 3);
+        let remove = GameLogEntry::RemoveEffect {
+            entity_id: "/npcs/troll".to_string(),
+            effect_type: "stunned".to_string(),
+        };
+        assert_eq!(apply(&pool, &remove).await.unwrap(), 1);
+
+        let effects: Vec<(String,)> = sqlx::query_as("SELECT id FROM active_effects")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(effects, vec![("e2".to_string(),)]);
+
+        let delete_state = GameLogEntry::DeleteCombatState {
+            entity_id: "/npcs/troll".to_string(),
+        };
+        assert_eq!(apply(&pool, &delete_state).await.unwrap(), 1);
+    }
+}
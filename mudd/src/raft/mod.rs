@@ -12,7 +12,9 @@
 //! being applied to the database.
 
 pub mod config;
+pub mod log_entry;
 pub mod network;
+pub mod rpc;
 pub mod snapshot;
 pub mod state_machine;
 pub mod storage;
@@ -25,12 +27,13 @@ pub mod writer;
 
 // Re-exports
 pub use config::{create_openraft_config, RaftNodeConfig};
-pub use network::{NetworkConfig, RaftNetworkFactoryImpl};
+pub use log_entry::{EffectRecord, GameLogEntry, ItemTransfer, LOG_ENTRY_VERSION};
+pub use network::{NetworkConfig, RaftNetworkFactoryImpl, SharedNetworkConfig};
 pub use snapshot::SnapshotStore;
 pub use state_machine::SnapshotData;
 pub use storage::CombinedStorage;
-pub use types::{NodeId, Request, Response, TypeConfig};
-pub use writer::RaftWriter;
+pub use types::{NodeId, Request, Response, Statement, TypeConfig};
+pub use writer::{write_entry, RaftWriter};
 
 use openraft::storage::Adaptor;
 use openraft::Raft;
@@ -47,17 +50,18 @@ pub type GameRaft = Raft<TypeConfig>;
 ///
 /// Returns the Raft instance and a clone of the pool for read operations.
 /// Writes must go through the Raft instance; reads can go directly to the pool.
+/// The network config is shared so membership changes can add or remove peers.
 pub async fn create_raft_node(
     node_id: NodeId,
     pool: SqlitePool,
-    network_config: NetworkConfig,
+    network_config: SharedNetworkConfig,
 ) -> anyhow::Result<(GameRaft, SqlitePool)> {
     info!("Creating Raft node {}", node_id);
 
     let config = create_openraft_config();
     let read_pool = pool.clone();
     let storage = CombinedStorage::new(pool).await?;
-    let network = RaftNetworkFactoryImpl::new(network_config);
+    let network = RaftNetworkFactoryImpl::with_shared(network_config);
 
     // Wrap storage with Adaptor to satisfy sealed traits
     let (log_store, state_machine) = Adaptor::new(storage);
@@ -81,8 +85,13 @@ mod tests {
             "INSERT INTO test VALUES (?)",
             vec![serde_json::json!("hello")],
         );
-        assert_eq!(req.sql, "INSERT INTO test VALUES (?)");
-        assert_eq!(req.params.len(), 1);
+        assert_eq!(
+            req.entry,
+            GameLogEntry::statement(
+                "INSERT INTO test VALUES (?)",
+                vec![serde_json::json!("hello")]
+            )
+        );
     }
 
     #[test]
@@ -121,4 +130,124 @@ mod tests {
         let storage = CombinedStorage::new(pool).await;
         assert!(storage.is_ok());
     }
+
+    /// Collect non-test source of every .rs file under `dir`
+    fn collect_sources(dir: &std::path::Path, out: &mut Vec<(String, String)>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                collect_sources(&path, out);
+            } else if path.extension().is_some_and(|e| e == "rs") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                let source = match source.find("#[cfg(test)]\nmod tests") {
+                    Some(idx) => source[..idx].to_string(),
+                    None => source,
+                };
+                out.push((path.display().to_string(), source));
+            }
+        }
+    }
+
+    /// Offsets of direct SQL writes in `source`
+    ///
+    /// Catches write statements handed to `sqlx::query*` as a literal, a
+    /// `format!` or a constant, raw SQL, and any `.execute(` against a
+    /// pool, connection or transaction.
+    fn direct_writes(source: &str) -> Vec<usize> {
+        const WRITE: &str = r#"r?#*"\s*(?:INSERT|UPDATE|DELETE|REPLACE)\b"#;
+        const QUERY: &str = r"sqlx::query(?:_as|_scalar)?(?:::<[^>]*>)?\(\s*";
+        let mut patterns = vec![
+            format!(r"(?i){}{}", QUERY, WRITE),
+            format!(r"(?i){}&?\s*format!\(\s*{}", QUERY, WRITE),
+            r"sqlx::raw_sql\(".to_string(),
+            r"\.execute(?:_many)?\(\s*(?:&\s*)?(?:mut\s+)?\*?\s*(?:\w+\.)*(?:pool|tx|txn|conn|transaction)\b"
+                .to_string(),
+        ];
+        let constant = regex::Regex::new(&format!(
+            r"(?i)(?:const|static)\s+(\w+)\s*:\s*&(?:'static\s+)?str\s*=\s*{}",
+            WRITE
+        ))
+        .unwrap();
+        for c in constant.captures_iter(source) {
+            patterns.push(format!(r"{}&?\s*{}\b", QUERY, &c[1]));
+        }
+
+        let mut offsets: Vec<usize> = patterns
+            .iter()
+            .flat_map(|p| {
+                let re = regex::Regex::new(p).unwrap();
+                re.find_iter(source).map(|m| m.start()).collect::<Vec<_>>()
+            })
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
+
+    #[test]
+    fn test_direct_writes_patterns() {
+        let writes = [
+            r#"sqlx::query("UPDATE sessions SET x = ?")"#,
+            r#"sqlx::query_as::<_, (i64,)>(r"INSERT INTO t VALUES (1) RETURNING id")"#,
+            r#"sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))"#,
+            "const SQL: &str = \"REPLACE INTO t VALUES (?)\";\nsqlx::query(SQL)",
+            r#"sqlx::raw_sql("anything")"#,
+            "sqlx::query(sql).execute(&self.pool)",
+            "query.execute(&mut *tx)",
+            "query.execute(state.db.pool())",
+        ];
+        for source in writes {
+            assert!(!direct_writes(source).is_empty(), "missed: {}", source);
+        }
+
+        let reads_and_raft = [
+            r#"sqlx::query_as("SELECT * FROM sessions").fetch_all(&self.pool)"#,
+            r#"Statement::new("UPDATE sessions SET x = ?", params)"#,
+            r#"self.raft_writer.execute("DELETE FROM images WHERE hash = ?", params)"#,
+        ];
+        for source in reads_and_raft {
+            assert!(direct_writes(source).is_empty(), "flagged: {}", source);
+        }
+    }
+
+    /// Every mutating statement must be applied by the Raft state machine.
+    /// Modules build GameLogEntry values instead of writing to the pool.
+    #[test]
+    fn test_no_direct_sql_writes_outside_raft() {
+        const ALLOWED: &[&str] = &[
+            "src/raft/log_entry.rs",
+            "src/raft/state_machine.rs",
+            "src/raft/storage.rs",
+            "src/raft/snapshot.rs",
+            // Schema migrations run before Raft starts
+            "src/db/mod.rs",
+        ];
+
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut sources = Vec::new();
+        collect_sources(&root.join("src"), &mut sources);
+        assert!(!sources.is_empty());
+
+        let mut offenders = Vec::new();
+        for (path, source) in &sources {
+            let relative = path
+                .strip_prefix(root.to_str().unwrap())
+                .unwrap_or(path)
+                .trim_start_matches('/')
+                .replace('\\', "/");
+            if ALLOWED.contains(&relative.as_str()) {
+                continue;
+            }
+            for offset in direct_writes(source) {
+                let line = source[..offset].lines().count();
+                offenders.push(format!("{}:{}", relative, line));
+            }
+        }
+
+        assert!(
+            offenders.is_empty(),
+            "direct SQL writes outside the Raft state machine: {:?}",
+            offenders
+        );
+    }
 }
@@ -15,6 +15,7 @@ use openraft::raft::{
     VoteRequest, VoteResponse,
 };
 use openraft::BasicNode;
+use parking_lot::RwLock;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error, warn};
@@ -85,34 +86,64 @@ impl NetworkConfig {
     pub fn get_addr(&self, node_id: NodeId) -> Option<&String> {
         self.nodes.get(&node_id)
     }
+
+    /// Add or update a node's address
+    pub fn set_addr(&mut self, node_id: NodeId, addr: impl Into<String>) {
+        self.nodes.insert(node_id, addr.into());
+    }
+
+    /// Remove a node's address
+    pub fn remove_addr(&mut self, node_id: NodeId) -> Option<String> {
+        self.nodes.remove(&node_id)
+    }
 }
 
+/// Network configuration shared between the Raft network and membership changes
+pub type SharedNetworkConfig = Arc<RwLock<NetworkConfig>>;
+
 /// Factory for creating network connections to peers
 #[derive(Debug, Clone)]
 pub struct RaftNetworkFactoryImpl {
-    config: Arc<NetworkConfig>,
+    config: SharedNetworkConfig,
     client: Client,
 }
 
 impl RaftNetworkFactoryImpl {
     /// Create a new network factory
     pub fn new(config: NetworkConfig) -> Self {
+        Self::with_shared(Arc::new(RwLock::new(config)))
+    }
+
+    /// Create a network factory over a config that may change at runtime
+    pub fn with_shared(config: SharedNetworkConfig) -> Self {
+        let timeout = config.read().timeout;
         let client = Client::builder()
-            .timeout(config.timeout)
+            .timeout(timeout)
             .build()
             .expect("failed to create HTTP client");
 
-        Self {
-            config: Arc::new(config),
-            client,
-        }
+        Self { config, client }
+    }
+
+    /// Get the shared network configuration
+    pub fn config(&self) -> SharedNetworkConfig {
+        Arc::clone(&self.config)
     }
 }
 
 impl RaftNetworkFactory<TypeConfig> for RaftNetworkFactoryImpl {
     type Network = RaftNetworkImpl;
 
-    async fn new_client(&mut self, target: NodeId, _node: &BasicNode) -> Self::Network {
+    async fn new_client(&mut self, target: NodeId, node: &BasicNode) -> Self::Network {
+        // Membership entries carry node addresses. They win over what we
+        // knew, so a node re-added on a new address is reachable again.
+        if !node.addr.is_empty() {
+            let mut config = self.config.write();
+            if config.get_addr(target) != Some(&node.addr) {
+                config.set_addr(target, node.addr.clone());
+            }
+        }
+
         RaftNetworkImpl {
             target,
             config: Arc::clone(&self.config),
@@ -125,7 +156,7 @@ impl RaftNetworkFactory<TypeConfig> for RaftNetworkFactoryImpl {
 #[derive(Debug, Clone)]
 pub struct RaftNetworkImpl {
     target: NodeId,
-    config: Arc<NetworkConfig>,
+    config: SharedNetworkConfig,
     client: Client,
 }
 
@@ -136,12 +167,17 @@ impl RaftNetworkImpl {
         endpoint: &str,
         request: &T,
     ) -> Result<R, Unreachable> {
-        let addr = self.config.get_addr(self.target).ok_or_else(|| {
-            Unreachable::new(&io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("node {} not found in config", self.target),
-            ))
-        })?;
+        let addr = self
+            .config
+            .read()
+            .get_addr(self.target)
+            .cloned()
+            .ok_or_else(|| {
+                Unreachable::new(&io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("node {} not found in config", self.target),
+                ))
+            })?;
 
         let url = format!("http://{}/raft/{}", addr, endpoint);
         debug!("Sending RPC to {}: {}", self.target, url);
@@ -233,4 +269,36 @@ mod tests {
         let config = NetworkConfig::default();
         let _factory = RaftNetworkFactoryImpl::new(config);
     }
+
+    #[test]
+    fn test_set_and_remove_addr() {
+        let mut config = NetworkConfig::default();
+        config.set_addr(4, "127.0.0.1:9004");
+        assert_eq!(config.get_addr(4), Some(&"127.0.0.1:9004".to_string()));
+        assert_eq!(config.remove_addr(4), Some("127.0.0.1:9004".to_string()));
+        assert_eq!(config.get_addr(4), None);
+    }
+
+    #[tokio::test]
+    async fn test_new_client_learns_node_addr() {
+        let mut factory = RaftNetworkFactoryImpl::new(NetworkConfig::default());
+        let shared = factory.config();
+
+        factory
+            .new_client(5, &BasicNode::new("127.0.0.1:9005"))
+            .await;
+        assert_eq!(
+            shared.read().get_addr(5),
+            Some(&"127.0.0.1:9005".to_string())
+        );
+
+        // Re-added on a new address
+        factory
+            .new_client(5, &BasicNode::new("127.0.0.1:9105"))
+            .await;
+        assert_eq!(
+            shared.read().get_addr(5),
+            Some(&"127.0.0.1:9105".to_string())
+        );
+    }
 }
@@ -0,0 +1,81 @@
+//! Raft RPC listener for inter-node communication
+//!
+//! Serves the HTTP/JSON endpoints that `RaftNetworkImpl` posts to and
+//! hands each request to the local Raft instance.
+
+use std::fmt::Display;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::post, Json, Router};
+use openraft::raft::{AppendEntriesRequest, InstallSnapshotRequest, VoteRequest};
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::types::{NodeId, TypeConfig};
+use super::GameRaft;
+
+/// Build the router for Raft RPC endpoints
+pub fn router(raft: GameRaft) -> Router {
+    Router::new()
+        .route("/raft/append_entries", post(append_entries))
+        .route("/raft/install_snapshot", post(install_snapshot))
+        .route("/raft/vote", post(vote))
+        .with_state(raft)
+}
+
+/// Serve Raft RPCs on the given listener until shutdown is signalled
+pub async fn serve(
+    listener: TcpListener,
+    raft: GameRaft,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    info!("Raft RPC listening on {}", listener.local_addr()?);
+
+    axum::serve(listener, router(raft))
+        .with_graceful_shutdown(async move {
+            shutdown_rx.changed().await.ok();
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Convert a Raft result into an HTTP response
+///
+/// Successful responses are serialized as-is so the client can decode the
+/// openraft response type directly. Errors become a 500, which the client
+/// treats as the peer being unreachable.
+fn rpc_response<T: Serialize, E: Display>(
+    endpoint: &str,
+    result: Result<T, E>,
+) -> impl IntoResponse {
+    match result {
+        Ok(resp) => Json(resp).into_response(),
+        Err(e) => {
+            warn!("Raft {} failed: {}", endpoint, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+async fn append_entries(
+    State(raft): State<GameRaft>,
+    Json(req): Json<AppendEntriesRequest<TypeConfig>>,
+) -> impl IntoResponse {
+    rpc_response("append_entries", raft.append_entries(req).await)
+}
+
+async fn install_snapshot(
+    State(raft): State<GameRaft>,
+    Json(req): Json<InstallSnapshotRequest<TypeConfig>>,
+) -> impl IntoResponse {
+    rpc_response("install_snapshot", raft.install_snapshot(req).await)
+}
+
+async fn vote(
+    State(raft): State<GameRaft>,
+    Json(req): Json<VoteRequest<NodeId>>,
+) -> impl IntoResponse {
+    rpc_response("vote", raft.vote(req).await)
+}
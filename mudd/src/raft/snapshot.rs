@@ -167,7 +167,7 @@ impl SnapshotStore {
         }
 
         // Sort by modification time, newest first
-        snapshots.sort_by(|a, b| b.1.cmp(&a.1));
+        snapshots.sort_by_key(|s| std::cmp::Reverse(s.1));
 
         // Delete old snapshots beyond the keep count
         for (path, _) in snapshots.into_iter().skip(keep) {
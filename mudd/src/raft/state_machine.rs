@@ -9,9 +9,9 @@ use openraft::{BasicNode, LogId, StorageError, StorageIOError, StoredMembership}
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePool;
 use tokio::sync::RwLock;
-use tracing::{debug, error, warn};
+use tracing::{debug, warn};
 
-use super::types::{NodeId, Request, Response};
+use super::types::NodeId;
 
 /// Snapshot data format
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,49 +58,6 @@ impl GameStateMachine {
         }
     }
 
-    /// Execute a SQL statement against the database
-    #[allow(dead_code)]
-    async fn execute_sql(&self, request: &Request) -> Response {
-        debug!("Executing SQL: {}", request.sql);
-
-        // Build the query with parameters
-        let mut query = sqlx::query(&request.sql);
-
-        // Bind JSON parameters
-        for param in &request.params {
-            query = match param {
-                serde_json::Value::Null => query.bind(Option::<String>::None),
-                serde_json::Value::Bool(b) => query.bind(*b),
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        query.bind(i)
-                    } else if let Some(f) = n.as_f64() {
-                        query.bind(f)
-                    } else {
-                        query.bind(n.to_string())
-                    }
-                }
-                serde_json::Value::String(s) => query.bind(s.clone()),
-                serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
-                    // Serialize complex types as JSON strings
-                    query.bind(param.to_string())
-                }
-            };
-        }
-
-        match query.execute(&self.pool).await {
-            Ok(result) => {
-                let rows = result.rows_affected();
-                debug!("SQL executed successfully, {} rows affected", rows);
-                Response::ok(rows)
-            }
-            Err(e) => {
-                error!("SQL execution failed: {}", e);
-                Response::error(e.to_string())
-            }
-        }
-    }
-
     /// Get the database pool
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
@@ -177,64 +134,6 @@ mod tests {
             .unwrap();
     }
 
-    #[tokio::test]
-    async fn test_execute_sql_insert() {
-        let pool = test_pool().await;
-        setup_test_table(&pool).await;
-        let sm = GameStateMachine::new(pool);
-
-        let request = Request::new(
-            "INSERT INTO test (id, value) VALUES (?, ?)",
-            vec![serde_json::json!(1), serde_json::json!("hello")],
-        );
-
-        let response = sm.execute_sql(&request).await;
-        assert!(response.success);
-        assert_eq!(response.rows_affected, 1);
-    }
-
-    #[tokio::test]
-    async fn test_execute_sql_update() {
-        let pool = test_pool().await;
-        setup_test_table(&pool).await;
-        let sm = GameStateMachine::new(pool.clone());
-
-        // Insert first
-        sqlx::query("INSERT INTO test (id, value) VALUES (1, 'old')")
-            .execute(&pool)
-            .await
-            .unwrap();
-
-        // Update via state machine
-        let request = Request::new(
-            "UPDATE test SET value = ? WHERE id = ?",
-            vec![serde_json::json!("new"), serde_json::json!(1)],
-        );
-
-        let response = sm.execute_sql(&request).await;
-        assert!(response.success);
-        assert_eq!(response.rows_affected, 1);
-
-        // Verify
-        let row: (String,) = sqlx::query_as("SELECT value FROM test WHERE id = 1")
-            .fetch_one(&pool)
-            .await
-            .unwrap();
-        assert_eq!(row.0, "new");
-    }
-
-    #[tokio::test]
-    async fn test_execute_sql_error() {
-        let pool = test_pool().await;
-        let sm = GameStateMachine::new(pool);
-
-        // Invalid SQL
-        let request = Request::simple("SELECT * FROM nonexistent_table");
-        let response = sm.execute_sql(&request).await;
-        assert!(!response.success);
-        assert!(response.error.is_some());
-    }
-
     // Note: apply_entries test removed - state machine functionality is now
     // tested via CombinedStorage in storage.rs
 
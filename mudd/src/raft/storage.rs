@@ -18,6 +18,7 @@ use sqlx::sqlite::SqlitePool;
 use tokio::sync::RwLock;
 use tracing::{debug, error};
 
+use super::log_entry::ApplyError;
 use super::state_machine::SnapshotData;
 use super::types::{NodeId, Request, Response, TypeConfig};
 
@@ -114,19 +115,31 @@ impl CombinedStorage {
             }
         }
 
+        if let Some((value,)) =
+            sqlx::query_as::<_, (String,)>("SELECT value FROM raft_meta WHERE key = 'membership'")
+                .fetch_optional(&*pool)
+                .await
+                .map_err(|e| StorageIOError::read(&io::Error::other(e)))?
+        {
+            if let Ok(membership) = serde_json::from_str(&value) {
+                *self.membership.write().await = membership;
+            }
+        }
+
         Ok(())
     }
 
     fn row_to_entry(
         log_index: i64,
         term: i64,
+        node_id: i64,
         entry_type: &str,
         payload: Option<&str>,
     ) -> Result<Entry<TypeConfig>, StorageError<NodeId>> {
         let log_id = LogId {
             leader_id: openraft::LeaderId {
                 term: term as u64,
-                node_id: 0,
+                node_id: node_id as u64,
             },
             index: log_index as u64,
         };
@@ -158,47 +171,35 @@ impl CombinedStorage {
         })
     }
 
-    async fn execute_sql(&self, request: &Request) -> Response {
-        use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    async fn save_membership(&self) -> Result<(), StorageError<NodeId>> {
+        let membership = self.membership.read().await.clone();
+        let json = serde_json::to_string(&membership)
+            .map_err(|e| StorageIOError::write(&io::Error::other(e)))?;
+
+        let pool = self.pool.read().await;
+        sqlx::query("INSERT OR REPLACE INTO raft_meta (key, value) VALUES ('membership', ?)")
+            .bind(&json)
+            .execute(&*pool)
+            .await
+            .map_err(|e| StorageIOError::write(&io::Error::other(e)))?;
 
-        debug!("Executing SQL: {}", request.sql);
+        Ok(())
+    }
 
+    /// Apply a request in its own transaction; any failure rolls back the whole entry
+    async fn execute_sql(&self, request: &Request, index: u64) -> Response {
         let pool = self.pool.read().await;
 
-        let mut query = sqlx::query(&request.sql);
-        for param in &request.params {
-            query = match param {
-                serde_json::Value::Null => query.bind(Option::<String>::None),
-                serde_json::Value::Bool(b) => query.bind(*b),
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        query.bind(i)
-                    } else if let Some(f) = n.as_f64() {
-                        query.bind(f)
-                    } else {
-                        query.bind(n.to_string())
-                    }
-                }
-                serde_json::Value::String(s) => {
-                    // Handle "blob:" prefix for binary data
-                    if let Some(b64_data) = s.strip_prefix("blob:") {
-                        match BASE64.decode(b64_data) {
-                            Ok(bytes) => query.bind(bytes),
-                            Err(e) => {
-                                error!("Failed to decode base64 blob: {}", e);
-                                query.bind(s.clone())
-                            }
-                        }
-                    } else {
-                        query.bind(s.clone())
-                    }
-                }
-                _ => query.bind(param.to_string()),
-            };
+        let result = async {
+            let mut tx = pool.begin().await?;
+            let rows_affected = request.apply(&mut tx, index).await?;
+            tx.commit().await?;
+            Ok::<_, ApplyError>(rows_affected)
         }
+        .await;
 
-        match query.execute(&*pool).await {
-            Ok(result) => {
+        match result {
+            Ok(rows_affected) => {
                 // Checkpoint WAL to ensure read visibility across all connections
                 // This is needed because reads may use different connections than writes
                 if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
@@ -207,10 +208,10 @@ impl CombinedStorage {
                 {
                     debug!("WAL checkpoint failed (non-fatal): {}", e);
                 }
-                Response::ok(result.rows_affected())
+                Response::ok(rows_affected)
             }
             Err(e) => {
-                error!("SQL execution failed: {}", e);
+                error!("Failed to apply {} entry: {}", request.entry.kind(), e);
                 Response::error(e.to_string())
             }
         }
@@ -238,8 +239,8 @@ impl RaftLogReader<TypeConfig> for CombinedStorage {
 
         let pool = self.pool.read().await;
 
-        let rows: Vec<(i64, i64, String, Option<String>)> = sqlx::query_as(
-            "SELECT log_index, term, entry_type, payload FROM raft_log
+        let rows: Vec<(i64, i64, i64, String, Option<String>)> = sqlx::query_as(
+            "SELECT log_index, term, node_id, entry_type, payload FROM raft_log
              WHERE log_index >= ? AND log_index < ? ORDER BY log_index",
         )
         .bind(start)
@@ -249,8 +250,8 @@ impl RaftLogReader<TypeConfig> for CombinedStorage {
         .map_err(|e| StorageIOError::read_logs(&io::Error::other(e)))?;
 
         rows.into_iter()
-            .map(|(idx, term, etype, payload)| {
-                Self::row_to_entry(idx, term, &etype, payload.as_deref())
+            .map(|(idx, term, node_id, etype, payload)| {
+                Self::row_to_entry(idx, term, node_id, &etype, payload.as_deref())
             })
             .collect()
     }
@@ -320,16 +321,17 @@ impl RaftStorage<TypeConfig> for CombinedStorage {
         let last_purged = *self.last_purged.read().await;
         let pool = self.pool.read().await;
 
-        let last_log: Option<(i64, i64)> =
-            sqlx::query_as("SELECT log_index, term FROM raft_log ORDER BY log_index DESC LIMIT 1")
-                .fetch_optional(&*pool)
-                .await
-                .map_err(|e| StorageIOError::read_logs(&io::Error::other(e)))?;
+        let last_log: Option<(i64, i64, i64)> = sqlx::query_as(
+            "SELECT log_index, term, node_id FROM raft_log ORDER BY log_index DESC LIMIT 1",
+        )
+        .fetch_optional(&*pool)
+        .await
+        .map_err(|e| StorageIOError::read_logs(&io::Error::other(e)))?;
 
-        let last_log_id = last_log.map(|(index, term)| LogId {
+        let last_log_id = last_log.map(|(index, term, node_id)| LogId {
             leader_id: openraft::LeaderId {
                 term: term as u64,
-                node_id: 0,
+                node_id: node_id as u64,
             },
             index: index as u64,
         });
@@ -403,10 +405,11 @@ impl RaftStorage<TypeConfig> for CombinedStorage {
             };
 
             sqlx::query(
-                "INSERT OR REPLACE INTO raft_log (log_index, term, entry_type, payload) VALUES (?, ?, ?, ?)",
+                "INSERT OR REPLACE INTO raft_log (log_index, term, node_id, entry_type, payload) VALUES (?, ?, ?, ?, ?)",
             )
             .bind(entry.log_id.index as i64)
             .bind(entry.log_id.leader_id.term as i64)
+            .bind(entry.log_id.leader_id.node_id as i64)
             .bind(entry_type)
             .bind(payload)
             .execute(&mut *tx)
@@ -476,7 +479,7 @@ impl RaftStorage<TypeConfig> for CombinedStorage {
             match &entry.payload {
                 EntryPayload::Blank => results.push(Response::ok(0)),
                 EntryPayload::Normal(request) => {
-                    let response = self.execute_sql(request).await;
+                    let response = self.execute_sql(request, entry.log_id.index).await;
                     results.push(response);
                 }
                 EntryPayload::Membership(membership) => {
@@ -499,6 +502,14 @@ impl RaftStorage<TypeConfig> for CombinedStorage {
                 .map_err(|e| StorageIOError::write(&io::Error::other(e)))?;
         }
 
+        // Persist membership so a restarted node knows its cluster
+        if entries
+            .iter()
+            .any(|e| matches!(e.payload, EntryPayload::Membership(_)))
+        {
+            self.save_membership().await?;
+        }
+
         Ok(results)
     }
 
@@ -574,6 +585,7 @@ impl RaftStorage<TypeConfig> for CombinedStorage {
         *self.last_applied.write().await = snapshot_data.last_applied_log;
         *self.membership.write().await = snapshot_data.last_membership;
         *self.current_snapshot.write().await = Some((meta.clone(), data));
+        self.save_membership().await?;
 
         debug!("Installed snapshot at {:?}", meta.last_log_id);
         Ok(())
@@ -595,6 +607,14 @@ mod tests {
     use super::*;
     use crate::db::test_utils::test_pool;
 
+    /// Create a test-specific table for apply tests
+    async fn setup_test_table(pool: &SqlitePool) {
+        sqlx::query("CREATE TABLE IF NOT EXISTS test (id INTEGER PRIMARY KEY, value TEXT)")
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_storage_creation() {
         let pool = test_pool().await;
@@ -642,4 +662,84 @@ mod tests {
         let read = storage.try_get_log_entries(1..2).await.unwrap();
         assert_eq!(read.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_legacy_log_payload_still_reads() {
+        let pool = test_pool().await;
+        sqlx::query(
+            "INSERT INTO raft_log (log_index, term, node_id, entry_type, payload) VALUES (1, 1, 1, 'normal', ?)",
+        )
+        .bind(r#"{"sql": "SELECT 1", "params": []}"#)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut storage = CombinedStorage::new(pool).await.unwrap();
+        let read = storage.try_get_log_entries(1..2).await.unwrap();
+        match &read[0].payload {
+            EntryPayload::Normal(request) => {
+                assert_eq!(request.version, 0);
+                assert_eq!(request.entry.kind(), "mutations");
+            }
+            _ => panic!("expected normal entry"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_sql_insert() {
+        let pool = test_pool().await;
+        setup_test_table(&pool).await;
+        let storage = CombinedStorage::new(pool).await.unwrap();
+
+        let request = Request::new(
+            "INSERT INTO test (id, value) VALUES (?, ?)",
+            vec![serde_json::json!(1), serde_json::json!("hello")],
+        );
+
+        let response = storage.execute_sql(&request, 1).await;
+        assert!(response.success);
+        assert_eq!(response.rows_affected, 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_sql_update() {
+        let pool = test_pool().await;
+        setup_test_table(&pool).await;
+        let storage = CombinedStorage::new(pool.clone()).await.unwrap();
+
+        // Insert first
+        sqlx::query("INSERT INTO test (id, value) VALUES (1, 'old')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Update through the apply path
+        let request = Request::new(
+            "UPDATE test SET value = ? WHERE id = ?",
+            vec![serde_json::json!("new"), serde_json::json!(1)],
+        );
+
+        let response = storage.execute_sql(&request, 1).await;
+        assert!(response.success);
+        assert_eq!(response.rows_affected, 1);
+
+        // Verify
+        let row: (String,) = sqlx::query_as("SELECT value FROM test WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row.0, "new");
+    }
+
+    #[tokio::test]
+    async fn test_execute_sql_error() {
+        let pool = test_pool().await;
+        let storage = CombinedStorage::new(pool).await.unwrap();
+
+        // Invalid SQL
+        let request = Request::simple("SELECT * FROM nonexistent_table");
+        let response = storage.execute_sql(&request, 1).await;
+        assert!(!response.success);
+        assert!(response.error.is_some());
+    }
 }
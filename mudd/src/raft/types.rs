@@ -7,6 +7,9 @@ use std::io::Cursor;
 
 use openraft::{BasicNode, Entry, RaftTypeConfig};
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnection;
+
+use super::log_entry::{ApplyError, GameLogEntry, LOG_ENTRY_VERSION};
 
 /// Node ID type for Raft cluster
 pub type NodeId = u64;
@@ -34,32 +37,112 @@ impl RaftTypeConfig for TypeConfig {
     type Responder = openraft::impls::OneshotResponder<TypeConfig>;
 }
 
-/// Application request - SQL command to replicate
-///
-/// All non-deterministic values (UUIDs, timestamps) must be
-/// pre-computed by the leader before replication.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Request {
+/// A single SQL statement with its bound parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Statement {
     /// SQL statement to execute
     pub sql: String,
     /// Bound parameters as JSON values
     pub params: Vec<serde_json::Value>,
 }
 
-impl Request {
-    /// Create a new request
+impl Statement {
+    /// Create a new statement
     pub fn new(sql: impl Into<String>, params: Vec<serde_json::Value>) -> Self {
         Self {
             sql: sql.into(),
             params,
         }
     }
+}
+
+/// Application request - a versioned, typed log entry
+///
+/// All non-deterministic values (UUIDs, timestamps) must be
+/// pre-computed by the leader before replication.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RawRequest")]
+pub struct Request {
+    /// Entry format version (0 = legacy raw SQL)
+    pub version: u32,
+    /// The change to apply
+    pub entry: GameLogEntry,
+}
+
+impl Request {
+    /// Wrap a log entry in the current format version
+    pub fn entry(entry: GameLogEntry) -> Self {
+        Self {
+            version: LOG_ENTRY_VERSION,
+            entry,
+        }
+    }
+
+    /// Create a new single-statement request
+    pub fn new(sql: impl Into<String>, params: Vec<serde_json::Value>) -> Self {
+        Self::entry(GameLogEntry::statement(sql, params))
+    }
 
     /// Create a simple request with no parameters
     pub fn simple(sql: impl Into<String>) -> Self {
-        Self {
-            sql: sql.into(),
-            params: vec![],
+        Self::new(sql, vec![])
+    }
+
+    /// Create an atomic batch request
+    pub fn batch(statements: Vec<Statement>) -> Self {
+        Self::entry(GameLogEntry::Mutations {
+            universe_id: None,
+            statements,
+        })
+    }
+
+    /// Apply this request within an open transaction
+    ///
+    /// `index` is the request's position in the Raft log; entries from
+    /// older versions derive the fields they lack from it.
+    pub async fn apply(&self, conn: &mut SqliteConnection, index: u64) -> Result<u64, ApplyError> {
+        if self.version > LOG_ENTRY_VERSION {
+            return Err(ApplyError::UnsupportedVersion(self.version));
+        }
+        if self.version < 2 {
+            return self.entry.clone().upgrade_v1(index).apply(conn).await;
+        }
+        self.entry.apply(conn).await
+    }
+}
+
+/// Every request format that has ever been written to the log
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRequest {
+    /// Typed entry (version >= 1)
+    Versioned {
+        version: u32,
+        entry: Box<GameLogEntry>,
+    },
+    /// Legacy single statement
+    Statement(Statement),
+    /// Legacy batch of statements
+    Batch(Vec<Statement>),
+}
+
+impl From<RawRequest> for Request {
+    fn from(raw: RawRequest) -> Self {
+        let legacy = |statements| Self {
+            version: 0,
+            entry: GameLogEntry::Mutations {
+                universe_id: None,
+                statements,
+            },
+        };
+
+        match raw {
+            RawRequest::Versioned { version, entry } => Self {
+                version,
+                entry: *entry,
+            },
+            RawRequest::Statement(stmt) => legacy(vec![stmt]),
+            RawRequest::Batch(stmts) => legacy(stmts),
         }
     }
 }
@@ -105,15 +188,53 @@ mod tests {
             "INSERT INTO test VALUES (?)",
             vec![serde_json::json!("hello")],
         );
-        assert_eq!(req.sql, "INSERT INTO test VALUES (?)");
-        assert_eq!(req.params.len(), 1);
+        assert_eq!(req.version, LOG_ENTRY_VERSION);
+        match req.entry {
+            GameLogEntry::Mutations { statements, .. } => {
+                assert_eq!(statements.len(), 1);
+                assert_eq!(statements[0].sql, "INSERT INTO test VALUES (?)");
+                assert_eq!(statements[0].params.len(), 1);
+            }
+            other => panic!("unexpected entry {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_request_batch() {
+        let req = Request::batch(vec![
+            Statement::new("DELETE FROM a", vec![]),
+            Statement::new("DELETE FROM b", vec![]),
+        ]);
+        assert!(matches!(
+            req.entry,
+            GameLogEntry::Mutations { ref statements, .. } if statements.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_request_serde_round_trip() {
+        let req = Request::simple("SELECT 1");
+        let json = serde_json::to_string(&req).unwrap();
+        let parsed: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.version, LOG_ENTRY_VERSION);
+        assert_eq!(parsed.entry, req.entry);
     }
 
     #[test]
-    fn test_request_simple() {
-        let req = Request::simple("DELETE FROM test");
-        assert_eq!(req.sql, "DELETE FROM test");
-        assert!(req.params.is_empty());
+    fn test_legacy_requests_decode_as_mutations() {
+        let single: Request = serde_json::from_str(r#"{"sql": "SELECT 1", "params": []}"#).unwrap();
+        assert_eq!(single.version, 0);
+        assert_eq!(single.entry, GameLogEntry::statement("SELECT 1", vec![]));
+
+        let batch: Request = serde_json::from_str(
+            r#"[{"sql": "SELECT 1", "params": []}, {"sql": "SELECT 2", "params": []}]"#,
+        )
+        .unwrap();
+        assert_eq!(batch.version, 0);
+        assert!(matches!(
+            batch.entry,
+            GameLogEntry::Mutations { ref statements, .. } if statements.len() == 2
+        ));
     }
 
     #[test]
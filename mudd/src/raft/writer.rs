@@ -3,23 +3,42 @@
 //! All SQLite writes must go through this coordinator to ensure
 //! consensus across the cluster.
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{bail, Result};
-use openraft::BasicNode;
+use openraft::{BasicNode, ChangeMembers};
+use parking_lot::RwLock;
+use serde::Serialize;
 use sqlx::SqlitePool;
 use tracing::{debug, info};
 
 use super::config::RaftNodeConfig;
-use super::network::NetworkConfig;
-use super::types::{NodeId, Request, Response};
+use super::log_entry::GameLogEntry;
+use super::network::{NetworkConfig, SharedNetworkConfig};
+use super::types::{NodeId, Request, Response, Statement};
 use super::{create_raft_node, GameRaft};
 
+/// Snapshot of the cluster membership as seen by this node
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterMembership {
+    /// Current leader (if known)
+    pub leader: Option<NodeId>,
+    /// Voting members
+    pub voters: Vec<NodeId>,
+    /// Non-voting members receiving replication
+    pub learners: Vec<NodeId>,
+    /// Member addresses: node_id -> "host:port"
+    pub nodes: BTreeMap<NodeId, String>,
+}
+
 /// Central coordinator for all database writes via Raft consensus
 pub struct RaftWriter {
     raft: GameRaft,
     node_id: NodeId,
     db_path: String,
+    network: SharedNetworkConfig,
 }
 
 impl RaftWriter {
@@ -33,9 +52,9 @@ impl RaftWriter {
         info!("Creating single-node RaftWriter (node_id={})", node_id);
 
         let config = RaftNodeConfig::single(node_id, port).with_db_path(db_path);
-        let network_config = NetworkConfig::from_raft_config(&config);
+        let network = Arc::new(RwLock::new(NetworkConfig::from_raft_config(&config)));
 
-        let (raft, _read_pool) = create_raft_node(node_id, pool, network_config).await?;
+        let (raft, _read_pool) = create_raft_node(node_id, pool, network.clone()).await?;
 
         // Initialize as single-node cluster
         let mut members = std::collections::BTreeMap::new();
@@ -47,6 +66,7 @@ impl RaftWriter {
             raft,
             node_id,
             db_path: db_path.to_string(),
+            network,
         })
     }
 
@@ -58,15 +78,17 @@ impl RaftWriter {
         );
 
         let node_id = config.node_id;
-        let network_config = NetworkConfig::from_raft_config(&config);
+        let network = Arc::new(RwLock::new(NetworkConfig::from_raft_config(&config)));
 
-        let (raft, _read_pool) = create_raft_node(node_id, pool, network_config).await?;
+        let (raft, _read_pool) = create_raft_node(node_id, pool, network.clone()).await?;
 
-        // Build initial membership from peers
-        let members: std::collections::BTreeMap<NodeId, BasicNode> = config
-            .peers
-            .keys()
-            .map(|&id| (id, BasicNode::default()))
+        // Build initial membership from peers, recording addresses so that
+        // nodes which never saw the static peer list can still reach them
+        let members: BTreeMap<NodeId, BasicNode> = network
+            .read()
+            .nodes
+            .iter()
+            .map(|(&id, addr)| (id, BasicNode::new(addr)))
             .collect();
 
         // Only initialize if this is the first node or if not already initialized
@@ -81,19 +103,18 @@ impl RaftWriter {
             raft,
             node_id,
             db_path: db_path.to_string(),
+            network,
         })
     }
 
-    /// Execute a single SQL write via Raft consensus
+    /// Propose a typed log entry via Raft consensus
     ///
-    /// The SQL and params are replicated to all nodes before being applied.
-    /// Non-deterministic values (UUIDs, timestamps) must be pre-computed.
-    pub async fn execute(&self, sql: &str, params: Vec<serde_json::Value>) -> Result<Response> {
-        let request = Request::new(sql, params);
-
-        debug!("Submitting write to Raft: {}", sql);
+    /// The entry is replicated to all nodes, then applied by the state
+    /// machine in a single SQLite transaction.
+    pub async fn propose(&self, entry: GameLogEntry) -> Result<Response> {
+        debug!("Submitting {} entry to Raft", entry.kind());
 
-        let response = self.raft.client_write(request).await?;
+        let response = self.raft.client_write(Request::entry(entry)).await?;
 
         if !response.data.success {
             if let Some(ref err) = response.data.error {
@@ -105,23 +126,31 @@ impl RaftWriter {
         Ok(response.data)
     }
 
+    /// Execute a single SQL write via Raft consensus
+    ///
+    /// The SQL and params are replicated to all nodes before being applied.
+    /// Non-deterministic values (UUIDs, timestamps) must be pre-computed.
+    pub async fn execute(&self, sql: &str, params: Vec<serde_json::Value>) -> Result<Response> {
+        debug!("Submitting write to Raft: {}", sql);
+        self.propose(GameLogEntry::statement(sql, params)).await
+    }
+
     /// Execute a batch of SQL statements atomically via Raft consensus
     ///
-    /// All statements are committed together. If any fails, the batch is rejected.
+    /// The statements are proposed as a single log entry and applied in one
+    /// SQLite transaction. If any fails, none of them take effect.
     pub async fn execute_batch(
         &self,
         statements: Vec<(String, Vec<serde_json::Value>)>,
-    ) -> Result<()> {
-        for (sql, params) in statements {
-            let response = self.execute(&sql, params).await?;
-            if !response.success {
-                if let Some(err) = response.error {
-                    bail!("Batch statement failed: {}", err);
-                }
-                bail!("Batch statement failed (unknown error)");
-            }
-        }
-        Ok(())
+    ) -> Result<Response> {
+        self.propose(GameLogEntry::Mutations {
+            universe_id: None,
+            statements: statements
+                .into_iter()
+                .map(|(sql, params)| Statement::new(sql, params))
+                .collect(),
+        })
+        .await
     }
 
     /// Wait for this node to have a leader (either self or another node)
@@ -169,6 +198,130 @@ impl RaftWriter {
     pub fn current_leader(&self) -> Option<NodeId> {
         self.raft.metrics().borrow().current_leader
     }
+
+    /// Get the current cluster membership
+    pub fn membership(&self) -> ClusterMembership {
+        let metrics = self.raft.metrics().borrow().clone();
+        let membership = metrics.membership_config.membership();
+
+        // The replicated membership is authoritative for addresses; the
+        // local network config only fills in nodes it records none for
+        let network = self.network.read();
+        let nodes = membership
+            .nodes()
+            .filter_map(|(&id, node)| {
+                let addr = Some(&node.addr)
+                    .filter(|addr| !addr.is_empty())
+                    .or_else(|| network.get_addr(id))?;
+                Some((id, addr.clone()))
+            })
+            .collect();
+
+        ClusterMembership {
+            leader: metrics.current_leader,
+            voters: membership.voter_ids().collect(),
+            learners: membership.learner_ids().collect(),
+            nodes,
+        }
+    }
+
+    /// Add a node as a learner (non-voting member)
+    ///
+    /// Must be called on the leader. Blocks until the learner has caught up
+    /// with the log. The address is recorded in the membership so every node
+    /// can reach the learner after a leader change.
+    pub async fn add_learner(&self, node_id: NodeId, addr: &str) -> Result<()> {
+        info!("Adding learner node {} at {}", node_id, addr);
+
+        self.network.write().set_addr(node_id, addr);
+
+        if let Err(e) = self
+            .raft
+            .add_learner(node_id, BasicNode::new(addr), true)
+            .await
+        {
+            // Don't keep routing to a node that never joined
+            if !self.membership().learners.contains(&node_id) {
+                self.network.write().remove_addr(node_id);
+            }
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Promote an existing learner to a voting member
+    ///
+    /// Must be called on the leader.
+    pub async fn promote_voter(&self, node_id: NodeId) -> Result<()> {
+        let membership = self.membership();
+        if membership.voters.contains(&node_id) {
+            bail!("Node {} is already a voter", node_id);
+        }
+        if !membership.learners.contains(&node_id) {
+            bail!(
+                "Node {} is not a learner; add it as a learner first",
+                node_id
+            );
+        }
+
+        info!("Promoting node {} to voter", node_id);
+
+        self.raft
+            .change_membership(ChangeMembers::AddVoterIds(BTreeSet::from([node_id])), false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove a node (voter or learner) from the cluster
+    ///
+    /// Must be called on the leader.
+    pub async fn remove_node(&self, node_id: NodeId) -> Result<()> {
+        let membership = self.membership();
+        let ids = BTreeSet::from([node_id]);
+
+        let change = if membership.voters.contains(&node_id) {
+            if membership.voters.len() == 1 {
+                bail!("Cannot remove the last voter");
+            }
+            ChangeMembers::RemoveVoters(ids)
+        } else if membership.learners.contains(&node_id) {
+            ChangeMembers::RemoveNodes(ids)
+        } else {
+            bail!("Node {} is not a cluster member", node_id);
+        };
+
+        info!("Removing node {} from cluster", node_id);
+
+        // retain=false also drops a removed voter from the node list
+        self.raft.change_membership(change, false).await?;
+
+        self.network.write().remove_addr(node_id);
+
+        Ok(())
+    }
+}
+
+/// Apply a log entry through Raft if there is a writer, otherwise directly
+///
+/// The direct path runs the same `GameLogEntry::apply` as the state machine
+/// in a local transaction, so tests and offline tools such as `mudd_init`
+/// change the database exactly as a replicated write would. Returns the
+/// number of rows affected.
+pub async fn write_entry(
+    raft_writer: Option<&RaftWriter>,
+    pool: &SqlitePool,
+    entry: GameLogEntry,
+) -> Result<u64> {
+    if let Some(raft_writer) = raft_writer {
+        return Ok(raft_writer.propose(entry).await?.rows_affected);
+    }
+
+    let mut tx = pool.begin().await?;
+    let rows_affected = entry.apply(&mut tx).await?;
+    tx.commit().await?;
+    Ok(rows_affected)
 }
 
 #[cfg(test)]
@@ -188,6 +341,7 @@ mod tests {
             "CREATE TABLE raft_log (
                 log_index INTEGER PRIMARY KEY,
                 term INTEGER NOT NULL,
+                node_id INTEGER NOT NULL DEFAULT 0,
                 entry_type TEXT NOT NULL,
                 payload TEXT,
                 created_at INTEGER NOT NULL DEFAULT (unixepoch())
@@ -264,6 +418,55 @@ mod tests {
         assert_eq!(response.rows_affected, 1);
     }
 
+    #[tokio::test]
+    async fn test_batch_is_atomic() {
+        let pool = test_pool_with_raft_tables().await;
+        let writer = RaftWriter::single_node(pool.clone(), 1, 19003, "/tmp/test.db")
+            .await
+            .unwrap();
+
+        writer
+            .wait_for_leader(Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        let response = writer
+            .execute_batch(vec![
+                (
+                    "INSERT INTO test_data (id, value) VALUES (?, ?)".to_string(),
+                    vec![serde_json::json!("a"), serde_json::json!("1")],
+                ),
+                (
+                    "INSERT INTO test_data (id, value) VALUES (?, ?)".to_string(),
+                    vec![serde_json::json!("b"), serde_json::json!("2")],
+                ),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(response.rows_affected, 2);
+
+        // Second statement violates the primary key, so the first must not apply
+        let result = writer
+            .execute_batch(vec![
+                (
+                    "INSERT INTO test_data (id, value) VALUES (?, ?)".to_string(),
+                    vec![serde_json::json!("c"), serde_json::json!("3")],
+                ),
+                (
+                    "INSERT INTO test_data (id, value) VALUES (?, ?)".to_string(),
+                    vec![serde_json::json!("a"), serde_json::json!("dup")],
+                ),
+            ])
+            .await;
+        assert!(result.is_err());
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM test_data")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 2);
+    }
+
     #[tokio::test]
     async fn test_is_leader() {
         let pool = test_pool_with_raft_tables().await;
@@ -14,7 +14,7 @@ use sqlx::SqlitePool;
 use tokio::sync::RwLock;
 use tracing::{debug, warn};
 
-use crate::raft::RaftWriter;
+use crate::raft::{write_entry, GameLogEntry, RaftWriter};
 
 /// A one-shot timer that fires after a delay
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -158,27 +158,8 @@ impl TimerManager {
     pub async fn add_timer(&self, timer: Timer) -> String {
         let id = timer.id.clone();
 
-        // Persist via Raft if available, otherwise direct SQL
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = self.persist_timer(&timer, raft_writer).await {
-                warn!("Failed to persist timer: {}", e);
-            }
-        } else if let Some(ref pool) = self.pool {
-            // Direct SQL fallback (for tests)
-            if let Err(e) = sqlx::query(
-                "INSERT OR REPLACE INTO timers (id, universe_id, object_id, method, fire_at, args) VALUES (?, ?, ?, ?, ?, ?)",
-            )
-            .bind(&timer.id)
-            .bind(&timer.universe_id)
-            .bind(&timer.object_id)
-            .bind(&timer.method)
-            .bind(timer.fire_at)
-            .bind(&timer.args)
-            .execute(pool)
-            .await
-            {
-                warn!("Failed to persist timer: {}", e);
-            }
+        if let Err(e) = self.persist_timer(&timer).await {
+            warn!("Failed to persist timer: {}", e);
         }
 
         self.timers.write().await.insert(id.clone(), timer);
@@ -187,20 +168,8 @@ impl TimerManager {
 
     /// Remove a timer by ID
     pub async fn remove_timer(&self, timer_id: &str) -> bool {
-        // Remove from DB via Raft if available, otherwise direct SQL
-        if let Some(ref raft_writer) = self.raft_writer {
-            if let Err(e) = self.delete_timer_db(timer_id, raft_writer).await {
-                warn!("Failed to delete timer from DB: {}", e);
-            }
-        } else if let Some(ref pool) = self.pool {
-            // Direct SQL fallback (for tests)
-            if let Err(e) = sqlx::query("DELETE FROM timers WHERE id = ?")
-                .bind(timer_id)
-                .execute(pool)
-                .await
-            {
-                warn!("Failed to delete timer from DB: {}", e);
-            }
+        if let Err(e) = self.delete_timer_db(timer_id).await {
+            warn!("Failed to delete timer from DB: {}", e);
         }
 
         self.timers.write().await.remove(timer_id).is_some()
@@ -217,19 +186,8 @@ impl TimerManager {
 
         for id in &ids_to_remove {
             timers.remove(id);
-            if let Some(ref raft_writer) = self.raft_writer {
-                if let Err(e) = self.delete_timer_db(id, raft_writer).await {
-                    warn!("Failed to delete timer from DB: {}", e);
-                }
-            } else if let Some(ref pool) = self.pool {
-                // Direct SQL fallback (for tests)
-                if let Err(e) = sqlx::query("DELETE FROM timers WHERE id = ?")
-                    .bind(id)
-                    .execute(pool)
-                    .await
-                {
-                    warn!("Failed to delete timer from DB: {}", e);
-                }
+            if let Err(e) = self.delete_timer_db(id).await {
+                warn!("Failed to delete timer from DB: {}", e);
             }
         }
     }
@@ -288,7 +246,10 @@ impl TimerManager {
         fired
     }
 
-    /// Load timers from database on startup
+    /// Load timers from database, replacing any held in memory
+    ///
+    /// Called on startup, and again when this node becomes Raft leader so it
+    /// picks up timers added or fired while another node was leading.
     pub async fn load_from_db(&self) -> anyhow::Result<()> {
         let Some(ref pool) = self.pool else {
             return Ok(());
@@ -300,6 +261,7 @@ impl TimerManager {
                 .await?;
 
         let mut timers = self.timers.write().await;
+        timers.clear();
         for (id, universe_id, object_id, method, fire_at, args) in rows {
             let timer = Timer {
                 id: id.clone(),
@@ -316,38 +278,35 @@ impl TimerManager {
         Ok(())
     }
 
-    /// Persist a timer to database via Raft
-    async fn persist_timer(&self, timer: &Timer, raft_writer: &RaftWriter) -> anyhow::Result<()> {
-        raft_writer
-            .execute(
-                "INSERT OR REPLACE INTO timers (id, universe_id, object_id, method, fire_at, args) VALUES (?, ?, ?, ?, ?, ?)",
-                vec![
-                    serde_json::json!(&timer.id),
-                    serde_json::json!(&timer.universe_id),
-                    serde_json::json!(&timer.object_id),
-                    serde_json::json!(&timer.method),
-                    serde_json::json!(timer.fire_at),
-                    serde_json::json!(&timer.args),
-                ],
-            )
-            .await?;
-
+    /// Apply a write via Raft if available, otherwise directly (for tests)
+    ///
+    /// Without a database pool timers are kept in memory only.
+    async fn write(&self, entry: GameLogEntry) -> anyhow::Result<()> {
+        if let Some(ref pool) = self.pool {
+            write_entry(self.raft_writer.as_deref(), pool, entry).await?;
+        }
         Ok(())
     }
 
-    /// Delete a timer from database via Raft
-    async fn delete_timer_db(
-        &self,
-        timer_id: &str,
-        raft_writer: &RaftWriter,
-    ) -> anyhow::Result<()> {
-        raft_writer
-            .execute(
-                "DELETE FROM timers WHERE id = ?",
-                vec![serde_json::json!(timer_id)],
-            )
-            .await?;
-        Ok(())
+    /// Persist a timer to database
+    async fn persist_timer(&self, timer: &Timer) -> anyhow::Result<()> {
+        self.write(GameLogEntry::SetTimer {
+            timer_id: timer.id.clone(),
+            universe_id: timer.universe_id.clone(),
+            object_id: timer.object_id.clone(),
+            method: timer.method.clone(),
+            fire_at: timer.fire_at,
+            args: timer.args.clone(),
+        })
+        .await
+    }
+
+    /// Delete a timer from database
+    async fn delete_timer_db(&self, timer_id: &str) -> anyhow::Result<()> {
+        self.write(GameLogEntry::DeleteTimer {
+            timer_id: timer_id.to_string(),
+        })
+        .await
     }
 
     /// Get count of active timers
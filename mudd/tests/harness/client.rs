@@ -78,6 +78,8 @@ pub struct TestClient {
     account_id: Option<String>,
     auth_token: Option<String>,
     player_id: Option<String>,
+    /// Highest `seq` received, for `resume`
+    last_seq: u64,
 }
 
 impl TestClient {
@@ -135,7 +137,7 @@ impl TestClient {
                     Role::Builder { .. } | Role::Wizard { .. } | Role::Admin { .. } => {
                         let level = role.access_level();
                         let account_service =
-                            mudd::auth::accounts::AccountService::new(server.pool().clone());
+                            mudd::auth::accounts::AccountService::new(server.pool().clone(), None);
                         account_service.set_access_level(&account_id, level).await?;
                     }
                     _ => {}
@@ -169,6 +171,7 @@ impl TestClient {
             account_id,
             auth_token,
             player_id: None,
+            last_seq: 0,
         };
 
         // Receive and process welcome message
@@ -217,6 +220,36 @@ impl TestClient {
         Ok(())
     }
 
+    /// Opt in to typed events with a hello message
+    pub async fn send_hello(&mut self, capabilities: &[&str]) -> Result<()> {
+        let msg = serde_json::json!({
+            "type": "hello",
+            "protocol": 1,
+            "capabilities": capabilities
+        });
+        self.write
+            .send(Message::Text(msg.to_string().into()))
+            .await?;
+        Ok(())
+    }
+
+    /// Ask for the messages sent after `last_seq` while disconnected
+    pub async fn send_resume(&mut self, last_seq: u64) -> Result<()> {
+        let msg = serde_json::json!({
+            "type": "resume",
+            "last_seq": last_seq
+        });
+        self.write
+            .send(Message::Text(msg.to_string().into()))
+            .await?;
+        Ok(())
+    }
+
+    /// Highest sequence number received so far
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
     /// Send a ping message
     pub async fn send_ping(&mut self) -> Result<()> {
         let msg = serde_json::json!({
@@ -233,7 +266,11 @@ impl TestClient {
         loop {
             match self.read.next().await {
                 Some(Ok(Message::Text(text))) => {
-                    return Ok(serde_json::from_str(&text)?);
+                    let msg: Value = serde_json::from_str(&text)?;
+                    if let Some(seq) = msg["seq"].as_u64() {
+                        self.last_seq = self.last_seq.max(seq);
+                    }
+                    return Ok(msg);
                 }
                 Some(Ok(Message::Close(_))) | None => {
                     bail!("WebSocket closed");
@@ -4,6 +4,7 @@
 //! - `TestServer` - Spawns a real server on random port with in-memory DB
 //! - `TestClient` - Role-based authenticated WebSocket client
 //! - `TestWorld` - Pre-configured universe with regions and rooms
+//! - `TelnetClient` - Raw client for the telnet gateway
 //!
 //! # Example
 //!
@@ -28,6 +29,7 @@
 
 mod client;
 mod server;
+mod telnet;
 mod world;
 
 // Primary exports
@@ -37,6 +39,8 @@ pub use client::TestClient;
 #[allow(unused_imports)]
 pub use server::{MuddTest, RawWsClient, TestServer};
 #[allow(unused_imports)]
+pub use telnet::TelnetClient;
+#[allow(unused_imports)]
 pub use world::TestWorld;
 
 // Backward compatibility alias - WsClient now points to RawWsClient
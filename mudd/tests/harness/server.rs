@@ -22,6 +22,8 @@ use super::world::TestWorld;
 /// Uses on-disk SQLite in a temp directory for realistic testing
 pub struct TestServer {
     pub addr: SocketAddr,
+    /// Telnet gateway address
+    pub telnet_addr: SocketAddr,
     pub client: Client,
     child: Child,
     world: Option<TestWorld>,
@@ -48,7 +50,10 @@ impl TestServer {
         // Find a random available port
         let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
         let addr = listener.local_addr()?;
+        let telnet_listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let telnet_addr = telnet_listener.local_addr()?;
         drop(listener);
+        drop(telnet_listener);
 
         // Find the binary paths
         let mudd_path = find_binary_path("mudd")?;
@@ -80,6 +85,8 @@ impl TestServer {
             .arg(addr.to_string())
             .arg("--database")
             .arg(db_path.to_string_lossy().as_ref())
+            .arg("--telnet")
+            .arg(telnet_addr.to_string())
             .env("RUST_LOG", "info")
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
@@ -126,6 +133,7 @@ impl TestServer {
 
         let mut test_server = Self {
             addr,
+            telnet_addr,
             client,
             child,
             world: None,
@@ -157,6 +165,26 @@ impl TestServer {
             .await?)
     }
 
+    /// Make an authenticated GET request
+    pub async fn get_auth(&self, path: &str, token: &str) -> Result<reqwest::Response> {
+        Ok(self
+            .client
+            .get(format!("{}{}", self.base_url(), path))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await?)
+    }
+
+    /// Make an authenticated DELETE request
+    pub async fn delete_auth(&self, path: &str, token: &str) -> Result<reqwest::Response> {
+        Ok(self
+            .client
+            .delete(format!("{}{}", self.base_url(), path))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await?)
+    }
+
     /// Make a POST request with JSON body
     pub async fn post<T: serde::Serialize + ?Sized>(
         &self,
@@ -0,0 +1,152 @@
+//! TelnetClient - Raw TCP client for the telnet gateway
+//!
+//! Strips telnet negotiation from the stream and keeps the text and any
+//! GMCP messages the server sends.
+
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::server::TestServer;
+
+const IAC: u8 = 255;
+const DO: u8 = 253;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const GMCP: u8 = 201;
+
+/// Telnet test client
+pub struct TelnetClient {
+    stream: TcpStream,
+    /// Raw bytes not yet parsed (an incomplete command)
+    raw: Vec<u8>,
+    /// Text received and not yet consumed by `expect_text`
+    text: String,
+    /// GMCP messages received ("Package json")
+    gmcp: Vec<String>,
+}
+
+impl TelnetClient {
+    /// Connect to the server's telnet port
+    pub async fn connect(server: &TestServer) -> Result<Self> {
+        let stream = TcpStream::connect(server.telnet_addr).await?;
+        Ok(Self {
+            stream,
+            raw: Vec::new(),
+            text: String::new(),
+            gmcp: Vec::new(),
+        })
+    }
+
+    /// Agree to receive GMCP
+    pub async fn enable_gmcp(&mut self) -> Result<()> {
+        self.stream.write_all(&[IAC, DO, GMCP]).await?;
+        Ok(())
+    }
+
+    /// Send a line of input
+    pub async fn send_line(&mut self, line: &str) -> Result<()> {
+        self.stream
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    /// Read until `needle` shows up in the text, returning everything up to
+    /// and including it
+    pub async fn expect_text(&mut self, needle: &str) -> Result<String> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(pos) = self.text.find(needle) {
+                let end = pos + needle.len();
+                let seen = self.text[..end].to_string();
+                self.text.drain(..end);
+                return Ok(seen);
+            }
+            if !self.read_some(deadline).await? {
+                bail!("Timeout waiting for {:?}, got {:?}", needle, self.text);
+            }
+        }
+    }
+
+    /// Read until a GMCP message for `package` arrives, returning its data
+    pub async fn expect_gmcp(&mut self, package: &str) -> Result<serde_json::Value> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let prefix = format!("{} ", package);
+            if let Some(pos) = self.gmcp.iter().position(|m| m.starts_with(&prefix)) {
+                let msg = self.gmcp.remove(pos);
+                return Ok(serde_json::from_str(&msg[prefix.len()..])?);
+            }
+            if !self.read_some(deadline).await? {
+                bail!("Timeout waiting for GMCP {}, got {:?}", package, self.gmcp);
+            }
+        }
+    }
+
+    /// Read and parse one chunk; false on timeout
+    async fn read_some(&mut self, deadline: tokio::time::Instant) -> Result<bool> {
+        let mut buf = [0u8; 4096];
+        let n = match tokio::time::timeout_at(deadline, self.stream.read(&mut buf)).await {
+            Ok(n) => n?,
+            Err(_) => return Ok(false),
+        };
+        if n == 0 {
+            bail!("Connection closed, got {:?}", self.text);
+        }
+        self.raw.extend_from_slice(&buf[..n]);
+        self.parse();
+        Ok(true)
+    }
+
+    /// Move complete text and commands out of `raw`
+    fn parse(&mut self) {
+        let mut text = Vec::new();
+        let mut i = 0;
+        while i < self.raw.len() {
+            if self.raw[i] != IAC {
+                text.push(self.raw[i]);
+                i += 1;
+                continue;
+            }
+            let Some(&command) = self.raw.get(i + 1) else {
+                break;
+            };
+            match command {
+                IAC => {
+                    text.push(IAC);
+                    i += 2;
+                }
+                SB => {
+                    let Some(end) = self.raw[i..]
+                        .windows(2)
+                        .position(|w| w == [IAC, SE])
+                        .map(|p| i + p)
+                    else {
+                        break;
+                    };
+                    if self.raw.get(i + 2) == Some(&GMCP) {
+                        let payload = &self.raw[i + 3..end];
+                        self.gmcp.push(String::from_utf8_lossy(payload).to_string());
+                    }
+                    i = end + 2;
+                }
+                // WILL, WONT, DO, DONT
+                251..=254 => {
+                    if i + 2 >= self.raw.len() {
+                        break;
+                    }
+                    i += 3;
+                }
+                _ => i += 2,
+            }
+        }
+        self.raw.drain(..i);
+        self.text
+            .push_str(&String::from_utf8_lossy(&text).replace('\r', ""));
+    }
+}
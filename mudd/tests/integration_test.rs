@@ -4,6 +4,7 @@ mod harness;
 mod scenarios;
 
 use harness::{MuddTest, TestServer};
+use mudd::auth::wallet;
 use mudd::objects::{ClassRegistry, Object, ObjectStore};
 
 #[tokio::test]
@@ -210,18 +211,18 @@ async fn test_class_registry() {
     let registry = ClassRegistry::new();
 
     // Verify inheritance chain
-    assert!(registry.is_a("weapon", "item"));
-    assert!(registry.is_a("weapon", "thing"));
-    assert!(registry.is_a("player", "living"));
+    assert!(registry.is_a("test-universe", "weapon", "item"));
+    assert!(registry.is_a("test-universe", "weapon", "thing"));
+    assert!(registry.is_a("test-universe", "player", "living"));
 
     // Verify property resolution
-    let props = registry.resolve_properties("weapon");
+    let props = registry.resolve_properties("test-universe", "weapon");
     assert!(props.contains_key("damage_dice")); // from weapon
     assert!(props.contains_key("weight")); // from item
     assert!(props.contains_key("name")); // from thing
 
     // Verify handlers resolution
-    let handlers = registry.resolve_handlers("npc");
+    let handlers = registry.resolve_handlers("test-universe", "npc");
     assert!(handlers.contains(&"heart_beat".to_string())); // from living
     assert!(handlers.contains(&"ai_idle_tick".to_string())); // from npc
     assert!(handlers.contains(&"on_init".to_string())); // from thing
@@ -1165,7 +1166,7 @@ async fn test_combat_state_persistence() {
         .await
         .expect("Failed to create combat_state table");
 
-        let combat = CombatManager::with_db(pool);
+        let combat = CombatManager::with_db(pool, None);
         combat
             .init_entity_with_universe("player1", Some("universe1"), 100)
             .await;
@@ -1191,7 +1192,7 @@ async fn test_combat_state_persistence() {
             .await
             .expect("Failed to connect");
 
-        let combat = CombatManager::with_db(pool);
+        let combat = CombatManager::with_db(pool, None);
         combat
             .load_from_db()
             .await
@@ -1234,7 +1235,7 @@ async fn test_effects_persistence() {
         .await
         .expect("Failed to create active_effects table");
 
-        let effects = EffectRegistry::with_db(pool);
+        let effects = EffectRegistry::with_db(pool, None);
         effects
             .add_effect("player1", StatusEffect::new(EffectType::Poisoned, 10, 5))
             .await;
@@ -1253,7 +1254,7 @@ async fn test_effects_persistence() {
             .await
             .expect("Failed to connect");
 
-        let effects = EffectRegistry::with_db(pool);
+        let effects = EffectRegistry::with_db(pool, None);
         effects
             .load_from_db()
             .await
@@ -1317,3 +1318,412 @@ async fn test_eval_create_object() {
         obj_id
     );
 }
+
+// =============================================================================
+// Session Tests
+// =============================================================================
+
+/// Test: Each login is its own session, listed and revocable per device
+#[tokio::test]
+async fn test_sessions_list_and_revoke() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut player = server
+        .connect_as(harness::Role::Player {
+            username: "twodevices".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let first_token = player.auth_token().unwrap().to_string();
+
+    // A second login leaves the first session valid
+    let login: serde_json::Value = server
+        .post(
+            "/auth/login",
+            &serde_json::json!({"username": "twodevices", "password": "test123"}),
+        )
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let second_token = login["token"].as_str().unwrap().to_string();
+    for token in [&first_token, &second_token] {
+        let validate: serde_json::Value = server
+            .get(&format!("/auth/validate?token={}", token))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(validate["valid"], true);
+    }
+
+    let sessions: serde_json::Value = server
+        .get_auth("/auth/sessions", &second_token)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let first_session = sessions
+        .iter()
+        .find(|s| s["current"] == false)
+        .expect("first session listed");
+    assert_eq!(first_session["ip_address"], "127.0.0.1");
+
+    // Revoking the first device's session closes its connection
+    let response = server
+        .delete_auth(
+            &format!("/auth/sessions/{}", first_session["id"].as_str().unwrap()),
+            &second_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let msg = player.expect("disconnect").await.unwrap();
+    assert_eq!(msg["reason"], "Session revoked");
+
+    let response = server
+        .get_auth("/auth/sessions", &first_token)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let response = server
+        .get_auth("/auth/sessions", &second_token)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+/// Test: Admin force-logout ends every session and closes WebSockets
+#[tokio::test]
+async fn test_admin_force_logout() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let admin = server
+        .connect_as(harness::Role::Admin {
+            username: "sessionadmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut player = server
+        .connect_as(harness::Role::Player {
+            username: "kicked".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let player_token = player.auth_token().unwrap().to_string();
+    let path = format!("/auth/accounts/{}/logout", player.account_id().unwrap());
+
+    // Players can't force-logout others
+    let response = server
+        .post_auth(&path, &serde_json::json!({}), &player_token)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let result: serde_json::Value = server
+        .post_auth(&path, &serde_json::json!({}), admin.auth_token().unwrap())
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(result["revoked"], 1);
+    assert_eq!(result["disconnected"], 1);
+
+    let msg = player.expect("disconnect").await.unwrap();
+    assert_eq!(msg["reason"], "Logged out by an administrator");
+
+    // The old token can't open a new connection
+    let reconnect = tokio_tungstenite::connect_async(server.ws_url_with_token(&player_token)).await;
+    assert!(reconnect.is_err());
+}
+
+/// Build and sign a SIWE message with a fresh nonce from the server
+async fn sign_in_message(server: &TestServer, key: &k256::ecdsa::SigningKey) -> serde_json::Value {
+    let address = wallet::signer_address(key);
+    let challenge: serde_json::Value = server
+        .post(
+            "/auth/wallet/nonce",
+            &serde_json::json!({"address": address}),
+        )
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let domain = challenge["domain"].as_str().unwrap().to_string();
+    let now = chrono::Utc::now();
+    let message = wallet::SiweMessage {
+        uri: format!("http://{}", domain),
+        domain,
+        address,
+        statement: Some("Sign in to HemiMUD".to_string()),
+        version: "1".to_string(),
+        chain_id: 1,
+        nonce: challenge["nonce"].as_str().unwrap().to_string(),
+        issued_at: now,
+        expiration_time: Some(now + chrono::Duration::minutes(5)),
+        not_before: None,
+        request_id: None,
+        resources: Vec::new(),
+    }
+    .to_string();
+    let signature = wallet::sign_message(key, &message);
+    serde_json::json!({"message": message, "signature": signature})
+}
+
+/// Test: Wallet sign-in creates an account, and password accounts can link a wallet
+#[tokio::test]
+async fn test_wallet_sign_in_and_link() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let key = k256::ecdsa::SigningKey::from_slice(&[42u8; 32]).unwrap();
+    let address = wallet::signer_address(&key);
+
+    // First sign-in creates an account named after the wallet
+    let signed = sign_in_message(&server, &key).await;
+    let response = server.post("/auth/wallet/login", &signed).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let auth: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(auth["username"], address.as_str());
+    let wallet_account = auth["account_id"].as_str().unwrap().to_string();
+
+    // Replaying the same signed message fails
+    let response = server.post("/auth/wallet/login", &signed).await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // A signature from another wallet is rejected
+    let other = k256::ecdsa::SigningKey::from_slice(&[43u8; 32]).unwrap();
+    let mut forged = sign_in_message(&server, &key).await;
+    forged["signature"] = wallet::sign_message(&other, forged["message"].as_str().unwrap()).into();
+    let response = server.post("/auth/wallet/login", &forged).await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // A password account links a second wallet and can then sign in with it
+    let player = server
+        .connect_as(harness::Role::Player {
+            username: "walletlinker".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let token = player.auth_token().unwrap();
+    let signed = sign_in_message(&server, &other).await;
+    let linked: serde_json::Value = server
+        .post_auth("/auth/wallet/link", &signed, token)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        linked["wallet_address"],
+        wallet::signer_address(&other).as_str()
+    );
+
+    let signed = sign_in_message(&server, &other).await;
+    let auth: serde_json::Value = server
+        .post("/auth/wallet/login", &signed)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(auth["account_id"], player.account_id().unwrap());
+
+    // The first wallet already belongs to its own account
+    let signed = sign_in_message(&server, &key).await;
+    let response = server
+        .post_auth("/auth/wallet/link", &signed, token)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 409);
+    assert_ne!(wallet_account, player.account_id().unwrap());
+}
+
+/// Test: Wallet sign-in only accepts messages for the server's own domain,
+/// whatever Host header the request carries
+#[tokio::test]
+async fn test_wallet_sign_in_rejects_other_domains() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let key = k256::ecdsa::SigningKey::from_slice(&[44u8; 32]).unwrap();
+
+    let signed = sign_in_message(&server, &key).await;
+    let domain = server.base_url().trim_start_matches("http://").to_string();
+    let message = signed["message"]
+        .as_str()
+        .unwrap()
+        .replace(&domain, "phish.example.com");
+    assert!(message.starts_with("phish.example.com wants you"));
+    let phished = serde_json::json!({
+        "message": message,
+        "signature": wallet::sign_message(&key, &message),
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/auth/wallet/login", server.base_url()))
+        .header("Host", "phish.example.com")
+        .json(&phished)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    // The nonce wasn't spent, so the genuine message still signs in
+    let response = server.post("/auth/wallet/login", &signed).await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+// =============================================================================
+// Credit Tests
+// =============================================================================
+
+/// Test: Credit changes are recorded in the ledger and visible to admins and Lua
+#[tokio::test]
+async fn test_credit_ledger() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let universe_id = server.universe_id().to_string();
+
+    let admin = server
+        .connect_as(harness::Role::Admin {
+            username: "ledgeradmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut wizard = server
+        .connect_as(harness::Role::Wizard {
+            username: "ledgerwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    let admin_token = admin.auth_token().unwrap();
+    let wizard_token = wizard.auth_token().unwrap();
+    let wizard_id = wizard.account_id().unwrap().to_string();
+    let base = format!("/credits/{}/{}", universe_id, wizard_id);
+
+    // Only admins may use the credits API
+    let response = server
+        .post_auth(
+            &format!("{}/grant", base),
+            &serde_json::json!({"amount": 100, "reason": "self-service"}),
+            wizard_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = server
+        .post_auth(
+            &format!("{}/grant", base),
+            &serde_json::json!({"amount": 75, "reason": "contest prize"}),
+            admin_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let audit: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(audit["balance"], 75);
+    assert_eq!(audit["consistent"], true);
+
+    let transactions: serde_json::Value = server
+        .get_auth(&format!("{}/transactions", base), admin_token)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(transactions[0]["amount"], 75);
+    assert_eq!(transactions[0]["balance_after"], 75);
+    assert_eq!(transactions[0]["reason"], "contest prize");
+
+    // Players see their own history from Lua
+    wizard
+        .command("eval local t = game.get_transactions(); return #t .. ' ' .. t[1].reason")
+        .await
+        .unwrap();
+    wizard.expect("echo").await.unwrap();
+    let output = wizard.expect("output").await.unwrap();
+    assert_eq!(output["text"], "1 contest prize");
+}
+
+/// Test: Eval in a universe with a cost table is charged to the player
+#[tokio::test]
+async fn test_eval_is_billed() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let universe_id = server.universe_id().to_string();
+    let store = server.world().store();
+
+    let admin = server
+        .connect_as(harness::Role::Admin {
+            username: "billingadmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut wizard = server
+        .connect_as(harness::Role::Wizard {
+            username: "billingwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    let base = format!("/credits/{}/{}", universe_id, wizard.account_id().unwrap());
+
+    // Writes cost 3 credits; everything else in this eval is free
+    store
+        .set_universe_setting(&universe_id, "costs", r#"{"db_write": 3000}"#)
+        .await
+        .unwrap();
+    let response = server
+        .post_auth(
+            &format!("{}/grant", base),
+            &serde_json::json!({"amount": 10, "reason": "test funds"}),
+            admin.auth_token().unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    wizard
+        .command("eval return game.create_object('/items/paid-coin', 'item', nil, {}).id")
+        .await
+        .unwrap();
+    wizard.expect("echo").await.unwrap();
+    let output = wizard.expect("output").await.unwrap();
+    assert_eq!(output["text"], "/items/paid-coin");
+
+    let transactions: serde_json::Value = server
+        .get_auth(
+            &format!("{}/transactions", base),
+            admin.auth_token().unwrap(),
+        )
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(transactions[0]["amount"], -3);
+    assert_eq!(transactions[0]["balance_after"], 7);
+    assert!(transactions[0]["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("eval: "));
+
+    // An execution that could cost more than the balance is refused
+    store
+        .set_universe_setting(
+            &universe_id,
+            "costs",
+            r#"{"instructions_per_million": 1000000}"#,
+        )
+        .await
+        .unwrap();
+    wizard.command("eval return 1").await.unwrap();
+    wizard.expect("echo").await.unwrap();
+    let error = wizard.expect("error").await.unwrap();
+    assert!(error["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Insufficient credits"));
+}
@@ -0,0 +1,218 @@
+//! Integration tests for multi-node Raft replication
+//!
+//! Starts several in-process `Server`s on loopback, each with its own
+//! database, and verifies that writes on the leader reach the followers.
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use mudd::{Config, Server};
+use tempfile::TempDir;
+
+/// Reserve a free port on loopback
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Initialize a fresh database for one node
+async fn init_node_db(dir: &TempDir, node_id: u64) -> String {
+    let db_path = dir.path().join(format!("node{}.db", node_id));
+    let no_libs = dir.path().join("no_libs");
+    mudd::init::init_database(
+        &db_path,
+        Some("admin"),
+        Some("password123"),
+        HashMap::new(),
+        Some(no_libs.as_path()),
+    )
+    .await
+    .unwrap();
+    db_path.to_str().unwrap().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_three_node_replication() {
+    let dir = TempDir::new().unwrap();
+
+    let raft_ports: Vec<u16> = (0..3).map(|_| free_port()).collect();
+    let peers = raft_ports
+        .iter()
+        .enumerate()
+        .map(|(i, port)| format!("{}=127.0.0.1:{}", i + 1, port))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut configs = Vec::new();
+    for (i, &raft_port) in raft_ports.iter().enumerate() {
+        let node_id = (i + 1) as u64;
+        configs.push(Config {
+            bind_addr: format!("127.0.0.1:{}", free_port()).parse().unwrap(),
+            db_path: init_node_db(&dir, node_id).await,
+            node_id,
+            raft_port,
+            peers: Some(peers.clone()),
+            join: false,
+            telnet_addr: None,
+            public_domain: None,
+            login_limits: Default::default(),
+        });
+    }
+
+    // Each server waits for a leader, so all three must start together
+    let (s1, s2, s3) = tokio::join!(
+        Server::new(configs[0].clone()),
+        Server::new(configs[1].clone()),
+        Server::new(configs[2].clone()),
+    );
+    let servers = [s1.unwrap(), s2.unwrap(), s3.unwrap()];
+
+    let leader = servers
+        .iter()
+        .find(|s| s.raft_writer().is_leader())
+        .expect("no leader elected");
+
+    leader
+        .raft_writer()
+        .execute(
+            "INSERT INTO code_store (hash, source) VALUES (?, ?)",
+            vec![
+                serde_json::json!("replication-test"),
+                serde_json::json!("return 42"),
+            ],
+        )
+        .await
+        .unwrap();
+
+    for server in &servers {
+        let pool = server.db().pool().clone();
+        let mut found = None;
+        for _ in 0..50 {
+            found = sqlx::query_as::<_, (String,)>(
+                "SELECT source FROM code_store WHERE hash = 'replication-test'",
+            )
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+            if found.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(
+            found.map(|(source,)| source).as_deref(),
+            Some("return 42"),
+            "write not replicated to node {}",
+            server.raft_writer().node_id()
+        );
+    }
+
+    for server in &servers {
+        server.shutdown();
+    }
+}
+
+/// Poll a node's database until a code_store entry appears
+async fn wait_for_code(server: &Server, hash: &str) -> Option<String> {
+    let pool = server.db().pool().clone();
+    for _ in 0..50 {
+        let found = sqlx::query_as::<_, (String,)>("SELECT source FROM code_store WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        if let Some((source,)) = found {
+            return Some(source);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    None
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_add_promote_and_remove_node() {
+    let dir = TempDir::new().unwrap();
+    let (port1, port2) = (free_port(), free_port());
+
+    // Start a one-node cluster
+    let leader = Server::new(Config {
+        bind_addr: format!("127.0.0.1:{}", free_port()).parse().unwrap(),
+        db_path: init_node_db(&dir, 1).await,
+        node_id: 1,
+        raft_port: port1,
+        peers: Some(format!("1=127.0.0.1:{}", port1)),
+        join: false,
+        telnet_addr: None,
+        public_domain: None,
+        login_limits: Default::default(),
+    })
+    .await
+    .unwrap();
+    assert!(leader.raft_writer().is_leader());
+
+    // Start a node that only knows its own address and waits to be added
+    let joiner = Server::new(Config {
+        bind_addr: format!("127.0.0.1:{}", free_port()).parse().unwrap(),
+        db_path: init_node_db(&dir, 2).await,
+        node_id: 2,
+        raft_port: port2,
+        peers: Some(format!("2=127.0.0.1:{}", port2)),
+        join: true,
+        telnet_addr: None,
+        public_domain: None,
+        login_limits: Default::default(),
+    })
+    .await
+    .unwrap();
+
+    let writer = leader.raft_writer();
+    writer
+        .add_learner(2, &format!("127.0.0.1:{}", port2))
+        .await
+        .unwrap();
+    assert_eq!(writer.membership().learners, vec![2]);
+
+    writer
+        .execute(
+            "INSERT INTO code_store (hash, source) VALUES (?, ?)",
+            vec![
+                serde_json::json!("learner-test"),
+                serde_json::json!("return 1"),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        wait_for_code(&joiner, "learner-test").await.as_deref(),
+        Some("return 1")
+    );
+
+    writer.promote_voter(2).await.unwrap();
+    let membership = writer.membership();
+    assert_eq!(membership.voters, vec![1, 2]);
+    assert!(membership.learners.is_empty());
+
+    writer.remove_node(2).await.unwrap();
+    let membership = writer.membership();
+    assert_eq!(membership.voters, vec![1]);
+    assert!(!membership.nodes.contains_key(&2));
+
+    // The cluster keeps accepting writes after the node is gone
+    writer
+        .execute(
+            "INSERT INTO code_store (hash, source) VALUES (?, ?)",
+            vec![
+                serde_json::json!("after-remove"),
+                serde_json::json!("return 2"),
+            ],
+        )
+        .await
+        .unwrap();
+
+    leader.shutdown();
+    joiner.shutdown();
+}
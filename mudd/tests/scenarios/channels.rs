@@ -0,0 +1,178 @@
+//! Channel tests
+//!
+//! Tests chat channels, their history and permissions, moderation, tells
+//! and ignore lists
+
+use crate::harness::{Role, TestServer};
+
+/// Test: players talk on auto-joined channels, wizards alone get the wizard
+/// channel, and ignored players' messages and tells don't arrive
+#[tokio::test]
+async fn test_channels_tells_and_ignore() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut alice = server
+        .connect_as(Role::Player {
+            username: "alice".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut bob = server
+        .connect_as(Role::Player {
+            username: "bob".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut wiz = server
+        .connect_as(Role::Wizard {
+            username: "merlin".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    alice.drain().await;
+    bob.drain().await;
+    wiz.drain().await;
+
+    alice.command("gossip hello all").await.unwrap();
+    let said = alice.expect("output").await.expect("no gossip echo");
+    assert_eq!(said["text"], "[gossip] alice: hello all");
+    let heard = bob.expect("output").await.expect("bob missed gossip");
+    assert_eq!(heard["text"], "[gossip] alice: hello all");
+    let heard = wiz.expect("output").await.expect("wizard missed gossip");
+    assert_eq!(heard["text"], "[gossip] alice: hello all");
+
+    bob.command("channels").await.unwrap();
+    let list = bob.expect("output").await.expect("no channel list");
+    let list = list["text"].as_str().unwrap();
+    assert!(list.contains("gossip"), "channels: {}", list);
+    assert!(list.contains("chat"), "channels: {}", list);
+    assert!(!list.contains("wiz"), "channels: {}", list);
+
+    bob.command("wiz let me in").await.unwrap();
+    let refused = bob.expect("output").await.expect("no refusal");
+    assert_eq!(refused["text"], "Unknown command: wiz");
+
+    wiz.command("wiz wizards only").await.unwrap();
+    let said = wiz.expect("output").await.expect("no wiz echo");
+    assert_eq!(said["text"], "[wiz] merlin: wizards only");
+
+    bob.command("ignore alice").await.unwrap();
+    let ignoring = bob.expect("output").await.expect("no ignore output");
+    assert_eq!(ignoring["text"], "You are now ignoring alice.");
+
+    alice.command("tell bob psst").await.unwrap();
+    let refused = alice.expect("output").await.expect("no tell output");
+    assert_eq!(refused["text"], "bob isn't listening to you.");
+
+    alice.command("chat anyone here?").await.unwrap();
+    alice.expect("output").await.expect("no chat echo");
+    let heard = wiz.expect("output").await.expect("wizard missed chat");
+    assert_eq!(heard["text"], "[chat] alice: anyone here?");
+
+    wiz.command("tell bob hi there").await.unwrap();
+    let told = wiz.expect("output").await.expect("no tell output");
+    assert_eq!(told["text"], "You tell bob: hi there");
+
+    // Alice's chat never reached bob, so the tell comes first
+    let heard = bob.expect("output").await.expect("bob missed tell");
+    assert_eq!(heard["text"], "merlin tells you: hi there");
+
+    bob.command("channel history gossip").await.unwrap();
+    let history = bob.expect("output").await.expect("no history");
+    let history = history["text"].as_str().unwrap();
+    assert!(
+        history.starts_with("Recent messages on gossip:"),
+        "history: {}",
+        history
+    );
+    assert!(history.contains("alice: hello all"), "history: {}", history);
+}
+
+/// Test: moderators mute players, players leave channels, universes add
+/// their own channels and guests only listen
+#[tokio::test]
+async fn test_channel_moderation_and_universe_channels() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO universe_settings (universe_id, key, value)
+         VALUES (?, 'channels', '[{\"name\": \"builders\", \"min_level\": \"builder\", \"auto_join\": true}]')",
+    )
+    .bind(server.universe_id())
+    .execute(server.pool())
+    .await
+    .expect("Failed to set channels");
+
+    let mut wiz = server
+        .connect_as(Role::Wizard {
+            username: "moderator".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut pest = server
+        .connect_as(Role::Player {
+            username: "pest".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut guest = server.connect_guest().await.expect("Failed to connect");
+    wiz.drain().await;
+    pest.drain().await;
+    guest.drain().await;
+
+    wiz.command("builders new channel").await.unwrap();
+    let said = wiz.expect("output").await.expect("no builders echo");
+    assert_eq!(said["text"], "[builders] moderator: new channel");
+
+    pest.command("channel mute chat moderator").await.unwrap();
+    let denied = pest.expect("error").await.expect("player could moderate");
+    assert!(denied["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Permission denied"));
+
+    wiz.command("channel mute chat pest").await.unwrap();
+    let muted = wiz.expect("output").await.expect("no mute output");
+    assert_eq!(muted["text"], "pest is muted on chat.");
+
+    pest.command("chat spam").await.unwrap();
+    let refused = pest.expect("output").await.expect("no mute notice");
+    assert_eq!(refused["text"], "You have been muted on chat.");
+
+    wiz.command("channel unmute chat pest").await.unwrap();
+    let unmuted = wiz.expect("output").await.expect("no unmute output");
+    assert_eq!(unmuted["text"], "pest may talk on chat again.");
+
+    guest.command("chat hello?").await.unwrap();
+    let refused = guest.expect("error").await.expect("guest could talk");
+    assert_eq!(refused["message"], "Guests can only listen on channels.");
+
+    pest.command("channel leave chat").await.unwrap();
+    let left = pest.expect("output").await.expect("no leave output");
+    assert_eq!(left["text"], "You leave chat.");
+
+    wiz.command("chat quiet now").await.unwrap();
+    wiz.expect("output").await.expect("no chat echo");
+    let heard = guest.expect("output").await.expect("guest missed chat");
+    assert_eq!(heard["text"], "[chat] moderator: quiet now");
+    assert!(
+        pest.drain().await.iter().all(|m| m["type"] != "output"),
+        "pest heard a channel they left"
+    );
+
+    // Leaving is remembered on the player object
+    drop(pest);
+    let mut pest = server
+        .connect_as(Role::Player {
+            username: "pest".to_string(),
+        })
+        .await
+        .expect("Failed to reconnect");
+    pest.drain().await;
+    pest.command("chat back again").await.unwrap();
+    let refused = pest.expect("output").await.expect("no refusal");
+    assert_eq!(
+        refused["text"],
+        "You aren't on chat. Join it with: channel join chat"
+    );
+}
@@ -0,0 +1,275 @@
+//! Command dispatch scenario tests
+//!
+//! Tests that verbs reach actions registered with game.add_action before
+//! falling back to mudlib commands and built-ins
+
+use crate::harness::{Role, TestServer};
+use mudd::objects::Object;
+use std::time::Duration;
+
+const LEVER_CODE: &str = r#"
+return {
+    on_pull = function(args)
+        game.send(args.actor_id, "Clunk!")
+        return "You pull the " .. args.args .. "."
+    end,
+    on_look = function(args)
+        return false
+    end
+}
+"#;
+
+/// Put a lever with action handlers in the entrance
+async fn create_lever(server: &TestServer) -> String {
+    let world = server.world();
+    let hash = world.store().store_code(LEVER_CODE).await.unwrap();
+    let mut lever = Object::new("/items/lever", &world.universe_id, "item").unwrap();
+    lever.parent_id = Some(world.entrance_id.clone());
+    lever.set_property("name", serde_json::json!("lever"));
+    lever.code_hash = Some(hash);
+    world.store().create(&lever).await.unwrap();
+    lever.id
+}
+
+/// Test: A room action handles a verb the server doesn't know
+#[tokio::test]
+async fn test_room_action_handles_custom_verb() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let lever_id = create_lever(&server).await;
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "leverwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard
+        .command(&format!(
+            r#"eval return game.add_action("pull", "{}", "on_pull")"#,
+            lever_id
+        ))
+        .await
+        .expect("eval failed");
+    wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("add_action failed");
+
+    wizard.command("pull lever").await.expect("pull failed");
+
+    let mut texts = Vec::new();
+    for _ in 0..2 {
+        let msg = wizard
+            .expect_timeout("output", Duration::from_secs(5))
+            .await
+            .expect("no response to pull");
+        texts.push(msg["text"].as_str().unwrap_or("").to_string());
+    }
+    assert!(texts.contains(&"Clunk!".to_string()), "{:?}", texts);
+    assert!(
+        texts.contains(&"You pull the lever.".to_string()),
+        "{:?}",
+        texts
+    );
+}
+
+/// Test: An action that returns false lets the built-in command run
+#[tokio::test]
+async fn test_declined_action_falls_through() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let lever_id = create_lever(&server).await;
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "lookwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard
+        .command(&format!(
+            r#"eval return game.add_action("look", "{}", "on_look")"#,
+            lever_id
+        ))
+        .await
+        .expect("eval failed");
+    wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("add_action failed");
+
+    wizard.command("look").await.expect("look failed");
+    wizard
+        .expect_timeout("room", Duration::from_secs(5))
+        .await
+        .expect("look should still show the room");
+}
+
+/// Test: Verbs nobody handles are reported as unknown
+#[tokio::test]
+async fn test_unknown_verb() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "unknownwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard.command("xyzzy").await.expect("command failed");
+    let msg = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no response");
+    assert!(msg["text"]
+        .as_str()
+        .unwrap_or("")
+        .contains("Unknown command"));
+}
+
+/// Test: An object's init() gives a verb to players who walk in, and they
+/// Test: Guests get answers to take, drop and inventory
+#[tokio::test]
+async fn test_guests_can_use_inventory_verbs() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let mut guest = server.connect_guest().await.expect("Failed to connect");
+    guest.drain().await;
+
+    for command in ["inventory", "take lamp", "drop lamp"] {
+        guest.command(command).await.expect("command failed");
+        let msg = guest
+            .expect_timeout("output", Duration::from_secs(5))
+            .await
+            .expect("no response");
+        let text = msg["text"].as_str().unwrap_or("");
+        assert!(
+            !text.starts_with("Unknown command"),
+            "{}: {}",
+            command,
+            text
+        );
+    }
+}
+
+/// lose it when they walk out
+#[tokio::test]
+async fn test_init_actions_follow_the_player() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let world = server.world();
+
+    let code = r#"
+return {
+    on_init = function(ctx)
+        game.add_action("ring", ctx.object_id, "on_ring")
+    end,
+    on_ring = function(ctx)
+        return "Ding! " .. ctx.actor_id .. " rings the bell."
+    end
+}
+"#;
+    let hash = world.store().store_code(code).await.unwrap();
+    let mut bell = Object::new("/items/bell", &world.universe_id, "item").unwrap();
+    bell.parent_id = Some(world.passage_id.clone());
+    bell.set_property("name", serde_json::json!("bell"));
+    bell.code_hash = Some(hash);
+    world.store().create(&bell).await.unwrap();
+
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "ringer".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    player.drain().await;
+    let player_id = player.player_id().unwrap().to_string();
+
+    player.command("ring").await.expect("ring failed");
+    let msg = player
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no response");
+    assert_eq!(msg["text"], "Unknown command: ring");
+
+    player.command("north").await.expect("north failed");
+    player.expect("room").await.expect("no room after north");
+    player.command("ring").await.expect("ring failed");
+    let msg = player
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no response");
+    assert_eq!(msg["text"], format!("Ding! {} rings the bell.", player_id));
+
+    player.command("south").await.expect("south failed");
+    player.expect("room").await.expect("no room after south");
+    player.command("ring").await.expect("ring failed");
+    let msg = player
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no response");
+    assert_eq!(msg["text"], "Unknown command: ring");
+}
+
+/// Test: Globals don't leak from one command's sandbox to the next, and a
+/// new library is picked up by the next command
+#[tokio::test]
+async fn test_warm_sandboxes_reset_and_follow_libraries() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let world = server.world();
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "poolwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard
+        .command(r#"eval leaked = "yes"; return type(Commands)"#)
+        .await
+        .expect("eval failed");
+    let msg = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no eval result");
+    assert_eq!(msg["text"], "table");
+
+    wizard
+        .command("eval return tostring(leaked)")
+        .await
+        .expect("eval failed");
+    let msg = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no eval result");
+    assert_eq!(msg["text"], "nil");
+
+    // Libraries run in name order, so this one extends the core Commands
+    let hash = world
+        .store()
+        .store_code(r#"Commands.flourish = function(player_id, args) return "You flourish." end"#)
+        .await
+        .unwrap();
+    let mut libs = world.store().get_core_lib_hashes().await.unwrap();
+    libs.insert("zz_flourish".to_string(), hash);
+    world
+        .store()
+        .update_universe(
+            &world.universe_id,
+            serde_json::json!({ "lib_hashes": libs }),
+        )
+        .await
+        .unwrap();
+
+    wizard.command("flourish").await.expect("flourish failed");
+    let msg = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no response");
+    assert_eq!(msg["text"], "You flourish.");
+}
@@ -326,3 +326,55 @@ async fn test_get_nonexistent() {
         );
     }
 }
+
+/// Test: Others in the room see a take, but the taker isn't echoed twice
+#[tokio::test]
+async fn test_take_is_broadcast_to_room() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut taker = server
+        .connect_as(Role::Wizard {
+            username: "taker".to_string(),
+        })
+        .await
+        .expect("Failed to connect taker");
+    let mut watcher = server
+        .connect_as(Role::Wizard {
+            username: "watcher".to_string(),
+        })
+        .await
+        .expect("Failed to connect watcher");
+
+    // Both start at the entrance with the Rusty Short Sword
+    taker.drain().await;
+    watcher.drain().await;
+
+    taker.command("take sword").await.expect("take failed");
+
+    let msg = watcher
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("watcher saw nothing");
+    let text = msg["text"].as_str().unwrap_or("");
+    assert!(
+        text.contains("takes"),
+        "Expected a take broadcast: {}",
+        text
+    );
+
+    let msg = taker
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("taker got no response");
+    assert!(msg["text"].as_str().unwrap_or("").contains("You take"));
+
+    let extra = taker.drain().await;
+    assert!(
+        extra.iter().all(|m| {
+            let text = m["text"].as_str().unwrap_or("");
+            !text.contains("takes") && !text.contains("You take")
+        }),
+        "Taker received duplicate messages: {:?}",
+        extra
+    );
+}
@@ -0,0 +1,151 @@
+//! Lifecycle handler scenario tests
+//!
+//! Tests class handler code defined with game.define_class, parent() calls
+//! up the inheritance chain, and the handlers the server fires on create,
+//! movement and combat
+
+use crate::harness::{Role, TestServer};
+use std::time::Duration;
+
+/// Test: Class handlers are inherited, chain to their parent with
+/// parent(ctx) and run on_create when an object is made
+#[tokio::test]
+async fn test_class_handlers_chain_to_parent() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "classwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard
+        .command(
+            r#"eval
+game.define_class("blade", {parent = "item", code = [[
+return {
+    on_use = function(ctx) return "blade" end,
+    on_create = function(ctx) game.update_object(ctx.object_id, {forged = true}) end
+}
+]]})
+game.define_class("flame_blade", {parent = "blade", code = [[
+return {
+    on_use = function(ctx) return "flame " .. parent(ctx) end
+}
+]]})
+local sword = game.create_object("/items/flamberge", "flame_blade", nil, {name = "flamberge"})
+local used = game.use_object(sword.id, "someone", "use", nil)
+return used .. " " .. tostring(game.get_object(sword.id).metadata.forged)"#,
+        )
+        .await
+        .expect("eval failed");
+
+    let result = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no eval result");
+    assert_eq!(result["text"], "flame blade true");
+
+    wizard
+        .command(r#"eval return table.concat(game.get_class("flame_blade").handlers, ",")"#)
+        .await
+        .expect("eval failed");
+    let handlers = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no eval result");
+    assert_eq!(handlers["text"], "on_use");
+}
+
+/// Test: A room's class runs on_enter when a player walks in, and a
+/// monster's class hears about damage and death
+#[tokio::test]
+async fn test_server_fires_enter_damage_and_death() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let world = server.world();
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "trapwizard".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    wizard.drain().await;
+
+    wizard
+        .command(
+            r#"eval
+game.define_class("trap_room", {parent = "room", code = [[
+return {
+    on_enter = function(ctx) game.send(ctx.actor_id, "A pressure plate clicks.") end
+}
+]]})
+game.define_class("frail_monster", {parent = "npc", code = [[
+return {
+    on_damage = function(ctx) game.send(ctx.actor_id, "It shrieks at " .. ctx.damage_type .. " damage!") end,
+    on_death = function(ctx) game.send(ctx.actor_id, "It crumbles to dust.") end
+}
+]]})
+return true"#,
+        )
+        .await
+        .expect("eval failed");
+    wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("define_class failed");
+
+    let mut passage = world.store().get(&world.passage_id).await.unwrap().unwrap();
+    passage.class = "trap_room".to_string();
+    world.store().update(&passage).await.unwrap();
+
+    wizard.command("north").await.expect("north failed");
+    let trap = wizard
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("on_enter didn't run");
+    assert_eq!(trap["text"], "A pressure plate clicks.");
+    wizard.expect("room").await.expect("no room after north");
+
+    // A monster with one hit point dies to the first hit
+    let mut rat =
+        mudd::objects::Object::new("/npcs/rat", &world.universe_id, "frail_monster").unwrap();
+    rat.parent_id = Some(world.passage_id.clone());
+    rat.set_property("name", serde_json::json!("rat"));
+    rat.set_property("hp", serde_json::json!(1));
+    world.store().create(&rat).await.unwrap();
+
+    let mut texts = Vec::new();
+    'fight: for _ in 0..20 {
+        wizard.command("attack rat").await.expect("attack failed");
+        // Handler messages arrive before the attacker's account of the round
+        loop {
+            let msg = wizard
+                .expect_timeout("output", Duration::from_secs(5))
+                .await
+                .expect("no attack output");
+            let text = msg["text"].as_str().unwrap().to_string();
+            let round_over = text.starts_with("You ") || text.starts_with("CRITICAL");
+            let slain = text.contains("is slain!");
+            texts.push(text);
+            if slain {
+                break 'fight;
+            }
+            if round_over {
+                break;
+            }
+        }
+    }
+    assert!(
+        texts.contains(&"It shrieks at physical damage!".to_string()),
+        "{:?}",
+        texts
+    );
+    assert!(
+        texts.contains(&"It crumbles to dust.".to_string()),
+        "{:?}",
+        texts
+    );
+}
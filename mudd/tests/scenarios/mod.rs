@@ -5,12 +5,28 @@
 //! - Inventory: Item pickup, drop, and listing
 //! - Combat: NPC attacks and damage
 //! - Chat: Say command and messaging
+//! - Channels: Chat channels, history, moderation, tells and ignore lists
+//! - Commands: Dispatch to registered actions and mudlib commands
+//! - Lifecycle: Class handlers, parent() chaining and server-fired events
 //! - Multiuser: Builder permissions, path grants, multi-user interactions
 //! - Player lifecycle: Persistent objects, safe zones, disconnect, reconnect
+//! - Shop: Buying from vendors with credits
+//! - Presence: Who, finger, AFK and friend notifications
+//! - Protocol: Typed events negotiated with hello
+//! - Rate limits: Command limits and the login lockout
+//! - Telnet: Login, GMCP and shared rooms over the telnet gateway
 
+pub mod channels;
 pub mod chat;
 pub mod combat;
+pub mod commands;
 pub mod inventory;
+pub mod lifecycle;
 pub mod movement;
 pub mod multiuser;
 pub mod player_lifecycle;
+pub mod presence;
+pub mod protocol;
+pub mod rate_limits;
+pub mod shop;
+pub mod telnet;
@@ -308,6 +308,63 @@ async fn test_reconnect_grace_period() {
     wizard2.close().await.ok();
 }
 
+/// Test: Messages sent while disconnected are replayed on resume
+#[tokio::test]
+async fn test_resume_replays_missed_messages() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut alice = server
+        .connect_as(Role::Player {
+            username: "resume_alice".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut bob = server
+        .connect_as(Role::Player {
+            username: "resume_bob".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    alice.drain().await;
+    bob.drain().await;
+    let last_seq = alice.last_seq();
+    assert!(last_seq > 0, "messages should carry sequence numbers");
+
+    // Alice's connection drops; Bob keeps talking
+    alice.close().await.ok();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    bob.command("say still there?").await.expect("say failed");
+    bob.expect("output").await.expect("no say confirmation");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let mut alice = server
+        .connect_as(Role::Player {
+            username: "resume_alice".to_string(),
+        })
+        .await
+        .expect("Failed to reconnect");
+    alice.send_resume(last_seq).await.expect("resume failed");
+
+    let mut replayed = Vec::new();
+    let resumed = loop {
+        let msg = alice.expect_any().await.expect("no resumed reply");
+        if msg["type"] == "resumed" {
+            break msg;
+        }
+        replayed.push(msg);
+    };
+    assert!(resumed["replayed"].as_u64().unwrap() >= 1);
+    assert_eq!(resumed["missed"], 0);
+    assert!(
+        replayed
+            .iter()
+            .any(|m| m["text"] == "resume_bob says, \"still there?\""
+                && m["seq"].as_u64().unwrap() > last_seq),
+        "missed speech not replayed: {:?}",
+        replayed
+    );
+}
+
 /// Test: Workroom is a safe zone
 #[tokio::test]
 async fn test_workroom_is_safe_zone() {
@@ -0,0 +1,131 @@
+//! Presence tests
+//!
+//! Tests who, finger, AFK status, the online endpoint and friend
+//! notifications
+
+use std::time::Duration;
+
+use crate::harness::{Role, TestServer};
+
+/// Test: who, finger and the online endpoint show connected players and
+/// their AFK status
+#[tokio::test]
+async fn test_who_finger_and_afk() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "watcher".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "napper".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    wizard.drain().await;
+    player.drain().await;
+
+    player.command("afk lunch").await.unwrap();
+    let afk = player.expect("output").await.expect("no afk confirmation");
+    assert_eq!(afk["text"], "You are now AFK: lunch");
+
+    wizard.command("who").await.unwrap();
+    let who = wizard.expect("output").await.expect("no who output");
+    let who = who["text"].as_str().unwrap();
+    assert!(who.starts_with("2 players online:"), "who: {}", who);
+    assert!(who.contains("watcher"), "who: {}", who);
+    assert!(who.contains("[AFK: lunch]"), "who: {}", who);
+
+    wizard.command("finger napper").await.unwrap();
+    let finger = wizard.expect("output").await.expect("no finger output");
+    let finger = finger["text"].as_str().unwrap();
+    assert!(finger.starts_with("napper [player]"), "finger: {}", finger);
+    assert!(finger.contains("AFK: lunch"), "finger: {}", finger);
+    assert!(finger.contains("Last login:"), "finger: {}", finger);
+
+    wizard
+        .command("eval return #game.online_players()")
+        .await
+        .unwrap();
+    let count = wizard.expect("output").await.expect("no eval output");
+    assert!(count["text"].as_str().unwrap().contains('2'));
+
+    let path = format!("/universe/{}/online", server.universe_id());
+    let resp = server
+        .get_auth(&path, wizard.auth_token().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let online: serde_json::Value = resp.json().await.unwrap();
+    let napper = online
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["name"] == "napper")
+        .expect("napper not listed");
+    assert_eq!(napper["afk"], "lunch");
+    assert_eq!(server.get(&path).await.unwrap().status(), 401);
+
+    // Any other command ends AFK
+    player.command("look").await.unwrap();
+    let back = player.expect("output").await.expect("no return message");
+    assert_eq!(back["text"], "You are no longer AFK.");
+}
+
+/// Test: friends hear when a player logs in and, after the grace period,
+/// out
+#[tokio::test]
+async fn test_friends_notified_of_login_and_logout() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let resp = server
+        .post(
+            "/auth/register",
+            &serde_json::json!({"username": "pal", "password": Role::password()}),
+        )
+        .await
+        .unwrap();
+    assert!(resp.status().is_success());
+
+    let mut host = server
+        .connect_as(Role::Player {
+            username: "host".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    host.drain().await;
+
+    host.command("friend add pal").await.unwrap();
+    let added = host.expect("output").await.expect("no friend output");
+    assert_eq!(added["text"], "pal is now your friend.");
+
+    let pal = server
+        .connect_as(Role::Player {
+            username: "pal".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let login = host.expect("output").await.expect("no login notice");
+    assert_eq!(login["text"], "Your friend pal has logged in.");
+
+    host.command("friend").await.unwrap();
+    let list = host.expect("output").await.expect("no friends list");
+    assert_eq!(list["text"], "Friends:\n  pal (online)");
+
+    drop(pal);
+    let logout = host
+        .expect_timeout("output", Duration::from_secs(10))
+        .await
+        .expect("no logout notice");
+    assert_eq!(logout["text"], "Your friend pal has logged out.");
+
+    host.command("finger pal").await.unwrap();
+    let finger = host.expect("output").await.expect("no finger output");
+    assert!(finger["text"]
+        .as_str()
+        .unwrap()
+        .contains("Offline, last seen"));
+}
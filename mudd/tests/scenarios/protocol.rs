@@ -0,0 +1,120 @@
+//! Typed event protocol tests
+//!
+//! Tests the hello handshake, state events and plain-text fallback for
+//! clients that never negotiate
+
+use crate::harness::{Role, TestServer};
+
+/// Test: hello negotiates events and starts the client off with its state
+#[tokio::test]
+async fn test_hello_negotiates_typed_events() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut alice = server
+        .connect_as(Role::Player {
+            username: "helloalice".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    alice.expect("room").await.expect("no initial room");
+
+    alice
+        .send_hello(&["vitals", "inventory", "prompt", "telepathy"])
+        .await
+        .expect("hello failed");
+    let hello = alice.expect("hello").await.expect("no hello reply");
+    assert_eq!(hello["protocol"], 1);
+    assert_eq!(
+        hello["capabilities"],
+        serde_json::json!(["inventory", "prompt", "vitals"])
+    );
+
+    let vitals = alice.expect("vitals").await.expect("no vitals");
+    assert_eq!(vitals["hp"], vitals["max_hp"]);
+    assert_eq!(vitals["in_combat"], false);
+    let inventory = alice.expect("inventory").await.expect("no inventory");
+    assert!(inventory["items"].is_array());
+
+    // Unchanged state isn't sent again after a command
+    alice.command("look").await.expect("look failed");
+    alice.expect("room").await.expect("no room");
+    let after = alice.drain().await;
+    assert!(
+        !after
+            .iter()
+            .any(|m| m["type"] == "vitals" || m["type"] == "inventory"),
+        "unchanged state was resent: {:?}",
+        after
+    );
+}
+
+/// Test: occupants and speech reach negotiated clients as events and
+/// other clients as text
+#[tokio::test]
+async fn test_room_events_and_legacy_fallback() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut rich = server
+        .connect_as(Role::Player {
+            username: "richclient".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    rich.expect("room").await.expect("no initial room");
+    rich.send_hello(&["room_occupants_changed", "channel_message"])
+        .await
+        .expect("hello failed");
+    rich.expect("hello").await.expect("no hello reply");
+    rich.expect("room_occupants_changed")
+        .await
+        .expect("no initial occupants");
+
+    let mut plain = server
+        .connect_as(Role::Player {
+            username: "plainclient".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    plain.expect("room").await.expect("no initial room");
+
+    // The rich client sees the newcomer arrive
+    let occupants = rich
+        .expect("room_occupants_changed")
+        .await
+        .expect("no occupants update");
+    let names: Vec<&str> = occupants["occupants"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|o| o["name"].as_str())
+        .collect();
+    assert!(names.contains(&"richclient"), "occupants: {:?}", names);
+    assert!(names.contains(&"plainclient"), "occupants: {:?}", names);
+
+    // Speech arrives as a channel message for one and text for the other
+    plain.command("say hello there").await.expect("say failed");
+    let said = rich
+        .expect("channel_message")
+        .await
+        .expect("no channel message");
+    assert_eq!(said["channel"], "say");
+    assert_eq!(said["sender"], "plainclient");
+    assert_eq!(said["text"], "hello there");
+
+    rich.command("say hi back").await.expect("say failed");
+    let heard = plain.expect("output").await.expect("no output");
+    let heard = if heard["text"] == "You say, \"hello there\"" {
+        plain.expect("output").await.expect("no output")
+    } else {
+        heard
+    };
+    assert_eq!(heard["text"], "richclient says, \"hi back\"");
+
+    // Events the plain client never asked for don't reach it
+    let rest = plain.drain().await;
+    assert!(
+        !rest.iter().any(|m| m["type"] == "room_occupants_changed"),
+        "legacy client got a typed event: {:?}",
+        rest
+    );
+}
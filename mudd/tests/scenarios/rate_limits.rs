@@ -0,0 +1,79 @@
+//! Rate limit tests
+//!
+//! Tests command limits from universe settings and the login lockout
+
+use crate::harness::{Role, TestServer};
+
+/// Test: commands over the universe's limit are refused with a typed error
+#[tokio::test]
+async fn test_commands_over_limit_are_refused() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO universe_settings (universe_id, key, value)
+         VALUES (?, 'rate_limits', '{\"commands_per_minute\": 2}')",
+    )
+    .bind(server.universe_id())
+    .execute(server.pool())
+    .await
+    .expect("Failed to set rate limits");
+
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "spammer".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    player.expect("room").await.expect("no initial room");
+
+    for _ in 0..2 {
+        player.command("look").await.unwrap();
+        player.expect("echo").await.expect("command refused early");
+    }
+
+    player.command("look").await.unwrap();
+    let limited = player
+        .expect("rate_limited")
+        .await
+        .expect("third command not limited");
+    assert_eq!(limited["action"], "command");
+    assert!(limited["retry_after_secs"].as_u64().unwrap() >= 1);
+}
+
+/// Test: repeated failed logins lock the account out, even with the right
+/// password
+#[tokio::test]
+async fn test_failed_logins_lock_out_account() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let player = server
+        .connect_as(Role::Player {
+            username: "forgetful".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    drop(player);
+
+    for _ in 0..5 {
+        let resp = server
+            .post(
+                "/auth/login",
+                &serde_json::json!({"username": "forgetful", "password": "wrongpass"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 401);
+    }
+
+    let resp = server
+        .post(
+            "/auth/login",
+            &serde_json::json!({"username": "forgetful", "password": Role::password()}),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(body["action"], "login");
+}
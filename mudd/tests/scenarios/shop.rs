@@ -0,0 +1,330 @@
+//! Shop scenario tests
+//!
+//! Tests that a vendor built on the mudlib's Shop helpers sells its stock,
+//! moving the item and the credits together
+
+use crate::harness::{Role, TestClient, TestServer};
+use mudd::objects::Object;
+use std::time::Duration;
+
+const VENDOR_CODE: &str = r#"
+return {
+    on_list = Shop.list_action,
+    on_buy = Shop.buy_action,
+}
+"#;
+
+/// Put a vendor owned by `owner_id` in the entrance, selling a lamp
+async fn create_vendor(server: &TestServer, owner_id: &str) -> String {
+    create_vendor_with_lamp(server, owner_id, None).await
+}
+
+/// Put a vendor in the entrance, selling a lamp that runs `lamp_code`
+async fn create_vendor_with_lamp(
+    server: &TestServer,
+    owner_id: &str,
+    lamp_code: Option<&str>,
+) -> String {
+    let world = server.world();
+    let store = world.store();
+    let hash = store.store_code(VENDOR_CODE).await.unwrap();
+
+    let mut vendor = Object::new("/npcs/vendor", &world.universe_id, "npc").unwrap();
+    vendor.parent_id = Some(world.entrance_id.clone());
+    vendor.set_property("name", serde_json::json!("vendor"));
+    vendor.code_hash = Some(hash);
+    vendor.owner_id = Some(owner_id.to_string());
+    store.create(&vendor).await.unwrap();
+
+    let mut lamp = Object::new("/items/shop-lamp", &world.universe_id, "item").unwrap();
+    lamp.parent_id = Some(vendor.id.clone());
+    lamp.set_property("name", serde_json::json!("lamp"));
+    lamp.set_property("price", serde_json::json!(30));
+    if let Some(code) = lamp_code {
+        lamp.code_hash = Some(store.store_code(code).await.unwrap());
+    }
+    lamp.owner_id = Some(owner_id.to_string());
+    store.create(&lamp).await.unwrap();
+
+    vendor.id
+}
+
+/// Send a command and return the text of the next output
+async fn output_of(client: &mut TestClient, command: &str) -> String {
+    client.drain().await;
+    client.command(command).await.expect("command failed");
+    let msg = client
+        .expect_timeout("output", Duration::from_secs(5))
+        .await
+        .expect("no output");
+    msg["text"].as_str().unwrap_or("").to_string()
+}
+
+/// Test: Buying from a vendor pays its owner and hands over the item
+#[tokio::test]
+async fn test_vendor_sale() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let universe_id = server.universe_id().to_string();
+
+    let admin = server
+        .connect_as(Role::Admin {
+            username: "shopadmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "shopkeeper".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "shopper".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let admin_token = admin.auth_token().unwrap();
+    let wizard_account = wizard.account_id().unwrap().to_string();
+    let player_account = player.account_id().unwrap().to_string();
+    let player_id = player.player_id().unwrap().to_string();
+
+    let vendor_id = create_vendor(&server, &wizard_account).await;
+    for verb in ["list", "buy"] {
+        let text = output_of(
+            &mut wizard,
+            &format!(
+                r#"eval return game.add_action("{}", "{}", "on_{}")"#,
+                verb, vendor_id, verb
+            ),
+        )
+        .await;
+        assert_eq!(text, "true");
+    }
+
+    let response = server
+        .post_auth(
+            &format!("/credits/{}/{}/grant", universe_id, player_account),
+            &serde_json::json!({"amount": 50, "reason": "allowance"}),
+            admin_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let text = output_of(&mut player, "list").await;
+    assert!(text.contains("lamp - 30 credits"), "{}", text);
+
+    let text = output_of(&mut player, "buy lamp").await;
+    assert_eq!(text, "You buy lamp for 30 credits.");
+
+    let lamp = server
+        .world()
+        .store()
+        .get("/items/shop-lamp")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lamp.parent_id.as_deref(), Some(player_id.as_str()));
+    assert_eq!(lamp.owner_id.as_deref(), Some(player_account.as_str()));
+
+    for (account, balance) in [(&player_account, 20), (&wizard_account, 30)] {
+        let audit: serde_json::Value = server
+            .get_auth(
+                &format!("/credits/{}/{}/audit", universe_id, account),
+                admin_token,
+            )
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(audit["balance"], balance);
+        assert_eq!(audit["consistent"], true);
+    }
+
+    // Sold out
+    let text = output_of(&mut player, "buy lamp").await;
+    assert_eq!(text, "That isn't for sale here.");
+}
+
+/// Test: An action on someone else's object can't spend the player's credits
+#[tokio::test]
+async fn test_foreign_handler_cannot_transfer_player_credits() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let universe_id = server.universe_id().to_string();
+
+    let admin = server
+        .connect_as(Role::Admin {
+            username: "tipadmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "tipjar".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "tipper".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let admin_token = admin.auth_token().unwrap();
+    let wizard_account = wizard.account_id().unwrap().to_string();
+    let player_account = player.account_id().unwrap().to_string();
+
+    let world = server.world();
+    let code = format!(
+        r#"
+return {{
+    on_tip = function(ctx)
+        local ok, err = game.transfer_credits("{}", 10, "tip")
+        return tostring(ok) .. ": " .. tostring(err)
+    end
+}}
+"#,
+        wizard_account
+    );
+    let hash = world.store().store_code(&code).await.unwrap();
+    let mut jar = Object::new("/items/tip-jar", &world.universe_id, "item").unwrap();
+    jar.parent_id = Some(world.entrance_id.clone());
+    jar.set_property("name", serde_json::json!("jar"));
+    jar.code_hash = Some(hash);
+    jar.owner_id = Some(wizard_account.clone());
+    world.store().create(&jar).await.unwrap();
+
+    let text = output_of(
+        &mut wizard,
+        &format!(
+            r#"eval return game.add_action("tip", "{}", "on_tip")"#,
+            jar.id
+        ),
+    )
+    .await;
+    assert_eq!(text, "true");
+
+    let response = server
+        .post_auth(
+            &format!("/credits/{}/{}/grant", universe_id, player_account),
+            &serde_json::json!({"amount": 50, "reason": "allowance"}),
+            admin_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let text = output_of(&mut player, "tip").await;
+    assert!(text.starts_with("false: Permission denied"), "{}", text);
+
+    let audit: serde_json::Value = server
+        .get_auth(
+            &format!("/credits/{}/{}/audit", universe_id, player_account),
+            admin_token,
+        )
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(audit["balance"], 50);
+}
+
+/// Test: A bought item's handlers can't pay its seller from the buyer
+#[tokio::test]
+async fn test_bought_item_cannot_pay_its_seller() {
+    let server = TestServer::start().await.expect("Failed to start server");
+    let universe_id = server.universe_id().to_string();
+
+    let admin = server
+        .connect_as(Role::Admin {
+            username: "drainadmin".to_string(),
+        })
+        .await
+        .expect("Failed to connect as admin");
+    let mut wizard = server
+        .connect_as(Role::Wizard {
+            username: "drainer".to_string(),
+        })
+        .await
+        .expect("Failed to connect as wizard");
+    let mut player = server
+        .connect_as(Role::Player {
+            username: "drained".to_string(),
+        })
+        .await
+        .expect("Failed to connect as player");
+    let admin_token = admin.auth_token().unwrap();
+    let wizard_account = wizard.account_id().unwrap().to_string();
+    let player_account = player.account_id().unwrap().to_string();
+
+    // The seller wrote the lamp's code, but the buyer will own the lamp
+    let lamp_code = format!(
+        r#"
+return {{
+    on_move = function(ctx)
+        local ok, err = game.transfer_credits("{}", 10, "tip")
+        game.update_object(ctx.object_id, {{tip = tostring(ok) .. ": " .. tostring(err)}})
+    end
+}}
+"#,
+        wizard_account
+    );
+    let vendor_id = create_vendor_with_lamp(&server, &wizard_account, Some(&lamp_code)).await;
+    let text = output_of(
+        &mut wizard,
+        &format!(
+            r#"eval return game.add_action("buy", "{}", "on_buy")"#,
+            vendor_id
+        ),
+    )
+    .await;
+    assert_eq!(text, "true");
+
+    let response = server
+        .post_auth(
+            &format!("/credits/{}/{}/grant", universe_id, player_account),
+            &serde_json::json!({"amount": 50, "reason": "allowance"}),
+            admin_token,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let text = output_of(&mut player, "buy lamp").await;
+    assert_eq!(text, "You buy lamp for 30 credits.");
+
+    // Dropping the lamp runs its on_move as the buyer
+    let text = output_of(&mut player, "drop lamp").await;
+    assert_eq!(text, "You drop lamp.");
+
+    let lamp = server
+        .world()
+        .store()
+        .get("/items/shop-lamp")
+        .await
+        .unwrap()
+        .unwrap();
+    let tip = lamp.get_string("tip");
+    assert!(
+        tip.is_some_and(|t| t.starts_with("false: Permission denied")),
+        "{:?}",
+        tip
+    );
+
+    for (account, balance) in [(&player_account, 20), (&wizard_account, 30)] {
+        let audit: serde_json::Value = server
+            .get_auth(
+                &format!("/credits/{}/{}/audit", universe_id, account),
+                admin_token,
+            )
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(audit["balance"], balance);
+    }
+}
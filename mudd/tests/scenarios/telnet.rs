@@ -0,0 +1,124 @@
+//! Telnet gateway tests
+//!
+//! Tests login over telnet, GMCP and sharing rooms with WebSocket players
+
+use crate::harness::{Role, TelnetClient, TestServer};
+use std::time::Duration;
+
+/// Test: a telnet player registers, gets GMCP state and talks with a
+/// browser player in the same room
+#[tokio::test]
+async fn test_telnet_player_shares_room_with_websocket_player() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    let mut browser = server
+        .connect_as(Role::Player {
+            username: "wsbuddy".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    browser.expect("room").await.expect("no initial room");
+
+    let mut telnet = TelnetClient::connect(&server)
+        .await
+        .expect("Failed to connect over telnet");
+    telnet.enable_gmcp().await.expect("enable gmcp failed");
+    telnet
+        .expect_text("Username")
+        .await
+        .expect("no login prompt");
+    telnet.send_line("new").await.unwrap();
+    telnet.expect_text("Choose a username").await.unwrap();
+    telnet.send_line("telnetter").await.unwrap();
+    telnet.expect_text("Password").await.unwrap();
+    telnet.send_line("secret123").await.unwrap();
+
+    telnet
+        .expect_text("Cave Entrance")
+        .await
+        .expect("no room description");
+    let vitals = telnet.expect_gmcp("Char.Vitals").await.expect("no vitals");
+    assert_eq!(vitals["hp"], vitals["max_hp"]);
+    telnet.expect_text("HP > ").await.expect("no prompt");
+
+    // Browser to telnet
+    browser.command("say hello telnet").await.unwrap();
+    browser
+        .expect("output")
+        .await
+        .expect("no confirmation of say");
+    telnet
+        .expect_text("wsbuddy says, \"hello telnet\"")
+        .await
+        .expect("telnet player didn't hear the browser player");
+
+    // Telnet to browser
+    telnet.send_line("say hi browser").await.unwrap();
+    telnet
+        .expect_text("You say, \"hi browser\"")
+        .await
+        .expect("no confirmation of say");
+    let heard = browser.expect("output").await.expect("no output");
+    assert_eq!(heard["text"], "telnetter says, \"hi browser\"");
+}
+
+/// Test: telnet logins go through the account service, and their session
+/// ends with the connection
+#[tokio::test]
+async fn test_telnet_login_checks_password() {
+    let server = TestServer::start().await.expect("Failed to start server");
+
+    // Register the account over HTTP
+    let browser = server
+        .connect_as(Role::Player {
+            username: "telnetuser".to_string(),
+        })
+        .await
+        .expect("Failed to connect");
+    let account_id = browser.account_id().unwrap().to_string();
+    drop(browser);
+    let sessions = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sessions WHERE account_id = ?")
+            .bind(&account_id)
+            .fetch_one(server.pool())
+            .await
+            .unwrap()
+    };
+    let before = sessions().await;
+
+    let mut telnet = TelnetClient::connect(&server)
+        .await
+        .expect("Failed to connect over telnet");
+    telnet.expect_text("Username").await.unwrap();
+    telnet.send_line("telnetuser").await.unwrap();
+    telnet.expect_text("Password").await.unwrap();
+    telnet.send_line("wrongpass").await.unwrap();
+    telnet
+        .expect_text("Invalid username or password")
+        .await
+        .expect("bad password accepted");
+
+    telnet.expect_text("Username").await.unwrap();
+    telnet.send_line("telnetuser").await.unwrap();
+    telnet.expect_text("Password").await.unwrap();
+    telnet.send_line(Role::password()).await.unwrap();
+    telnet
+        .expect_text("Cave Entrance")
+        .await
+        .expect("valid login refused");
+    assert_eq!(sessions().await, before + 1);
+
+    telnet.send_line("quit").await.unwrap();
+    telnet.expect_text("Goodbye.").await.expect("no goodbye");
+    for _ in 0..50 {
+        if sessions().await == before {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+        sessions().await,
+        before,
+        "telnet session outlived the connection"
+    );
+}